@@ -0,0 +1,111 @@
+//! Python bindings of scaficionado, for automation written in Python.
+//!
+//! ```python
+//! import scaficionado
+//!
+//! scaficionado.validate("scaffolding.toml", {"project_name": "billing", "team": "payments"}, "services/billing")
+//! summary = scaficionado.generate("scaffolding.toml", {"project_name": "billing", "team": "payments"}, "services/billing")
+//! ```
+//!
+//! Variables and results cross into Python as JSON, so dicts, lists, strings, numbers and booleans are supported.
+//! The functions may be called from several threads; their runs take turns.
+
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyString};
+use std::error::Error;
+use std::sync::Mutex;
+
+create_exception!(scaficionado, ScaficionadoError, PyException, "A run of scaficionado failed.");
+
+/// The message of an error and of the errors that caused it, as the command line prints them.
+fn error_message(error: &(dyn Error + 'static)) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        message.push_str(&format!(": {}", cause));
+        source = cause.source();
+    }
+    message
+}
+
+/// Raise a failed run as a ScaficionadoError with its exit code, e.g. 3 for a clone failure.
+fn raise(py: Python<'_>, (message, code): (String, i32)) -> PyErr {
+    let error = ScaficionadoError::new_err(message);
+    if let Err(e) = error.value(py).setattr("exit_code", code) {
+        return e;
+    }
+    error
+}
+
+/// Held by the call running scaficionado: runs keep some of their state in the process, e.g. the summary
+/// `generate` returns and the preview of `validate`, so calls from several Python threads take turns.
+static RUNS: Mutex<()> = Mutex::new(());
+
+/// Run `f` without holding the GIL, so that other Python threads run while it clones, renders and runs hooks, and
+/// after the runs of other threads.
+fn run<T: Send>(py: Python<'_>, f: impl FnOnce() -> Result<T, Box<dyn Error>> + Send) -> PyResult<T> {
+    py.detach(|| {
+        let _turn = RUNS.lock().unwrap_or_else(|e| e.into_inner());
+        f().map_err(|e| (error_message(e.as_ref()), scaficionado::exit_code(e.as_ref())))
+    })
+    .map_err(|e| raise(py, e))
+}
+
+fn to_json(py: Python<'_>, value: Option<&Bound<'_, PyDict>>) -> PyResult<serde_json::Map<String, serde_json::Value>> {
+    let Some(value) = value else {
+        return Ok(serde_json::Map::new());
+    };
+    let json: String = py.import("json")?.call_method1("dumps", (value,))?.extract()?;
+    serde_json::from_str(&json).map_err(|e| ScaficionadoError::new_err(format!("Invalid variables: {}", e)))
+}
+
+fn from_json<'py>(py: Python<'py>, value: &serde_json::Value) -> PyResult<Bound<'py, PyAny>> {
+    py.import("json")?.call_method1("loads", (PyString::new(py, &value.to_string()),))
+}
+
+/// Generate the project of the configuration file `config` into `output` without prompting.  `variables` override
+/// the global variables and those of every scaffold, and prompts take their defaults; a `project_name` variable
+/// names the project.  Returns the summary of the run, as written by --report.
+#[pyfunction]
+#[pyo3(signature = (config, variables=None, output="generated"))]
+fn generate<'py>(py: Python<'py>, config: &str, variables: Option<&Bound<'py, PyDict>>, output: &str) -> PyResult<Bound<'py, PyAny>> {
+    let variables = to_json(py, variables)?;
+    let summary = run(py, || scaficionado::scaffold_project(config, &variables, output))?;
+    from_json(py, &summary)
+}
+
+/// Check that `generate` would succeed with these arguments, by rendering the project without writing anything or
+/// running hooks.  Raises ScaficionadoError with the problems found.
+#[pyfunction]
+#[pyo3(signature = (config, variables=None, output="generated"))]
+fn validate(py: Python<'_>, config: &str, variables: Option<&Bound<'_, PyDict>>, output: &str) -> PyResult<()> {
+    let variables = to_json(py, variables)?;
+    run(py, || scaficionado::validate_project(config, &variables, output))
+}
+
+/// The scaffolds of the configuration file `config`, each a dict with its name, repositories and prompts.
+#[pyfunction]
+fn list_scaffolds<'py>(py: Python<'py>, config: &str) -> PyResult<Bound<'py, PyAny>> {
+    let listing = run(py, || scaficionado::list_scaffolds(config))?;
+    from_json(py, &listing["scaffolds"])
+}
+
+/// The variables a Tera template reads, mapped to whether they are required.
+#[pyfunction]
+fn referenced_variables<'py>(py: Python<'py>, source: &str) -> PyResult<Bound<'py, PyAny>> {
+    let variables = run(py, || scaficionado::referenced_variables(source))?;
+    from_json(py, &serde_json::json!(variables))
+}
+
+#[pymodule]
+#[pyo3(name = "scaficionado")]
+fn scaficionado_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("ScaficionadoError", m.py().get_type::<ScaficionadoError>())?;
+    m.add_function(wrap_pyfunction!(generate, m)?)?;
+    m.add_function(wrap_pyfunction!(validate, m)?)?;
+    m.add_function(wrap_pyfunction!(list_scaffolds, m)?)?;
+    m.add_function(wrap_pyfunction!(referenced_variables, m)?)?;
+    Ok(())
+}
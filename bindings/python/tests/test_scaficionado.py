@@ -0,0 +1,73 @@
+"""Tests of the Python bindings.  Build them first, e.g. with `maturin develop`, then run `python -m unittest`."""
+
+import concurrent.futures
+import os
+import pathlib
+import tempfile
+import unittest
+
+import scaficionado
+
+
+class ScaficionadoTest(unittest.TestCase):
+    def setUp(self):
+        self.dir = tempfile.TemporaryDirectory()
+        root = pathlib.Path(self.dir.name)
+        # Neither the user's configuration nor their clones are used.
+        os.environ["SCAFICIONADO_CONFIG"] = str(root / "config.toml")
+        os.environ["SCAFICIONADO_CACHE_DIR"] = str(root / "cache")
+        (root / "repo" / "templates").mkdir(parents=True)
+        (root / "repo" / "templates" / "readme.md.tera").write_text("{{ project_name }} by {{ team }}")
+        self.config = root / "scaffolding.toml"
+        self.config.write_text(
+            f"""
+[[scaffolds]]
+name = "Service"
+repo = "{(root / 'repo').as_posix()}"
+template.files = [{{ src = "readme.md.tera", dest = "README.md" }}]
+prompts = [{{ name = "team", message = "Owning team" }}, {{ name = "replicas", default = 2 }}]
+"""
+        )
+        self.output = root / "out"
+
+    def tearDown(self):
+        self.dir.cleanup()
+
+    def test_generate(self):
+        summary = scaficionado.generate(str(self.config), {"project_name": "billing", "team": "payments"}, str(self.output))
+        self.assertEqual(summary["project"], "billing")
+        self.assertEqual(summary["errors"], 0)
+        self.assertEqual((self.output / "README.md").read_text(), "billing by payments")
+
+    def test_concurrent_generate(self):
+        # Each call returns the summary of its own run.
+        teams = [f"team{n}" for n in range(8)]
+        with concurrent.futures.ThreadPoolExecutor(max_workers=4) as pool:
+            summaries = list(
+                pool.map(
+                    lambda team: scaficionado.generate(str(self.config), {"project_name": team, "team": team}, str(self.output / team)),
+                    teams,
+                )
+            )
+        self.assertEqual([summary["project"] for summary in summaries], teams)
+        for team in teams:
+            self.assertEqual((self.output / team / "README.md").read_text(), f"{team} by {team}")
+
+    def test_validate(self):
+        scaficionado.validate(str(self.config), {"team": "payments"}, str(self.output))
+        self.assertFalse(self.output.exists())
+        with self.assertRaises(scaficionado.ScaficionadoError) as raised:
+            scaficionado.validate(str(self.config), {}, str(self.output))
+        self.assertIn("team", str(raised.exception))
+        self.assertEqual(raised.exception.exit_code, 1)
+
+    def test_introspection(self):
+        [scaffold] = scaficionado.list_scaffolds(str(self.config))
+        self.assertEqual(scaffold["name"], "Service")
+        self.assertEqual([prompt["name"] for prompt in scaffold["prompts"]], ["team", "replicas"])
+        self.assertEqual(scaffold["prompts"][1]["default"], 2)
+        self.assertEqual(scaficionado.referenced_variables("{{ a }} {{ b | default(value=1) }}"), {"a": True, "b": False})
+
+
+if __name__ == "__main__":
+    unittest.main()
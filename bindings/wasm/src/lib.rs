@@ -0,0 +1,4 @@
+//! The WebAssembly module of the template playground (index.html).  Build it with
+//! `wasm-pack build --target web`; the functions are those of `scaficionado::playground`.
+
+pub use scaficionado::playground::*;
@@ -1,882 +1,13 @@
-use clap::Parser;
-use git2::Repository;
-use serde::Deserialize;
-use std::collections::HashMap;
-use std::error::Error;
-use std::fs;
-use std::io;
-use std::path::{Path, PathBuf};
-use std::process::Command;
-use tempfile::TempDir;
-use tera::{Context, Tera};
-
-// Defaults
-const DEFAULT_CONFIG_PATH: &str = "scaffolding.toml";
-const DEFAULT_PROJECT_NAME: &str = "MyExampleProject";
-const DEFAULT_OUTPUT: &str = "generated";
-const DEFAULT_OVERWRITE: bool = false;
-
-// ================================================
-// ========== MAIN FUNCTION =======================
-// ================================================
-
-pub fn run() -> Result<(), Box<dyn Error>> {
-    let args = Args::parse();
-    let config_path = Path::new(&args.config);
-    println!("Loading configuration from: {:?}", config_path);
-    let mut config = load_config(config_path)?;
-    println!("The configuration project_name and output are: {:?}", config.project);
-
-    overwrite_project_settings_with_args(&args, &mut config);
-
-    let project_name = get_project_name(&args, &config);
-    let output = get_output_directory(&args, &config);
-    let output_base = Path::new(&output);
-    let overwrite = get_overwrite(&args, &config);
-    println!(
-        "Scaffolding project '{}' to: {:?}, overwrite={}",
-        project_name, output_base, overwrite
-    );
-
-    let mut persistent_dirs: Vec<PathBuf> = Vec::new();
-    for scaffold in &config.scaffolds {
-        println!("Processing scaffold: {}", scaffold.name.as_deref().unwrap_or("unnamed"));
-        if let Some(dir) = process_scaffold(scaffold, &project_name, output_base, overwrite)? {
-            persistent_dirs.push(dir);
-        }
-    }
-
-    println!("Scaffolding for project '{}' created successfully!", project_name);
-    clean_up_persistent_dirs(persistent_dirs)?;
-
-    Ok(())
-}
-
-// ================================================
-// ========== COMMAND LINE ARGUMENTS ==============
-// ================================================
-
-#[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
-struct Args {
-    /// The name of the project to scaffold.  Overwrites project_name set in configuration file.
-    #[arg(short, long, default_value = DEFAULT_PROJECT_NAME)]
-    project_name: String,
-
-    /// The output directory where the generated files will be placed.  Overwrites output set in configuration file.
-    #[arg(short = 'o', long, default_value = DEFAULT_OUTPUT)]
-    output: String,
-
-    /// The configuration file path.
-    #[arg(short, long, default_value = DEFAULT_CONFIG_PATH)]
-    config: String,
-
-    /// Overwrite existing files if set. [default: false].  Overwrites overwrite=false set in configuration file.
-    #[arg(short = 'w', long, default_value_t = DEFAULT_OVERWRITE)]
-    overwrite: bool,
-}
-
-// ================================================
-// ========== UTILITY FUNCTIONS ===================
-// ================================================
-
-/// Check if the given repository URL is local.
-/// We assume it is local if it doesn't start with "http://", "https://", or "git://".
-fn is_local_repo(repo_url: &str) -> bool {
-    !repo_url.starts_with("http://") && !repo_url.starts_with("https://") && !repo_url.starts_with("git://")
-}
-
-/// Obtain the repository at `repo_url`.
-/// If it is a local repository, open it directly;
-/// if remote, clone it to the specified destination (`dest`).
-fn obtain_template_repo(repo_url: &str, dest: &Path) -> Result<Repository, Box<dyn Error>> {
-    if is_local_repo(repo_url) {
-        let repo = Repository::open(repo_url)?;
-        Ok(repo)
-    } else {
-        let repo = Repository::clone(repo_url, dest)?;
-        Ok(repo)
-    }
-}
-
-/// Run a hook script located at `script_path`.
-fn run_hook(script_path: &Path) -> io::Result<()> {
-    let status = Command::new(script_path).status()?;
-    if !status.success() {
-        Err(io::Error::new(io::ErrorKind::Other, "Hook script failed"))
-    } else {
-        Ok(())
-    }
-}
-
-/// Overwrite the project settings in the configuration with the values from the command line arguments
-/// only if they differ from the defaults.
-fn overwrite_project_settings_with_args(args: &Args, config: &mut Config) {
-    if args.project_name != DEFAULT_PROJECT_NAME {
-        if let Some(ref mut project) = config.project {
-            project.name = Some(args.project_name.clone());
-        } else {
-            config.project = Some(ProjectConfig {
-                name: Some(args.project_name.clone()),
-                output: None,
-                overwrite: None,
-            });
-        }
-    }
-    if args.output != DEFAULT_OUTPUT {
-        if let Some(ref mut project) = config.project {
-            project.output = Some(args.output.clone());
-        } else {
-            config.project = Some(ProjectConfig {
-                name: None,
-                output: Some(args.output.clone()),
-                overwrite: None,
-            });
-        }
-    }
-    if args.overwrite != DEFAULT_OVERWRITE {
-        if let Some(ref mut project) = config.project {
-            project.overwrite = Some(args.overwrite);
-        } else {
-            config.project = Some(ProjectConfig {
-                name: None,
-                output: None,
-                overwrite: Some(args.overwrite),
-            });
-        }
-    }
-}
-
-/// Get the project name: use the config value if present; otherwise fall back to the CLI default.
-fn get_project_name(args: &Args, config: &Config) -> String {
-    config
-        .project
-        .as_ref()
-        .and_then(|proj| proj.name.clone())
-        .unwrap_or_else(|| args.project_name.clone())
-}
-
-/// Get the output directory: use the config value if present; otherwise fall back to the CLI default.
-fn get_output_directory(args: &Args, config: &Config) -> String {
-    config
-        .project
-        .as_ref()
-        .and_then(|proj| proj.output.clone())
-        .unwrap_or_else(|| args.output.clone())
-}
-
-/// Get the overwrite flag: use the config value if present; otherwise fall back to the CLI default.
-fn get_overwrite(args: &Args, config: &Config) -> bool {
-    config.project.as_ref().and_then(|proj| proj.overwrite).unwrap_or(args.overwrite)
-}
-
-/// Clean up the persistent temporary directories used for remote clones.
-fn clean_up_persistent_dirs(dirs: Vec<PathBuf>) -> Result<(), Box<dyn Error>> {
-    for dir in dirs {
-        println!("Cleaning up temporary clone at: {:?}", dir);
-        fs::remove_dir_all(&dir)?;
-    }
-    Ok(())
-}
-
-// ================================================
-// ========== DATA STRUCTURES =====================
-// ================================================
-
-#[derive(Deserialize)]
-struct TemplateFile {
-    src: String,
-    dest: String,
-}
-
-#[derive(Deserialize)]
-struct TemplateConfig {
-    files: Vec<TemplateFile>,
-}
-
-#[derive(Deserialize)]
-struct HooksConfig {
-    pre: Option<String>,
-    post: Option<String>,
-}
-
-/// Represents a single scaffold configuration. Each scaffold specifies:
-/// - A name (optional)
-/// - A repository URL (local or remote)
-/// - An optional template directory (defaults to "templates" if not provided)
-/// - A set of template file definitions
-/// - Optional hooks to run before and after generation.
-#[derive(Deserialize)]
-struct Scaffold {
-    name: Option<String>,
-    repo: String,
-    template_dir: Option<String>,
-    template: TemplateConfig,
-    hooks: Option<HooksConfig>,
-    variables: Option<HashMap<String, toml::Value>>,
-}
-
-// Add a new struct for top-level project configuration.
-#[derive(Deserialize, Debug)]
-struct ProjectConfig {
-    name: Option<String>,
-    output: Option<String>,
-    overwrite: Option<bool>,
-}
-
-#[derive(Deserialize)]
-struct Config {
-    project: Option<ProjectConfig>,
-    scaffolds: Vec<Scaffold>,
-}
-
-/// Load the configuration from a TOML file at `config_path`.
-fn load_config(config_path: &Path) -> Result<Config, Box<dyn Error>> {
-    let config_str = fs::read_to_string(config_path)?;
-    let config: Config = toml::from_str(&config_str)?;
-    Ok(config)
-}
-
-// ================================================
-// ========== TEMPLATE RENDERING ==================
-// ================================================
-
-fn write_file(dest: &Path, content: &[u8], overwrite: bool) -> Result<(), Box<dyn Error>> {
-    if dest.exists() && !overwrite {
-        println!("Skipping existing file: {:?}", dest);
-        return Ok(());
-    }
-    if let Some(parent) = dest.parent() {
-        fs::create_dir_all(parent)?;
-    }
-    fs::write(dest, content)?;
-    Ok(())
-}
-
-fn copy_file(src: &Path, dest: &Path, overwrite: bool) -> Result<(), Box<dyn Error>> {
-    if dest.exists() && !overwrite {
-        println!("Skipping existing file: {:?}", dest);
-        return Ok(());
-    }
-    if let Some(parent) = dest.parent() {
-        fs::create_dir_all(parent)?;
-    }
-    fs::copy(src, dest)?;
-    Ok(())
-}
-
-// ===== Updated process_directory =====
-fn process_directory(src_dir: &Path, dest_dir: &Path, context: &Context, overwrite: bool) -> Result<(), Box<dyn Error>> {
-    for entry in fs::read_dir(src_dir)? {
-        let entry = entry?;
-        let path = entry.path();
-        let relative = path.strip_prefix(src_dir)?;
-        let dest_path = dest_dir.join(relative);
-        if path.is_dir() {
-            fs::create_dir_all(&dest_path)?;
-            process_directory(&path, &dest_path, context, overwrite)?;
-        } else {
-            if let Some(ext) = path.extension() {
-                if ext == "tera" {
-                    let content = fs::read_to_string(&path)?;
-                    let rendered = Tera::one_off(&content, context, false)?;
-                    let dest_file = dest_path.with_extension(""); // remove .tera extension
-                    write_file(&dest_file, rendered.as_bytes(), overwrite)?;
-                    continue;
-                }
-            }
-            copy_file(&path, &dest_path, overwrite)?;
-        }
-    }
-    Ok(())
-}
-
-// ===== Updated render_templates =====
-fn render_templates(
-    templates_dir: &Path,
-    output_base: &Path,
-    scaffold: &Scaffold,
-    context_data: &Context,
-    overwrite: bool,
-) -> Result<(), Box<dyn Error>> {
-    let mut tera = Tera::default();
-
-    // Register individual template files ending in ".tera".
-    for file in &scaffold.template.files {
-        let src_path = templates_dir.join(&file.src);
-        if src_path.is_file() && file.src.ends_with(".tera") {
-            let key = if file.src.starts_with("templates/") {
-                &file.src["templates/".len()..]
-            } else {
-                &file.src
-            };
-            tera.add_template_file(src_path.clone(), Some(key))?;
-        }
-    }
-
-    for file in &scaffold.template.files {
-        let dest_path_str = Tera::one_off(&file.dest, context_data, false)?;
-        let dest_path = output_base.join(dest_path_str);
-        let src_path = templates_dir.join(&file.src);
-
-        if src_path.is_dir() {
-            println!("Processing directory: {:?}", src_path);
-            fs::create_dir_all(&dest_path)?;
-            process_directory(&src_path, &dest_path, context_data, overwrite)?;
-            continue;
-        }
-
-        if dest_path.exists() && !overwrite {
-            println!("Skipping existing file: {:?} because overwrite=false", dest_path);
-            continue;
-        } else if dest_path.exists() {
-            println!("Overwriting existing file: {:?}", dest_path);
-        } else {
-            println!("Creating file: {:?}", dest_path);
-        }
-
-        if file.src.ends_with(".tera") {
-            let key = if file.src.starts_with("templates/") {
-                &file.src["templates/".len()..]
-            } else {
-                &file.src
-            };
-            let rendered = tera.render(key, context_data)?;
-            write_file(&dest_path, rendered.as_bytes(), overwrite)?;
-        } else {
-            copy_file(&src_path, &dest_path, overwrite)?;
-        }
-    }
-    Ok(())
-}
-
-// ================================================
-// ========== SCAFFOLD PROCESSING =================
-// ================================================
-
-/// Process a single scaffold. Added parameter `overwrite: bool` to pass
-/// the overwrite flag to render_templates.
-fn process_scaffold(
-    scaffold: &Scaffold,
-    project_name: &str,
-    output_base: &Path,
-    overwrite: bool,
-) -> Result<Option<PathBuf>, Box<dyn Error>> {
-    // --- Obtain the Scaffold Repository ---
-    let scaffold_repo_base: PathBuf = if is_local_repo(&scaffold.repo) {
-        let path = fs::canonicalize(&scaffold.repo)?;
-        println!("Using local scaffold repository at: {:?}", path);
-        path
-    } else {
-        let temp_dir = TempDir::new()?;
-        let scaffold_dir = temp_dir.path().join(scaffold.name.as_deref().unwrap_or("unnamed"));
-        if scaffold_dir.exists() {
-            fs::remove_dir_all(&scaffold_dir)?;
-        }
-        println!("Cloning repo {:?}", scaffold.repo);
-        let _repo = obtain_template_repo(&scaffold.repo, &scaffold_dir)?;
-        let persistent_temp_dir = temp_dir.into_path();
-        persistent_temp_dir.join(scaffold.name.as_deref().unwrap_or("unnamed"))
-    };
-
-    // --- Determine the Templates Directory ---
-    let templates_dir = scaffold_repo_base.join(scaffold.template_dir.as_deref().unwrap_or("templates"));
-    println!("Rendering templates from: {:?}", templates_dir);
-
-    // --- Set Up the Templating Context ---
-    let mut context = Context::new();
-    context.insert("project_name", project_name);
-    if let Some(vars) = &scaffold.variables {
-        for (key, value) in vars {
-            // println!("Setting variable: {} = {:?}", key, value);
-            context.insert(key, value);
-        }
-    }
-
-    // --- Render Templates / Copy Files (with overwrite flag) ---
-    render_templates(&templates_dir, output_base, scaffold, &context, overwrite)?;
-
-    // --- Run Pre-Generation Hook (if any) ---
-    if let Some(hooks) = &scaffold.hooks {
-        if let Some(pre_script) = &hooks.pre {
-            let pre_hook_path = scaffold_repo_base.join(pre_script);
-            println!("Running pre-generation hook: {:?}", pre_hook_path);
-            run_hook(&pre_hook_path)?;
-        }
-    }
-
-    // --- Run Post-Generation Hook (if any) ---
-    if let Some(hooks) = &scaffold.hooks {
-        if let Some(post_script) = &hooks.post {
-            let post_hook_path = scaffold_repo_base.join(post_script);
-            println!("Running post-generation hook: {:?}", post_hook_path);
-            run_hook(&post_hook_path)?;
-        }
-    }
-
-    if is_local_repo(&scaffold.repo) {
-        Ok(None)
-    } else {
-        Ok(Some(scaffold_repo_base))
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::collections::HashMap;
-    use std::fs;
-    use tempfile::TempDir;
-    use tera::Context;
-
-    // Test the is_local_repo helper function.
-    #[test]
-    fn test_is_local_repo() {
-        // Local path should return true.
-        assert!(is_local_repo("some/local/path"));
-        // URLs should return false.
-        assert!(!is_local_repo("https://github.com/example/repo.git"));
-        assert!(!is_local_repo("http://example.com/repo"));
-        assert!(!is_local_repo("git://example.com/repo"));
-    }
-
-    // Test loading configuration from a TOML string.
-    #[test]
-    fn test_load_config() -> Result<(), Box<dyn std::error::Error>> {
-        let toml_content = r#"
-[[scaffolds]]
-name = "Local"
-repo = "local_repo"
-template_dir = "templates"
-
-[scaffolds.template]
-files = [
-    { src = "kind_config.yaml.tera", dest = "kind_config.yaml" }
-]
-
-[scaffolds.hooks]
-pre = "scripts/pre.sh"
-post = "scripts/post.sh"
-
-[scaffolds.variables]
-kind_workers = 3
-environment = "development"
-"#;
-        let temp_dir = TempDir::new()?;
-        let config_path = temp_dir.path().join("scaffolding.toml");
-        fs::write(&config_path, toml_content)?;
-
-        let config = load_config(&config_path)?;
-        assert_eq!(config.scaffolds.len(), 1);
-        let scaffold = &config.scaffolds[0];
-        assert_eq!(scaffold.name.as_deref(), Some("Local"));
-        assert_eq!(scaffold.repo, "local_repo");
-        Ok(())
-    }
-
-    // Test the render_templates function.
-    #[test]
-    fn test_render_templates() -> Result<(), Box<dyn std::error::Error>> {
-        // Create temporary directories for templates and output.
-        let templates_dir = TempDir::new()?;
-        let output_dir = TempDir::new()?;
-
-        // Write a simple Tera template file with a placeholder.
-        let template_content = "Hello, {{ project_name }}!";
-        let template_file_path = templates_dir.path().join("greeting.txt.tera");
-        fs::write(&template_file_path, template_content)?;
-
-        // Build a Scaffold instance that uses this template.
-        let scaffold = Scaffold {
-            name: Some("Test".to_string()),
-            repo: "local_repo".to_string(),     // Not used in this test.
-            template_dir: Some("".to_string()), // Use the root of the templates_dir.
-            template: TemplateConfig {
-                files: vec![TemplateFile {
-                    src: "greeting.txt.tera".to_string(),
-                    dest: "greeting.txt".to_string(),
-                }],
-            },
-            hooks: None,
-            variables: None,
-        };
-
-        // Create a Tera context and insert a value for project_name.
-        let mut context = Context::new();
-        context.insert("project_name", "TestProject");
-
-        // Render the template.
-        render_templates(templates_dir.path(), output_dir.path(), &scaffold, &context, true)?;
-
-        // Verify the rendered output.
-        let output_file_path = output_dir.path().join("greeting.txt");
-        let rendered_content = fs::read_to_string(output_file_path)?;
-        assert_eq!(rendered_content, "Hello, TestProject!");
-
-        Ok(())
-    }
-
-    // Test process_scaffold with a simulated local repository.
-    #[test]
-    fn test_process_scaffold_local() -> Result<(), Box<dyn std::error::Error>> {
-        // Create a temporary directory to simulate a local repository.
-        let local_repo_dir = TempDir::new()?;
-        // Create the "templates" directory within it.
-        let templates_subdir = local_repo_dir.path().join("templates");
-        fs::create_dir_all(&templates_subdir)?;
-
-        // Write a sample template into the local repo.
-        let template_content = "Hello, {{ project_name }}!";
-        let template_file_path = templates_subdir.join("greeting.txt.tera");
-        fs::write(&template_file_path, template_content)?;
-
-        // Build a Scaffold instance referring to the local repository.
-        let scaffold = Scaffold {
-            name: Some("LocalTest".to_string()),
-            repo: local_repo_dir.path().to_string_lossy().to_string(),
-            template_dir: Some("templates".to_string()),
-            template: TemplateConfig {
-                files: vec![TemplateFile {
-                    src: "greeting.txt.tera".to_string(),
-                    dest: "greeting.txt".to_string(),
-                }],
-            },
-            hooks: None,
-            variables: None,
-        };
-
-        // Create a temporary output directory.
-        let output_dir = TempDir::new()?;
-
-        // Process the scaffold.
-        let result = process_scaffold(&scaffold, "LocalProject", output_dir.path(), true)?;
-        // For local repositories, process_scaffold should return Ok(None).
-        assert!(result.is_none());
-
-        // Verify that the rendered file has been created.
-        let output_file_path = output_dir.path().join("greeting.txt");
-        let rendered_content = fs::read_to_string(output_file_path)?;
-        assert_eq!(rendered_content, "Hello, LocalProject!");
-
-        Ok(())
-    }
-
-    // Test the run_hook function with a hook that succeeds.
-    #[test]
-    fn test_run_hook_success() -> Result<(), Box<dyn std::error::Error>> {
-        let temp_dir = TempDir::new()?;
-        let script_path = temp_dir.path().join("hook.sh");
-
-        // Create a simple script that exits successfully.
-        #[cfg(unix)]
-        fs::write(&script_path, "#!/bin/sh\nexit 0")?;
-        #[cfg(windows)]
-        fs::write(&script_path, "@echo off\r\nexit 0")?;
-
-        // On Unix, ensure the script is executable.
-        #[cfg(unix)]
-        {
-            use std::os::unix::fs::PermissionsExt;
-            let mut perms = fs::metadata(&script_path)?.permissions();
-            perms.set_mode(0o755);
-            fs::set_permissions(&script_path, perms)?;
-        }
-
-        // run_hook should complete without error.
-        run_hook(&script_path)?;
-        Ok(())
-    }
-
-    // Test the run_hook function with a hook that fails.
-    #[test]
-    fn test_run_hook_failure() -> Result<(), Box<dyn std::error::Error>> {
-        let temp_dir = TempDir::new()?;
-        let script_path = temp_dir.path().join("hook_fail.sh");
-
-        // Create a simple script that exits with a non-zero status.
-        #[cfg(unix)]
-        fs::write(&script_path, "#!/bin/sh\nexit 1")?;
-        #[cfg(windows)]
-        fs::write(&script_path, "@echo off\r\nexit 1")?;
-
-        #[cfg(unix)]
-        {
-            use std::os::unix::fs::PermissionsExt;
-            let mut perms = fs::metadata(&script_path)?.permissions();
-            perms.set_mode(0o755);
-            fs::set_permissions(&script_path, perms)?;
-        }
-
-        // run_hook should return an error.
-        let result = run_hook(&script_path);
-        assert!(result.is_err());
-        Ok(())
-    }
-
-    #[test]
-    fn test_dest_file_expands_variables_from_scaffold_variables() -> Result<(), Box<dyn std::error::Error>> {
-        // Create a temporary directory to simulate a local repository.
-        let local_repo_dir = TempDir::new()?;
-        let templates_dir = local_repo_dir.path().join("templates");
-        fs::create_dir_all(&templates_dir)?;
-
-        // Create necessary sub-directory and template file.
-        let kind_cluster_dir = templates_dir.join("kind-cluster");
-        fs::create_dir_all(&kind_cluster_dir)?;
-        let template_file_path = kind_cluster_dir.join("kind_config.yaml.tera");
-        fs::write(
-            &template_file_path,
-            "config: {{project_name}}, workers: {{kind_workers}}, env: {{environment}}",
-        )?;
-
-        // Build a Scaffold instance referring to the local repository.
-        let scaffold = Scaffold {
-            name: Some("LocalTest".to_string()),
-            repo: local_repo_dir.path().to_string_lossy().to_string(),
-            template_dir: Some("templates".to_string()),
-            template: TemplateConfig {
-                files: vec![TemplateFile {
-                    src: "kind-cluster/kind_config.yaml.tera".to_string(),
-                    dest: "{{project_name}}-{{environment}}-kind_config{{kind_workers}}.yaml".to_string(),
-                }],
-            },
-            hooks: None,
-            variables: Some({
-                let mut map = HashMap::new();
-                map.insert("kind_workers".to_string(), toml::Value::Integer(3));
-                map.insert("environment".to_string(), toml::Value::String("development".to_string()));
-                map
-            }),
-        };
-
-        // Create a temporary output directory.
-        let output_dir = TempDir::new()?;
-        process_scaffold(&scaffold, "MyProject", output_dir.path(), true)?;
-
-        // Verify that the destination filename has expanded variables.
-        let expected_output_file = output_dir.path().join("MyProject-development-kind_config3.yaml");
-        assert!(expected_output_file.exists());
-        assert!(expected_output_file
-            .file_name()
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .contains("MyProject-development-kind_config3.yaml"));
-        let output_content = fs::read_to_string(expected_output_file)?;
-        assert!(output_content.contains("config: MyProject, workers: 3, env: development"));
-        Ok(())
-    }
-
-    #[test]
-    fn test_overwrite_project_settings_with_args() -> Result<(), Box<dyn std::error::Error>> {
-        // Test when config.project is None.
-        let args = Args {
-            project_name: "ArgProject".into(),
-            output: "arg_output".into(),
-            config: "dummy".into(),
-            overwrite: false,
-        };
-        let mut config = Config {
-            project: None,
-            scaffolds: vec![],
-        };
-        overwrite_project_settings_with_args(&args, &mut config);
-        let proj = config.project.unwrap();
-        assert_eq!(proj.name.unwrap(), "ArgProject");
-        assert_eq!(proj.output.unwrap(), "arg_output");
-
-        // Test when config.project already exists.
-        let mut config = Config {
-            project: Some(ProjectConfig {
-                name: Some("OldProject".into()),
-                output: Some("old_output".into()),
-                overwrite: Some(false),
-            }),
-            scaffolds: vec![],
-        };
-        // Overwrite with new values.
-        let args = Args {
-            project_name: "NewProject".into(),
-            output: "new_output".into(),
-            config: "dummy".into(),
-            overwrite: false,
-        };
-        overwrite_project_settings_with_args(&args, &mut config);
-        let proj = config.project.unwrap();
-        assert_eq!(proj.name.unwrap(), "NewProject");
-        assert_eq!(proj.output.unwrap(), "new_output");
-        Ok(())
-    }
-
-    #[test]
-    fn test_get_project_name_and_get_output_directory() -> Result<(), Box<dyn std::error::Error>> {
-        // When project config exists.
-        let args = Args {
-            project_name: "CLIProject".into(),
-            output: "CLOutput".into(),
-            config: "dummy".into(),
-            overwrite: false,
-        };
-        let config = Config {
-            project: Some(ProjectConfig {
-                name: Some("ConfigProject".into()),
-                output: Some("ConfigOutput".into()),
-                overwrite: Some(false),
-            }),
-            scaffolds: vec![],
-        };
-        assert_eq!(get_project_name(&args, &config), "ConfigProject");
-        assert_eq!(get_output_directory(&args, &config), "ConfigOutput");
-
-        // When project config is missing.
-        let config = Config {
-            project: None,
-            scaffolds: vec![],
-        };
-        assert_eq!(get_project_name(&args, &config), "CLIProject");
-        assert_eq!(get_output_directory(&args, &config), "CLOutput");
-        Ok(())
-    }
-
-    #[test]
-    fn test_clean_up_persistent_dirs() -> Result<(), Box<dyn std::error::Error>> {
-        // Create two temporary directories and then call clean_up_persistent_dirs.
-        let temp_dir1 = tempfile::TempDir::new()?;
-        let temp_path1 = temp_dir1.into_path();
-        let temp_dir2 = tempfile::TempDir::new()?;
-        let temp_path2 = temp_dir2.into_path();
-
-        // Ensure directories exist before cleanup.
-        assert!(temp_path1.exists());
-        assert!(temp_path2.exists());
-
-        clean_up_persistent_dirs(vec![temp_path1.clone(), temp_path2.clone()])?;
-
-        // Verify directories are removed.
-        assert!(!temp_path1.exists());
-        assert!(!temp_path2.exists());
-        Ok(())
-    }
-
-    #[test]
-    fn test_overwrite_flag() -> Result<(), Box<dyn std::error::Error>> {
-        // Set up temporary directories.
-        let templates_dir = TempDir::new()?;
-        let output_dir = TempDir::new()?;
-
-        // Write a simple template file.
-        let template_file_path = templates_dir.path().join("template.tera");
-        fs::write(&template_file_path, "Original content: {{ value }}")?;
-
-        // Build a scaffold using the template.
-        let scaffold = Scaffold {
-            name: Some("OverwriteTest".into()),
-            repo: "dummy".into(), // not used in render_templates
-            template_dir: Some("".into()),
-            template: TemplateConfig {
-                files: vec![TemplateFile {
-                    src: "template.tera".into(),
-                    dest: "test.txt".into(),
-                }],
-            },
-            hooks: None,
-            variables: None,
-        };
-
-        // Prepare a Tera context.
-        let mut context = Context::new();
-        context.insert("value", "new");
-
-        let output_file_path = output_dir.path().join("test.txt");
-
-        // Pre-create the output file with content "old".
-        fs::write(&output_file_path, "old")?;
-
-        // Render templates with overwrite = false; file should remain unchanged.
-        render_templates(templates_dir.path(), output_dir.path(), &scaffold, &context, false)?;
-        let content = fs::read_to_string(&output_file_path)?;
-        assert_eq!(content, "old");
-
-        // Render templates with overwrite = true; file should be overwritten.
-        render_templates(templates_dir.path(), output_dir.path(), &scaffold, &context, true)?;
-        let content = fs::read_to_string(&output_file_path)?;
-        assert_eq!(content, "Original content: new");
-
-        Ok(())
-    }
-
-    #[test]
-    fn test_render_full_directory_templating_and_skipping() -> Result<(), Box<dyn std::error::Error>> {
-        // Create temporary directories for full directory test.
-        let templates_dir = TempDir::new()?;
-        let output_dir = TempDir::new()?;
-
-        // Create a full directory structure in templates_dir.
-        let full_dir = templates_dir.path().join("full_dir");
-        fs::create_dir_all(&full_dir)?;
-
-        // Create file1.txt.tera in full_dir.
-        let file1_path = full_dir.join("file1.txt.tera");
-        fs::write(&file1_path, "Hello, {{ project_name }}!")?;
-
-        // Create file2.txt (non-templated) in full_dir.
-        let file2_path = full_dir.join("file2.txt");
-        fs::write(&file2_path, "Static content")?;
-
-        // Create nested directory and file3.txt.tera.
-        let nested_dir = full_dir.join("sub_dir");
-        fs::create_dir_all(&nested_dir)?;
-        let file3_path = nested_dir.join("file3.txt.tera");
-        fs::write(&file3_path, "Nested {{ project_name }}!")?;
-
-        // Build a scaffold that processes the entire "full_dir" directory.
-        let scaffold = Scaffold {
-            name: Some("FullDirTest".to_string()),
-            repo: "dummy".to_string(),          // not used in render_templates.
-            template_dir: Some("".to_string()), // Use the root of templates_dir.
-            template: TemplateConfig {
-                files: vec![TemplateFile {
-                    src: "full_dir".into(),
-                    dest: "rendered_dir".into(),
-                }],
-            },
-            hooks: None,
-            variables: None,
-        };
-
-        // Create a Tera context.
-        let mut context = Context::new();
-        context.insert("project_name", "TestProject");
-
-        // First rendering with overwrite true.
-        render_templates(templates_dir.path(), output_dir.path(), &scaffold, &context, true)?;
-
-        // Verify file1.txt is rendered and its .tera extension is removed.
-        let rendered_file1 = output_dir.path().join("rendered_dir").join("file1.txt");
-        assert!(rendered_file1.exists());
-        let content1 = fs::read_to_string(&rendered_file1)?;
-        assert_eq!(content1, "Hello, TestProject!");
-
-        // Verify file2.txt is copied as-is.
-        let rendered_file2 = output_dir.path().join("rendered_dir").join("file2.txt");
-        assert!(rendered_file2.exists());
-        let content2 = fs::read_to_string(&rendered_file2)?;
-        assert_eq!(content2, "Static content");
-
-        // Verify the nested templated file is processed and renamed.
-        let rendered_file3 = output_dir.path().join("rendered_dir").join("sub_dir").join("file3.txt");
-        assert!(rendered_file3.exists());
-        let content3 = fs::read_to_string(&rendered_file3)?;
-        assert_eq!(content3, "Nested TestProject!");
-
-        // Now, simulate an existing file scenario.
-        fs::write(&rendered_file1, "Old Content")?;
-        // Run templating again with overwrite = false.
-        render_templates(templates_dir.path(), output_dir.path(), &scaffold, &context, false)?;
-        let content1_after = fs::read_to_string(&rendered_file1)?;
-        // The pre-existing file should remain unchanged.
-        assert_eq!(content1_after, "Old Content");
-
-        Ok(())
-    }
-}
+// The command line and the library API are in `native`, which needs git, the network and processes.  Without the
+// `native` feature, only the rendering core is built, for the WebAssembly playground of the `wasm` feature; without
+// either, the crate is empty.
+#[cfg(any(feature = "native", feature = "wasm"))]
+mod render;
+
+#[cfg(feature = "native")]
+mod native;
+#[cfg(feature = "native")]
+pub use native::*;
+
+#[cfg(feature = "wasm")]
+pub mod playground;
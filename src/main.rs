@@ -1,6 +1,6 @@
 fn main() {
     if let Err(e) = scaficionado::run() {
-        eprintln!("Error: {:?}", e);
-        std::process::exit(1);
+        eprintln!("Error: {}", e);
+        std::process::exit(scaficionado::exit_code(e.as_ref()));
     }
 }
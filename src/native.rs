@@ -0,0 +1,67 @@
+//! The command line and the library API of scaficionado.
+//!
+//! Each concern lives in a module of its own; the modules share their items with each other through the glob
+//! imports below, and only the API of applications is exported from the crate.
+
+/// Print a progress message on stdout, or on stderr while stdout carries a generated file (`--output -`).
+macro_rules! say {
+    ($($arg:tt)*) => {
+        if messages_to_stderr() {
+            eprintln!($($arg)*)
+        } else {
+            println!($($arg)*)
+        }
+    };
+}
+
+mod batch;
+mod bundle;
+mod cache;
+mod cli;
+mod commands;
+mod compat;
+mod config;
+mod generate;
+mod hooks;
+mod library;
+mod output;
+mod paths;
+mod prompts;
+mod registry;
+mod report;
+mod serve;
+mod sources;
+mod templates;
+#[cfg(test)]
+mod testing;
+
+use batch::*;
+use bundle::*;
+use cache::*;
+use cli::*;
+use commands::*;
+use compat::*;
+use config::*;
+use generate::*;
+use hooks::*;
+use library::*;
+use output::*;
+use paths::*;
+use prompts::*;
+use registry::*;
+use report::*;
+use serve::*;
+use sources::*;
+use templates::*;
+#[cfg(test)]
+use testing::*;
+
+pub use cli::run;
+#[cfg(feature = "async")]
+pub use library::RunLimit;
+pub use library::{
+    list_scaffolds, register_observer, scaffold_project, set_cancellation_token, validate_project, ScaffoldObserver, Scaffolder,
+};
+pub use report::exit_code;
+pub use sources::{register_source, ScaffoldSource, SourceFactory};
+pub use templates::referenced_variables;
@@ -0,0 +1,305 @@
+//! The `batch` subcommand: a project per row of a CSV or TOML file.
+
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+
+use super::*;
+
+/// A `batch` input in TOML: `[[projects]]` tables with `project_name`, an optional `output`, and variables.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BatchFile {
+    #[serde(default)]
+    projects: Vec<toml::Table>,
+}
+
+/// Read the projects of a `batch` input: a TOML file of `[[projects]]`, or a CSV file whose header names the columns.
+/// Empty CSV cells are left out, so that the configured values apply.
+fn load_batch_rows(path: &Path) -> Result<Vec<BatchRow>, Box<dyn Error>> {
+    let content = read_text_file(path)?;
+    let tables: Vec<toml::Table> = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => from_toml::<BatchFile>(&content)?.projects,
+        Some("csv") => {
+            let mut reader = csv::Reader::from_reader(content.as_bytes());
+            let headers = reader.headers()?.clone();
+            let mut tables = Vec::new();
+            for record in reader.records() {
+                let record = record?;
+                let cells = headers.iter().zip(record.iter()).filter(|(_, value)| !value.is_empty());
+                tables.push(
+                    cells
+                        .map(|(name, value)| (name.trim().to_string(), toml::Value::String(value.to_string())))
+                        .collect(),
+                );
+            }
+            tables
+        }
+        _ => return Err(format!("Batch input {:?} must be a .csv or .toml file", path).into()),
+    };
+    tables
+        .into_iter()
+        .enumerate()
+        .map(|(index, mut table)| {
+            let mut take_string = |name: &str| match table.remove(name) {
+                Some(toml::Value::String(value)) => Ok(Some(value)),
+                Some(other) => Err(format!(
+                    "Project {} of {:?}: `{}` must be a string, got {}",
+                    index + 1,
+                    path,
+                    name,
+                    other
+                )),
+                None => Ok(None),
+            };
+            let project_name =
+                take_string("project_name")?.ok_or_else(|| format!("Project {} of {:?} has no project_name", index + 1, path))?;
+            let output = take_string("output")?;
+            Ok(BatchRow {
+                project_name,
+                output,
+                variables: table.into_iter().collect(),
+            })
+        })
+        .collect()
+}
+
+/// Set up the configuration for one project of a batch.  Its variables override the global variables and those of
+/// every scaffold.  Without an `output` of its own, the project goes to a subdirectory of the configured output
+/// named after it, unless that output already uses the project name.
+pub(crate) fn apply_batch_row(args: &Args, mut config: Config, row: &BatchRow) -> Config {
+    let configured = get_output_directory(args, &config);
+    let output = row.output.clone().unwrap_or_else(|| match configured.contains("project_name") {
+        true => configured,
+        false => Path::new(&configured).join(&row.project_name).to_string_lossy().into_owned(),
+    });
+    let project = config.project.get_or_insert_with(Default::default);
+    project.name = Some(row.project_name.clone());
+    project.output = Some(output);
+    let variables = row.variables.iter().map(|(name, value)| (name.clone(), value.clone()));
+    config.variables.get_or_insert_with(Default::default).extend(variables);
+    for scaffold in &mut config.scaffolds {
+        if let Some(variables) = &mut scaffold.variables {
+            for (name, value) in &row.variables {
+                if let Some(variable) = variables.get_mut(name) {
+                    *variable = value.clone();
+                }
+            }
+        }
+    }
+    config
+}
+
+/// The outcome of one project of a batch, in the consolidated report.
+#[derive(Debug, Serialize)]
+struct BatchProject {
+    project: String,
+    output: String,
+    elapsed_seconds: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    /// The run summary of the project, when it got as far as rendering.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    summary: Option<RunSummary>,
+}
+
+/// The consolidated report of `batch`, printed at the end and written as JSON with --report.
+#[derive(Debug, Serialize)]
+struct BatchReport {
+    projects: Vec<BatchProject>,
+}
+
+impl BatchReport {
+    fn failed(&self) -> usize {
+        self.projects.iter().filter(|project| project.error.is_some()).count()
+    }
+
+    fn table(&self) -> String {
+        let width = self
+            .projects
+            .iter()
+            .map(|project| project.project.len())
+            .chain(["Project".len()])
+            .max()
+            .unwrap_or(0);
+        let mut table = format!(
+            "Batch summary ({} project(s), {} failed):\n  {:<width$}  Status  Output\n",
+            self.projects.len(),
+            self.failed(),
+            "Project"
+        );
+        for project in &self.projects {
+            let status = match project.error {
+                Some(_) => "failed",
+                None => "ok",
+            };
+            table.push_str(&format!("  {:<width$}  {:<6}  {}", project.project, status, project.output));
+            if let Some(error) = &project.error {
+                table.push_str(&format!(": {}", error));
+            }
+            table.push('\n');
+        }
+        table
+    }
+}
+
+/// Generate every project of a batch input with the configuration, one after the other.  A failed project doesn't
+/// stop the others; the run fails at the end if any did.  Prompts take their defaults, so the rows must set the
+/// variables of prompts without one.  --report writes the consolidated report instead of per-project summaries.
+pub(crate) fn generate_batch(args: &Args, input: &Path) -> Result<(), Box<dyn Error>> {
+    let rows = load_batch_rows(input)?;
+    say!("Generating {} project(s) from {:?}", rows.len(), input);
+    let mut report = BatchReport { projects: Vec::new() };
+    for row in &rows {
+        let row_args = Args {
+            project_name: row.project_name.clone(),
+            defaults: true,
+            open: false,
+            report: None,
+            error_report: None,
+            ..args.clone()
+        };
+        let started = Instant::now();
+        LAST_SUMMARY.with(|last| last.borrow_mut().take());
+        let mut output = row.output.clone().unwrap_or_default();
+        let result = load_config_with_args(&row_args).and_then(|config| {
+            let config = apply_batch_row(&row_args, config, row);
+            output = get_output_directory(&row_args, &config);
+            generate_config(&row_args, config)
+        });
+        let summary = LAST_SUMMARY.with(|last| last.borrow_mut().take());
+        report.projects.push(BatchProject {
+            project: row.project_name.clone(),
+            output: summary.as_ref().map_or(output, |summary| summary.output.clone()),
+            elapsed_seconds: started.elapsed().as_secs_f64(),
+            error: result.err().map(|e| e.to_string()),
+            summary,
+        });
+    }
+    say!("\n{}", report.table());
+    if let Some(path) = &args.report {
+        fs::write(path, serde_json::to_string_pretty(&report)?)?;
+        say!("Wrote batch report to: {:?}", path);
+    }
+    match report.failed() {
+        0 => Ok(()),
+        failed => Err(format!("{} of {} project(s) failed", failed, report.projects.len()).into()),
+    }
+}
+
+/// `serve` subcommand: an HTTP API over the scaffolds of the configuration, for self-service portals.  Up to
+/// SERVE_THREADS connections are answered at the same time, each in a run of its own:
+/// - `GET /` is an HTML form for the variables of the prompts, which downloads the generated project,
+/// - `GET /scaffolds` lists the configured scaffolds and their prompts,
+/// - `POST /validate` checks a project, `{"project_name": "api", "variables": {...}}` as in a `batch` row, by
+///   rendering it without writing anything,
+/// - `POST /generate` generates it and returns it as a .tar.gz archive of a directory named after the project.  The
+///   scaffolds' hooks run as for any other run, so whoever can reach the server runs them.
+pub(crate) fn serve(args: &Args, listen: &str) -> Result<(), Box<dyn Error>> {
+    let listener = std::net::TcpListener::bind(listen).map_err(|e| format!("Failed to listen on {}: {}", listen, e))?;
+    say!("Serving the scaffolds of {} on http://{}", args.config, listener.local_addr()?);
+    serve_requests(args, &listener);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use std::collections::BTreeMap;
+    use std::fs;
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    // Test that `batch` generates a project per row, isolates failures and writes a consolidated report.
+    #[test]
+    fn test_batch_generation() -> Result<(), Box<dyn std::error::Error>> {
+        let repo_dir = TempDir::new()?;
+        fs::create_dir_all(repo_dir.path().join("templates"))?;
+        fs::write(
+            repo_dir.path().join("templates/readme.md.tera"),
+            "{{ project_name }} by {{ team }} for {{ owner }}, tier {{ tier }}",
+        )?;
+        let work_dir = TempDir::new()?;
+        let config_path = work_dir.path().join("scaffolding.toml");
+        fs::write(
+            &config_path,
+            format!(
+                r#"
+[variables]
+team = "platform"
+
+[[scaffolds]]
+name = "Service"
+repo = "{}"
+template.files = [{{ src = "readme.md.tera", dest = "README.md" }}]
+variables = {{ tier = "2" }}
+prompts = [{{ name = "owner" }}]
+"#,
+                repo_dir.path().to_string_lossy().replace('\\', "/")
+            ),
+        )?;
+        let input = work_dir.path().join("projects.csv");
+        let custom = work_dir.path().join("custom");
+        fs::write(
+            &input,
+            format!(
+                "project_name,team,owner,tier,output\napi,payments,alice,1,\nworker,,bob,,\nbroken,,,,\nweb,,carol,,{}\n",
+                custom.display()
+            ),
+        )?;
+        let output = work_dir.path().join("out");
+        let report_path = work_dir.path().join("report.json");
+        let args = Args::try_parse_from([
+            "scaficionado".as_ref(),
+            "--config".as_ref(),
+            config_path.as_os_str(),
+            "--output".as_ref(),
+            output.as_os_str(),
+            "--report".as_ref(),
+            report_path.as_os_str(),
+            "batch".as_ref(),
+            "--input".as_ref(),
+            input.as_os_str(),
+        ])?;
+        let Some(Commands::Batch { input }) = &args.command else {
+            return Err("not a batch command".into());
+        };
+        let error = generate_batch(&args, Path::new(input))
+            .err()
+            .ok_or("the broken row should fail the batch")?;
+        assert_eq!(error.to_string(), "1 of 4 project(s) failed");
+
+        let read = |dir: &Path| fs::read_to_string(dir.join("README.md"));
+        assert_eq!(read(&output.join("api"))?, "api by payments for alice, tier 1");
+        assert_eq!(read(&output.join("worker"))?, "worker by platform for bob, tier 2");
+        assert_eq!(read(&custom)?, "web by platform for carol, tier 2");
+        assert!(!output.join("broken").exists());
+
+        let report: serde_json::Value = serde_json::from_str(&fs::read_to_string(&report_path)?)?;
+        let projects = report["projects"].as_array().ok_or("no projects")?;
+        let names: Vec<_> = projects.iter().map(|project| project["project"].as_str()).collect();
+        assert_eq!(names, [Some("api"), Some("worker"), Some("broken"), Some("web")]);
+        assert_eq!(projects[0]["summary"]["scaffolds"][0]["created"], 1);
+        assert!(projects[2]["error"].as_str().is_some_and(|error| error.contains("'owner'")));
+
+        // TOML input keeps the types of the variables.
+        let toml_input = work_dir.path().join("projects.toml");
+        fs::write(&toml_input, "[[projects]]\nproject_name = \"api\"\nreplicas = 3\n")?;
+        let rows = load_batch_rows(&toml_input)?;
+        assert_eq!(
+            rows,
+            [BatchRow {
+                project_name: "api".to_string(),
+                output: None,
+                variables: BTreeMap::from([("replicas".to_string(), toml::Value::Integer(3))]),
+            }]
+        );
+        fs::write(&toml_input, "[[projects]]\nteam = \"a\"\n")?;
+        let error = load_batch_rows(&toml_input).err().ok_or("the row without a name should fail")?;
+        assert!(error.to_string().ends_with("has no project_name"), "{}", error);
+        Ok(())
+    }
+}
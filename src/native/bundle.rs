@@ -0,0 +1,285 @@
+//! The `bundle` subcommand and `bundle:` repositories: scaffolds packed with their templates and configuration
+//! for offline generation.
+
+use heck::ToKebabCase;
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::*;
+
+/// Scheme of the scaffolds of a bundle written by the `bundle` subcommand.  Its configuration names them
+/// `bundle:<scaffold>`, which loading it turns into `bundle:<path of the bundle>#<scaffold>`.
+pub(crate) const BUNDLE_SCHEME: &str = "bundle:";
+
+/// The configuration file in a bundle.
+const BUNDLE_CONFIG_FILE: &str = "scaffolding.toml";
+
+/// A scaffold packed in a bundle, `bundle:<path of the bundle>#<scaffold>`.  Bundles are local files, so they work
+/// offline.
+pub(crate) struct BundleSource {
+    pub(crate) location: String,
+}
+
+impl ScaffoldSource for BundleSource {
+    fn fetch(&self, dest: &Path) -> Result<PathBuf, Box<dyn Error>> {
+        let (bundle, scaffold) = self.location[BUNDLE_SCHEME.len()..].rsplit_once('#').ok_or_else(|| {
+            format!(
+                "Invalid bundle repository {:?}: expected bundle:<path of the bundle>#<scaffold>",
+                self.location
+            )
+        })?;
+        let data = fs::File::open(bundle).map_err(|e| format!("Failed to read bundle {}: {}", bundle, e))?;
+        say!("Unpacking scaffold '{}' from bundle {}", scaffold, bundle);
+        unpack_archive(data, dest).map_err(|e| format!("Failed to unpack {}: {}", bundle, e))?;
+        let path = dest.join("scaffolds").join(scaffold);
+        match path.is_dir() {
+            true => Ok(path),
+            false => Err(format!("Bundle {} has no scaffold '{}'", bundle, scaffold).into()),
+        }
+    }
+
+    fn identity(&self) -> String {
+        self.location.clone()
+    }
+}
+
+/// Check whether a configuration location is a bundle written by the `bundle` subcommand rather than a TOML file.
+pub(crate) fn is_bundle(location: &str) -> bool {
+    !is_url(location) && is_archive(location)
+}
+
+/// Read the configuration of a bundle, which must match `expected_sha256` if it is given.
+pub(crate) fn read_bundle_config(location: &str, expected_sha256: Option<&str>) -> Result<String, Box<dyn Error>> {
+    let data = fs::read(location).map_err(|e| format!("Failed to read {:?}: {}", location, e))?;
+    if let Some(expected) = expected_sha256 {
+        verify_sha256(location, &data, expected)?;
+    }
+    let unpacked = tempfile::tempdir()?;
+    unpack_archive(data.as_slice(), unpacked.path()).map_err(|e| format!("Failed to unpack {:?}: {}", location, e))?;
+    let path = unpacked.path().join(BUNDLE_CONFIG_FILE);
+    fs::read_to_string(&path).map_err(|e| format!("{:?} is not a bundle, it has no {}: {}", location, BUNDLE_CONFIG_FILE, e).into())
+}
+
+/// `bundle` subcommand: pack the `selected` scaffolds, or every scaffold, into the archive `output` with their template
+/// repositories and a configuration that generates them from the archive, e.g. for teams without network access.
+/// Repositories are obtained, pinned and verified as for a run, so the bundle holds exactly what a run would use.
+pub(crate) fn bundle_scaffolds(args: &Args, selected: &[String], output: &Path) -> Result<(), Box<dyn Error>> {
+    let config = load_config_with_args(args)?;
+    let git = get_git_options(args, &config);
+    for name in selected {
+        if !config.scaffolds.iter().any(|scaffold| scaffold.name.as_ref() == Some(name)) {
+            return Err(format!("No scaffold named '{}' in {}", name, args.config).into());
+        }
+    }
+    // The configuration is written from its TOML rather than from `config`, which has the user configuration and
+    // resolved `extends` in it.  Its scaffolds are in the same order.
+    let mut table = flattened_config_table(&args.config, args.config_sha256.as_deref())?;
+    let raw_scaffolds = match table.remove("scaffolds") {
+        Some(toml::Value::Array(scaffolds)) => scaffolds,
+        _ => Vec::new(),
+    };
+    let mut bundled_names: BTreeSet<&str> = selected.iter().map(String::as_str).collect();
+    let mut extending: Vec<&str> = bundled_names.iter().copied().collect();
+    while let Some(name) = extending.pop() {
+        let base = config
+            .scaffolds
+            .iter()
+            .zip(&raw_scaffolds)
+            .find(|(scaffold, _)| scaffold.name.as_deref() == Some(name))
+            .and_then(|(_, raw)| raw.get("extends")?.as_str());
+        if let Some(base) = base.filter(|base| bundled_names.insert(base)) {
+            extending.push(base);
+        }
+    }
+
+    let staging = tempfile::tempdir()?;
+    let mut scaffolds = Vec::new();
+    for (scaffold, raw) in config.scaffolds.iter().zip(&raw_scaffolds) {
+        if !selected.is_empty() && !scaffold.name.as_deref().is_some_and(|name| bundled_names.contains(name)) {
+            continue;
+        }
+        let mut raw = raw.as_table().cloned().unwrap_or_default();
+        if raw.contains_key("repo") {
+            let mut dir_name = scaffold_name(scaffold).to_kebab_case();
+            if staging.path().join("scaffolds").join(&dir_name).exists() {
+                dir_name = format!("{}-{}", dir_name, scaffolds.len());
+            }
+            say!("Bundling scaffold '{}'", scaffold_name(scaffold));
+            let (repo_base, _) = prepare_scaffold(scaffold, &git)?;
+            let archive = pack_archive(&repo_base);
+            if is_temporary_clone(&repo_base) {
+                clean_up_persistent_dirs(vec![repo_base])?;
+            }
+            unpack_archive(archive?.as_slice(), &staging.path().join("scaffolds").join(&dir_name))?;
+            raw.insert("repo".to_string(), format!("{}{}", BUNDLE_SCHEME, dir_name).into());
+            // The bundled files are already the pinned and verified ones.
+            for pinning in ["version", "sha256", "signature", "submodules"] {
+                raw.remove(pinning);
+            }
+        }
+        scaffolds.push(toml::Value::Table(raw));
+    }
+    if scaffolds.is_empty() {
+        return Err(format!("{} has no scaffolds to bundle", args.config).into());
+    }
+    let count = scaffolds.len();
+    table.insert("scaffolds".to_string(), toml::Value::Array(scaffolds));
+    fs::write(staging.path().join(BUNDLE_CONFIG_FILE), toml::to_string_pretty(&table)?)?;
+    let archive = pack_archive(staging.path())?;
+    fs::write(output, &archive).map_err(|e| format!("Failed to write {:?}: {}", output, e))?;
+    println!(
+        "Bundled {} scaffold(s) into {:?} ({} KiB); generate from it with --config {:?}",
+        count,
+        output,
+        archive.len() / 1024,
+        output
+    );
+    Ok(())
+}
+
+/// The TOML of the configuration file at `location` combined with the files it includes, as load_config_file
+/// combines them: their scaffolds come first, and the project settings, variables and profiles of the including
+/// file take precedence key by key.
+fn flattened_config_table(location: &str, expected_sha256: Option<&str>) -> Result<toml::Table, Box<dyn Error>> {
+    let mut table: toml::Table = read_config_source(location, expected_sha256)?
+        .parse()
+        .map_err(|e| format!("Failed to parse {:?}: {}", location, e))?;
+    let includes = table.remove("include");
+    let mut composed = toml::Table::new();
+    for include in includes
+        .iter()
+        .filter_map(toml::Value::as_array)
+        .flatten()
+        .filter_map(toml::Value::as_str)
+    {
+        merge_config_table(&mut composed, flattened_config_table(&resolve_include(location, include), None)?);
+    }
+    merge_config_table(&mut composed, table);
+    Ok(composed)
+}
+
+/// Merge the TOML of a configuration file into `composed`, like merge_config.
+fn merge_config_table(composed: &mut toml::Table, table: toml::Table) {
+    for (key, value) in table {
+        match (key.as_str(), composed.get_mut(&key), value) {
+            ("scaffolds", Some(toml::Value::Array(scaffolds)), toml::Value::Array(merged)) => scaffolds.extend(merged),
+            ("project" | "variables", Some(toml::Value::Table(settings)), toml::Value::Table(merged)) => settings.extend(merged),
+            ("profile", Some(toml::Value::Table(profiles)), toml::Value::Table(merged)) => {
+                for (name, profile) in merged {
+                    match (profiles.get_mut(&name), profile) {
+                        (Some(toml::Value::Table(target)), toml::Value::Table(profile)) => merge_config_table(target, profile),
+                        (_, profile) => {
+                            profiles.insert(name, profile);
+                        }
+                    }
+                }
+            }
+            (_, _, value) => {
+                composed.insert(key, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    // Test `bundle`: the selected scaffolds, the scaffolds they extend and the included configuration are packed, and
+    // the bundle generates them without the original repositories.
+    #[test]
+    fn test_bundle() -> Result<(), Box<dyn std::error::Error>> {
+        let repo_dir = TempDir::new()?;
+        fs::create_dir_all(repo_dir.path().join("templates"))?;
+        fs::create_dir_all(repo_dir.path().join(".git"))?;
+        fs::write(
+            repo_dir.path().join("templates/readme.md.tera"),
+            "{{ project_name }} by {{ owner }}",
+        )?;
+        let work_dir = TempDir::new()?;
+        let repo = repo_dir.path().to_string_lossy().replace('\\', "/");
+        fs::write(
+            work_dir.path().join("shared.toml"),
+            format!(
+                r#"
+[variables]
+owner = "platform"
+
+[[scaffolds]]
+name = "Base"
+repo = "{}"
+version = "^1"
+template.files = [{{ src = "readme.md.tera", dest = "README.md" }}]
+"#,
+                repo
+            ),
+        )?;
+        let config_path = work_dir.path().join("scaffolding.toml");
+        fs::write(
+            &config_path,
+            format!(
+                r#"
+include = ["shared.toml"]
+
+[variables]
+owner = "payments"
+
+[[scaffolds]]
+name = "Service"
+extends = "Base"
+remove = ["readme.md.tera"]
+template.files = [{{ src = "readme.md.tera", dest = "SERVICE.md" }}]
+
+[[scaffolds]]
+name = "Other"
+repo = "{}/missing"
+"#,
+                repo
+            ),
+        )?;
+        let bundle = work_dir.path().join("golden-paths.tar.gz");
+        let args = Args {
+            config: config_path.to_string_lossy().into_owned(),
+            ..Args::default()
+        };
+        let Err(e) = bundle_scaffolds(&args, &["Unknown".to_string()], &bundle) else {
+            panic!("bundled an unknown scaffold");
+        };
+        assert!(e.to_string().contains("No scaffold named 'Unknown'"), "{}", e);
+        // Repositories are pinned as for a run, and this one has no tags to pin.
+        let Err(_) = bundle_scaffolds(&args, &["Service".to_string()], &bundle) else {
+            panic!("bundled a version of a repository without tags");
+        };
+        let shared = fs::read_to_string(work_dir.path().join("shared.toml"))?;
+        fs::write(work_dir.path().join("shared.toml"), shared.replace("version = \"^1\"\n", ""))?;
+        bundle_scaffolds(&args, &["Service".to_string()], &bundle)?;
+
+        let unpacked = TempDir::new()?;
+        unpack_archive(fs::File::open(&bundle)?, unpacked.path())?;
+        assert!(unpacked.path().join("scaffolds/base/templates/readme.md.tera").is_file());
+        assert!(!unpacked.path().join("scaffolds/base/.git").exists());
+        let bundled: toml::Table = fs::read_to_string(unpacked.path().join(BUNDLE_CONFIG_FILE))?.parse()?;
+        assert!(!bundled.contains_key("include"));
+        assert_eq!(bundled["variables"]["owner"].as_str(), Some("payments"));
+        let scaffolds = bundled["scaffolds"].as_array().cloned().unwrap_or_default();
+        let names: Vec<_> = scaffolds.iter().filter_map(|scaffold| scaffold["name"].as_str()).collect();
+        assert_eq!(names, ["Base", "Service"]);
+        assert_eq!(scaffolds[0]["repo"].as_str(), Some("bundle:base"));
+
+        drop(repo_dir);
+        let output = work_dir.path().join("out");
+        let summary = scaffold_project(
+            &bundle.to_string_lossy(),
+            &serde_json::Map::from_iter([("project_name".to_string(), "billing".into())]),
+            &output.to_string_lossy(),
+        )?;
+        assert_eq!(summary["errors"], 0);
+        assert_eq!(fs::read_to_string(output.join("README.md"))?, "billing by payments");
+        assert_eq!(fs::read_to_string(output.join("SERVICE.md"))?, "billing by payments");
+        Ok(())
+    }
+}
@@ -0,0 +1,480 @@
+//! The temporary clones of runs and the cache of earlier clones, shared by concurrent runs.
+
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::*;
+
+/// Lock file in every temporary clone directory, holding the PID of the run that made it.
+pub(crate) const CLONE_LOCK_FILE: &str = ".scaficionado.pid";
+
+/// Lock file of the clone cache.  Runs share it; stale clones are only removed while no other run holds it.
+const CACHE_LOCK_FILE: &str = ".lock";
+
+/// File in the `.git` directory of a cached clone holding when it was last used, in seconds since the epoch.
+const CACHE_USED_FILE: &str = "scaficionado-used";
+
+/// Age after which a temporary clone is stale when it can't be checked whether its run is still going.
+const STALE_CLONE_AGE: std::time::Duration = std::time::Duration::from_secs(24 * 60 * 60);
+
+/// Where the last clone of `repo_url` is cached: a directory named after a hash of the URL, next to the temporary
+/// clones.
+pub(crate) fn cached_repo_path(repo_url: &str) -> PathBuf {
+    repos_dir().join(&sha256_hex(repo_url.as_bytes())[..16])
+}
+
+/// Lock file of the output directory `output_base`: named after a hash of its absolute path, with the symbolic links
+/// of the part that exists resolved, in a directory next to the temporary clones.
+pub(crate) fn output_lock_path(output_base: &Path) -> io::Result<PathBuf> {
+    let absolute = std::path::absolute(output_base)?;
+    let resolved = resolve_links(&absolute).unwrap_or(absolute);
+    let name = format!("{}.lock", &sha256_hex(resolved.to_string_lossy().as_bytes())[..16]);
+    Ok(clones_dir().with_file_name("locks").join(name))
+}
+
+/// Directory holding the cached clones, next to the temporary clones.
+pub(crate) fn repos_dir() -> PathBuf {
+    clones_dir().with_file_name("repos")
+}
+
+/// Record that the cached clone at `cached` was used now, for `cache list` and `cache prune`.
+pub(crate) fn mark_cache_used(cached: &Path) {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    // Only informational; a clone without it counts as used when its directory last changed.
+    fs::write(cached.join(".git").join(CACHE_USED_FILE), now.to_string()).ok();
+}
+
+/// A cached clone of a remote repository.
+pub(crate) struct CachedRepo {
+    pub(crate) path: PathBuf,
+    /// The URL of the repository, or None for clones cached before it was recorded.
+    pub(crate) url: Option<String>,
+    pub(crate) bytes: u64,
+    last_used: SystemTime,
+}
+
+/// The cached clones in `root`, least recently used first.
+pub(crate) fn cached_repos(root: &Path) -> Result<Vec<CachedRepo>, Box<dyn Error>> {
+    if !root.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut repos = Vec::new();
+    for entry in fs::read_dir(root)? {
+        let path = entry?.path();
+        // Staging directories of runs that are still caching, or were interrupted, are left alone.
+        if !path.is_dir() || path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.')) {
+            continue;
+        }
+        let url = git_library().ok().and_then(|library| {
+            let remotes = library.remotes(&path);
+            remotes.into_iter().find(|(name, _)| name == "origin").map(|(_, url)| url)
+        });
+        let last_used = fs::read_to_string(path.join(".git").join(CACHE_USED_FILE))
+            .ok()
+            .and_then(|secs| secs.trim().parse().ok())
+            .map(|secs| UNIX_EPOCH + Duration::from_secs(secs))
+            .or_else(|| fs::metadata(&path).and_then(|metadata| metadata.modified()).ok())
+            .unwrap_or(UNIX_EPOCH);
+        repos.push(CachedRepo {
+            bytes: directory_size(&path),
+            path,
+            url,
+            last_used,
+        });
+    }
+    repos.sort_by_key(|repo| repo.last_used);
+    Ok(repos)
+}
+
+/// The `cache list` table of cached clones, with their total size.
+pub(crate) fn cached_repos_table(repos: &[CachedRepo]) -> String {
+    if repos.is_empty() {
+        return "The clone cache is empty\n".to_string();
+    }
+    let rows: Vec<(String, String, String)> = repos
+        .iter()
+        .map(|repo| {
+            let url = repo.url.clone().unwrap_or_else(|| format!("(unknown) {}", repo.path.display()));
+            let age = format_age(repo.last_used.elapsed().unwrap_or_default());
+            (url, format!("{} KiB", repo.bytes / 1024), format!("{} ago", age))
+        })
+        .collect();
+    let width = rows.iter().map(|(url, _, _)| url.len()).max().unwrap_or(0).max("REPOSITORY".len());
+    let mut table = format!("{:<width$}  {:>10}  LAST USED\n", "REPOSITORY", "SIZE");
+    for (url, size, age) in rows {
+        table.push_str(&format!("{:<width$}  {:>10}  {}\n", url, size, age));
+    }
+    let total: u64 = repos.iter().map(|repo| repo.bytes).sum();
+    table.push_str(&format!("{} cached clone(s), {} KiB\n", repos.len(), total / 1024));
+    table
+}
+
+/// Remove the cached clones in `root` unused for longer than `older_than`, or all of them, returning how many were
+/// removed and the bytes they used.  Like removing stale clones, this needs the clone cache's lock in `lock_root`.
+pub(crate) fn remove_cached_repos(
+    root: &Path,
+    lock_root: &Path,
+    older_than: Option<Duration>,
+    wait: bool,
+) -> Result<(usize, u64), Box<dyn Error>> {
+    let _lock = lock_clone_cache(lock_root, wait)?.ok_or("Another scaficionado run is using the clone cache; use --wait to wait for it")?;
+    let (mut count, mut bytes) = (0, 0);
+    for repo in cached_repos(root)? {
+        let unused = repo.last_used.elapsed().unwrap_or_default();
+        if older_than.is_none_or(|older_than| unused > older_than) {
+            fs::remove_dir_all(&repo.path)?;
+            count += 1;
+            bytes += repo.bytes;
+        }
+    }
+    Ok((count, bytes))
+}
+
+/// Parse an age such as "30d": a number of seconds (s), minutes (m), hours (h), days (d) or weeks (w).
+pub(crate) fn parse_age(age: &str) -> Result<Duration, String> {
+    let split = age.trim().find(|c: char| !c.is_ascii_digit()).unwrap_or(age.trim().len());
+    let (number, unit) = age.trim().split_at(split);
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => 0,
+    };
+    match number.parse::<u64>() {
+        Ok(number) if seconds > 0 => Ok(Duration::from_secs(number * seconds)),
+        _ => Err(format!(
+            "invalid age '{}': expected a number and a unit (s, m, h, d or w), e.g. 30d",
+            age
+        )),
+    }
+}
+
+/// Describe a duration in its largest whole unit, e.g. "3 days".
+pub(crate) fn format_age(age: Duration) -> String {
+    let seconds = age.as_secs();
+    let (count, unit) = [(7 * 24 * 60 * 60, "week"), (24 * 60 * 60, "day"), (60 * 60, "hour"), (60, "minute")]
+        .into_iter()
+        .find(|(length, _)| seconds >= *length)
+        .map_or((seconds, "second"), |(length, unit)| (seconds / length, unit));
+    match count {
+        1 => format!("1 {}", unit),
+        _ => format!("{} {}s", count, unit),
+    }
+}
+
+/// Check before anything is cloned that every remote scaffold repository, or one of its mirrors, is in the clone
+/// cache, reporting every missing one at once.
+pub(crate) fn check_offline_cache(scaffolds: &[Scaffold]) -> Result<(), Box<dyn Error>> {
+    let missing: Vec<String> = scaffolds
+        .iter()
+        .filter(|scaffold| {
+            let urls = scaffold.repo.urls();
+            !urls.iter().any(|url| is_local_repo(url) || cached_repo_path(url).is_dir())
+        })
+        .map(|scaffold| format!("scaffold '{}': {}", scaffold_name(scaffold), scaffold.repo.urls().join(", ")))
+        .collect();
+    if !missing.is_empty() {
+        return Err(format!(
+            "Offline, but these repositories aren't in the clone cache (run once without --offline to cache them):\n  {}",
+            missing.join("\n  ")
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Clean up the persistent temporary directories used for remote clones, including the clone directory with the
+/// lock file that holds each of them.
+pub(crate) fn clean_up_persistent_dirs(dirs: Vec<PathBuf>) -> Result<(), Box<dyn Error>> {
+    for dir in dirs {
+        let clone_dir = clone_dir_of(&dir).unwrap_or(&dir);
+        say!("Cleaning up temporary clone at: {:?}", clone_dir);
+        fs::remove_dir_all(clone_dir)?;
+    }
+    Ok(())
+}
+
+/// The temporary clones a command uses, removed when it drops them, so that an error returned while they are in use
+/// doesn't leave them behind.
+#[derive(Default)]
+pub(crate) struct TemporaryClones(pub(crate) Vec<PathBuf>);
+
+impl TemporaryClones {
+    /// Keep the repository at `repo_base` until the clones are removed, if it is a temporary clone.
+    pub(crate) fn add(&mut self, repo_base: &Path) {
+        if is_temporary_clone(repo_base) {
+            self.0.push(repo_base.to_path_buf());
+        }
+    }
+
+    /// Remove the clones now, reporting a failure to do so.
+    pub(crate) fn clean_up(mut self) -> Result<(), Box<dyn Error>> {
+        clean_up_persistent_dirs(std::mem::take(&mut self.0))
+    }
+}
+
+impl Drop for TemporaryClones {
+    fn drop(&mut self) {
+        clean_up_persistent_dirs(std::mem::take(&mut self.0)).ok();
+    }
+}
+
+/// Directory holding the temporary clones of remote template repositories: `$SCAFICIONADO_CACHE_DIR`, `cache_dir` of
+/// the user configuration, or a directory in the system's temp directory.
+pub(crate) fn clones_dir() -> PathBuf {
+    let cache_dir = env_path("SCAFICIONADO_CACHE_DIR")
+        .or_else(|| user_config().ok()?.cache_dir.as_ref().map(PathBuf::from))
+        .unwrap_or_else(|| std::env::temp_dir().join("scaficionado"));
+    cache_dir.join("clones")
+}
+
+/// Create a directory for a temporary clone, locked by this process until it is cleaned up.
+pub(crate) fn new_clone_dir() -> Result<PathBuf, Box<dyn Error>> {
+    let root = clones_dir();
+    fs::create_dir_all(&root)?;
+    let dir = tempfile::Builder::new().prefix("clone-").tempdir_in(&root)?.keep();
+    fs::write(dir.join(CLONE_LOCK_FILE), std::process::id().to_string())?;
+    Ok(dir)
+}
+
+/// Whether a temporary clone was left behind by a run that has ended without cleaning up: the process in its lock
+/// file isn't running or, where that can't be checked (no /proc), the clone is older than STALE_CLONE_AGE.
+fn is_stale_clone(dir: &Path) -> bool {
+    let pid = fs::read_to_string(dir.join(CLONE_LOCK_FILE))
+        .ok()
+        .and_then(|pid| pid.trim().parse::<u32>().ok());
+    match pid {
+        Some(pid) if pid == std::process::id() => false,
+        Some(pid) if Path::new("/proc/self").exists() => !Path::new("/proc").join(pid.to_string()).exists(),
+        _ => fs::metadata(dir)
+            .and_then(|metadata| metadata.modified())
+            .is_ok_and(|modified| modified.elapsed().is_ok_and(|age| age > STALE_CLONE_AGE)),
+    }
+}
+
+/// Remove the stale clones in `root`, returning how many were removed and the bytes they used.  Nothing is removed
+/// while another run uses the cache: returns None, or with `wait` waits for the other runs to end.
+pub(crate) fn remove_stale_clones(root: &Path, wait: bool) -> Result<Option<(usize, u64)>, Box<dyn Error>> {
+    if !root.is_dir() {
+        return Ok(Some((0, 0)));
+    }
+    let Some(_lock) = lock_clone_cache(root, wait)? else {
+        return Ok(None);
+    };
+    let (mut count, mut bytes) = (0, 0);
+    for entry in fs::read_dir(root)? {
+        let path = entry?.path();
+        if path.is_dir() && is_stale_clone(&path) {
+            bytes += directory_size(&path);
+            fs::remove_dir_all(&path)?;
+            count += 1;
+        }
+    }
+    Ok(Some((count, bytes)))
+}
+
+/// Lock the clone cache in `root` for this process alone.  Returns None while another run uses the cache, or with
+/// `wait` waits for the other runs to end.
+fn lock_clone_cache(root: &Path, wait: bool) -> Result<Option<fs::File>, Box<dyn Error>> {
+    let lock = open_lock_file(&root.join(CACHE_LOCK_FILE))?;
+    match lock.try_lock() {
+        Ok(()) => Ok(Some(lock)),
+        Err(fs::TryLockError::WouldBlock) if wait => {
+            say!("Waiting for other scaficionado runs to stop using the clone cache...");
+            lock.lock()?;
+            Ok(Some(lock))
+        }
+        Err(fs::TryLockError::WouldBlock) => Ok(None),
+        Err(fs::TryLockError::Error(e)) => Err(e.into()),
+    }
+}
+
+/// Open a lock file, creating it and its directory if needed.
+pub(crate) fn open_lock_file(path: &Path) -> io::Result<fs::File> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::OpenOptions::new().create(true).truncate(false).write(true).open(path)
+}
+
+/// Take a shared lock on the clone cache for the rest of the run.  Without a usable cache directory, runs aren't
+/// protected from each other's clean-ups, which only remove clones of ended runs anyway.
+pub(crate) fn share_clone_cache(root: &Path) -> Option<fs::File> {
+    let lock = open_lock_file(&root.join(CACHE_LOCK_FILE)).ok()?;
+    lock.lock_shared().ok()?;
+    Some(lock)
+}
+
+/// The total size of the files below `dir`, ignoring anything that can't be read.
+pub(crate) fn directory_size(dir: &Path) -> u64 {
+    fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .fold(0, |size, entry| match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => size + directory_size(&entry.path()),
+            _ => size + entry.metadata().map_or(0, |metadata| metadata.len()),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::native::testing::git;
+    use std::fs;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_clean_up_persistent_dirs() -> Result<(), Box<dyn std::error::Error>> {
+        // Create two temporary directories and then call clean_up_persistent_dirs.
+        let temp_dir1 = tempfile::TempDir::new()?;
+        let temp_path1 = temp_dir1.keep();
+        let temp_dir2 = tempfile::TempDir::new()?;
+        let temp_path2 = temp_dir2.keep();
+
+        // Ensure directories exist before cleanup.
+        assert!(temp_path1.exists());
+        assert!(temp_path2.exists());
+
+        clean_up_persistent_dirs(vec![temp_path1.clone(), temp_path2.clone()])?;
+
+        // Verify directories are removed.
+        assert!(!temp_path1.exists());
+        assert!(!temp_path2.exists());
+        Ok(())
+    }
+
+    // Test that clones of ended runs are removed while clones in use are kept.
+    #[test]
+    fn test_remove_stale_clones() -> Result<(), Box<dyn std::error::Error>> {
+        let root = TempDir::new()?;
+        let in_use = root.path().join("clone-in-use");
+        fs::create_dir_all(in_use.join("repo"))?;
+        fs::write(in_use.join(CLONE_LOCK_FILE), std::process::id().to_string())?;
+        // A clone without a lock file is only stale once it is old.
+        let unlocked = root.path().join("clone-unlocked");
+        fs::create_dir_all(&unlocked)?;
+        let crashed = root.path().join("clone-crashed");
+        fs::create_dir_all(crashed.join("repo"))?;
+        fs::write(crashed.join("repo").join("file.txt"), "12345")?;
+        fs::write(crashed.join(CLONE_LOCK_FILE), u32::MAX.to_string())?;
+
+        let (count, bytes) = remove_stale_clones(root.path(), false)?.ok_or("nothing else uses the cache")?;
+        assert!(in_use.exists());
+        assert!(unlocked.exists());
+        if Path::new("/proc/self").exists() {
+            assert_eq!(count, 1);
+            assert!(bytes >= 5);
+            assert!(!crashed.exists());
+        }
+
+        // Cleaning up after a run removes the clone directory with its lock file.
+        clean_up_persistent_dirs(vec![in_use.join("repo")])?;
+        assert!(!in_use.exists());
+        Ok(())
+    }
+
+    // Test that `cache list` shows the cached clones and that `cache prune` and `cache clear` remove them.
+    #[test]
+    fn test_cache_management() -> Result<(), Box<dyn std::error::Error>> {
+        let root = TempDir::new()?;
+        let lock_root = TempDir::new()?;
+        let days_ago = |days: u64| SystemTime::now() - Duration::from_secs(days * 24 * 60 * 60);
+        for (name, url, used) in [
+            ("old", "https://example.com/old.git", days_ago(40)),
+            ("new", "https://example.com/new.git", days_ago(2)),
+        ] {
+            fs::create_dir(root.path().join(name))?;
+            git(&root.path().join(name), &["init", "-q"])?;
+            git(&root.path().join(name), &["remote", "add", "origin", url])?;
+            let secs = used.duration_since(UNIX_EPOCH)?.as_secs();
+            fs::write(root.path().join(name).join(".git").join(CACHE_USED_FILE), secs.to_string())?;
+        }
+        fs::create_dir(root.path().join(".staging-123"))?;
+
+        let repos = cached_repos(root.path())?;
+        let urls: Vec<Option<&str>> = repos.iter().map(|repo| repo.url.as_deref()).collect();
+        assert_eq!(urls, [Some("https://example.com/old.git"), Some("https://example.com/new.git")]);
+        let table = cached_repos_table(&repos);
+        assert!(table.starts_with("REPOSITORY"), "{}", table);
+        assert!(table.contains("5 weeks ago") && table.contains("2 days ago"), "{}", table);
+        assert!(table.contains("\n2 cached clone(s), "), "{}", table);
+        assert_eq!(cached_repos_table(&[]), "The clone cache is empty\n");
+
+        assert_eq!(parse_age("30d"), Ok(Duration::from_secs(30 * 24 * 60 * 60)));
+        assert_eq!(parse_age("12h"), Ok(Duration::from_secs(12 * 60 * 60)));
+        assert!(parse_age("30").is_err() && parse_age("d").is_err() && parse_age("3y").is_err());
+        assert_eq!(format_age(Duration::from_secs(90)), "1 minute");
+
+        let (count, _) = remove_cached_repos(root.path(), lock_root.path(), Some(Duration::from_secs(30 * 24 * 60 * 60)), false)?;
+        assert_eq!(count, 1);
+        assert!(!root.path().join("old").exists() && root.path().join("new").exists());
+
+        // Clearing waits for no run, but fails while one uses the cache.
+        let shared = share_clone_cache(lock_root.path()).ok_or("the cache should be lockable")?;
+        let error = remove_cached_repos(root.path(), lock_root.path(), None, false).unwrap_err();
+        assert!(error.to_string().starts_with("Another scaficionado run is using the clone cache"));
+        drop(shared);
+        assert_eq!(remove_cached_repos(root.path(), lock_root.path(), None, false)?.0, 1);
+        assert!(root.path().join(".staging-123").exists());
+        Ok(())
+    }
+
+    // Test that clones are cached, and that --offline uses the cache and lists the repositories missing from it.
+    #[test]
+    fn test_offline_clone_cache() -> Result<(), Box<dyn std::error::Error>> {
+        let upstream = TempDir::new()?;
+        git(upstream.path(), &["init", "-q"])?;
+        fs::write(upstream.path().join("README.md"), "# Templates")?;
+        git(upstream.path(), &["add", "README.md"])?;
+        git(upstream.path(), &["commit", "-q", "-m", "Add templates"])?;
+
+        // A path is cloned like a URL, which stands in for a remote repository here.
+        let url = upstream.path().to_string_lossy().into_owned();
+        let dest = TempDir::new()?;
+        clone_repo(&url, &dest.path().join("online"), &GitOptions::default())?;
+        let cached = cached_repo_path(&url);
+        assert!(cached.join("README.md").is_file());
+
+        let offline = GitOptions {
+            offline: true,
+            ..GitOptions::default()
+        };
+        clone_repo(&url, &dest.path().join("offline"), &offline)?;
+        assert_eq!(fs::read_to_string(dest.path().join("offline").join("README.md"))?, "# Templates");
+        assert_eq!(git(&dest.path().join("offline"), &["remote", "get-url", "origin"])?, url);
+
+        let missing = "https://example.invalid/missing.git";
+        let Err(error) = clone_repo(missing, &dest.path().join("missing"), &offline) else {
+            return Err("the repository isn't cached".into());
+        };
+        assert!(error.to_string().contains("isn't in the clone cache"));
+
+        let config: Config = from_toml(&format!(
+            r#"
+[[scaffolds]]
+name = "Cached"
+repo = ["{}", "{}"]
+
+[[scaffolds]]
+name = "Missing"
+repo = "{}"
+"#,
+            missing, url, missing
+        ))?;
+        let error = check_offline_cache(&config.scaffolds).err().ok_or("a repository is missing")?;
+        assert!(
+            error.to_string().ends_with(&format!("\n  scaffold 'Missing': {}", missing)),
+            "{}",
+            error
+        );
+        fs::remove_dir_all(cached)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,788 @@
+//! The command line: its arguments, the dispatch of subcommands and the project settings they override.
+
+use clap::builder::PossibleValuesParser;
+use clap::{CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use std::error::Error;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use super::*;
+
+// Defaults
+pub(crate) const DEFAULT_CONFIG_PATH: &str = "scaffolding.toml";
+pub(crate) const DEFAULT_PROJECT_NAME: &str = "MyExampleProject";
+const DEFAULT_OUTPUT: &str = "generated";
+const DEFAULT_OVERWRITE: bool = false;
+const DEFAULT_STRICT: bool = false;
+
+/// The output directory that writes the generated file to stdout instead.
+pub(crate) const STDOUT_OUTPUT: &str = "-";
+
+pub fn run() -> Result<(), Box<dyn Error>> {
+    run_with_args(&Args::parse())
+}
+
+/// Run the command of `args`.
+pub(crate) fn run_with_args(args: &Args) -> Result<(), Box<dyn Error>> {
+    let _cancellation = CancellationScope;
+    let _run = RunScope::enter(RunContext {
+        offline: args.offline,
+        colors: !args.no_color && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty()),
+        messages_to_stderr: false,
+    });
+    RUN_STARTED.get_or_init(Instant::now);
+    #[cfg(feature = "git2")]
+    configure_ca_bundle()?;
+    // Clones left behind by crashed runs would otherwise pile up, so they are removed on every start.
+    let managing_cache = matches!(args.command, Some(Commands::Cache { .. }));
+    let stale_clones = remove_stale_clones(&clones_dir(), managing_cache && args.wait);
+    if !managing_cache {
+        // Reported on stderr to keep the output of e.g. `schema` clean.
+        match &stale_clones {
+            Ok(None | Some((0, _))) => {}
+            Ok(Some((count, bytes))) => eprintln!("Removed {} stale clone(s) of previous runs ({} KiB)", count, bytes / 1024),
+            Err(e) => eprintln!("Could not remove stale clones in {:?}: {}", clones_dir(), e),
+        }
+    }
+    // Held until the run ends, so that other runs leave the clones of this one alone.  Cache commands clone nothing
+    // and lock the cache for themselves.
+    let _cache_lock = (!managing_cache).then(|| share_clone_cache(&clones_dir()));
+    match &args.command {
+        Some(Commands::Vars) => print_variable_report(args),
+        Some(Commands::Diff) => diff_output(args),
+        Some(Commands::Simulate { scaffold, seed }) => simulate_scaffold(args, scaffold, *seed),
+        Some(Commands::NewTemplate { name }) => new_template(Path::new(name)),
+        Some(Commands::Outdated) => outdated_scaffolds(args),
+        Some(Commands::Describe { scaffold }) => describe_scaffold(args, scaffold),
+        Some(Commands::Batch { input }) => generate_batch(args, Path::new(input)),
+        Some(Commands::Serve { listen }) => serve(args, listen),
+        Some(Commands::Publish { reference, dir }) => publish_template(Path::new(dir), reference),
+        Some(Commands::Bundle { archive, scaffolds }) => bundle_scaffolds(args, scaffolds, Path::new(archive)),
+        Some(Commands::Search { query, registry }) => search_registry(registry.as_deref(), query),
+        Some(Commands::Add { name, registry }) => {
+            let index = load_registry_index(registry.as_deref())?;
+            add_from_registry(&index, name, Path::new(&args.config))
+        }
+        Some(Commands::Test { bless }) => test_template(Path::new("."), *bless),
+        Some(Commands::Schema) => {
+            println!("{}", config_schema()?);
+            Ok(())
+        }
+        Some(Commands::Completions { shell }) => {
+            print_completions(*shell, &args.config, &mut io::stdout());
+            Ok(())
+        }
+        Some(Commands::Cache { command: CacheCommand::Gc }) => {
+            let (count, bytes) = stale_clones?.ok_or("Another scaficionado run is using the clone cache; use --wait to wait for it")?;
+            let in_use = fs::read_dir(clones_dir()).map_or(0, |entries| entries.flatten().filter(|entry| entry.path().is_dir()).count());
+            println!(
+                "Removed {} stale clone(s) ({} KiB) from {:?}; {} clone(s) in use",
+                count,
+                bytes / 1024,
+                clones_dir(),
+                in_use
+            );
+            Ok(())
+        }
+        Some(Commands::Cache {
+            command: CacheCommand::List,
+        }) => {
+            print!("{}", cached_repos_table(&cached_repos(&repos_dir())?));
+            Ok(())
+        }
+        Some(Commands::Cache {
+            command: CacheCommand::Clear,
+        }) => {
+            let (count, bytes) = remove_cached_repos(&repos_dir(), &clones_dir(), None, args.wait)?;
+            println!("Removed {} cached clone(s) ({} KiB) from {:?}", count, bytes / 1024, repos_dir());
+            Ok(())
+        }
+        Some(Commands::Cache {
+            command: CacheCommand::Prune { older_than },
+        }) => {
+            let (count, bytes) = remove_cached_repos(&repos_dir(), &clones_dir(), Some(*older_than), args.wait)?;
+            println!(
+                "Removed {} cached clone(s) ({} KiB) unused for more than {}",
+                count,
+                bytes / 1024,
+                format_age(*older_than)
+            );
+            Ok(())
+        }
+        None => generate(args),
+    }
+}
+
+/// The settings of the run on this thread, from its arguments.  Each run of `run_with_args` has its own, so that
+/// concurrent runs (of `Scaffolder::execute_async`, or in a service embedding scaficionado) don't change each other's.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct RunContext {
+    /// --offline: nothing is downloaded, and remote repositories come from the clone cache.
+    pub(crate) offline: bool,
+    /// Whether output may be colored: not with --no-color or NO_COLOR.  Off outside of runs, e.g. in tests.
+    pub(crate) colors: bool,
+    /// Set while stdout carries a generated file (`--output -`), so that messages, prompts and hooks use stderr.
+    pub(crate) messages_to_stderr: bool,
+}
+
+thread_local! {
+    /// The settings of the run on this thread, see [`RunScope`].
+    static RUN_CONTEXT: std::cell::Cell<RunContext> = std::cell::Cell::new(RunContext::default());
+}
+
+/// The settings of the run on this thread.
+pub(crate) fn run_context() -> RunContext {
+    RUN_CONTEXT.with(|context| context.get())
+}
+
+/// Change the settings of the run on this thread, e.g. to send its messages to stderr.
+pub(crate) fn update_run_context(update: impl FnOnce(&mut RunContext)) {
+    RUN_CONTEXT.with(|context| {
+        let mut settings = context.get();
+        update(&mut settings);
+        context.set(settings);
+    });
+}
+
+/// Makes a [`RunContext`] the settings of the calling thread, e.g. of a thread a run starts, and restores the
+/// previous ones when dropped.
+pub(crate) struct RunScope(RunContext);
+
+impl RunScope {
+    pub(crate) fn enter(context: RunContext) -> Self {
+        RunScope(RUN_CONTEXT.with(|current| current.replace(context)))
+    }
+}
+
+impl Drop for RunScope {
+    fn drop(&mut self) {
+        RUN_CONTEXT.with(|current| current.set(self.0));
+    }
+}
+
+/// Load the configuration file named by the command line arguments, with the CLI overrides applied.
+pub(crate) fn load_config_with_args(args: &Args) -> Result<Config, Box<dyn Error>> {
+    say!("Loading configuration from: {:?}", args.config);
+    let mut config = load_config(&args.config, args.config_sha256.as_deref()).map_err(ConfigError)?;
+    if let Some(profile) = &args.profile {
+        apply_profile(&mut config, profile).map_err(ConfigError)?;
+    }
+    let mut config = apply_user_config(config, user_config().map_err(ConfigError)?);
+    say!("The configuration project_name and output are: {:?}", config.project);
+
+    overwrite_project_settings_with_args(args, &mut config);
+    apply_variable_overrides(&variable_overrides(args), &mut config).map_err(ConfigError)?;
+    Ok(config)
+}
+
+/// After a successful interactive run, offer to generate another project with the same answers: ask for its name
+/// and output directory.  Returns None when the name is left empty.
+pub(crate) fn ask_another_project(
+    args: &Args,
+    config: &Config,
+    previous_output: &str,
+    input: &mut impl AnswerInput,
+) -> Result<Option<(String, String)>, Box<dyn Error>> {
+    print_partial("\nGenerate another project with the same answers?  Project name (leave empty to finish): ")?;
+    let mut line = String::new();
+    input.read_line(&mut line)?;
+    let name = line.trim().to_string();
+    if name.is_empty() {
+        return Ok(None);
+    }
+
+    let context = build_context(&name, config.variables.as_ref(), None);
+    let options = get_render_options(args, config);
+    let mut output = native_separators(&render_string(
+        &get_output_directory(args, config),
+        "project output",
+        &context,
+        &options,
+    )?);
+    // An output directory that doesn't use the project name would mix the projects: default to a sibling directory.
+    if output == previous_output {
+        let parent = Path::new(previous_output).parent().unwrap_or(Path::new(""));
+        output = parent.join(&name).to_string_lossy().into_owned();
+    }
+    let output_prompt = Prompt {
+        name: "output".to_string(),
+        message: Some("Output directory".to_string()),
+        default: Some(toml::Value::String(output)),
+        kind: None,
+        choices: None,
+        when: None,
+        group: None,
+        secret: false,
+    };
+    Ok(Some((name, display_value(&ask_prompt(&output_prompt, input)?))))
+}
+
+#[derive(Parser, Debug, Default, Clone)]
+#[command(author, version, about, long_about = None)]
+pub(crate) struct Args {
+    #[command(subcommand)]
+    pub(crate) command: Option<Commands>,
+
+    /// The name of the project to scaffold.  Overwrites project_name set in configuration file.
+    #[arg(short, long, global = true, default_value = DEFAULT_PROJECT_NAME)]
+    pub(crate) project_name: String,
+
+    /// The output directory where the generated files will be placed.  Overwrites output set in configuration file.  "-" writes the generated file to stdout.
+    #[arg(short = 'o', long, global = true, default_value = DEFAULT_OUTPUT)]
+    pub(crate) output: String,
+
+    /// With --output -, the generated file to write to stdout, relative to the output directory.  Needed when more than one file is generated.
+    #[arg(long)]
+    pub(crate) select_file: Option<String>,
+
+    /// The configuration file path, a bundle written by `bundle`, or an HTTP(S) URL to download it from.
+    #[arg(short, long, global = true, default_value = DEFAULT_CONFIG_PATH)]
+    pub(crate) config: String,
+
+    /// Expected SHA-256 checksum (hex) of the configuration file.  The run fails if the file doesn't match.
+    #[arg(long, global = true)]
+    pub(crate) config_sha256: Option<String>,
+
+    /// Apply the [profile.<PROFILE>] section of the configuration file, e.g. "prod".
+    #[arg(long, global = true)]
+    pub(crate) profile: Option<String>,
+
+    /// Overwrite existing files if set. [default: false].  Overwrites overwrite=false set in configuration file.
+    #[arg(short = 'w', long, default_value_t = DEFAULT_OVERWRITE)]
+    pub(crate) overwrite: bool,
+
+    /// Fail when a template references an undefined variable. [default: false].  Overwrites strict=false set in configuration file.
+    #[arg(short = 's', long, default_value_t = DEFAULT_STRICT)]
+    pub(crate) strict: bool,
+
+    /// Continue with the remaining files and scaffolds after an error and report every error at the end.
+    #[arg(long)]
+    pub(crate) keep_going: bool,
+
+    /// Warn instead of failing when two scaffolds write the same file.  The later scaffold overwrites it with --overwrite and skips it otherwise.
+    #[arg(long)]
+    pub(crate) allow_collisions: bool,
+
+    /// Write the errors of the run as JSON to this file, for CI annotation tools.
+    #[arg(long)]
+    pub(crate) error_report: Option<String>,
+
+    /// Write a summary of the run (files created, overwritten, unchanged and skipped and hooks run per scaffold) as JSON to this file.
+    #[arg(long)]
+    pub(crate) report: Option<String>,
+
+    /// Print how long loading the configuration and each scaffold's clone, render and hooks took, and add it to the --report.
+    #[arg(long)]
+    pub(crate) timings: bool,
+
+    /// Emit GitHub Actions workflow commands and a job summary of the written files.  Enabled automatically when GITHUB_ACTIONS=true.
+    #[arg(long)]
+    pub(crate) gha: bool,
+
+    /// Write files to destinations the current user can't write (e.g. /etc) to this directory instead, with an install.sh that places them using sudo.
+    #[arg(long)]
+    pub(crate) stage: Option<String>,
+
+    /// Print the remote's progress messages and transfer statistics while cloning template repositories.
+    #[arg(short, long, global = true)]
+    pub(crate) verbose: bool,
+
+    /// Disable colored output.  Colors are also disabled when NO_COLOR is set or the output isn't a terminal.
+    #[arg(long, global = true)]
+    pub(crate) no_color: bool,
+
+    /// Trust SSH host keys of template repositories that aren't in known_hosts, for automation.
+    #[arg(long, global = true)]
+    pub(crate) accept_hostkey: bool,
+
+    /// Forbid network access: remote template repositories come from the clone cache of earlier runs.
+    #[arg(long, global = true)]
+    pub(crate) offline: bool,
+
+    /// Wait for another run on the same output directory (or cache commands for runs using the clone cache) to finish instead of failing.
+    #[arg(long, global = true)]
+    pub(crate) wait: bool,
+
+    /// Replay the variables recorded in an answers file (e.g. generated/.scaficionado/answers.toml) instead of prompting again.
+    #[arg(long)]
+    pub(crate) answers: Option<String>,
+
+    /// Set a variable, e.g. --var replicas=3, over the configuration, the answers file and SCAFICIONADO_VAR_<NAME> environment variables.  Converted to its declared type.
+    #[arg(long = "var", value_name = "NAME=VALUE", global = true, value_parser = parse_variable_assignment)]
+    pub(crate) vars: Vec<(String, String)>,
+
+    /// Accept the default of every prompt without asking, and fail on prompts without a default.  Implied when stdin isn't a terminal.
+    #[arg(long)]
+    pub(crate) defaults: bool,
+
+    /// Send the project name and the names and descriptions of the prompts to the LLM endpoint of the user configuration, and offer its suggestions as defaults.
+    #[arg(long)]
+    pub(crate) suggest: bool,
+
+    /// Open the generated project in an editor after a successful run.  Uses open_with set in configuration file, then $VISUAL or $EDITOR.
+    #[arg(long)]
+    pub(crate) open: bool,
+
+    /// Initialize a git repository in the output directory and stage the generated files after a successful run.  Configured with [project.git].
+    #[arg(long)]
+    pub(crate) git_init: bool,
+
+    /// Write any number of files of any size.  By default a run fails when it exceeds the limits of [project.limits].
+    #[arg(long)]
+    pub(crate) no_limits: bool,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub(crate) enum Commands {
+    /// Report the variables referenced by every scaffold's templates and whether they are set, without generating anything.
+    Vars,
+    /// Render every scaffold in memory, without running hooks, and print a unified diff against the files in the output directory.
+    Diff,
+    /// Render a scaffold with fake values for its variables into a temporary directory, without running hooks.
+    Simulate {
+        /// Name of the scaffold to simulate.
+        scaffold: String,
+        /// Seed for the fake values.  The same seed always produces the same values.
+        #[arg(long)]
+        seed: Option<u64>,
+    },
+    /// Create the skeleton of a new template repository (manifest, templates, hooks and a test fixture).
+    NewTemplate {
+        /// Directory of the new template repository.  Its name is used as the template name.
+        name: String,
+    },
+    /// Search the scaffold registry index for scaffolds whose name, description or tags match.
+    Search {
+        /// Text to look for, e.g. "kafka".
+        query: String,
+        /// URL or path of the registry index, instead of `registry` in the user configuration.
+        #[arg(long)]
+        registry: Option<String>,
+    },
+    /// Append a scaffold from the registry index to the configuration file.
+    Add {
+        /// Name of the scaffold in the registry index.
+        name: String,
+        /// URL or path of the registry index, instead of `registry` in the user configuration.
+        #[arg(long)]
+        registry: Option<String>,
+    },
+    /// Check the repositories of the scaffolds recorded in the output directory's answers file for newer tags or commits.
+    Outdated,
+    /// Generate one project per row of a CSV or TOML file, each with its own name, output directory and variables, and report on all of them.
+    Batch {
+        /// CSV file with a header row, or TOML file of [[projects]]: `project_name`, an optional `output`, and variables.
+        #[arg(long)]
+        input: String,
+    },
+    /// Serve an HTTP API that lists the configured scaffolds, validates the variables of a project and generates it as a .tar.gz archive, running its hooks.
+    Serve {
+        /// Address to listen on.
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        listen: String,
+    },
+    /// Print a Markdown description of a scaffold: its variables, the files it creates, its hooks and the tools it needs.
+    Describe {
+        /// Name of the scaffold to describe.
+        scaffold: String,
+    },
+    /// Package a template repository as an OCI artifact and push it to a registry.
+    Publish {
+        /// Where to push it, e.g. oci://registry.example.com/templates/rust-service:1.2.0.
+        reference: String,
+        /// Directory of the template repository.
+        #[arg(long, default_value = ".")]
+        dir: String,
+    },
+    /// Pack scaffolds with their template repositories and configuration into one archive that generates them offline
+    /// with `--config <archive>`.
+    Bundle {
+        /// Path of the archive to write, e.g. golden-paths.tar.gz.
+        archive: String,
+        /// Names of the scaffolds to bundle, with the scaffolds they extend.  Defaults to every scaffold.
+        scaffolds: Vec<String>,
+    },
+    /// Print a JSON Schema of scaffolding.toml, for validation and autocompletion in editors.
+    Schema,
+    /// Manage the temporary and cached clones of template repositories.
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+    /// Generate every fixture in tests/ of the current template repository and compare it with tests/expected/<fixture>.
+    Test {
+        /// Replace the expected output with the generated output instead of comparing them.
+        #[arg(long)]
+        bless: bool,
+    },
+    /// Print a shell completion script.  Run it in a project to also complete its scaffold names.
+    Completions {
+        /// Shell to complete in.
+        shell: Shell,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub(crate) enum CacheCommand {
+    /// Remove temporary clones left behind by crashed or killed runs.  This also happens on every start.
+    Gc,
+    /// List the cached clones used by --offline, with their size and when they were last used.
+    List,
+    /// Remove every cached clone.  --offline needs a run without it to cache the repositories again.
+    Clear,
+    /// Remove the cached clones that haven't been used for a while.
+    Prune {
+        /// How long a cached clone may go unused, e.g. "30d", "12h" or "2w".
+        #[arg(long, value_parser = parse_age)]
+        older_than: Duration,
+    },
+}
+
+/// Get the clone options from the command line arguments and the `[project.retry]` settings.
+pub(crate) fn get_git_options(args: &Args, config: &Config) -> GitOptions {
+    GitOptions {
+        verbose: args.verbose,
+        accept_hostkey: args.accept_hostkey,
+        retry: config.project.as_ref().and_then(|project| project.retry).unwrap_or_default(),
+        offline: args.offline,
+        // Set per scaffold.
+        submodules: false,
+        max_download: get_limits(args, config).map(|limits| limits.max_total_bytes),
+    }
+}
+
+/// Open `path` with the `editor` command.  The command may include arguments, e.g. "code -n".
+pub(crate) fn open_in_editor(editor: &str, path: &Path) -> Result<(), Box<dyn Error>> {
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().ok_or("Editor command is empty")?;
+    let path = canonical_path(path).unwrap_or_else(|_| path.to_path_buf());
+    say!("Opening {:?} with: {}", path, editor);
+    let status = Command::new(program).args(parts).arg(&path).status()?;
+    if !status.success() {
+        return Err(format!("Editor command '{}' failed with {}", editor, status).into());
+    }
+    Ok(())
+}
+
+/// Get the git post-action: `[project.git]` if present, otherwise the defaults when --git-init is set.
+pub(crate) fn get_git_init(args: &Args, config: &Config) -> Option<GitInitConfig> {
+    let configured = config.project.as_ref().and_then(|proj| proj.git.clone());
+    configured.or_else(|| args.git_init.then(GitInitConfig::default))
+}
+
+/// Overwrite the project settings in the configuration with the values from the command line arguments
+/// only if they differ from the defaults.
+fn overwrite_project_settings_with_args(args: &Args, config: &mut Config) {
+    if args.project_name != DEFAULT_PROJECT_NAME {
+        config.project.get_or_insert_with(Default::default).name = Some(args.project_name.clone());
+    }
+    if args.output != DEFAULT_OUTPUT {
+        config.project.get_or_insert_with(Default::default).output = Some(args.output.clone());
+    }
+    if args.overwrite != DEFAULT_OVERWRITE {
+        config.project.get_or_insert_with(Default::default).overwrite = Some(args.overwrite);
+    }
+    if args.strict != DEFAULT_STRICT {
+        config.project.get_or_insert_with(Default::default).strict = Some(args.strict);
+    }
+}
+
+/// Get the project name: use the config value if present; otherwise fall back to the CLI default.
+pub(crate) fn get_project_name(args: &Args, config: &Config) -> String {
+    config
+        .project
+        .as_ref()
+        .and_then(|proj| proj.name.clone())
+        .unwrap_or_else(|| args.project_name.clone())
+}
+
+/// Get the output directory: use the config value if present; otherwise fall back to the CLI default.
+pub(crate) fn get_output_directory(args: &Args, config: &Config) -> String {
+    config
+        .project
+        .as_ref()
+        .and_then(|proj| proj.output.clone())
+        .unwrap_or_else(|| args.output.clone())
+}
+
+/// Get the overwrite flag: use the config value if present; otherwise fall back to the CLI default.
+fn get_overwrite(args: &Args, config: &Config) -> bool {
+    config.project.as_ref().and_then(|proj| proj.overwrite).unwrap_or(args.overwrite)
+}
+
+/// Get the strict flag: use the config value if present; otherwise fall back to the CLI default.
+fn get_strict(args: &Args, config: &Config) -> bool {
+    config.project.as_ref().and_then(|proj| proj.strict).unwrap_or(args.strict)
+}
+
+pub(crate) fn get_render_options(args: &Args, config: &Config) -> RenderOptions {
+    RenderOptions {
+        overwrite: get_overwrite(args, config),
+        strict: get_strict(args, config),
+        keep_going: args.keep_going,
+        allow_collisions: args.allow_collisions,
+        defaults: args.defaults || !io::stdin().is_terminal(),
+        suggest: args.suggest,
+        line_endings: config.project.as_ref().and_then(|proj| proj.line_endings),
+        limits: get_limits(args, config),
+    }
+}
+
+/// Get the limits of a run: the configured ones, with defaults for those not set, unless --no-limits is set.
+pub(crate) fn get_limits(args: &Args, config: &Config) -> Option<Limits> {
+    if args.no_limits {
+        return None;
+    }
+    let configured = config.project.as_ref().and_then(|proj| proj.limits).unwrap_or_default();
+    Some(Limits {
+        max_file_size: configured.max_file_size.unwrap_or(DEFAULT_MAX_FILE_SIZE),
+        max_files: configured.max_files.unwrap_or(DEFAULT_MAX_FILES),
+        max_total_bytes: configured.max_total_bytes.unwrap_or(DEFAULT_MAX_TOTAL_BYTES),
+        max_template_size: configured.max_template_size.unwrap_or(DEFAULT_MAX_TEMPLATE_SIZE),
+    })
+}
+
+/// Get the editor used by --open: use the config value if present; otherwise fall back to $VISUAL, then $EDITOR.
+pub(crate) fn get_open_with(config: &Config) -> Option<String> {
+    config
+        .project
+        .as_ref()
+        .and_then(|proj| proj.open_with.clone())
+        .or_else(|| std::env::var("VISUAL").ok())
+        .or_else(|| std::env::var("EDITOR").ok())
+        .filter(|editor| !editor.trim().is_empty())
+}
+
+/// `completions` subcommand: print the completion script of `shell`.  The scaffold names of the configuration file,
+/// if it is a local file that can be read, complete the scaffold argument of `simulate` and `describe`.  Names with whitespace are
+/// left out, since the generated scripts split values on it.
+fn print_completions(shell: Shell, config_path: &str, out: &mut impl Write) {
+    let config = fs::read_to_string(config_path)
+        .ok()
+        .filter(|_| !is_url(config_path))
+        .and_then(|content| from_toml::<Config>(&content).ok())
+        .unwrap_or_default();
+    let names: Vec<String> = config
+        .scaffolds
+        .into_iter()
+        .filter_map(|scaffold| scaffold.name)
+        .filter(|name| !name.contains(char::is_whitespace))
+        .collect();
+    let mut command = Args::command();
+    if !names.is_empty() {
+        for subcommand in ["simulate", "describe"] {
+            command = command.mut_subcommand(subcommand, |subcommand| {
+                subcommand.mut_arg("scaffold", |arg| arg.value_parser(PossibleValuesParser::new(&names)))
+            });
+        }
+    }
+    clap_complete::generate(shell, &mut command, env!("CARGO_PKG_NAME"), out);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use clap_complete::Shell;
+    use std::collections::BTreeMap;
+    use std::fs;
+    use std::io;
+    use tempfile::TempDir;
+
+    // Test that another project is offered with a sibling output directory unless the output uses the project name.
+    #[test]
+    fn test_ask_another_project() -> Result<(), Box<dyn std::error::Error>> {
+        let args = Args::try_parse_from(["scaficionado"])?;
+        let config = Config::default();
+        let mut input = io::Cursor::new("svc-b\n\n");
+        let next = ask_another_project(&args, &config, "generated", &mut input)?;
+        assert_eq!(next, Some(("svc-b".to_string(), "svc-b".to_string())));
+        let mut input = io::Cursor::new("svc-b\nelsewhere\n");
+        let next = ask_another_project(&args, &config, "generated", &mut input)?;
+        assert_eq!(next, Some(("svc-b".to_string(), "elsewhere".to_string())));
+
+        let config: Config = from_toml("[project]\noutput = \"services/{{ project_name }}\"")?;
+        let next = ask_another_project(&args, &config, "services/svc-a", &mut io::Cursor::new("svc-b\n\n"))?;
+        assert_eq!(next, Some(("svc-b".to_string(), "services/svc-b".to_string())));
+        let output_dir = TempDir::new()?;
+        let previous = output_dir.path().join("svc-a").to_string_lossy().into_owned();
+        let config: Config = from_toml(&format!("[project]\noutput = {:?}", previous))?;
+        let next = ask_another_project(&args, &config, &previous, &mut io::Cursor::new("svc-b\n\n"))?;
+        let sibling = output_dir.path().join("svc-b").to_string_lossy().into_owned();
+        assert_eq!(next, Some(("svc-b".to_string(), sibling)));
+
+        // An empty name or the end of input finishes the session.
+        assert_eq!(ask_another_project(&args, &config, &previous, &mut io::Cursor::new("\n"))?, None);
+        assert_eq!(ask_another_project(&args, &config, &previous, &mut io::Cursor::new(""))?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_overwrite_project_settings_with_args() -> Result<(), Box<dyn std::error::Error>> {
+        // Test when config.project is None.
+        let args = Args {
+            project_name: "ArgProject".into(),
+            output: "arg_output".into(),
+            config: "dummy".into(),
+            ..Default::default()
+        };
+        let mut config = Config {
+            project: None,
+            variables: None,
+            scaffolds: vec![],
+            include: Vec::new(),
+            profile: BTreeMap::new(),
+            requires_version: None,
+        };
+        overwrite_project_settings_with_args(&args, &mut config);
+        let proj = config.project.unwrap();
+        assert_eq!(proj.name.unwrap(), "ArgProject");
+        assert_eq!(proj.output.unwrap(), "arg_output");
+
+        // Test when config.project already exists.
+        let mut config = Config {
+            project: Some(ProjectConfig {
+                name: Some("OldProject".into()),
+                output: Some("old_output".into()),
+                overwrite: Some(false),
+                ..Default::default()
+            }),
+            variables: None,
+            scaffolds: vec![],
+            include: Vec::new(),
+            profile: BTreeMap::new(),
+            requires_version: None,
+        };
+        // Overwrite with new values.
+        let args = Args {
+            project_name: "NewProject".into(),
+            output: "new_output".into(),
+            config: "dummy".into(),
+            ..Default::default()
+        };
+        overwrite_project_settings_with_args(&args, &mut config);
+        let proj = config.project.unwrap();
+        assert_eq!(proj.name.unwrap(), "NewProject");
+        assert_eq!(proj.output.unwrap(), "new_output");
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_project_name_and_get_output_directory() -> Result<(), Box<dyn std::error::Error>> {
+        // When project config exists.
+        let args = Args {
+            project_name: "CLIProject".into(),
+            output: "CLOutput".into(),
+            config: "dummy".into(),
+            ..Default::default()
+        };
+        let config = Config {
+            project: Some(ProjectConfig {
+                name: Some("ConfigProject".into()),
+                output: Some("ConfigOutput".into()),
+                overwrite: Some(false),
+                ..Default::default()
+            }),
+            variables: None,
+            scaffolds: vec![],
+            include: Vec::new(),
+            profile: BTreeMap::new(),
+            requires_version: None,
+        };
+        assert_eq!(get_project_name(&args, &config), "ConfigProject");
+        assert_eq!(get_output_directory(&args, &config), "ConfigOutput");
+
+        // When project config is missing.
+        let config = Config {
+            project: None,
+            variables: None,
+            scaffolds: vec![],
+            include: Vec::new(),
+            profile: BTreeMap::new(),
+            requires_version: None,
+        };
+        assert_eq!(get_project_name(&args, &config), "CLIProject");
+        assert_eq!(get_output_directory(&args, &config), "CLOutput");
+        Ok(())
+    }
+
+    // Test opening the output with an editor command, including arguments.
+    #[cfg(unix)]
+    #[test]
+    fn test_open_in_editor() -> Result<(), Box<dyn std::error::Error>> {
+        let output_dir = TempDir::new()?;
+        let config = Config {
+            project: Some(ProjectConfig {
+                open_with: Some("test -d".into()),
+                ..Default::default()
+            }),
+            variables: None,
+            scaffolds: vec![],
+            include: Vec::new(),
+            profile: BTreeMap::new(),
+            requires_version: None,
+        };
+        let editor = get_open_with(&config).unwrap();
+        open_in_editor(&editor, output_dir.path())?;
+        assert!(open_in_editor("false", output_dir.path()).is_err());
+        Ok(())
+    }
+
+    // Test that completion scripts complete the scaffold names of the local configuration.
+    #[test]
+    fn test_completions() -> Result<(), Box<dyn std::error::Error>> {
+        let config_dir = TempDir::new()?;
+        let config_path = config_dir.path().join("scaffolding.toml");
+        fs::write(
+            &config_path,
+            "[[scaffolds]]\nname = \"api-service\"\nrepo = \".\"\n\n[[scaffolds]]\nname = \"With Spaces\"\nrepo = \".\"\n",
+        )?;
+        let mut script = Vec::new();
+        print_completions(Shell::Bash, &config_path.to_string_lossy(), &mut script);
+        let script = String::from_utf8(script)?;
+        assert!(script.contains("completions"), "{}", script);
+        assert!(script.contains("api-service"), "{}", script);
+        assert!(!script.contains("With Spaces"), "{}", script);
+
+        // Without a configuration the script is still generated.
+        let mut script = Vec::new();
+        print_completions(Shell::Zsh, &config_dir.path().join("missing.toml").to_string_lossy(), &mut script);
+        assert!(String::from_utf8(script)?.contains("#compdef scaficionado"));
+        Ok(())
+    }
+
+    // Test that the settings of a run apply to its thread only, and end with the run.
+    #[test]
+    fn test_run_context() -> Result<(), Box<dyn std::error::Error>> {
+        let offline = RunContext {
+            offline: true,
+            ..RunContext::default()
+        };
+        {
+            let _run = RunScope::enter(offline);
+            send_messages_to_stderr();
+            assert!(run_context().offline && messages_to_stderr());
+            let other = std::thread::spawn(|| run_context().offline || messages_to_stderr()).join();
+            assert!(!other.map_err(|_| "the thread panicked")?);
+        }
+        assert!(!run_context().offline && !messages_to_stderr());
+
+        // A run with --offline refuses to check for updates, but the next run of the thread may.
+        let config_dir = TempDir::new()?;
+        let config = config_dir.path().join("missing.toml");
+        let args = |offline: &[&str]| {
+            let mut args = vec!["scaficionado", "--config", config.to_str().unwrap_or_default(), "outdated"];
+            args.splice(1..1, offline.iter().copied());
+            Args::try_parse_from(args)
+        };
+        let error = run_with_args(&args(&["--offline"])?).unwrap_err();
+        assert!(error.to_string().contains("--offline"), "{}", error);
+        let error = run_with_args(&args(&[])?).unwrap_err();
+        assert!(!error.to_string().contains("--offline"), "{}", error);
+        Ok(())
+    }
+}
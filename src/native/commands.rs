@@ -0,0 +1,1088 @@
+//! The subcommands inspecting configurations and templates: vars, simulate, describe, diff, outdated,
+//! new-template and test.
+
+use crate::render::template_variables;
+use clap::Parser;
+use heck::ToKebabCase;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error::Error;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tempfile::TempDir;
+use tera::{Context, Template};
+
+use super::*;
+
+/// Files of the template repository created by `new-template`, rendered with the template `name`.
+const TEMPLATE_SKELETON: [(&str, &str); 7] = [
+    ("README.md", include_str!("../skeleton/README.md")),
+    ("scaffold.toml", include_str!("../skeleton/scaffold.toml")),
+    ("templates/hello.txt.tera", include_str!("../skeleton/templates/hello.txt.tera")),
+    ("hooks/post.sh", include_str!("../skeleton/hooks/post.sh")),
+    ("tests/default.toml", include_str!("../skeleton/tests/default.toml")),
+    (
+        "tests/expected/default/Fixture/hello.txt",
+        include_str!("../skeleton/tests/expected/default/Fixture/hello.txt"),
+    ),
+    (".gitignore", include_str!("../skeleton/gitignore")),
+];
+
+/// Directory of a template repository with its test fixtures (`*.toml`) and their expected output (`expected/<fixture>`).
+const TEMPLATE_TESTS_DIR: &str = "tests";
+
+/// How a referenced variable is used across a scaffold's templates.
+#[derive(Debug, Default)]
+pub(crate) struct VariableUsage {
+    pub(crate) required: bool,
+    pub(crate) defined: bool,
+    locations: BTreeSet<String>,
+}
+
+/// Collect the variables referenced by a scaffold's templates and whether `context` defines them.
+pub(crate) fn scaffold_variable_usage(
+    templates_dir: &Path,
+    scaffold: &Scaffold,
+    context: &Context,
+) -> Result<BTreeMap<String, VariableUsage>, Box<dyn Error>> {
+    let mut usage: BTreeMap<String, VariableUsage> = BTreeMap::new();
+    for (location, source) in scaffold_template_sources(templates_dir, scaffold)? {
+        let template = Template::new(&location, None, &source)?;
+        for (name, required) in template_variables(&template) {
+            let entry = usage.entry(name.clone()).or_default();
+            entry.required |= required;
+            entry.defined = context.contains_key(&name);
+            entry.locations.insert(location.clone());
+        }
+    }
+    Ok(usage)
+}
+
+/// `vars` subcommand: print every variable referenced by each scaffold and whether the
+/// configuration or command line satisfies it.  Fails if any required variable is missing.
+pub(crate) fn print_variable_report(args: &Args) -> Result<(), Box<dyn Error>> {
+    let config = load_config_with_args(args)?;
+    let project_name = get_project_name(args, &config);
+
+    let target_repo = target_repo_metadata(Path::new(&get_output_directory(args, &config)));
+    let mut clones = TemporaryClones::default();
+    let mut missing = 0;
+    for scaffold in &config.scaffolds {
+        let (scaffold, scaffold_repo_base) = load_scaffold(scaffold, &get_git_options(args, &config))?;
+        let scaffold = &scaffold;
+        clones.add(&scaffold_repo_base);
+        let templates_dir = get_templates_dir(&scaffold_repo_base, scaffold);
+        let mut context = build_context(&project_name, config.variables.as_ref(), scaffold.variables.as_ref());
+        if let Some(target_repo) = target_repo.as_ref().filter(|_| !context.contains_key("target_repo")) {
+            context.insert("target_repo", target_repo);
+        }
+
+        println!("\nScaffold: {}", scaffold.name.as_deref().unwrap_or("unnamed"));
+        if scaffold.format == TemplateFormat::Cookiecutter {
+            // Every cookiecutter variable has a default, so nothing can be missing.
+            for name in load_cookiecutter_json(&templates_dir)?.keys().filter(|name| !name.starts_with('_')) {
+                let status = if context.contains_key(name) { "ok" } else { "prompt" };
+                println!("  {:<8} {:<24} (cookiecutter.json)", status, name);
+            }
+            continue;
+        }
+        if scaffold.format == TemplateFormat::CargoGenerate {
+            let config = load_cargo_generate_config(&templates_dir)?;
+            for prompt in cargo_generate_prompts(&config)? {
+                let status = if context.contains_key(&prompt.name) { "ok" } else { "prompt" };
+                println!("  {:<8} {:<24} (cargo-generate.toml)", status, prompt.name);
+            }
+            continue;
+        }
+        for (name, usage) in scaffold_variable_usage(&templates_dir, scaffold, &context)? {
+            let prompted = scaffold.prompts.iter().flatten().any(|prompt| prompt.name == name);
+            let status = match (usage.defined, prompted, usage.required) {
+                (true, _, _) => "ok",
+                (false, true, _) => "prompt",
+                (false, false, true) => "missing",
+                (false, false, false) => "optional",
+            };
+            if status == "missing" {
+                missing += 1;
+            }
+            let locations: Vec<&str> = usage.locations.iter().map(String::as_str).collect();
+            println!("  {:<8} {:<24} ({})", status, name, locations.join(", "));
+        }
+    }
+    clones.clean_up()?;
+
+    if missing > 0 {
+        return Err(format!("{} required variable(s) are missing", missing).into());
+    }
+    Ok(())
+}
+
+/// `simulate` subcommand: render one scaffold with fake values into a temporary directory that is kept for
+/// inspection, so template authors can check the layout without writing answers.
+pub(crate) fn simulate_scaffold(args: &Args, name: &str, seed: Option<u64>) -> Result<(), Box<dyn Error>> {
+    let config = load_config_with_args(args)?;
+    let scaffold = config
+        .scaffolds
+        .iter()
+        .find(|scaffold| scaffold_name(scaffold) == name)
+        .ok_or_else(|| format!("No scaffold named '{}' in {}", name, args.config))?;
+    let seed = seed.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos() as u64)
+    });
+    let output_base = TempDir::new()?.keep();
+
+    let (scaffold, repo_base) = load_scaffold(scaffold, &get_git_options(args, &config))?;
+    let result = simulate(&config, &scaffold, &repo_base, seed, &output_base);
+    if is_temporary_clone(&repo_base) {
+        clean_up_persistent_dirs(vec![repo_base])?;
+    }
+    let (variables, rendered) = result?;
+
+    println!("\nSimulated scaffold '{}' with --seed {}", name, seed);
+    println!("Variables:");
+    for (variable, value) in &variables {
+        println!("  {} = {}", variable, value);
+    }
+    println!("Files:");
+    for (path, _) in &rendered.written {
+        println!("  {}", path.strip_prefix(&output_base).unwrap_or(path).display());
+    }
+    for file_error in &rendered.errors {
+        println!("  [error] {}: {}", file_error.file, file_error.message);
+    }
+    println!("Output kept in: {:?}", output_base);
+    if !rendered.errors.is_empty() {
+        return Err(format!("{} file(s) failed to render", rendered.errors.len()).into());
+    }
+    Ok(())
+}
+
+/// One project of a `batch` input: its name, output directory and variables.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct BatchRow {
+    pub(crate) project_name: String,
+    pub(crate) output: Option<String>,
+    pub(crate) variables: BTreeMap<String, toml::Value>,
+}
+
+/// Describe a scaffold in Markdown: its repository, its variables with their defaults and descriptions, the files
+/// it creates, the hooks it runs and the tools it needs.
+pub(crate) fn scaffold_description(scaffold: &Scaffold, global_variables: Option<&HashMap<String, toml::Value>>) -> String {
+    let code = |text: &str| format!("`{}`", text.replace('|', "\\|"));
+    let mut doc = format!("# {}\n\n", scaffold_name(scaffold));
+    doc.push_str(&format!(
+        "Repository: {}\n",
+        scaffold.repo.urls().iter().map(|url| code(url)).collect::<Vec<_>>().join(", ")
+    ));
+    if let Some(version) = &scaffold.version {
+        doc.push_str(&format!("Version: {}\n", code(version)));
+    }
+    let format = match scaffold.format {
+        TemplateFormat::Native => None,
+        TemplateFormat::Cookiecutter => Some("cookiecutter"),
+        TemplateFormat::CargoGenerate => Some("cargo-generate"),
+    };
+    if let Some(format) = format {
+        doc.push_str(&format!("Format: {}\n", format));
+    }
+
+    // Prompts in the order they are asked, then the other variables by name.
+    let mut names: Vec<&str> = scaffold.prompts.iter().flatten().map(|prompt| prompt.name.as_str()).collect();
+    let mut others: BTreeSet<&str> = scaffold.variables.iter().flatten().map(|(name, _)| name.as_str()).collect();
+    others.extend(scaffold.required_variables.iter().map(String::as_str));
+    others.extend(scaffold.descriptions.keys().map(String::as_str));
+    others.extend(scaffold.computed.keys().map(String::as_str));
+    for name in &names {
+        others.remove(name);
+    }
+    names.extend(others);
+    doc.push_str("\n## Variables\n\n");
+    if names.is_empty() {
+        doc.push_str("None.\n");
+    } else {
+        doc.push_str("| Variable | Default | Asked | Description |\n| --- | --- | --- | --- |\n");
+    }
+    for name in names {
+        let prompt = scaffold.prompts.iter().flatten().find(|prompt| prompt.name == name);
+        let value = scaffold
+            .variables
+            .as_ref()
+            .and_then(|variables| variables.get(name))
+            .or_else(|| global_variables.and_then(|variables| variables.get(name)))
+            .or_else(|| prompt.and_then(|prompt| prompt.default.as_ref()));
+        let computed = scaffold.computed.get(name);
+        let default = match (computed, value) {
+            (Some(expression), _) => code(expression),
+            (None, Some(_)) if is_secret(scaffold, name) => REDACTED.to_string(),
+            (None, Some(value)) => code(&display_value(value)),
+            (None, None) if scaffold.required_variables.iter().any(|required| required == name) => "required".to_string(),
+            (None, None) => String::new(),
+        };
+        let asked = match prompt {
+            Some(Prompt { when: Some(when), .. }) => format!("when {}", code(when)),
+            Some(_) => "yes".to_string(),
+            None if computed.is_some() => "computed".to_string(),
+            None => String::new(),
+        };
+        let description = scaffold
+            .descriptions
+            .get(name)
+            .cloned()
+            .or_else(|| prompt.and_then(|prompt| prompt.message.clone()))
+            .unwrap_or_default();
+        doc.push_str(&format!(
+            "| {} | {} | {} | {} |\n",
+            code(name),
+            default,
+            asked,
+            description.replace('|', "\\|")
+        ));
+    }
+
+    doc.push_str("\n## Files\n\n");
+    if scaffold.template.files.is_empty() {
+        match format {
+            Some(format) => doc.push_str(&format!("Every file of the {} template.\n", format)),
+            None => doc.push_str("None.\n"),
+        }
+    }
+    for file in &scaffold.template.files {
+        let entry = match &file.dir {
+            Some(dir) => format!("- {} (directory)", code(dir)),
+            None => format!("- {} from {}", code(&scaffold.template.dest(file)), code(&file.src)),
+        };
+        doc.push_str(&entry);
+        if let Some(list) = &file.for_each {
+            doc.push_str(&format!(", once per item of {}", code(list)));
+        }
+        doc.push('\n');
+    }
+
+    if let Some(hooks) = &scaffold.hooks {
+        doc.push_str("\n## Hooks\n\n");
+        let when = match hooks.run_when {
+            HookRunWhen::Always => "",
+            HookRunWhen::OnChanges => ", only when files changed",
+        };
+        if let Some(plan) = &hooks.plan {
+            doc.push_str(&format!("- plan: {}, before anything is rendered\n", code(plan)));
+        }
+        for (stage, hook) in [("pre", &hooks.pre), ("post", &hooks.post)] {
+            match hook {
+                Some(Hook::Rhai(rhai)) => doc.push_str(&format!("- {}: {} (Rhai){}\n", stage, code(&rhai.rhai), when)),
+                Some(Hook::Script(script)) => doc.push_str(&format!("- {}: {}{}\n", stage, code(script), when)),
+                Some(Hook::Command(CommandHook {
+                    command,
+                    capture: Some(name),
+                })) => doc.push_str(&format!(
+                    "- {}: {} (command), captured as {} before anything is rendered\n",
+                    stage,
+                    code(command),
+                    code(name)
+                )),
+                Some(Hook::Command(CommandHook { command, capture: None })) => {
+                    doc.push_str(&format!("- {}: {} (command){}\n", stage, code(command), when))
+                }
+                None => {}
+            }
+        }
+    }
+
+    if let Some(requires) = scaffold
+        .requires
+        .as_ref()
+        .filter(|requires| !requires.binaries.is_empty() || !requires.env.is_empty())
+    {
+        doc.push_str("\n## Requirements\n\n");
+        for binary in &requires.binaries {
+            doc.push_str(&format!("- program {}\n", code(binary)));
+        }
+        for var in &requires.env {
+            doc.push_str(&format!("- environment variable {}\n", code(var)));
+        }
+    }
+    doc
+}
+
+/// Run the configured scaffolds without writing anything or running hooks, and print a unified diff between the
+/// files in the output directory and what the run would write (as with --overwrite).  Messages go to stderr.
+pub(crate) fn diff_output(args: &Args) -> Result<(), Box<dyn Error>> {
+    send_messages_to_stderr();
+    // Existing files are compared with what overwriting them would write.
+    let args = Args {
+        overwrite: true,
+        ..args.clone()
+    };
+    begin_preview();
+    let result = generate(&args);
+    let preview = finish_preview();
+    result?;
+    let colored = run_context().colors && io::stdout().is_terminal();
+    if write_diff(&preview, colored, &mut io::stdout().lock())? == 0 {
+        eprintln!("No differences: the output directory is up to date");
+    }
+    Ok(())
+}
+
+/// Write a unified diff of each previewed file against the file on disk, returning the number of files that differ.
+/// Additions and removals are colored when `colored` is set.
+fn write_diff(preview: &BTreeMap<PathBuf, Vec<u8>>, colored: bool, out: &mut impl Write) -> Result<usize, Box<dyn Error>> {
+    let mut changed = 0;
+    for (path, content) in preview {
+        let current = match fs::read(path) {
+            Ok(current) => Some(current),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(source) => {
+                return Err(FsError::Read {
+                    path: path.clone(),
+                    source,
+                }
+                .into())
+            }
+        };
+        if current.as_ref() == Some(content) {
+            continue;
+        }
+        changed += 1;
+        let old_header = match current {
+            Some(_) => format!("a/{}", path.display()),
+            None => "/dev/null".to_string(),
+        };
+        let new_header = format!("b/{}", path.display());
+        let (Ok(old), Ok(new)) = (
+            std::str::from_utf8(current.as_deref().unwrap_or_default()),
+            std::str::from_utf8(content),
+        ) else {
+            writeln!(out, "Binary files {} and {} differ", old_header, new_header)?;
+            continue;
+        };
+        let diff = similar::TextDiff::from_lines(old, new);
+        for line in diff.unified_diff().header(&old_header, &new_header).to_string().lines() {
+            let style = match line.chars().next() {
+                _ if line.starts_with("---") || line.starts_with("+++") => None,
+                Some('+') => Some(Style::Created),
+                Some('-') => Some(Style::Removed),
+                Some('@') => Some(Style::Unchanged),
+                _ => None,
+            };
+            match style {
+                Some(style) => writeln!(out, "{}", style.paint_if(colored, line))?,
+                None => writeln!(out, "{}", line)?,
+            }
+        }
+    }
+    Ok(changed)
+}
+
+/// Render a scaffold with fake values for every declared and referenced variable, returning the values used.
+pub(crate) fn simulate(
+    config: &Config,
+    scaffold: &Scaffold,
+    repo_base: &Path,
+    seed: u64,
+    output_base: &Path,
+) -> Result<(BTreeMap<String, toml::Value>, RenderResult), Box<dyn Error>> {
+    let templates_dir = get_templates_dir(repo_base, scaffold);
+    let mut fake = FakeValues::new(seed);
+
+    // Declared variables keep their type; the values of prompt choices are picked from the choices.
+    let mut declared: BTreeMap<String, Option<toml::Value>> = BTreeMap::new();
+    for vars in [config.variables.as_ref(), scaffold.variables.as_ref()].into_iter().flatten() {
+        declared.extend(vars.iter().map(|(name, value)| (name.clone(), Some(value.clone()))));
+    }
+    let placeholders = match scaffold.format {
+        TemplateFormat::CargoGenerate => cargo_generate_prompts(&load_cargo_generate_config(&templates_dir)?)?,
+        _ => Vec::new(),
+    };
+    for prompt in scaffold.prompts.iter().flatten().chain(&placeholders) {
+        let value = match &prompt.choices {
+            Some(choices) if !choices.is_empty() => Some(toml::Value::Array(choices.clone())),
+            _ => prompt.default.clone(),
+        };
+        declared.insert(prompt.name.clone(), value);
+    }
+    match scaffold.format {
+        TemplateFormat::Native => {
+            for (location, source) in scaffold_template_sources(&templates_dir, scaffold)? {
+                for name in template_variables(&Template::new(&location, None, &source)?).into_keys() {
+                    declared.entry(name).or_default();
+                }
+            }
+        }
+        TemplateFormat::Cookiecutter => {
+            for (name, value) in load_cookiecutter_json(&templates_dir)? {
+                if !name.starts_with('_') && !value.is_object() && !value.is_null() {
+                    declared.insert(name, Some(toml::Value::try_from(value)?));
+                }
+            }
+        }
+        TemplateFormat::CargoGenerate => {}
+    }
+    for (name, expression) in &scaffold.computed {
+        for used in template_variables(&Template::new(name, None, expression)?).into_keys() {
+            declared.entry(used).or_default();
+        }
+    }
+    for name in scaffold.computed.keys() {
+        declared.remove(name);
+    }
+
+    let project_name = fake.string("project_name");
+    declared.remove("project_name");
+    if let Some(None) = declared.get("target_repo") {
+        let remote_url = format!("https://github.com/example/{}.git", project_name.to_kebab_case());
+        let mut target_repo = toml::Table::new();
+        target_repo.insert("remote_url".to_string(), remote_url.into());
+        target_repo.insert("default_branch".to_string(), "main".into());
+        target_repo.insert("root_relative_path".to_string(), ".".into());
+        declared.insert("target_repo".to_string(), Some(target_repo.into()));
+    }
+    let mut variables: BTreeMap<String, toml::Value> = declared
+        .iter()
+        .map(|(name, value)| (name.clone(), fake.value(name, value.as_ref())))
+        .collect();
+    variables.insert("project_name".to_string(), toml::Value::String(project_name.clone()));
+
+    let mut context = build_context(&project_name, None, None);
+    for (name, value) in &variables {
+        context.insert(name, value);
+    }
+    let options = RenderOptions {
+        overwrite: true,
+        strict: true,
+        keep_going: true,
+        allow_collisions: true,
+        defaults: true,
+        suggest: false,
+        line_endings: None,
+        limits: None,
+    };
+    apply_computed_variables(scaffold, &mut context, &options)?;
+    let rendered = render_scaffold_templates(scaffold, &templates_dir, output_base, &context, &variables, &options)?;
+    Ok((variables, rendered))
+}
+
+/// A small deterministic generator of plausible fake values (SplitMix64).
+struct FakeValues {
+    state: u64,
+}
+
+impl FakeValues {
+    const ADJECTIVES: [&'static str; 8] = ["brave", "calm", "eager", "fuzzy", "jolly", "quiet", "rapid", "witty"];
+    const NOUNS: [&'static str; 8] = ["otter", "falcon", "maple", "comet", "harbor", "lantern", "badger", "pixel"];
+
+    fn new(seed: u64) -> Self {
+        FakeValues { state: seed }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `low..=high`.
+    fn range(&mut self, low: i64, high: i64) -> i64 {
+        low + (self.next() % (high - low + 1) as u64) as i64
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.next() as usize % items.len()]
+    }
+
+    /// A fake value of the same type as `declared`, or a string when the type is unknown.
+    fn value(&mut self, name: &str, declared: Option<&toml::Value>) -> toml::Value {
+        match declared {
+            Some(toml::Value::Boolean(_)) => toml::Value::Boolean(self.next() & 1 == 0),
+            Some(toml::Value::Integer(_)) if name.contains("port") => toml::Value::Integer(self.range(1024, 65535)),
+            Some(toml::Value::Integer(_)) => toml::Value::Integer(self.range(0, 10)),
+            Some(toml::Value::Float(_)) => toml::Value::Float(self.range(0, 1000) as f64 / 10.0),
+            // Prompt choices and cookiecutter lists: pick one of the entries.
+            Some(toml::Value::Array(items)) if !items.is_empty() => self.pick(items).clone(),
+            Some(other @ (toml::Value::Table(_) | toml::Value::Datetime(_))) => other.clone(),
+            _ => toml::Value::String(self.string(name)),
+        }
+    }
+
+    /// A fake string that suits the variable name, e.g. an email address for "author_email".
+    fn string(&mut self, name: &str) -> String {
+        let name = name.to_lowercase();
+        let adjective = *self.pick(&Self::ADJECTIVES);
+        let noun = *self.pick(&Self::NOUNS);
+        if name.contains("email") {
+            format!("{}.{}@example.com", adjective, noun)
+        } else if name.contains("url") {
+            format!("https://example.com/{}-{}", adjective, noun)
+        } else if name.contains("version") {
+            format!("{}.{}.{}", self.range(0, 3), self.range(0, 20), self.range(0, 9))
+        } else {
+            // Vary the separator and case to expose layout issues in rendered paths.
+            match self.range(0, 3) {
+                0 => format!("{}-{}", adjective, noun),
+                1 => format!("{}_{}", adjective, noun),
+                2 => format!("{}{}", capitalize(adjective), capitalize(noun)),
+                _ => format!("{} {}", capitalize(adjective), capitalize(noun)),
+            }
+        }
+    }
+}
+
+/// Uppercase the first character of a word.
+pub(crate) fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    chars
+        .next()
+        .map_or_else(String::new, |first| first.to_uppercase().chain(chars).collect())
+}
+
+/// `outdated` subcommand: report which scaffolds recorded in the answers file of the output directory have newer
+/// tags or commits upstream.
+pub(crate) fn outdated_scaffolds(args: &Args) -> Result<(), Box<dyn Error>> {
+    if run_context().offline {
+        return Err("Cannot check for updates with --offline".into());
+    }
+    let output = load_config_with_args(args).map_or_else(|_| args.output.clone(), |config| get_output_directory(args, &config));
+    let path = Path::new(&output).join(ANSWERS_FILE);
+    let answers: Answers = toml::from_str(&fs::read_to_string(&path).map_err(|source| FsError::Read {
+        path: path.clone(),
+        source,
+    })?)?;
+    if answers.sources.is_empty() {
+        println!("No scaffold in {:?} was generated from a git repository", path);
+        return Ok(());
+    }
+    let width = answers.sources.keys().map(String::len).max().unwrap_or(0);
+    let mut outdated = 0;
+    for (name, locked) in &answers.sources {
+        match remote_refs(&locked.repo) {
+            Ok(refs) => match available_update(locked, &refs) {
+                Some(update) => {
+                    outdated += 1;
+                    println!("{:width$}  update available: {}", name, update, width = width);
+                }
+                None => println!("{:width$}  up to date", name, width = width),
+            },
+            Err(e) => println!("{:width$}  could not check {}: {}", name, locked.repo, e, width = width),
+        }
+    }
+    match outdated {
+        0 => println!("\nEvery scaffold is up to date"),
+        n => println!(
+            "\n{} of {} scaffolds have updates; regenerate with --answers {:?} -w",
+            n,
+            answers.sources.len(),
+            path
+        ),
+    }
+    Ok(())
+}
+
+/// `new-template` subcommand: create the skeleton of a template repository in `dest`, which must be missing or empty.
+pub(crate) fn new_template(dest: &Path) -> Result<(), Box<dyn Error>> {
+    let skeleton = Scaffold {
+        mode: ScaffoldMode::Create,
+        ..Default::default()
+    };
+    check_scaffold_mode(&skeleton, dest)?;
+
+    let name = dest.file_name().ok_or_else(|| format!("Invalid template directory {:?}", dest))?;
+    let mut context = Context::new();
+    context.insert("name", &name.to_string_lossy());
+    for (path, content) in TEMPLATE_SKELETON {
+        let target = dest.join(path);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let rendered = render_string(content, path, &context, &RenderOptions::default())?;
+        write_file(&target, rendered.as_bytes(), false)?;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(dest.join("hooks/post.sh"), fs::Permissions::from_mode(0o755))?;
+    }
+
+    println!("\nCreated template repository {:?}.  Next steps:", dest);
+    println!("  - describe your files, variables and prompts in scaffold.toml");
+    println!("  - test it with: cd {:?} && scaficionado test", dest);
+    Ok(())
+}
+
+/// Generate every fixture of the template repository at `root` into a temporary directory and compare the output with
+/// its snapshot in tests/expected/<fixture>, or replace the snapshot with `bless`.  Local repositories in fixtures are
+/// relative to `root`, so `repo = "."` is the template itself.
+pub(crate) fn test_template(root: &Path, bless: bool) -> Result<(), Box<dyn Error>> {
+    let tests_dir = root.join(TEMPLATE_TESTS_DIR);
+    let mut fixtures: Vec<PathBuf> = fs::read_dir(&tests_dir)
+        .map_err(|e| format!("Cannot read fixtures in {:?}: {}", tests_dir, e))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+        .collect();
+    fixtures.sort();
+    if fixtures.is_empty() {
+        return Err(format!("No fixtures (*.toml) found in {:?}", tests_dir).into());
+    }
+
+    let mut failures = Vec::new();
+    for fixture in &fixtures {
+        let name = fixture.file_stem().unwrap_or_default().to_string_lossy().to_string();
+        let expected_dir = tests_dir.join("expected").join(&name);
+        let output_dir = TempDir::new()?;
+        let outcome = generate_fixture(root, fixture, output_dir.path()).and_then(|()| {
+            let actual = snapshot_files(output_dir.path())?;
+            if bless {
+                if expected_dir.exists() {
+                    fs::remove_dir_all(&expected_dir)?;
+                }
+                for (path, content) in &actual {
+                    let target = expected_dir.join(path);
+                    fs::create_dir_all(target.parent().unwrap_or(&expected_dir))?;
+                    fs::write(target, content)?;
+                }
+                return Ok(Vec::new());
+            }
+            Ok(snapshot_differences(&snapshot_files(&expected_dir)?, &actual))
+        });
+        match outcome {
+            Ok(differences) if differences.is_empty() => println!("fixture '{}': {}", name, if bless { "blessed" } else { "ok" }),
+            Ok(differences) => failures.push((name, differences)),
+            Err(e) => failures.push((name, vec![format!("generation failed: {}", e)])),
+        }
+    }
+
+    for (name, differences) in &failures {
+        println!("fixture '{}': FAILED", name);
+        for difference in differences {
+            println!("  {}", difference);
+        }
+    }
+    if !failures.is_empty() {
+        return Err(format!(
+            "{} of {} fixture(s) failed; run `scaficionado test --bless` to accept the new output",
+            failures.len(),
+            fixtures.len()
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Generate a fixture configuration into `output_dir`, overwriting and without asking for answers it doesn't set.
+fn generate_fixture(root: &Path, fixture: &Path, output_dir: &Path) -> Result<(), Box<dyn Error>> {
+    let args = Args::try_parse_from([
+        "scaficionado".as_ref(),
+        "--config".as_ref(),
+        fixture.as_os_str(),
+        "--output".as_ref(),
+        output_dir.as_os_str(),
+        "--overwrite".as_ref(),
+    ])?;
+    let mut config = load_config_with_args(&args)?;
+    for scaffold in &mut config.scaffolds {
+        scaffold.repo = scaffold.repo.map(|url| match is_local_repo(url) && Path::new(url).is_relative() {
+            true => root.join(url).to_string_lossy().to_string(),
+            false => url.to_string(),
+        });
+    }
+    generate_config(&args, config)
+}
+
+/// Read every file below `dir`, keyed by its relative path.  The answers file is left out: it records where the
+/// templates came from, which differs between machines.
+pub(crate) fn snapshot_files(dir: &Path) -> Result<BTreeMap<PathBuf, Vec<u8>>, Box<dyn Error>> {
+    fn collect(dir: &Path, root: &Path, files: &mut BTreeMap<PathBuf, Vec<u8>>) -> Result<(), Box<dyn Error>> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                collect(&path, root, files)?;
+            } else {
+                let relative = path.strip_prefix(root)?.to_path_buf();
+                if relative != Path::new(ANSWERS_FILE) {
+                    files.insert(relative, fs::read(&path)?);
+                }
+            }
+        }
+        Ok(())
+    }
+    let mut files = BTreeMap::new();
+    if dir.is_dir() {
+        collect(dir, dir, &mut files)?;
+    }
+    Ok(files)
+}
+
+/// Describe how the generated files differ from the expected ones, one line per file.
+fn snapshot_differences(expected: &BTreeMap<PathBuf, Vec<u8>>, actual: &BTreeMap<PathBuf, Vec<u8>>) -> Vec<String> {
+    let paths: BTreeSet<&PathBuf> = expected.keys().chain(actual.keys()).collect();
+    paths
+        .into_iter()
+        .filter_map(|path| match (expected.get(path), actual.get(path)) {
+            (Some(_), None) => Some(format!("missing: {}", path.display())),
+            (None, Some(_)) => Some(format!("unexpected: {}", path.display())),
+            (Some(old), Some(new)) if old != new => Some(format!("changed: {}", path.display())),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::native::testing::git;
+    use clap::Parser;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use tempfile::TempDir;
+
+    // Test that the vars report marks variables as defined, missing or optional.
+    #[test]
+    fn test_scaffold_variable_usage() -> Result<(), Box<dyn std::error::Error>> {
+        let templates_dir = TempDir::new()?;
+        let nested = templates_dir.path().join("app");
+        fs::create_dir_all(&nested)?;
+        fs::write(
+            nested.join("config.toml.tera"),
+            "team = \"{{ team }}\"\nport = {{ port | default(value=80) }}",
+        )?;
+
+        let scaffold = Scaffold {
+            name: Some("VarsTest".into()),
+            repo: "dummy".into(),
+            template_dir: Some("".into()),
+            template: TemplateConfig {
+                files: vec![TemplateFile {
+                    src: "app".into(),
+                    dest: "{{ project_name }}/{{ environment }}".into(),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let context = build_context("VarsProject", None, None);
+        let usage = scaffold_variable_usage(templates_dir.path(), &scaffold, &context)?;
+
+        assert!(usage["project_name"].defined);
+        assert!(!usage["environment"].defined && usage["environment"].required);
+        assert!(usage["environment"]
+            .locations
+            .contains("dest `{{ project_name }}/{{ environment }}`"));
+        assert!(!usage["team"].defined && usage["team"].required);
+        assert!(!usage["port"].defined && !usage["port"].required);
+        Ok(())
+    }
+
+    // Test that the vars subcommand removes the clones it made when a later scaffold fails.
+    #[test]
+    fn test_variable_report_cleans_up_on_error() -> Result<(), Box<dyn std::error::Error>> {
+        static FETCHED: std::sync::Mutex<Vec<PathBuf>> = std::sync::Mutex::new(Vec::new());
+        struct RecordingSource;
+        impl ScaffoldSource for RecordingSource {
+            fn fetch(&self, dest: &Path) -> Result<PathBuf, Box<dyn std::error::Error>> {
+                fs::create_dir_all(dest.join("templates"))?;
+                fs::write(dest.join("templates/README.md.tera"), "# {{ project_name }}")?;
+                FETCHED.lock().unwrap().push(dest.to_path_buf());
+                Ok(dest.to_path_buf())
+            }
+            fn identity(&self) -> String {
+                "vars-test://templates".to_string()
+            }
+        }
+        register_source(|url| url.starts_with("vars-test://").then(|| Box::new(RecordingSource) as _));
+
+        let dir = TempDir::new()?;
+        let cookiecutter = dir.path().join("cookiecutter");
+        fs::create_dir_all(&cookiecutter)?;
+        let config = dir.path().join("scaffolding.toml");
+        fs::write(
+            &config,
+            format!(
+                "[[scaffolds]]\nname = \"Fetched\"\nrepo = \"vars-test://templates\"\n\n\
+                 [[scaffolds]]\nname = \"Broken\"\nrepo = {:?}\nformat = \"cookiecutter\"\n",
+                cookiecutter.display().to_string()
+            ),
+        )?;
+        let args = Args::try_parse_from(["scaficionado", "--config", &config.to_string_lossy(), "vars"])?;
+        assert!(print_variable_report(&args).is_err());
+
+        // The cookiecutter scaffold has no cookiecutter.json, but the fetched clone is gone anyway.
+        let fetched = FETCHED.lock().unwrap().pop().ok_or("the source was fetched")?;
+        assert!(!fetched.exists());
+        Ok(())
+    }
+
+    // Test the Markdown description of a scaffold printed by `describe`.
+    #[test]
+    fn test_scaffold_description() -> Result<(), Box<dyn std::error::Error>> {
+        let config: Config = toml::from_str(
+            r#"
+[variables]
+team = "platform"
+
+[[scaffolds]]
+name = "Service"
+repo = "https://github.com/org/service-templates.git"
+version = "^1.2"
+required_variables = ["owner"]
+secret_variables = ["token"]
+
+[scaffolds.variables]
+replicas = 2
+token = "hunter2"
+
+[scaffolds.descriptions]
+replicas = "Pods of the deployment"
+team = "Team on call"
+
+[[scaffolds.prompts]]
+name = "database"
+message = "Database engine"
+default = "postgres"
+when = "replicas > 1"
+
+[scaffolds.template]
+files = [
+    { src = "deploy.yaml.tera", dest = "deploy/{{ env }}.yaml", for_each = "environments" },
+    { dir = "logs" },
+]
+
+[scaffolds.hooks]
+post = { rhai = "hooks/setup.rhai" }
+run_when = "on_changes"
+
+[scaffolds.requires]
+binaries = ["kubectl >= 1.27"]
+env = ["KUBECONFIG"]
+"#,
+        )?;
+        let description = scaffold_description(&config.scaffolds[0], config.variables.as_ref());
+        assert_eq!(
+            description,
+            r#"# Service
+
+Repository: `https://github.com/org/service-templates.git`
+Version: `^1.2`
+
+## Variables
+
+| Variable | Default | Asked | Description |
+| --- | --- | --- | --- |
+| `database` | `postgres` | when `replicas > 1` | Database engine |
+| `owner` | required |  |  |
+| `replicas` | `2` |  | Pods of the deployment |
+| `team` | `platform` |  | Team on call |
+| `token` | <secret> |  |  |
+
+## Files
+
+- `deploy/{{ env }}.yaml` from `deploy.yaml.tera`, once per item of `environments`
+- `logs` (directory)
+
+## Hooks
+
+- post: `hooks/setup.rhai` (Rhai), only when files changed
+
+## Requirements
+
+- program `kubectl >= 1.27`
+- environment variable `KUBECONFIG`
+"#
+        );
+        Ok(())
+    }
+
+    // Test that simulate renders every referenced variable with fake values of the declared type.
+    #[test]
+    fn test_simulate_with_fake_values() -> Result<(), Box<dyn std::error::Error>> {
+        let repo_dir = TempDir::new()?;
+        let templates = repo_dir.path().join("templates");
+        fs::create_dir_all(&templates)?;
+        fs::write(
+            templates.join("app.toml.tera"),
+            "name = \"{{ project_name }}\"\nreplicas = {{ replicas }}\ndebug = {{ debug }}\nowner = \"{{ owner_email }}\"\n",
+        )?;
+        let config: Config = toml::from_str(&format!(
+            r#"
+            [[scaffolds]]
+            name = "app"
+            repo = "{}"
+            [scaffolds.template]
+            files = [{{ src = "app.toml.tera", dest = "{{{{ project_name }}}}/app.toml" }}]
+            [scaffolds.variables]
+            replicas = 3
+            debug = false
+            "#,
+            repo_dir.path().display()
+        ))?;
+        let scaffold = &config.scaffolds[0];
+
+        let first = TempDir::new()?;
+        let (variables, rendered) = simulate(
+            &config,
+            scaffold,
+            &prepare_scaffold(scaffold, &GitOptions::default())?.0,
+            42,
+            first.path(),
+        )?;
+        assert!(rendered.errors.is_empty());
+        assert!(variables["replicas"].is_integer());
+        assert!(variables["debug"].is_bool());
+        assert!(variables["owner_email"]
+            .as_str()
+            .is_some_and(|email| email.ends_with("@example.com")));
+        let project_name = variables["project_name"].as_str().unwrap_or_default();
+        let content = fs::read_to_string(first.path().join(project_name).join("app.toml"))?;
+        assert!(content.contains(&format!("replicas = {}", variables["replicas"])));
+
+        // The same seed produces the same values.
+        let second = TempDir::new()?;
+        let (again, _) = simulate(
+            &config,
+            scaffold,
+            &prepare_scaffold(scaffold, &GitOptions::default())?.0,
+            42,
+            second.path(),
+        )?;
+        assert_eq!(variables, again);
+        Ok(())
+    }
+
+    // Test that new-template creates a template repository whose own fixture generates the expected output.
+    #[test]
+    fn test_new_template_dogfood() -> Result<(), Box<dyn std::error::Error>> {
+        let parent = TempDir::new()?;
+        let template_dir = parent.path().join("my-template");
+        new_template(&template_dir)?;
+        assert!(fs::read_to_string(template_dir.join("scaffold.toml"))?.contains("name = \"my-template\""));
+        // Refuses to overwrite an existing template.
+        assert!(new_template(&template_dir).is_err());
+
+        // The skeleton's own fixture passes the snapshot test.
+        test_template(&template_dir, false)?;
+
+        // Changing a template fails the test until the snapshot is blessed.
+        fs::write(template_dir.join("templates/hello.txt.tera"), "Hi {{ owner }}\n")?;
+        assert!(test_template(&template_dir, false).is_err());
+        test_template(&template_dir, true)?;
+        let expected = template_dir.join("tests/expected/default/Fixture/hello.txt");
+        assert_eq!(fs::read_to_string(expected)?, "Hi platform\n");
+        test_template(&template_dir, false)?;
+        Ok(())
+    }
+
+    // Test that a diff run writes nothing and diffs the rendered files against the output directory.
+    #[test]
+    fn test_diff_output() -> Result<(), Box<dyn std::error::Error>> {
+        let repo_dir = TempDir::new()?;
+        let templates = repo_dir.path().join("templates");
+        fs::create_dir_all(&templates)?;
+        fs::write(templates.join("README.md.tera"), "# {{ project_name }}\nfirst\nsecond\n")?;
+        fs::write(templates.join("same.txt"), "same\n")?;
+        fs::write(templates.join("new.txt"), "new\n")?;
+        let output_dir = TempDir::new()?;
+        fs::write(output_dir.path().join("README.md"), "# Demo\nfirst\nthird\n")?;
+        fs::write(output_dir.path().join("same.txt"), "same\n")?;
+
+        let repo = repo_dir.path().to_string_lossy().replace('\\', "/");
+        let config = from_toml::<Config>(&format!(
+            r#"
+[[scaffolds]]
+name = "Docs"
+repo = "{repo}"
+template.files = [
+  {{ src = "README.md.tera", dest = "README.md" }},
+  {{ src = "same.txt", dest = "same.txt" }},
+  {{ src = "new.txt", dest = "docs/new.txt" }},
+]
+hooks = {{ post = "missing-hook.sh" }}
+"#
+        ))?;
+        let output = output_dir.path().to_string_lossy().into_owned();
+        let args = Args::try_parse_from(["scaficionado", "-o", &output, "-p", "Demo", "-w"])?;
+        begin_preview();
+        let result = generate_config(&args, config);
+        let preview = finish_preview();
+        result?;
+        assert!(!output_dir.path().join("docs").exists());
+        assert!(!output_dir.path().join(ANSWERS_FILE).exists());
+
+        let mut out = Vec::new();
+        assert_eq!(write_diff(&preview, false, &mut out)?, 2);
+        let readme = output_dir.path().join("README.md").display().to_string();
+        let new = output_dir.path().join("docs/new.txt").display().to_string();
+        assert_eq!(
+            String::from_utf8(out)?,
+            format!(
+                "--- a/{readme}\n+++ b/{readme}\n@@ -1,3 +1,3 @@\n # Demo\n first\n-third\n+second\n\
+                 --- /dev/null\n+++ b/{new}\n@@ -0,0 +1 @@\n+new\n"
+            )
+        );
+        Ok(())
+    }
+
+    // Test that generated scaffolds are locked to their commit and that outdated compares them with upstream
+    #[test]
+    fn test_outdated() -> Result<(), Box<dyn std::error::Error>> {
+        let upstream = TempDir::new()?;
+        git(upstream.path(), &["init", "-q"])?;
+        let commit = |message: &str| -> Result<String, Box<dyn std::error::Error>> {
+            fs::write(upstream.path().join("file.txt"), message)?;
+            git(upstream.path(), &["add", "file.txt"])?;
+            git(upstream.path(), &["commit", "-q", "-m", message])?;
+            git(upstream.path(), &["rev-parse", "HEAD"])
+        };
+        let first = commit("first")?;
+        git(upstream.path(), &["tag", "v1.0.0"])?;
+        let url = format!("file://{}", upstream.path().display());
+
+        let clone = TempDir::new()?;
+        git(clone.path(), &["clone", "-q", &url, "."])?;
+        let scaffold = Scaffold {
+            name: Some("svc".to_string()),
+            repo: url.clone().into(),
+            ..Default::default()
+        };
+        let locked = locked_sources(&[(scaffold, clone.path().to_path_buf())]);
+        let expected = LockedSource {
+            repo: url.clone(),
+            commit: first.clone(),
+            tag: Some("v1.0.0".to_string()),
+        };
+        assert_eq!(locked.get("svc"), Some(&expected));
+
+        // Recorded in the answers file.
+        let answers = Answers {
+            sources: locked.clone(),
+            ..Default::default()
+        };
+        let reloaded: Answers = toml::from_str(&toml::to_string(&answers)?)?;
+        assert_eq!(reloaded.sources, locked);
+
+        assert_eq!(available_update(&expected, &remote_refs(&url)?), None);
+        let second = commit("second")?;
+        git(upstream.path(), &["tag", "v1.1.0"])?;
+        git(upstream.path(), &["tag", "-a", "-m", "Release", "v1.2.0"])?;
+        git(upstream.path(), &["tag", "not-a-version"])?;
+        let refs = remote_refs(&url)?;
+        assert_eq!(
+            available_update(&expected, &refs).as_deref(),
+            Some("v1.0.0 → v1.2.0 (2 newer tags)")
+        );
+
+        // Without a release tag, the remote HEAD is compared with the locked commit.
+        let untagged = LockedSource { tag: None, ..expected };
+        let delta = format!("{:.7} → {:.7}", first, second);
+        assert_eq!(available_update(&untagged, &refs), Some(delta));
+        let current = LockedSource {
+            commit: second,
+            ..untagged
+        };
+        assert_eq!(available_update(&current, &refs), None);
+        Ok(())
+    }
+}
@@ -0,0 +1,543 @@
+//! Cookiecutter and cargo-generate templates, rendered with Tera.
+
+use heck::{ToKebabCase, ToSnakeCase};
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+use tera::Context;
+
+use super::*;
+
+/// Load `cookiecutter.json` from the root of a cookiecutter template, keeping the order of its variables.
+pub(crate) fn load_cookiecutter_json(template_root: &Path) -> Result<serde_json::Map<String, serde_json::Value>, Box<dyn Error>> {
+    let path = template_root.join("cookiecutter.json");
+    let content = fs::read_to_string(&path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+    match serde_json::from_str(&content)? {
+        serde_json::Value::Object(variables) => Ok(variables),
+        _ => Err(format!("{:?} must contain a JSON object", path).into()),
+    }
+}
+
+/// Resolve the variables of a cookiecutter template in the order of `cookiecutter.json`.  Replayed answers and
+/// configured variables are used as-is; every other variable is asked as a prompt whose default is rendered
+/// against the answers so far, so that e.g. `project_slug` can be derived from `project_name`.
+/// Lists are choices whose first entry is the default.  Private (`_`) and dictionary variables are never asked.
+pub(crate) fn resolve_cookiecutter_variables(
+    scaffold: &Scaffold,
+    template_root: &Path,
+    project_name: &str,
+    global_variables: Option<&HashMap<String, toml::Value>>,
+    mut answers: BTreeMap<String, toml::Value>,
+    input: &mut impl AnswerInput,
+) -> Result<BTreeMap<String, toml::Value>, Box<dyn Error>> {
+    let mut context = build_context(project_name, global_variables, scaffold.variables.as_ref());
+    let mut cookiecutter = serde_json::Map::new();
+    for (name, value) in load_cookiecutter_json(template_root)? {
+        if name.starts_with('_') || value.is_object() || value.is_null() {
+            cookiecutter.insert(name, value);
+            continue;
+        }
+        let resolved = match (answers.get(&name), context.get(&name)) {
+            (Some(answer), _) => answer.clone(),
+            (None, Some(configured)) => toml::Value::try_from(configured)?,
+            (None, None) => {
+                context.insert("cookiecutter", &cookiecutter);
+                ask_prompt(&cookiecutter_prompt(&name, &value, &context)?, input)?
+            }
+        };
+        cookiecutter.insert(name.clone(), serde_json::to_value(&resolved)?);
+        answers.insert(name, resolved);
+    }
+    Ok(answers)
+}
+
+/// Build the prompt for a cookiecutter variable.
+fn cookiecutter_prompt(name: &str, value: &serde_json::Value, context: &Context) -> Result<Prompt, Box<dyn Error>> {
+    let render_default = |value: &serde_json::Value| -> Result<toml::Value, Box<dyn Error>> {
+        Ok(match value {
+            serde_json::Value::String(template) => {
+                let location = format!("cookiecutter.json `{}`", name);
+                toml::Value::String(render_string(
+                    &translate_jinja(template),
+                    &location,
+                    context,
+                    &RenderOptions::default(),
+                )?)
+            }
+            other => toml::Value::try_from(other)?,
+        })
+    };
+    let (default, choices) = match value {
+        serde_json::Value::Array(items) => {
+            let choices = items.iter().map(render_default).collect::<Result<Vec<_>, _>>()?;
+            let first = choices
+                .first()
+                .cloned()
+                .ok_or_else(|| format!("cookiecutter variable '{}' has no choices", name))?;
+            (first, Some(choices))
+        }
+        other => (render_default(other)?, None),
+    };
+    Ok(Prompt {
+        name: name.to_string(),
+        message: None,
+        default: Some(default),
+        kind: None,
+        choices,
+        when: None,
+        group: None,
+        secret: false,
+    })
+}
+
+/// Translate the Jinja method calls and filter arguments cookiecutter templates commonly use into Tera syntax,
+/// e.g. `{{ cookiecutter.name.lower().replace(' ', '_') }}` becomes `{{ cookiecutter.name | lower | replace(from=' ', to='_') }}`.
+/// Only the text inside `{{ }}` and `{% %}` tags is changed.
+fn translate_jinja(source: &str) -> String {
+    static PATTERNS: OnceLock<[Regex; 4]> = OnceLock::new();
+    let [tags, methods, replace, default] = PATTERNS.get_or_init(|| {
+        let quoted = r#"('[^']*'|"[^"]*")"#;
+        [
+            Regex::new(r"(?s)\{\{.*?\}\}|\{%.*?%\}").unwrap(),
+            Regex::new(r"\.(lower|upper|title|capitalize|strip)\(\)").unwrap(),
+            Regex::new(&format!(r"\.replace\(\s*{}\s*,\s*{}\s*\)", quoted, quoted)).unwrap(),
+            Regex::new(&format!(r"\|\s*default\(\s*({}|\w+)\s*\)", quoted)).unwrap(),
+        ]
+    });
+    tags.replace_all(source, |tag: &regex::Captures| {
+        let tag = methods.replace_all(&tag[0], |method: &regex::Captures| match &method[1] {
+            "strip" => " | trim".to_string(),
+            filter => format!(" | {}", filter),
+        });
+        let tag = replace.replace_all(&tag, " | replace(from=$1, to=$2)");
+        default.replace_all(&tag, "| default(value=$1)").into_owned()
+    })
+    .into_owned()
+}
+
+/// Render a cookiecutter template: the `{{cookiecutter.*}}` directory at the template root is rendered into the
+/// output directory.  Every file is a template and file and directory names are rendered too, except for paths
+/// matching `_copy_without_render`, which are copied as-is.  Cookiecutter's own `hooks` are not run.
+pub(crate) fn render_cookiecutter(
+    template_root: &Path,
+    output_base: &Path,
+    context: &Context,
+    answers: &BTreeMap<String, toml::Value>,
+    options: &RenderOptions,
+) -> Result<RenderResult, Box<dyn Error>> {
+    let variables = load_cookiecutter_json(template_root)?;
+    let mut cookiecutter = variables.clone();
+    for (name, value) in answers {
+        cookiecutter.insert(name.clone(), serde_json::to_value(value)?);
+    }
+    let mut context = context.clone();
+    context.insert("cookiecutter", &cookiecutter);
+
+    let copy_without_render = variables
+        .get("_copy_without_render")
+        .and_then(serde_json::Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(serde_json::Value::as_str)
+        .map(glob::Pattern::new)
+        .collect::<Result<Vec<_>, _>>()?;
+    if template_root.join("hooks").is_dir() {
+        say!(
+            "Ignoring cookiecutter hooks in {:?}; configure [scaffolds.hooks] to run scripts",
+            template_root.join("hooks")
+        );
+    }
+
+    let project_dir = fs::read_dir(template_root)?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .find(|path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            path.is_dir() && name.contains("{{") && name.contains("cookiecutter")
+        })
+        .ok_or_else(|| format!("No {{{{cookiecutter.*}}}} directory found in {:?}", template_root))?;
+
+    let mut tree = TemplateTree::new(template_root, translate_jinja);
+    tree.verbatim = copy_without_render;
+    let mut result = RenderResult::default();
+    render_tree_path(&tree, &project_dir, output_base, &context, options, &mut result)?;
+    Ok(result)
+}
+
+/// The parts of a cargo-generate template's `cargo-generate.toml` that scaficionado understands.
+#[derive(Deserialize, Default)]
+pub(crate) struct CargoGenerateConfig {
+    #[serde(default)]
+    pub(crate) template: CargoGenerateTemplate,
+    /// Placeholders in the order they are asked, e.g. `gh_username = { prompt = "GitHub user?", type = "string" }`.
+    #[serde(default)]
+    pub(crate) placeholders: toml::Table,
+    /// Rhai hooks, which are not supported.
+    pub(crate) hooks: Option<toml::Value>,
+}
+
+#[derive(Deserialize, Default)]
+pub(crate) struct CargoGenerateTemplate {
+    /// Paths that are not copied.
+    #[serde(default)]
+    pub(crate) ignore: Vec<String>,
+    /// If set, only these paths are rendered.
+    #[serde(default)]
+    pub(crate) include: Vec<String>,
+    /// Paths copied without rendering.
+    #[serde(default)]
+    pub(crate) exclude: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct Placeholder {
+    prompt: Option<String>,
+    default: Option<toml::Value>,
+    choices: Option<Vec<toml::Value>>,
+    #[serde(rename = "type")]
+    kind: Option<String>,
+}
+
+/// Load `cargo-generate.toml` from the template root.  Templates without one use the defaults.
+pub(crate) fn load_cargo_generate_config(template_root: &Path) -> Result<CargoGenerateConfig, Box<dyn Error>> {
+    let path = template_root.join("cargo-generate.toml");
+    if !path.is_file() {
+        return Ok(CargoGenerateConfig::default());
+    }
+    let content = fs::read_to_string(&path)?;
+    toml::from_str(&content).map_err(|e| format!("Failed to parse {:?}: {}", path, e).into())
+}
+
+/// Map the placeholders of a cargo-generate template to prompts.  Hyphens in names become underscores,
+/// matching how `translate_liquid` rewrites them in templates.
+pub(crate) fn cargo_generate_prompts(config: &CargoGenerateConfig) -> Result<Vec<Prompt>, Box<dyn Error>> {
+    let mut prompts = Vec::new();
+    for (name, value) in &config.placeholders {
+        let placeholder: Placeholder = value
+            .clone()
+            .try_into()
+            .map_err(|e| format!("Invalid placeholder '{}': {}", name, e))?;
+        let kind = match placeholder.kind.as_deref() {
+            Some("bool") => Some(VariableType::Bool),
+            _ => None,
+        };
+        let default = match (placeholder.default, kind) {
+            (None, Some(VariableType::Bool)) => Some(toml::Value::Boolean(false)),
+            (default, _) => default,
+        };
+        prompts.push(Prompt {
+            name: name.replace('-', "_"),
+            message: placeholder.prompt,
+            default,
+            kind,
+            choices: placeholder.choices,
+            when: None,
+            group: None,
+            secret: false,
+        });
+    }
+    Ok(prompts)
+}
+
+/// Resolve the placeholders of a cargo-generate template (and any configured prompts), asking those that
+/// aren't configured or replayed.
+pub(crate) fn resolve_cargo_generate_placeholders(
+    scaffold: &Scaffold,
+    template_root: &Path,
+    project_name: &str,
+    global_variables: Option<&HashMap<String, toml::Value>>,
+    answers: BTreeMap<String, toml::Value>,
+    input: &mut impl AnswerInput,
+) -> Result<BTreeMap<String, toml::Value>, Box<dyn Error>> {
+    let placeholders = cargo_generate_prompts(&load_cargo_generate_config(template_root)?)?;
+    let context = build_context(project_name, global_variables, scaffold.variables.as_ref());
+    ask_missing_prompts(scaffold.prompts.iter().flatten().chain(&placeholders), &context, answers, input)
+}
+
+/// Translate the Liquid syntax of cargo-generate templates into Tera: hyphenated names such as `project-name`
+/// become `project_name`, `unless` and `assign` become `if not` and `set`, and Liquid filters such as
+/// `upcase` or `replace: "a", "b"` become their Tera equivalents.  Only the text inside tags is changed.
+pub(crate) fn translate_liquid(source: &str) -> String {
+    static PATTERNS: OnceLock<[Regex; 5]> = OnceLock::new();
+    let [tags, unless, keywords, filters, arguments] = PATTERNS.get_or_init(|| {
+        let argument = r#"('[^']*'|"[^"]*"|[\w.]+)"#;
+        [
+            Regex::new(r"(?s)\{\{.*?\}\}|\{%.*?%\}").unwrap(),
+            Regex::new(r"(?s)^\{%(-?)\s*unless\s+(.*?)\s*(-?)%\}$").unwrap(),
+            Regex::new(r"^\{%(-?)\s*(endunless|assign)\b").unwrap(),
+            Regex::new(r"\|\s*(upcase|downcase)\b").unwrap(),
+            Regex::new(&format!(r"\|\s*(replace|default)\s*:\s*{}(?:\s*,\s*{})?", argument, argument)).unwrap(),
+        ]
+    });
+    tags.replace_all(source, |tag: &regex::Captures| {
+        let tag = dehyphenate(&tag[0]);
+        let tag = unless.replace(&tag, "{%$1 if not ($2) $3%}");
+        let tag = keywords.replace(&tag, |keyword: &regex::Captures| match &keyword[2] {
+            "assign" => format!("{{%{} set", &keyword[1]),
+            _ => format!("{{%{} endif", &keyword[1]),
+        });
+        let tag = filters.replace_all(&tag, |filter: &regex::Captures| match &filter[1] {
+            "upcase" => "| upper",
+            _ => "| lower",
+        });
+        arguments
+            .replace_all(&tag, |filter: &regex::Captures| match (&filter[1], filter.get(3)) {
+                ("replace", Some(to)) => format!("| replace(from={}, to={})", &filter[2], to.as_str()),
+                _ => format!("| default(value={})", &filter[2]),
+            })
+            .into_owned()
+    })
+    .into_owned()
+}
+
+/// Translate Handlebars templates into Tera: `#if`, `#unless` and `#each` blocks become `if`, `if not` and `for`
+/// (the current item stays `this`), `{{else}}` becomes `{% else %}`, `{{{raw}}}` becomes `{{ raw }}`, `../`
+/// parent paths are dropped, `@index`, `@first` and `@last` become `loop` variables and comments become Tera
+/// comments.  Other block helpers are left as they are and fail to parse.
+pub(crate) fn translate_handlebars(source: &str) -> String {
+    static PATTERNS: OnceLock<[Regex; 4]> = OnceLock::new();
+    let [tags, blocks, otherwise, data] = PATTERNS.get_or_init(|| {
+        [
+            Regex::new(r"(?s)\{\{\{(.*?)\}\}\}|\{\{!--(.*?)--\}\}|\{\{(.*?)\}\}").unwrap(),
+            Regex::new(r"(?s)^(~?)\s*([#/])\s*(\w+)\s*(.*?)\s*(~?)$").unwrap(),
+            Regex::new(r"^(~?)\s*else\s*(~?)$").unwrap(),
+            Regex::new(r"@(index|first|last)\b").unwrap(),
+        ]
+    });
+    let trim = |marker: &str| if marker == "~" { "-" } else { "" };
+    tags.replace_all(source, |tag: &regex::Captures| {
+        if let Some(raw) = tag.get(1) {
+            return format!("{{{{ {} }}}}", raw.as_str().trim());
+        }
+        if let Some(comment) = tag.get(2).or_else(|| tag.get(3).filter(|inner| inner.as_str().starts_with('!'))) {
+            return format!("{{#{}#}}", comment.as_str().trim_start_matches('!'));
+        }
+        let inner = data.replace_all(&tag[3], |name: &regex::Captures| match &name[1] {
+            "index" => "loop.index0",
+            "first" => "loop.first",
+            _ => "loop.last",
+        });
+        let inner = inner.replace("../", "");
+        if let Some(markers) = otherwise.captures(&inner) {
+            return format!("{{%{} else {}%}}", trim(&markers[1]), trim(&markers[2]));
+        }
+        let Some(block) = blocks.captures(&inner) else {
+            return format!("{{{{{}}}}}", inner);
+        };
+        let statement = match (&block[2], &block[3]) {
+            ("#", "if") => format!("if {}", &block[4]),
+            ("#", "unless") => format!("if not ({})", &block[4]),
+            ("#", "each") => format!("for this in {}", &block[4]),
+            ("/", "if" | "unless") => "endif".to_string(),
+            ("/", "each") => "endfor".to_string(),
+            _ => return tag[0].to_string(),
+        };
+        format!("{{%{} {} {}%}}", trim(&block[1]), statement, trim(&block[5]))
+    })
+    .into_owned()
+}
+
+/// Replace hyphens that join two words outside of string literals, e.g. `project-name` becomes `project_name`.
+fn dehyphenate(tag: &str) -> String {
+    let chars: Vec<char> = tag.chars().collect();
+    let mut quote = None;
+    let mut result = String::with_capacity(tag.len());
+    for (i, &c) in chars.iter().enumerate() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            None if c == '"' || c == '\'' => quote = Some(c),
+            _ => {}
+        }
+        let joins_words = i > 0
+            && (chars[i - 1].is_alphanumeric() || chars[i - 1] == '_')
+            && chars.get(i + 1).is_some_and(|next| next.is_alphabetic() || *next == '_');
+        result.push(if c == '-' && quote.is_none() && joins_words { '_' } else { c });
+    }
+    result
+}
+
+/// Render a cargo-generate template into the output directory.  Every file is a template (a `.liquid` suffix
+/// is removed), and the built-in placeholders `project-name`, `crate_name`, `crate_type`, `authors`,
+/// `username` and `os-arch` are available.  `cargo-generate.toml` and `.git` are never copied.
+pub(crate) fn render_cargo_generate(
+    template_root: &Path,
+    output_base: &Path,
+    context: &Context,
+    options: &RenderOptions,
+) -> Result<RenderResult, Box<dyn Error>> {
+    let config = load_cargo_generate_config(template_root)?;
+    if config.hooks.is_some() {
+        say!("Ignoring cargo-generate hooks; configure [scaffolds.hooks] to run scripts");
+    }
+
+    // cargo-generate kebab-cases the project name and derives the crate name from it.
+    let project_name = context
+        .get("project_name")
+        .and_then(tera::Value::as_str)
+        .unwrap_or_default()
+        .to_kebab_case();
+    let (username, authors) = git_author();
+    let mut context = context.clone();
+    context.insert("crate_name", &project_name.to_snake_case());
+    context.insert("project_name", &project_name);
+    context.insert("crate_type", "bin");
+    context.insert("authors", &authors);
+    context.insert("username", &username);
+    context.insert("os_arch", &format!("{}-{}", std::env::consts::OS, std::env::consts::ARCH));
+    context.insert("is_init", &false);
+    context.insert("within_cargo_project", &false);
+
+    let patterns = |paths: &[String]| paths.iter().map(|path| glob::Pattern::new(path)).collect::<Result<Vec<_>, _>>();
+    let mut tree = TemplateTree::new(template_root, translate_liquid);
+    tree.ignored = patterns(&config.template.ignore)?;
+    tree.ignored
+        .extend(patterns(&[".git".to_string(), "cargo-generate.toml".to_string()])?);
+    tree.verbatim = patterns(&config.template.exclude)?;
+    tree.rendered_only = patterns(&config.template.include)?;
+    tree.strip_suffix = Some(".liquid");
+
+    let mut entries = fs::read_dir(template_root)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort();
+    let mut result = RenderResult::default();
+    for entry in entries {
+        render_tree_path(&tree, &entry, output_base, &context, options, &mut result)?;
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::fs;
+    use std::io;
+    use tempfile::TempDir;
+
+    // Test that a cookiecutter template is prompted from cookiecutter.json and rendered with Tera.
+    #[test]
+    fn test_cookiecutter_template() -> Result<(), Box<dyn std::error::Error>> {
+        let repo_dir = TempDir::new()?;
+        fs::write(
+            repo_dir.path().join("cookiecutter.json"),
+            r#"{
+                "project_name": "My Project",
+                "project_slug": "{{ cookiecutter.project_name.lower().replace(' ', '_') }}",
+                "license": ["MIT", "BSD"],
+                "_copy_without_render": ["*/static/*"]
+            }"#,
+        )?;
+        let project = repo_dir.path().join("{{cookiecutter.project_slug}}");
+        fs::create_dir_all(project.join("static"))?;
+        fs::write(
+            project.join("README.md"),
+            "# {{ cookiecutter.project_name }} ({{ cookiecutter.license }})\nname.lower() stays\n",
+        )?;
+        fs::write(
+            project.join("{{cookiecutter.project_slug}}.py"),
+            "NAME = '{{ cookiecutter.project_slug.upper() }}'",
+        )?;
+        fs::write(project.join("static").join("app.js"), "const x = `{{ raw }}`;")?;
+
+        let scaffold = Scaffold {
+            name: Some("Cookie".to_string()),
+            repo: repo_dir.path().to_string_lossy().to_string().into(),
+            format: TemplateFormat::Cookiecutter,
+            ..Default::default()
+        };
+        let (repo_base, _) = prepare_scaffold(&scaffold, &GitOptions::default())?;
+
+        // project_name comes from the project, the slug default is derived from it, and an answer outside the
+        // choices is asked again.
+        let mut input = io::Cursor::new("\nGPL\nBSD\n");
+        let templates_dir = get_templates_dir(&repo_base, &scaffold);
+        let answers = resolve_cookiecutter_variables(&scaffold, &templates_dir, "Demo App", None, BTreeMap::new(), &mut input)?;
+        assert_eq!(answers["project_slug"], toml::Value::String("demo_app".into()));
+        assert_eq!(answers["license"], toml::Value::String("BSD".into()));
+        assert!(!answers.contains_key("_copy_without_render"));
+
+        let output_dir = TempDir::new()?;
+        process_scaffold(
+            &scaffold,
+            &repo_base,
+            "Demo App",
+            None,
+            &answers,
+            output_dir.path(),
+            &overwrite_options(),
+        )?;
+        let generated = output_dir.path().join("demo_app");
+        assert_eq!(
+            fs::read_to_string(generated.join("README.md"))?,
+            "# Demo App (BSD)\nname.lower() stays\n"
+        );
+        assert_eq!(fs::read_to_string(generated.join("demo_app.py"))?, "NAME = 'DEMO_APP'");
+        assert_eq!(
+            fs::read_to_string(generated.join("static").join("app.js"))?,
+            "const x = `{{ raw }}`;"
+        );
+        Ok(())
+    }
+
+    // Test that a cargo-generate template's placeholders become prompts and its Liquid syntax renders with Tera.
+    #[test]
+    fn test_cargo_generate_template() -> Result<(), Box<dyn std::error::Error>> {
+        let repo_dir = TempDir::new()?;
+        let root = repo_dir.path();
+        fs::write(
+            root.join("cargo-generate.toml"),
+            r#"
+            [template]
+            ignore = ["notes.txt"]
+            exclude = ["static/*"]
+
+            [placeholders]
+            gh-user = { prompt = "GitHub user?", type = "string", default = "octocat" }
+            use_serde = { prompt = "Use serde?", type = "bool" }
+            "#,
+        )?;
+        fs::write(root.join("Cargo.toml"), "[package]\nname = \"{{project-name}}\"\n")?;
+        fs::create_dir_all(root.join("src"))?;
+        fs::write(
+            root.join("src").join("main.rs.liquid"),
+            "// {{ crate_name }} by {{ gh-user | upcase }}\n{% unless use_serde %}// no serde{% endunless %}\nstruct {{ project-name | pascal_case }};\nlet x = a - b;\n",
+        )?;
+        fs::create_dir_all(root.join("static"))?;
+        fs::write(root.join("static").join("page.html"), "{{ untouched }}")?;
+        fs::write(root.join("notes.txt"), "not copied")?;
+        fs::create_dir_all(root.join(".git"))?;
+        fs::write(root.join(".git").join("HEAD"), "ref: refs/heads/main")?;
+
+        let scaffold = Scaffold {
+            name: Some("Crate".to_string()),
+            repo: root.to_string_lossy().to_string().into(),
+            format: TemplateFormat::CargoGenerate,
+            ..Default::default()
+        };
+        let (repo_base, _) = prepare_scaffold(&scaffold, &GitOptions::default())?;
+        let templates_dir = get_templates_dir(&repo_base, &scaffold);
+        let mut input = io::Cursor::new("\nno\n");
+        let answers = resolve_cargo_generate_placeholders(&scaffold, &templates_dir, "My Tool", None, BTreeMap::new(), &mut input)?;
+        assert_eq!(answers["gh_user"], toml::Value::String("octocat".into()));
+        assert_eq!(answers["use_serde"], toml::Value::Boolean(false));
+
+        let output_dir = TempDir::new()?;
+        let out = output_dir.path();
+        process_scaffold(&scaffold, &repo_base, "My Tool", None, &answers, out, &overwrite_options())?;
+        assert_eq!(fs::read_to_string(out.join("Cargo.toml"))?, "[package]\nname = \"my-tool\"\n");
+        assert_eq!(
+            fs::read_to_string(out.join("src").join("main.rs"))?,
+            "// my_tool by OCTOCAT\n// no serde\nstruct MyTool;\nlet x = a - b;\n"
+        );
+        assert_eq!(fs::read_to_string(out.join("static").join("page.html"))?, "{{ untouched }}");
+        assert!(!out.join("notes.txt").exists());
+        assert!(!out.join(".git").exists());
+        assert!(!out.join("cargo-generate.toml").exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,1785 @@
+//! `scaffolding.toml` and the other configuration files: their structure, loading, includes, profiles, extended
+//! scaffolds, repository manifests and the user configuration.
+
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use super::*;
+
+/// Manifest a template repository can ship to describe its scaffold.
+pub(crate) const REPO_MANIFEST_FILE: &str = "scaffold.toml";
+
+/// A single entry of a scaffold's template file list.  An entry either maps a source file or
+/// directory (`src`) to a destination (`dest`), or creates an empty directory (`dir`).
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Default, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct TemplateFile {
+    #[serde(default)]
+    pub(crate) src: String,
+    #[serde(default)]
+    pub(crate) dest: String,
+    /// An empty directory to create in the output.  Supports template variables.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) dir: Option<String>,
+    /// Name of a list variable: the entry is rendered once per item, which templates read as `item`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) for_each: Option<String>,
+    /// Template engine of a file entry, instead of rendering only `.tera` files with Tera.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) engine: Option<TemplateEngine>,
+    /// Clean-ups applied in order to the rendered output before it is written.  Files that are copied as-is are
+    /// left alone.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) postprocess: Vec<PostProcess>,
+    /// Line endings of the rendered output, instead of those of `[project]`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) line_endings: Option<LineEndings>,
+}
+
+impl TemplateFile {
+    /// The engine that renders this entry's file: its `engine`, otherwise Tera for `.tera` files.
+    pub(crate) fn engine(&self) -> TemplateEngine {
+        match self.engine {
+            Some(engine) => engine,
+            None if self.src.ends_with(".tera") => TemplateEngine::Tera,
+            None => TemplateEngine::None,
+        }
+    }
+}
+
+/// The template language of a single template file.  Liquid and Handlebars templates are translated to Tera.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum TemplateEngine {
+    Tera,
+    Liquid,
+    Handlebars,
+    /// Copy the file as-is, even with a `.tera` extension.
+    #[serde(alias = "raw")]
+    None,
+}
+
+impl TemplateEngine {
+    /// Translate a template of this engine into Tera.
+    pub(crate) fn translate(self, source: &str) -> String {
+        match self {
+            TemplateEngine::Liquid => translate_liquid(source),
+            TemplateEngine::Handlebars => translate_handlebars(source),
+            TemplateEngine::Tera | TemplateEngine::None => source.to_string(),
+        }
+    }
+}
+
+/// A built-in clean-up of rendered output, for the whitespace that template tags commonly leave behind.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum PostProcess {
+    /// Remove whitespace at the end of every line.
+    StripTrailingWhitespace,
+    /// End a non-empty file with exactly one newline.
+    EnsureFinalNewline,
+    /// Sort the lines, e.g. of a generated list of dependencies.  Trailing blank lines are dropped.
+    SortLines,
+}
+
+impl PostProcess {
+    pub(crate) fn apply(self, content: &str) -> String {
+        let final_newline = if content.ends_with('\n') { "\n" } else { "" };
+        match self {
+            PostProcess::StripTrailingWhitespace => content.lines().map(str::trim_end).collect::<Vec<_>>().join("\n") + final_newline,
+            PostProcess::EnsureFinalNewline if content.is_empty() => String::new(),
+            PostProcess::EnsureFinalNewline => content.trim_end_matches(['\r', '\n']).to_string() + "\n",
+            PostProcess::SortLines => {
+                // Trailing blank lines are dropped instead of being sorted to the top.
+                let mut lines: Vec<&str> = content.trim_end_matches(['\r', '\n']).lines().collect();
+                lines.sort_unstable();
+                lines.join("\n") + final_newline
+            }
+        }
+    }
+}
+
+/// Apply the post-processing steps of a template file to its rendered output, in order.
+pub(crate) fn postprocess(steps: &[PostProcess], content: String) -> String {
+    steps.iter().fold(content, |content, step| step.apply(&content))
+}
+
+/// The line endings rendered output is written with, whichever ones the template was authored with.  Files that are
+/// copied as-is keep theirs.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum LineEndings {
+    Lf,
+    Crlf,
+    /// CRLF on Windows, LF elsewhere.
+    Native,
+}
+
+impl LineEndings {
+    pub(crate) fn apply(self, content: String) -> String {
+        let crlf = match self {
+            LineEndings::Lf => false,
+            LineEndings::Crlf => true,
+            LineEndings::Native => cfg!(windows),
+        };
+        let content = match content.contains("\r\n") {
+            true => content.replace("\r\n", "\n"),
+            false => content,
+        };
+        match crlf {
+            true => content.replace('\n', "\r\n"),
+            false => content,
+        }
+    }
+}
+
+/// Finish rendered output: apply the postprocess steps, then the line endings, if any.
+pub(crate) fn finish_rendered(steps: &[PostProcess], line_endings: Option<LineEndings>, content: String) -> String {
+    let content = postprocess(steps, content);
+    match line_endings {
+        Some(line_endings) => line_endings.apply(content),
+        None => content,
+    }
+}
+
+#[derive(Deserialize, JsonSchema, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct TemplateConfig {
+    #[serde(default)]
+    pub(crate) files: Vec<TemplateFile>,
+    /// Glob patterns, relative to the templates directory, of files and directories left out when a listed
+    /// directory is copied, e.g. "**/*.md" or "docs/**".  Patterns in a `.scaffoldignore` file apply too.
+    #[serde(default)]
+    pub(crate) exclude: Vec<String>,
+    /// Glob patterns, relative to the templates directory, of files copied byte for byte, e.g. "assets/**" or
+    /// "*.png": they're never rendered, even with a `.tera` extension, unless their entry sets an `engine`.
+    #[serde(default)]
+    pub(crate) raw: Vec<String>,
+    /// Derive a missing `dest` from `src`: the same relative path without the `.tera` suffix.
+    #[serde(default)]
+    pub(crate) auto_dest: bool,
+    /// Alternate delimiters of the Tera templates, for templates of files that use `{{ }}` themselves.
+    pub(crate) delimiters: Option<Delimiters>,
+}
+
+impl TemplateConfig {
+    /// The destination template of a file entry: its `dest`, or with auto_dest its `src` without `.tera`.
+    pub(crate) fn dest(&self, file: &TemplateFile) -> String {
+        if file.dest.is_empty() && self.auto_dest {
+            return file.src.strip_suffix(".tera").unwrap_or(&file.src).to_string();
+        }
+        file.dest.clone()
+    }
+
+    /// Whether the file at `relative`, a path relative to the templates directory, is in the `raw` list.
+    pub(crate) fn is_raw(&self, relative: &str) -> bool {
+        self.raw
+            .iter()
+            .any(|pattern| glob::Pattern::new(pattern).is_ok_and(|pattern| pattern.matches(relative)))
+    }
+
+    /// The engine that renders a file entry: its `engine`, otherwise none for raw files and Tera for `.tera` files.
+    pub(crate) fn engine(&self, file: &TemplateFile) -> TemplateEngine {
+        match file.engine {
+            None if self.is_raw(&file.src) => TemplateEngine::None,
+            _ => file.engine(),
+        }
+    }
+
+    /// Translate a template of `engine` into Tera, applying the scaffold's delimiters to Tera templates.
+    pub(crate) fn translate(&self, engine: TemplateEngine, source: &str) -> String {
+        match (engine, &self.delimiters) {
+            (TemplateEngine::Tera, Some(delimiters)) => delimiters.translate(source),
+            (engine, _) => engine.translate(source),
+        }
+    }
+}
+
+/// Start and end markers replacing Tera's `{{ }}`, `{% %}` and `{# #}`, e.g. `variable = ["[[", "]]"]`.  Text
+/// outside the markers is copied literally, including Tera's own delimiters.
+#[derive(Deserialize, JsonSchema, Debug, Default, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Delimiters {
+    pub(crate) variable: Option<(String, String)>,
+    pub(crate) block: Option<(String, String)>,
+    pub(crate) comment: Option<(String, String)>,
+}
+
+impl Delimiters {
+    /// Rewrite a template with these delimiters into Tera syntax.
+    pub(crate) fn translate(&self, source: &str) -> String {
+        let kinds = [(&self.variable, "{{", "}}"), (&self.block, "{%", "%}"), (&self.comment, "{#", "#}")];
+        let markers: Vec<(&str, &str, &str, &str)> = kinds
+            .iter()
+            .map(|(custom, start, end)| match custom {
+                Some((custom_start, custom_end)) => (custom_start.as_str(), custom_end.as_str(), *start, *end),
+                None => (*start, *end, *start, *end),
+            })
+            .collect();
+        // Tera delimiters that were replaced are literal text and must not be parsed.
+        let replaced: Vec<&str> = markers
+            .iter()
+            .filter(|(custom_start, _, start, _)| custom_start != start)
+            .map(|(_, _, start, _)| *start)
+            .collect();
+        let literal = |text: &str, output: &mut String| {
+            if replaced.iter().any(|start| text.contains(start)) {
+                output.push_str(&format!("{{% raw %}}{}{{% endraw %}}", text));
+            } else {
+                output.push_str(text);
+            }
+        };
+
+        let mut output = String::new();
+        let mut rest = source;
+        loop {
+            let next = markers
+                .iter()
+                .filter(|(custom_start, ..)| !custom_start.is_empty())
+                .filter_map(|marker| rest.find(marker.0).map(|position| (position, marker)))
+                .min_by_key(|(position, _)| *position);
+            let Some((position, (custom_start, custom_end, start, end))) = next else {
+                literal(rest, &mut output);
+                return output;
+            };
+            let inner_start = position + custom_start.len();
+            let Some(length) = rest[inner_start..].find(custom_end) else {
+                literal(rest, &mut output);
+                return output;
+            };
+            literal(&rest[..position], &mut output);
+            output.push_str(start);
+            output.push_str(&rest[inner_start..inner_start + length]);
+            output.push_str(end);
+            rest = &rest[inner_start + length + custom_end.len()..];
+        }
+    }
+}
+
+/// Controls when a scaffold's hooks are executed.
+#[derive(Deserialize, JsonSchema, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum HookRunWhen {
+    /// Run the hooks every time the scaffold is processed.
+    #[default]
+    Always,
+    /// Only run the hooks when the scaffold created or changed at least one file.
+    OnChanges,
+}
+
+/// Hook scripts, relative to the template repository.
+#[derive(Deserialize, JsonSchema, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct HooksConfig {
+    /// Run before anything is rendered with the scaffold's plan as JSON on stdin.  It may print a modified plan,
+    /// or reject it by exiting non-zero.
+    pub(crate) plan: Option<String>,
+    pub(crate) pre: Option<Hook>,
+    pub(crate) post: Option<Hook>,
+    #[serde(default)]
+    pub(crate) run_when: HookRunWhen,
+    /// On Windows, sibling scripts to look for instead of a hook with the given extension,
+    /// e.g. `{ sh = ["ps1", "bat"] }`.  Defaults to trying ps1, bat and cmd for sh hooks.
+    pub(crate) windows_extensions: Option<HashMap<String, Vec<String>>>,
+    /// Command that runs the hook scripts, e.g. "bash" or "pwsh -NoProfile -File"; the script path is appended.
+    pub(crate) interpreter: Option<String>,
+    /// Environment variables of the hooks, added to those of `[project.hooks_defaults]`.
+    pub(crate) env: Option<BTreeMap<String, String>>,
+    /// Working directory of the hooks, relative to the output directory.
+    pub(crate) cwd: Option<String>,
+    /// Seconds after which a hook is stopped and fails.
+    pub(crate) timeout: Option<u64>,
+}
+
+/// A pre- or post-generation hook: the path of a script, `{ rhai = "hooks/setup.rhai" }` for a script run in the
+/// embedded Rhai engine, which needs no shell on the host, or `{ command = "..." }` for a command run as it is.
+#[derive(Deserialize, JsonSchema, Debug, Clone)]
+#[serde(untagged)]
+pub(crate) enum Hook {
+    Script(String),
+    Rhai(RhaiHook),
+    Command(CommandHook),
+}
+
+/// A command run as a hook, e.g. `{ command = "git config user.name", capture = "author_name" }`.  It is split on
+/// whitespace and run without a shell.
+#[derive(Deserialize, JsonSchema, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct CommandHook {
+    pub(crate) command: String,
+    /// Variable that receives what the command prints, trimmed.  A pre hook that captures runs before the plan hook
+    /// and the templates are rendered, so that they can use the variable.
+    pub(crate) capture: Option<String>,
+}
+
+#[derive(Deserialize, JsonSchema, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RhaiHook {
+    pub(crate) rhai: String,
+}
+
+impl Hook {
+    /// The script path, relative to the template repository, or the command.
+    pub(crate) fn path(&self) -> &str {
+        match self {
+            Hook::Script(path) | Hook::Rhai(RhaiHook { rhai: path }) => path,
+            Hook::Command(CommandHook { command, .. }) => command,
+        }
+    }
+
+    /// The variable a command hook captures its output into.
+    pub(crate) fn capture(&self) -> Option<&str> {
+        match self {
+            Hook::Command(CommandHook { capture, .. }) => capture.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+/// `[project.hooks_defaults]`: hook settings inherited by every scaffold, which can override them in its hooks.
+#[derive(Deserialize, JsonSchema, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct HooksDefaults {
+    pub(crate) interpreter: Option<String>,
+    pub(crate) env: Option<BTreeMap<String, String>>,
+    pub(crate) cwd: Option<String>,
+    pub(crate) timeout: Option<u64>,
+}
+
+/// `[project.git]`: initialize a git repository in the output directory once every scaffold succeeded, and stage
+/// the generated files.
+#[derive(Deserialize, JsonSchema, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct GitInitConfig {
+    /// Patterns added to the `.gitignore` of the output directory when it doesn't list them yet.
+    #[serde(default)]
+    pub(crate) gitignore: Vec<String>,
+    /// Make an initial commit of the staged files.
+    #[serde(default)]
+    pub(crate) commit: bool,
+    /// Message of the initial commit (default: "Initial commit").
+    pub(crate) message: Option<String>,
+    /// Name of the initial branch (default: git's init.defaultBranch).
+    pub(crate) branch: Option<String>,
+}
+
+/// `[project.format]`: formatters run over the files a run created or changed, once every scaffold succeeded, so
+/// that templates don't need whitespace contortions for tidy output.
+#[derive(Deserialize, JsonSchema, Debug, Default, Clone)]
+pub(crate) struct FormatConfig {
+    /// What a failing formatter does: "warn" (default) or "fail" the run.
+    #[serde(default)]
+    pub(crate) on_failure: FormatFailure,
+    /// Formatter commands by glob pattern of the files they format, relative to the output directory, e.g.
+    /// `"**/*.rs" = "rustfmt"`.  The matching files are appended to the command.
+    #[serde(flatten)]
+    pub(crate) formatters: BTreeMap<String, String>,
+}
+
+/// What a failing formatter does.  The files are kept either way.
+#[derive(Deserialize, JsonSchema, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum FormatFailure {
+    /// Print a warning and finish the run.
+    #[default]
+    Warn,
+    /// Fail the run.
+    Fail,
+}
+
+impl FormatConfig {
+    /// The formatters with their compiled glob patterns.
+    pub(crate) fn formatters(&self) -> Result<Vec<(glob::Pattern, &str)>, Box<dyn Error>> {
+        self.formatters
+            .iter()
+            .map(|(pattern, command)| match glob::Pattern::new(pattern) {
+                Ok(glob) => Ok((glob, command.as_str())),
+                Err(e) => Err(format!("Invalid [project.format] pattern `{}`: {}", pattern, e).into()),
+            })
+            .collect()
+    }
+}
+
+/// Fill in the hook settings a scaffold doesn't set from `[project.hooks_defaults]`.  Environment variables are
+/// merged, with the scaffold's taking precedence.
+pub(crate) fn apply_hooks_defaults(hooks: &mut HooksConfig, defaults: &HooksDefaults) {
+    hooks.interpreter = hooks.interpreter.take().or_else(|| defaults.interpreter.clone());
+    hooks.cwd = hooks.cwd.take().or_else(|| defaults.cwd.clone());
+    hooks.timeout = hooks.timeout.or(defaults.timeout);
+    if let Some(env) = &defaults.env {
+        let mut merged = env.clone();
+        merged.extend(hooks.env.take().unwrap_or_default());
+        hooks.env = Some(merged);
+    }
+}
+
+/// Represents a single scaffold configuration. Each scaffold specifies:
+/// - A name (optional)
+/// - A repository URL (local or remote)
+/// - An optional template directory (defaults to "templates" if not provided)
+/// - A set of template file definitions
+/// - Optional hooks to run before and after generation.
+/// - Optional prompts for variables that should be asked interactively.
+/// - An optional template format; cookiecutter and cargo-generate templates are configured by their own files.
+/// - An optional mode restricting the output directories the scaffold may be applied to.
+#[derive(Deserialize, JsonSchema, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Scaffold {
+    pub(crate) name: Option<String>,
+    /// Name of a scaffold whose settings this one inherits and overrides.
+    pub(crate) extends: Option<String>,
+    /// File entries of the extended scaffold to leave out, by their `src`, `dest` or `dir`.
+    #[serde(default)]
+    pub(crate) remove: Vec<String>,
+    /// The template repository, or mirrors of it tried in order until one can be obtained.
+    #[serde(default)]
+    pub(crate) repo: RepoUrls,
+    /// A semantic version requirement, e.g. "^1.2": the highest release tag of the repository matching it is checked
+    /// out instead of the default branch.
+    pub(crate) version: Option<String>,
+    /// Expected SHA-256 checksum (hex) of an archive repository.
+    pub(crate) sha256: Option<String>,
+    /// Require a valid GPG or SSH signature on the checked-out release tag or commit.
+    pub(crate) signature: Option<SignatureConfig>,
+    pub(crate) template_dir: Option<String>,
+    #[serde(default)]
+    pub(crate) format: TemplateFormat,
+    #[serde(default)]
+    pub(crate) mode: ScaffoldMode,
+    #[serde(default)]
+    pub(crate) template: TemplateConfig,
+    pub(crate) hooks: Option<HooksConfig>,
+    #[schemars(with = "Option<HashMap<String, serde_json::Value>>")]
+    pub(crate) variables: Option<HashMap<String, toml::Value>>,
+    /// What each variable is for, by variable name, shown by `describe`.
+    #[serde(default)]
+    pub(crate) descriptions: HashMap<String, String>,
+    /// Variables whose values are Tera templates rendered with the other variables, e.g.
+    /// `image = "{{ registry }}/{{ project_name | kebab_case }}"`.  They may use each other.
+    #[serde(default)]
+    pub(crate) computed: BTreeMap<String, String>,
+    /// Declared types of variables, by variable name.  Values of another type are converted, or rejected.
+    #[serde(default)]
+    pub(crate) types: BTreeMap<String, VariableType>,
+    pub(crate) prompts: Option<Vec<Prompt>>,
+    /// Variables that must be set and non-empty, checked before anything is cloned.
+    #[serde(default)]
+    pub(crate) required_variables: Vec<String>,
+    /// Variables holding secrets such as API tokens, left out of the answers file like the answers of secret prompts.
+    #[serde(default)]
+    pub(crate) secret_variables: Vec<String>,
+    /// Allow destinations outside the output directory, e.g. absolute paths or `..`.  Only settable in the
+    /// configuration, not by a repository manifest.
+    #[serde(default)]
+    pub(crate) allow_outside_output: bool,
+    /// Programs and environment variables the scaffold needs, checked before anything is cloned.
+    pub(crate) requires: Option<Requirements>,
+    /// Overrides `[project.retry]` for this scaffold's repository.
+    pub(crate) retry: Option<RetryConfig>,
+    /// Check out the submodules of the repository after cloning it, e.g. partials shared between template repositories.
+    #[serde(default)]
+    pub(crate) submodules: bool,
+}
+
+/// A scaffold's `repo`: a URL or local path, or a list of mirrors, e.g. an internal URL reachable over VPN followed by
+/// a public one.
+#[derive(Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub(crate) enum RepoUrls {
+    One(String),
+    Mirrors(Vec<String>),
+}
+
+impl RepoUrls {
+    /// Every URL, in the order they are tried.
+    pub(crate) fn urls(&self) -> &[String] {
+        match self {
+            RepoUrls::One(url) => std::slice::from_ref(url),
+            RepoUrls::Mirrors(urls) => urls,
+        }
+    }
+
+    /// Whether no URL is set.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.urls().iter().all(|url| url.trim().is_empty())
+    }
+
+    /// Replace every URL with `f(url)`.
+    pub(crate) fn map(&self, f: impl Fn(&str) -> String) -> RepoUrls {
+        match self {
+            RepoUrls::One(url) => RepoUrls::One(f(url)),
+            RepoUrls::Mirrors(urls) => RepoUrls::Mirrors(urls.iter().map(|url| f(url)).collect()),
+        }
+    }
+}
+
+impl Default for RepoUrls {
+    fn default() -> Self {
+        RepoUrls::One(String::new())
+    }
+}
+
+impl From<String> for RepoUrls {
+    fn from(url: String) -> Self {
+        RepoUrls::One(url)
+    }
+}
+
+impl From<&str> for RepoUrls {
+    fn from(url: &str) -> Self {
+        RepoUrls::One(url.to_string())
+    }
+}
+
+/// `[scaffolds.signature]`: the keys trusted to sign a template repository's releases.
+#[derive(Deserialize, JsonSchema, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct SignatureConfig {
+    /// File of the SSH keys trusted for SSH signatures, in the `allowed_signers` format of ssh-keygen.
+    pub(crate) allowed_signers: Option<String>,
+    /// GPG keyring with the keys trusted for GPG signatures, instead of the user's default keyring.
+    pub(crate) keyring: Option<String>,
+}
+
+/// `[project.retry]` and `[scaffolds.retry]`: how long an attempt to clone may take, and how often a clone that
+/// failed for a transient reason (including a timeout) is tried again.
+#[derive(Deserialize, JsonSchema, Debug, Default, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RetryConfig {
+    /// Total number of attempts, including the first (default 3; 1 disables retries).
+    pub(crate) attempts: Option<u32>,
+    /// Seconds to wait before the first retry, doubled for every further one (default 1).
+    pub(crate) backoff: Option<f64>,
+    /// Seconds an attempt may take in total (default 600).
+    pub(crate) timeout: Option<f64>,
+    /// Seconds an attempt may go without receiving anything from the server (default 60).
+    pub(crate) stall_timeout: Option<f64>,
+}
+
+impl RetryConfig {
+    /// These settings, with the unset ones taken from `fallback`.
+    pub(crate) fn or(self, fallback: RetryConfig) -> RetryConfig {
+        RetryConfig {
+            attempts: self.attempts.or(fallback.attempts),
+            backoff: self.backoff.or(fallback.backoff),
+            timeout: self.timeout.or(fallback.timeout),
+            stall_timeout: self.stall_timeout.or(fallback.stall_timeout),
+        }
+    }
+}
+
+/// `[scaffolds.requires]`: the tools and environment variables a scaffold's templates and hooks rely on.
+#[derive(Deserialize, JsonSchema, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Requirements {
+    /// Programs that must be on the PATH, optionally with a minimum version reported by `--version`,
+    /// e.g. "docker" or "kubectl >= 1.27".
+    #[serde(default)]
+    pub(crate) binaries: Vec<String>,
+    /// Environment variables that must be set.
+    #[serde(default)]
+    pub(crate) env: Vec<String>,
+}
+
+/// Which output directories a scaffold may be applied to.
+#[derive(Deserialize, JsonSchema, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ScaffoldMode {
+    /// Any output directory.
+    #[default]
+    Any,
+    /// Only a new project: the output directory must be missing or empty.
+    Create,
+    /// Only an existing project: the output directory must contain a project root marker such as `.git`.
+    Augment,
+}
+
+/// The layout of a scaffold's template repository.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum TemplateFormat {
+    /// Template files are listed in `[scaffolds.template]`.
+    #[default]
+    Native,
+    /// A cookiecutter template with a `cookiecutter.json` and a `{{cookiecutter.*}}` directory.
+    Cookiecutter,
+    /// A cargo-generate template, optionally with a `cargo-generate.toml`.
+    CargoGenerate,
+}
+
+// Add a new struct for top-level project configuration.
+#[derive(Deserialize, JsonSchema, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ProjectConfig {
+    pub(crate) name: Option<String>,
+    pub(crate) output: Option<String>,
+    pub(crate) overwrite: Option<bool>,
+    /// Fail rendering when a template references an undefined variable.
+    pub(crate) strict: Option<bool>,
+    /// Editor command used by --open, e.g. "code".
+    pub(crate) open_with: Option<String>,
+    /// Hook settings inherited by every scaffold.
+    pub(crate) hooks_defaults: Option<HooksDefaults>,
+    /// Initialize a git repository in the output directory after a successful run.
+    pub(crate) git: Option<GitInitConfig>,
+    /// Retries of failed clones, for every scaffold.
+    pub(crate) retry: Option<RetryConfig>,
+    /// Formatters run over the generated files after a successful run.
+    pub(crate) format: Option<FormatConfig>,
+    /// Line endings of rendered files: "lf", "crlf" or "native".  Template entries can override it.
+    pub(crate) line_endings: Option<LineEndings>,
+    /// How much a run may write before it fails, e.g. because of a runaway `for_each`.
+    pub(crate) limits: Option<LimitsConfig>,
+}
+
+/// `[project.limits]`: the largest file, number of files and total bytes a run writes, and the largest template it
+/// renders.  Disabled with --no-limits.
+#[derive(Deserialize, JsonSchema, Debug, Default, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct LimitsConfig {
+    /// Bytes of the largest file (default 100 MB).
+    pub(crate) max_file_size: Option<u64>,
+    /// Files rendered or copied per run (default 10000).
+    pub(crate) max_files: Option<u64>,
+    /// Bytes written per run (default 1 GB).
+    pub(crate) max_total_bytes: Option<u64>,
+    /// Bytes of the largest file rendered as a template, which is read into memory (default 10 MB).  Larger files
+    /// are only copied, a chunk at a time.
+    pub(crate) max_template_size: Option<u64>,
+}
+
+/// The scaffolding.toml configuration.
+#[derive(Deserialize, JsonSchema, Default)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Config {
+    /// Versions of scaficionado that can use this file, e.g. ">=0.5".  Checked by check_required_version before the
+    /// rest of the file is parsed; declared so that it isn't an unknown field.
+    #[allow(dead_code)]
+    pub(crate) requires_version: Option<String>,
+    pub(crate) project: Option<ProjectConfig>,
+    /// Variables shared by every scaffold.  Per-scaffold variables take precedence.
+    #[schemars(with = "Option<HashMap<String, serde_json::Value>>")]
+    pub(crate) variables: Option<HashMap<String, toml::Value>>,
+    #[serde(default)]
+    pub(crate) scaffolds: Vec<Scaffold>,
+    /// Configuration files to combine with this one, relative to it.  Their scaffolds come first, and this
+    /// file's project settings and variables take precedence over theirs.
+    #[serde(default)]
+    pub(crate) include: Vec<String>,
+    /// Named overrides of the project settings and variables, selected with --profile.
+    #[serde(default)]
+    pub(crate) profile: BTreeMap<String, Profile>,
+}
+
+/// A `[profile.<name>]` section, e.g. the settings and variables of one environment.
+#[derive(Deserialize, JsonSchema, Default)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Profile {
+    pub(crate) project: Option<ProjectConfig>,
+    #[schemars(with = "Option<HashMap<String, serde_json::Value>>")]
+    pub(crate) variables: Option<HashMap<String, toml::Value>>,
+}
+
+/// The JSON Schema of the configuration file.  Like the configuration itself, it rejects unknown keys so that editors
+/// flag misspelled settings.
+pub(crate) fn config_schema() -> Result<String, Box<dyn Error>> {
+    Ok(serde_json::to_string_pretty(&schemars::schema_for!(Config))?)
+}
+
+/// Load the configuration from a file path or HTTP(S) URL, with the files it includes and the scaffolds it extends
+/// resolved.  The file must match `expected_sha256` if it is given.
+pub(crate) fn load_config(location: &str, expected_sha256: Option<&str>) -> Result<Config, Box<dyn Error>> {
+    let mut config = load_config_file(location, expected_sha256, &mut Vec::new())?;
+    config.scaffolds = resolve_extends(&config.scaffolds)?;
+    Ok(config)
+}
+
+/// Load a configuration file combined with the files it includes.  Includes of a downloaded configuration are
+/// downloaded relative to its URL.  `including` holds the files being loaded further up, to report include cycles.
+pub(crate) fn load_config_file(
+    location: &str,
+    expected_sha256: Option<&str>,
+    including: &mut Vec<String>,
+) -> Result<Config, Box<dyn Error>> {
+    let config_str = match is_bundle(location) {
+        true => read_bundle_config(location, expected_sha256)?,
+        false => read_config_source(location, expected_sha256)?,
+    };
+    check_required_version(&config_str, location)?;
+    let mut config: Config = from_toml(&config_str).map_err(|e| format!("Failed to parse {:?}: {}", location, e))?;
+    let canonical = match is_url(location) {
+        true => split_checksum(location).0.to_string(),
+        false => canonical_path(location)?.to_string_lossy().into_owned(),
+    };
+    if is_bundle(location) {
+        for scaffold in &mut config.scaffolds {
+            scaffold.repo = scaffold.repo.map(|url| match url.strip_prefix(BUNDLE_SCHEME) {
+                Some(name) if !name.contains('#') => format!("{}{}#{}", BUNDLE_SCHEME, canonical, name),
+                _ => url.to_string(),
+            });
+        }
+    }
+    if including.contains(&canonical) {
+        let cycle: Vec<String> = including.iter().chain([&canonical]).map(|path| format!("{:?}", path)).collect();
+        return Err(format!("Configuration include cycle: {}", cycle.join(" -> ")).into());
+    }
+    including.push(canonical);
+
+    let mut composed = Config::default();
+    for include in &config.include {
+        let include_location = resolve_include(location, include);
+        say!("Including configuration from: {:?}", include_location);
+        merge_config(&mut composed, load_config_file(&include_location, None, including)?);
+    }
+    merge_config(&mut composed, config);
+    including.pop();
+    Ok(composed)
+}
+
+/// Check the `requires_version` of a configuration file against the running version.  It is read on its own first, so
+/// that a file using settings of a newer version asks for an upgrade instead of failing with an unknown field.
+fn check_required_version(config_str: &str, location: &str) -> Result<(), Box<dyn Error>> {
+    // Syntax errors are reported when the whole file is parsed.
+    let Ok(table) = config_str.parse::<toml::Table>() else {
+        return Ok(());
+    };
+    let Some(required) = table.get("requires_version") else {
+        return Ok(());
+    };
+    let required = required
+        .as_str()
+        .ok_or_else(|| format!("requires_version in {:?} must be a string, e.g. \">=0.5\"", location))?;
+    let requirement =
+        semver::VersionReq::parse(required).map_err(|e| format!("Invalid requires_version {:?} in {:?}: {}", required, location, e))?;
+    let version = semver::Version::parse(env!("CARGO_PKG_VERSION"))?;
+    if !requirement.matches(&version) {
+        return Err(format!(
+            "{:?} requires scaficionado {}, but this is version {}; please upgrade",
+            location, required, version
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Check whether a configuration location is an HTTP(S) URL.
+pub(crate) fn is_url(location: &str) -> bool {
+    location.starts_with("https://") || location.starts_with("http://")
+}
+
+/// Split a `#sha256=<hex>` checksum off a URL.
+pub(crate) fn split_checksum(location: &str) -> (&str, Option<&str>) {
+    match location.split_once("#sha256=") {
+        Some((url, checksum)) if is_url(location) => (url, Some(checksum)),
+        _ => (location, None),
+    }
+}
+
+/// The location of a file included by the configuration at `location`: relative to its URL or directory.
+pub(crate) fn resolve_include(location: &str, include: &str) -> String {
+    if is_url(include) {
+        return include.to_string();
+    }
+    if is_url(location) {
+        let url = split_checksum(location).0;
+        return format!("{}{}", &url[..url.rfind('/').map_or(url.len(), |slash| slash + 1)], include);
+    }
+    Path::new(location)
+        .parent()
+        .unwrap_or(Path::new("."))
+        .join(include)
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Read a configuration file, downloading it if `location` is a URL.  The checksum is the expected one, or the
+/// one pinned in the URL with `#sha256=<hex>`.
+pub(crate) fn read_config_source(location: &str, expected_sha256: Option<&str>) -> Result<String, Box<dyn Error>> {
+    let (location, pinned) = split_checksum(location);
+    let content = match is_url(location) {
+        true if run_context().offline => return Err(format!("Cannot download {} with --offline", location).into()),
+        true => {
+            say!("Downloading configuration from: {}", location);
+            let mut response = http_agent(true)?
+                .get(location)
+                .call()
+                .map_err(|e| format!("Failed to download {}: {}", location, e))?;
+            response
+                .body_mut()
+                .read_to_string()
+                .map_err(|e| format!("Failed to download {}: {}", location, e))?
+        }
+        false => fs::read_to_string(location).map_err(|e| format!("Failed to read {:?}: {}", location, e))?,
+    };
+    if let Some(expected) = expected_sha256.or(pinned) {
+        verify_sha256(location, content.as_bytes(), expected)?;
+    }
+    Ok(content)
+}
+
+/// Check that `content` downloaded from `location` has the SHA-256 checksum `expected` (hex).
+pub(crate) fn verify_sha256(location: &str, content: &[u8], expected: &str) -> Result<(), Box<dyn Error>> {
+    check_sha256(location, &sha256_hex(content), expected)
+}
+
+/// Compare the SHA-256 checksum (hex) of the content from `location` with the expected one.
+pub(crate) fn check_sha256(location: &str, actual: &str, expected: &str) -> Result<(), Box<dyn Error>> {
+    match actual.eq_ignore_ascii_case(expected.trim()) {
+        true => Ok(()),
+        false => Err(format!("Checksum mismatch for {}: expected sha256 {}, got {}", location, expected, actual).into()),
+    }
+}
+
+/// The HTTP client for downloads.  Proxies come from `HTTPS_PROXY`, `HTTP_PROXY` and `NO_PROXY`, and the `ca_bundle`
+/// of the user configuration replaces the built-in certificate authorities.  Unless `status_as_error`, responses with
+/// an error status are returned to be inspected, e.g. for authentication challenges.
+pub(crate) fn http_agent(status_as_error: bool) -> Result<ureq::Agent, Box<dyn Error>> {
+    let mut config = ureq::Agent::config_builder().http_status_as_error(status_as_error);
+    if let Some(bundle) = user_config().ok().and_then(|user| user.ca_bundle.clone()) {
+        let pem = fs::read(&bundle).map_err(|e| format!("Failed to read ca_bundle {:?}: {}", bundle, e))?;
+        let certificates = ureq::tls::parse_pem(&pem)
+            .filter_map(|item| match item {
+                Ok(ureq::tls::PemItem::Certificate(certificate)) => Some(Ok(certificate)),
+                Ok(_) => None,
+                Err(e) => Some(Err(e)),
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Invalid ca_bundle {:?}: {}", bundle, e))?;
+        if certificates.is_empty() {
+            return Err(format!("Invalid ca_bundle {:?}: no certificates found", bundle).into());
+        }
+        config = config.tls_config(ureq::tls::TlsConfig::builder().root_certs(certificates.into()).build());
+    }
+    Ok(config.build().into())
+}
+
+/// Make libgit2 trust the `ca_bundle` of the user configuration, in addition to the system's certificate authorities.
+#[cfg(feature = "git2")]
+pub(crate) fn configure_ca_bundle() -> Result<(), Box<dyn Error>> {
+    let Some(bundle) = user_config().ok().and_then(|user| user.ca_bundle.clone()) else {
+        return Ok(());
+    };
+    // SAFETY: called once at the start of the run, before libgit2 is used.
+    unsafe { git2::opts::set_ssl_cert_file(&bundle) }.map_err(|e| format!("Failed to use ca_bundle {:?}: {}", bundle, e))?;
+    Ok(())
+}
+
+/// The SHA-256 digest of `bytes` in lowercase hex.
+pub(crate) fn sha256_hex(bytes: &[u8]) -> String {
+    use sha2::Digest;
+    format!("{:x}", sha2::Sha256::digest(bytes))
+}
+
+/// Standard base64, with padding as HTTP basic authentication uses it, or without as OpenSSH fingerprints do.
+pub(crate) fn base64(bytes: &[u8], padded: bool) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let value = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |value, (i, byte)| value | (*byte as u32) << (16 - 8 * i));
+        for i in 0..=chunk.len() {
+            encoded.push(ALPHABET[(value >> (18 - 6 * i) & 0x3f) as usize] as char);
+        }
+    }
+    while padded && !encoded.len().is_multiple_of(4) {
+        encoded.push('=');
+    }
+    encoded
+}
+
+/// Add `config` to `composed`: its scaffolds are appended, and its project settings and variables win.
+pub(crate) fn merge_config(composed: &mut Config, config: Config) {
+    merge_settings(&mut composed.project, &mut composed.variables, config.project, config.variables);
+    composed.scaffolds.extend(config.scaffolds);
+    for (name, profile) in config.profile {
+        let target = composed.profile.entry(name).or_default();
+        merge_settings(&mut target.project, &mut target.variables, profile.project, profile.variables);
+    }
+}
+
+/// Merge project settings and variables into `project` and `variables`; the merged values take precedence.
+fn merge_settings(
+    project: &mut Option<ProjectConfig>,
+    variables: &mut Option<HashMap<String, toml::Value>>,
+    merged_project: Option<ProjectConfig>,
+    merged_variables: Option<HashMap<String, toml::Value>>,
+) {
+    if let Some(merged) = merged_project {
+        let target = project.get_or_insert_with(Default::default);
+        target.name = merged.name.or(target.name.take());
+        target.output = merged.output.or(target.output.take());
+        target.overwrite = merged.overwrite.or(target.overwrite);
+        target.strict = merged.strict.or(target.strict);
+        target.open_with = merged.open_with.or(target.open_with.take());
+        target.hooks_defaults = merged.hooks_defaults.or(target.hooks_defaults.take());
+        target.git = merged.git.or(target.git.take());
+        target.retry = merged.retry.or(target.retry.take());
+        target.format = merged.format.or(target.format.take());
+        target.line_endings = merged.line_endings.or(target.line_endings);
+        target.limits = merged.limits.or(target.limits);
+    }
+    if let Some(merged) = merged_variables {
+        variables.get_or_insert_with(Default::default).extend(merged);
+    }
+}
+
+/// Apply the `[profile.<name>]` section selected with --profile: its project settings and variables take precedence
+/// over the rest of the configuration.
+pub(crate) fn apply_profile(config: &mut Config, name: &str) -> Result<(), Box<dyn Error>> {
+    let Some(profile) = config.profile.remove(name) else {
+        let available: Vec<&str> = config.profile.keys().map(String::as_str).collect();
+        return Err(match available.is_empty() {
+            true => format!("Unknown profile '{}': the configuration defines no profiles", name),
+            false => format!("Unknown profile '{}', available profiles: {}", name, available.join(", ")),
+        }
+        .into());
+    };
+    say!("Using profile: {}", name);
+    merge_settings(&mut config.project, &mut config.variables, profile.project, profile.variables);
+    Ok(())
+}
+
+/// Apply `extends` to every scaffold: a scaffold inherits the settings of the named scaffold (which may extend
+/// another one) and overrides them like a consumer overrides a repository manifest, except that file entries and
+/// hooks are overlaid on the base's instead of replacing them.  Its name is not inherited.
+fn resolve_extends(scaffolds: &[Scaffold]) -> Result<Vec<Scaffold>, Box<dyn Error>> {
+    fn resolve(scaffolds: &[Scaffold], scaffold: &Scaffold, chain: &mut Vec<String>) -> Result<Scaffold, Box<dyn Error>> {
+        let Some(base_name) = &scaffold.extends else {
+            if !scaffold.remove.is_empty() {
+                return Err(format!("Scaffold '{}' sets `remove` but doesn't extend a scaffold", scaffold_name(scaffold)).into());
+            }
+            return Ok(scaffold.clone());
+        };
+        chain.push(scaffold_name(scaffold).to_string());
+        if chain.contains(base_name) {
+            return Err(format!("Scaffold extends cycle: {} -> {}", chain.join(" -> "), base_name).into());
+        }
+        let base = scaffolds
+            .iter()
+            .find(|candidate| candidate.name.as_ref() == Some(base_name))
+            .ok_or_else(|| format!("Scaffold '{}' extends unknown scaffold '{}'", scaffold_name(scaffold), base_name))?;
+        let base = resolve(scaffolds, base, chain)?;
+        chain.pop();
+
+        let repo = match scaffold.repo.is_empty() {
+            true => base.repo.clone(),
+            false => scaffold.repo.clone(),
+        };
+        let template = overlay_template(&base.template, &scaffold.template, &scaffold.remove).map_err(|e| {
+            format!(
+                "Scaffold '{}' removes {}, which '{}' doesn't list",
+                scaffold_name(scaffold),
+                e,
+                base_name
+            )
+        })?;
+        let hooks = overlay_hooks(base.hooks.clone(), scaffold.hooks.clone());
+        Ok(Scaffold {
+            name: scaffold.name.clone(),
+            extends: None,
+            remove: Vec::new(),
+            repo,
+            template,
+            hooks,
+            allow_outside_output: scaffold.allow_outside_output || base.allow_outside_output,
+            requires: scaffold.requires.clone().or_else(|| base.requires.clone()),
+            retry: scaffold.retry.or(base.retry),
+            version: scaffold.version.clone().or_else(|| base.version.clone()),
+            sha256: scaffold.sha256.clone().or_else(|| base.sha256.clone()),
+            signature: scaffold.signature.clone().or_else(|| base.signature.clone()),
+            submodules: scaffold.submodules || base.submodules,
+            ..merge_scaffold(scaffold, RepoManifest::from(base))
+        })
+    }
+    scaffolds
+        .iter()
+        .map(|scaffold| resolve(scaffolds, scaffold, &mut Vec::new()))
+        .collect()
+}
+
+/// Overlay a scaffold's template settings on those of the scaffold it extends.  Its file entries replace the base's
+/// entries with the same destination and are added after the others; the base's entries named in `remove` (by
+/// `src`, `dest` or `dir`) are left out.  Names in `remove` that match none of the base's entries are returned
+/// as the error.
+fn overlay_template(base: &TemplateConfig, own: &TemplateConfig, remove: &[String]) -> Result<TemplateConfig, String> {
+    let unmatched: Vec<String> = remove
+        .iter()
+        .filter(|name| {
+            !base
+                .files
+                .iter()
+                .any(|file| [&file.src, &file.dest].contains(name) || file.dir.as_ref() == Some(*name))
+        })
+        .map(|name| format!("'{}'", name))
+        .collect();
+    if !unmatched.is_empty() {
+        return Err(unmatched.join(", "));
+    }
+    // Entries are matched by what they write: the destination of a file, or the directory created.
+    let target = |template: &TemplateConfig, file: &TemplateFile| file.dir.clone().unwrap_or_else(|| template.dest(file));
+    let mut files: Vec<TemplateFile> = base
+        .files
+        .iter()
+        .filter(|file| {
+            !remove
+                .iter()
+                .any(|name| [&file.src, &file.dest].contains(&name) || file.dir.as_ref() == Some(name))
+        })
+        .filter(|file| !own.files.iter().any(|own_file| target(own, own_file) == target(base, file)))
+        .cloned()
+        .collect();
+    files.extend(own.files.iter().cloned());
+    let mut exclude = base.exclude.clone();
+    exclude.extend(own.exclude.iter().filter(|pattern| !base.exclude.contains(pattern)).cloned());
+    let mut raw = base.raw.clone();
+    raw.extend(own.raw.iter().filter(|pattern| !base.raw.contains(pattern)).cloned());
+    Ok(TemplateConfig {
+        files,
+        exclude,
+        raw,
+        auto_dest: own.auto_dest || base.auto_dest,
+        delimiters: own.delimiters.clone().or_else(|| base.delimiters.clone()),
+    })
+}
+
+/// Overlay a scaffold's hook settings on those of the scaffold it extends: each hook and setting it sets replaces
+/// the base's, and its `env` is added to the base's.
+fn overlay_hooks(base: Option<HooksConfig>, own: Option<HooksConfig>) -> Option<HooksConfig> {
+    let (base, own) = match (base, own) {
+        (Some(base), Some(own)) => (base, own),
+        (base, own) => return own.or(base),
+    };
+    let env = match (base.env, own.env) {
+        (Some(mut env), Some(own_env)) => {
+            env.extend(own_env);
+            Some(env)
+        }
+        (env, own_env) => own_env.or(env),
+    };
+    Some(HooksConfig {
+        plan: own.plan.or(base.plan),
+        pre: own.pre.or(base.pre),
+        post: own.post.or(base.post),
+        run_when: match own.run_when {
+            HookRunWhen::Always => base.run_when,
+            run_when => run_when,
+        },
+        windows_extensions: own.windows_extensions.or(base.windows_extensions),
+        interpreter: own.interpreter.or(base.interpreter),
+        env,
+        cwd: own.cwd.or(base.cwd),
+        timeout: own.timeout.or(base.timeout),
+    })
+}
+
+/// Per-user settings from `~/.config/scaficionado/config.toml`, applied under every project's configuration.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct UserConfig {
+    /// Defaults for the `[project]` settings of every configuration.
+    pub(crate) project: Option<ProjectConfig>,
+    /// Defaults for the global variables of every configuration.
+    pub(crate) variables: Option<HashMap<String, toml::Value>>,
+    /// Directory for temporary clones, instead of the system's temp directory.
+    pub(crate) cache_dir: Option<String>,
+    /// Short names for repositories, usable as a scaffold's `repo`.
+    #[serde(default)]
+    aliases: BTreeMap<String, String>,
+    /// Access tokens for cloning over HTTPS, by host.
+    #[serde(default)]
+    pub(crate) tokens: BTreeMap<String, String>,
+    /// PEM file of certificate authorities to trust for HTTPS, e.g. of a proxy that intercepts TLS.  Not used by the
+    /// gix backend.
+    ca_bundle: Option<String>,
+    /// Clone and read template repositories with "libgit2" or "gix".  Defaults to gix when built with the `gix`
+    /// feature.
+    pub(crate) backend: Option<GitBackend>,
+    /// URL or path of the scaffold registry index used by `search` and `add`.
+    pub(crate) registry: Option<String>,
+    /// The LLM endpoint asked for suggested defaults with --suggest.
+    #[serde(default)]
+    pub(crate) suggest: SuggestConfig,
+}
+
+/// The path of the user configuration: `$SCAFICIONADO_CONFIG`, or `$XDG_CONFIG_HOME/scaficionado/config.toml`,
+/// falling back to `~/.config` (or `%APPDATA%` on Windows).
+fn user_config_path() -> Option<PathBuf> {
+    if let Some(path) = env_path("SCAFICIONADO_CONFIG") {
+        return Some(path);
+    }
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))?;
+    Some(config_home.join("scaficionado").join("config.toml"))
+}
+
+/// The path in the environment variable `name`, unless it is unset or empty.  Unit tests get paths of their own
+/// instead, so that they neither read the user's configuration nor remove the user's clones.
+pub(crate) fn env_path(name: &str) -> Option<PathBuf> {
+    #[cfg(test)]
+    return testing::env_path_in_tests(name);
+    #[cfg(not(test))]
+    std::env::var_os(name).filter(|path| !path.is_empty()).map(PathBuf::from)
+}
+
+/// Load a user configuration file.
+fn load_user_config(path: &Path) -> Result<UserConfig, Box<dyn Error>> {
+    let content = fs::read_to_string(path)?;
+    Ok(from_toml(&content).map_err(|e| format!("Failed to parse {:?}: {}", path, e))?)
+}
+
+/// The user configuration, read once per run.  Without a user configuration file, every setting is unset.
+pub(crate) fn user_config() -> Result<&'static UserConfig, Box<dyn Error>> {
+    static USER_CONFIG: OnceLock<Result<UserConfig, String>> = OnceLock::new();
+    USER_CONFIG
+        .get_or_init(|| match user_config_path().filter(|path| path.is_file()) {
+            Some(path) => load_user_config(&path).map_err(|e| e.to_string()),
+            None => Ok(UserConfig::default()),
+        })
+        .as_ref()
+        .map_err(|e| e.clone().into())
+}
+
+/// Apply the user configuration under `config`: the project's settings and variables take precedence over the
+/// user's, and scaffolds whose `repo` is an alias get the aliased repository.
+pub(crate) fn apply_user_config(config: Config, user: &UserConfig) -> Config {
+    let mut composed = Config {
+        project: user.project.clone(),
+        variables: user.variables.clone(),
+        ..Default::default()
+    };
+    merge_config(&mut composed, config);
+    for scaffold in &mut composed.scaffolds {
+        scaffold.repo = scaffold.repo.map(|url| match user.aliases.get(url) {
+            Some(repo) => {
+                say!("Using repository alias '{}': {}", url, repo);
+                repo.clone()
+            }
+            None => url.to_string(),
+        });
+    }
+    composed
+}
+
+/// Parse TOML, suggesting the closest known key when a key is unknown: "unknown field `templat_dir`, did you
+/// mean `template_dir`?".  The error keeps toml's line, column and snippet of the offending key.
+pub(crate) fn from_toml<T: serde::de::DeserializeOwned>(content: &str) -> Result<T, String> {
+    static UNKNOWN_FIELD: OnceLock<[Regex; 2]> = OnceLock::new();
+    let [unknown, quoted] = UNKNOWN_FIELD.get_or_init(|| {
+        [
+            Regex::new(r"unknown field `([^`]*)`, expected (.*)").unwrap(),
+            Regex::new(r"`([^`]*)`").unwrap(),
+        ]
+    });
+    toml::from_str(content).map_err(|e| {
+        let message = e.to_string();
+        unknown
+            .replace(&message, |field: &regex::Captures| {
+                let expected = quoted.captures_iter(&field[2]).map(|name| name[1].to_string());
+                match closest_name(&field[1], expected) {
+                    Some(suggestion) => format!("unknown field `{}`, did you mean `{}`?", &field[1], suggestion),
+                    None => field[0].to_string(),
+                }
+            })
+            .into_owned()
+    })
+}
+
+/// The candidate closest to `name` by edit distance, if it is close enough to be a likely typo.
+pub(crate) fn closest_name(name: &str, candidates: impl IntoIterator<Item = String>) -> Option<String> {
+    let max_distance = (name.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(name, &candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// The edit distance between two strings, counting insertions, deletions, substitutions and transpositions of two
+/// adjacent characters (`pots` for `post`) as one edit each.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    // distances[i][j] is the distance between the first i characters of a and the first j characters of b.
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    distances[0] = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let substitution = distances[i - 1][j - 1] + usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = substitution.min(distances[i - 1][j] + 1).min(distances[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+    distances[a.len()][b.len()]
+}
+
+/// A `scaffold.toml` shipped in the root of a template repository.  It describes the scaffold's files,
+/// default variables, prompts and hooks, so that consumers only need to set `repo` plus any overrides.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RepoManifest {
+    pub(crate) name: Option<String>,
+    pub(crate) template_dir: Option<String>,
+    pub(crate) format: Option<TemplateFormat>,
+    pub(crate) mode: Option<ScaffoldMode>,
+    pub(crate) template: Option<TemplateConfig>,
+    pub(crate) hooks: Option<HooksConfig>,
+    pub(crate) variables: Option<HashMap<String, toml::Value>>,
+    pub(crate) descriptions: Option<HashMap<String, String>>,
+    pub(crate) computed: Option<BTreeMap<String, String>>,
+    pub(crate) types: Option<BTreeMap<String, VariableType>>,
+    pub(crate) prompts: Option<Vec<Prompt>>,
+    pub(crate) required_variables: Option<Vec<String>>,
+    pub(crate) secret_variables: Option<Vec<String>>,
+}
+
+/// Combine a scaffold with the manifest in its repository.  The consumer's settings take precedence:
+/// its variables override the manifest's defaults, its prompts replace manifest prompts with the same name,
+/// and a non-empty file list, hooks, template_dir, non-native format or mode replace the manifest's.
+pub(crate) fn apply_repo_manifest(scaffold: &Scaffold, repo_base: &Path) -> Result<Scaffold, Box<dyn Error>> {
+    let path = repo_base.join(REPO_MANIFEST_FILE);
+    if !path.is_file() {
+        return Ok(scaffold.clone());
+    }
+    say!("Using repository manifest: {:?}", path);
+    let content = fs::read_to_string(&path)?;
+    let manifest: RepoManifest = from_toml(&content).map_err(|e| format!("Failed to parse {:?}: {}", path, e))?;
+    let merged = merge_scaffold(scaffold, manifest);
+    validate_scaffold(&merged)?;
+    Ok(merged)
+}
+
+/// Combine a scaffold with a base it overrides: a repository manifest, or the scaffold it extends.
+pub(crate) fn merge_scaffold(scaffold: &Scaffold, manifest: RepoManifest) -> Scaffold {
+    let mut variables = manifest.variables.unwrap_or_default();
+    variables.extend(scaffold.variables.clone().unwrap_or_default());
+    let mut descriptions = manifest.descriptions.unwrap_or_default();
+    descriptions.extend(scaffold.descriptions.clone());
+    let mut computed = manifest.computed.unwrap_or_default();
+    computed.extend(scaffold.computed.clone());
+    let mut types = manifest.types.unwrap_or_default();
+    types.extend(scaffold.types.clone());
+    let mut prompts: Vec<Prompt> = manifest
+        .prompts
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|prompt| !scaffold.prompts.iter().flatten().any(|own| own.name == prompt.name))
+        .collect();
+    prompts.extend(scaffold.prompts.clone().unwrap_or_default());
+    let union = |base: Option<Vec<String>>, own: &[String]| {
+        let mut names = base.unwrap_or_default();
+        for name in own {
+            if !names.contains(name) {
+                names.push(name.clone());
+            }
+        }
+        names
+    };
+
+    Scaffold {
+        name: scaffold.name.clone().or(manifest.name),
+        extends: scaffold.extends.clone(),
+        remove: scaffold.remove.clone(),
+        repo: scaffold.repo.clone(),
+        template_dir: scaffold.template_dir.clone().or(manifest.template_dir),
+        format: match scaffold.format {
+            TemplateFormat::Native => manifest.format.unwrap_or_default(),
+            format => format,
+        },
+        mode: match scaffold.mode {
+            ScaffoldMode::Any => manifest.mode.unwrap_or_default(),
+            mode => mode,
+        },
+        template: match scaffold.template.files.is_empty() {
+            true => manifest.template.unwrap_or_default(),
+            false => scaffold.template.clone(),
+        },
+        hooks: scaffold.hooks.clone().or(manifest.hooks),
+        variables: (!variables.is_empty()).then_some(variables),
+        descriptions,
+        computed,
+        types,
+        prompts: (!prompts.is_empty()).then_some(prompts),
+        required_variables: union(manifest.required_variables, &scaffold.required_variables),
+        secret_variables: union(manifest.secret_variables, &scaffold.secret_variables),
+        allow_outside_output: scaffold.allow_outside_output,
+        requires: scaffold.requires.clone(),
+        retry: scaffold.retry,
+        version: scaffold.version.clone(),
+        sha256: scaffold.sha256.clone(),
+        signature: scaffold.signature.clone(),
+        submodules: scaffold.submodules,
+    }
+}
+
+impl From<Scaffold> for RepoManifest {
+    fn from(scaffold: Scaffold) -> Self {
+        RepoManifest {
+            name: scaffold.name,
+            template_dir: scaffold.template_dir,
+            format: Some(scaffold.format),
+            mode: Some(scaffold.mode),
+            template: Some(scaffold.template),
+            hooks: scaffold.hooks,
+            variables: scaffold.variables,
+            descriptions: Some(scaffold.descriptions),
+            computed: Some(scaffold.computed),
+            types: Some(scaffold.types),
+            prompts: scaffold.prompts,
+            required_variables: Some(scaffold.required_variables),
+            secret_variables: Some(scaffold.secret_variables),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::fs;
+    use tempfile::TempDir;
+
+    // Test loading configuration from a TOML string.
+    #[test]
+    fn test_load_config() -> Result<(), Box<dyn std::error::Error>> {
+        let toml_content = r#"
+[[scaffolds]]
+name = "Local"
+repo = "local_repo"
+template_dir = "templates"
+
+[scaffolds.template]
+files = [
+    { src = "kind_config.yaml.tera", dest = "kind_config.yaml" }
+]
+
+[scaffolds.hooks]
+pre = "scripts/pre.sh"
+post = "scripts/post.sh"
+
+[scaffolds.variables]
+kind_workers = 3
+environment = "development"
+"#;
+        let temp_dir = TempDir::new()?;
+        let config_path = temp_dir.path().join("scaffolding.toml");
+        fs::write(&config_path, toml_content)?;
+
+        let config = load_config(&config_path.to_string_lossy(), None)?;
+        assert_eq!(config.scaffolds.len(), 1);
+        let scaffold = &config.scaffolds[0];
+        assert_eq!(scaffold.name.as_deref(), Some("Local"));
+        assert_eq!(scaffold.repo, RepoUrls::from("local_repo"));
+        Ok(())
+    }
+
+    // Test that a manifest in the template repository supplies the files and defaults the consumer doesn't set.
+    #[test]
+    fn test_repo_manifest() -> Result<(), Box<dyn std::error::Error>> {
+        let repo_dir = TempDir::new()?;
+        fs::create_dir_all(repo_dir.path().join("templates"))?;
+        fs::write(
+            repo_dir.path().join("templates").join("app.txt.tera"),
+            "{{ greeting }}, {{ owner }}!",
+        )?;
+        fs::write(
+            repo_dir.path().join(REPO_MANIFEST_FILE),
+            r#"
+            name = "Manifest App"
+            [template]
+            files = [{ src = "app.txt.tera", dest = "app.txt" }]
+            [variables]
+            greeting = "Hello"
+            owner = "nobody"
+            [[prompts]]
+            name = "owner"
+            message = "Owner?"
+            "#,
+        )?;
+        let config: Config = toml::from_str(&format!(
+            "[[scaffolds]]\nrepo = \"{}\"\n[scaffolds.variables]\nowner = \"platform\"\n",
+            repo_dir.path().display()
+        ))?;
+
+        let (scaffold, repo_base) = load_scaffold(&config.scaffolds[0], &GitOptions::default())?;
+        assert_eq!(scaffold_name(&scaffold), "Manifest App");
+        assert_eq!(scaffold.template.files.len(), 1);
+        assert_eq!(scaffold.prompts.as_ref().map(Vec::len), Some(1));
+
+        // The consumer's variables override the manifest's defaults.
+        let output_dir = TempDir::new()?;
+        process_scaffold(
+            &scaffold,
+            &repo_base,
+            "Manifest",
+            None,
+            &BTreeMap::new(),
+            output_dir.path(),
+            &overwrite_options(),
+        )?;
+        assert_eq!(fs::read_to_string(output_dir.path().join("app.txt"))?, "Hello, platform!");
+
+        // An invalid manifest is reported as a validation error.
+        fs::write(
+            repo_dir.path().join(REPO_MANIFEST_FILE),
+            "[template]\nfiles = [{ src = \"only-src\" }]\n",
+        )?;
+        let Err(error) = load_scaffold(&config.scaffolds[0], &GitOptions::default()) else {
+            panic!("expected an invalid manifest to fail");
+        };
+        assert_eq!(error.stage, ErrorStage::Validation);
+        assert_eq!(error.file.as_deref(), Some(REPO_MANIFEST_FILE));
+        Ok(())
+    }
+
+    // Test that the configuration schema describes scaffolding.toml and rejects unknown keys.
+    #[test]
+    fn test_config_schema() -> Result<(), Box<dyn std::error::Error>> {
+        let schema: serde_json::Value = serde_json::from_str(&config_schema()?)?;
+        assert_eq!(schema["title"], "Config");
+        assert_eq!(schema["additionalProperties"], false);
+        assert!(schema["properties"]["scaffolds"].is_object());
+        let scaffold = &schema["$defs"]["Scaffold"];
+        assert!(scaffold["properties"]["repo"].is_object());
+        assert!(scaffold["properties"]["extends"].is_object());
+        assert_eq!(scaffold["additionalProperties"], false);
+        assert!(scaffold["properties"]["hooks"].is_object());
+        let formats = serde_json::to_string(&schema["$defs"]["TemplateFormat"])?;
+        assert!(formats.contains("cargo_generate"));
+        Ok(())
+    }
+
+    // Test that unknown configuration keys are rejected with a suggestion and the location of the key.
+    #[test]
+    fn test_unknown_config_keys() -> Result<(), Box<dyn std::error::Error>> {
+        let error = from_toml::<Config>("[[scaffolds]]\nrepo = \".\"\ntemplat_dir = \"templates\"\n")
+            .err()
+            .ok_or("the typo should be rejected")?;
+        assert!(
+            error.contains("unknown field `templat_dir`, did you mean `template_dir`?"),
+            "{}",
+            error
+        );
+        assert!(error.contains("line 3"), "{}", error);
+
+        // Nested tables are checked too; keys that resemble nothing list the expected ones.
+        let error = from_toml::<Config>("[[scaffolds]]\nrepo = \".\"\n[scaffolds.hooks]\npots = \"post.sh\"\nzzz = 1\n")
+            .err()
+            .ok_or("the typo should be rejected")?;
+        assert!(error.contains("did you mean `post`?"), "{}", error);
+        let error = from_toml::<Config>("[project]\ncolour = \"red\"\n").err().ok_or("unknown key")?;
+        assert!(error.contains("unknown field `colour`, expected one of"), "{}", error);
+
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("pots", "post"), 1);
+        assert_eq!(
+            closest_name("stict", ["strict".to_string(), "output".to_string()]),
+            Some("strict".to_string())
+        );
+        Ok(())
+    }
+
+    // Test that included files and extended scaffolds are combined, with the including file and scaffold winning.
+    #[test]
+    fn test_config_include_and_extends() -> Result<(), Box<dyn std::error::Error>> {
+        let config_dir = TempDir::new()?;
+        fs::create_dir_all(config_dir.path().join("scaffolds"))?;
+        fs::write(
+            config_dir.path().join("scaffolds/k8s.toml"),
+            r#"
+[project]
+name = "Included"
+output = "included-output"
+
+[variables]
+team = "platform"
+region = "eu"
+
+[[scaffolds]]
+name = "Base"
+repo = "https://example.com/base.git"
+template = { files = [{ src = "a.tera", dest = "a" }, { src = "b.tera", dest = "b" }, { dir = "logs" }] }
+variables = { replicas = 1, port = 80 }
+hooks = { pre = "hooks/pre.sh", post = "hooks/post.sh", env = { STAGE = "base", TEAM = "platform" } }
+"#,
+        )?;
+        fs::write(
+            config_dir.path().join("scaffolding.toml"),
+            r#"
+include = ["scaffolds/k8s.toml"]
+
+[project]
+name = "Main"
+
+[variables]
+region = "us"
+
+[[scaffolds]]
+name = "Api"
+extends = "Base"
+variables = { replicas = 3 }
+template = { files = [{ src = "api/a.tera", dest = "a" }, { src = "c.tera", dest = "c" }] }
+remove = ["b.tera"]
+hooks = { post = "hooks/api-post.sh", env = { STAGE = "api" } }
+
+[[scaffolds]]
+name = "Worker"
+extends = "Api"
+repo = "https://example.com/worker.git"
+"#,
+        )?;
+        let config = load_config(&config_dir.path().join("scaffolding.toml").to_string_lossy(), None)?;
+        let project = config.project.as_ref().ok_or("no project")?;
+        assert_eq!(project.name.as_deref(), Some("Main"));
+        assert_eq!(project.output.as_deref(), Some("included-output"));
+        let variables = config.variables.as_ref().ok_or("no variables")?;
+        assert_eq!(variables["team"].as_str(), Some("platform"));
+        assert_eq!(variables["region"].as_str(), Some("us"));
+
+        let names: Vec<&str> = config.scaffolds.iter().map(scaffold_name).collect();
+        assert_eq!(names, ["Base", "Api", "Worker"]);
+        let api = &config.scaffolds[1];
+        assert_eq!(api.repo, RepoUrls::from("https://example.com/base.git"));
+        // File entries are overlaid: "a" is replaced, "b" removed and "c" added after the base's.
+        let files: Vec<(&str, Option<&str>)> = api
+            .template
+            .files
+            .iter()
+            .map(|file| (file.src.as_str(), file.dir.as_deref()))
+            .collect();
+        assert_eq!(files, [("", Some("logs")), ("api/a.tera", None), ("c.tera", None)]);
+        let hooks = api.hooks.as_ref().ok_or("no hooks")?;
+        assert_eq!(hooks.pre.as_ref().map(Hook::path), Some("hooks/pre.sh"));
+        assert_eq!(hooks.post.as_ref().map(Hook::path), Some("hooks/api-post.sh"));
+        let env = hooks.env.as_ref().ok_or("no env")?;
+        assert_eq!((env["STAGE"].as_str(), env["TEAM"].as_str()), ("api", "platform"));
+        let api_variables = api.variables.as_ref().ok_or("no variables")?;
+        assert_eq!(api_variables["replicas"].as_integer(), Some(3));
+        assert_eq!(api_variables["port"].as_integer(), Some(80));
+        let worker = &config.scaffolds[2];
+        assert_eq!(worker.repo, RepoUrls::from("https://example.com/worker.git"));
+        assert_eq!(worker.variables.as_ref().ok_or("no variables")?["replicas"].as_integer(), Some(3));
+
+        // Cycles and unknown bases are reported.
+        fs::write(config_dir.path().join("a.toml"), "include = [\"b.toml\"]\n")?;
+        fs::write(config_dir.path().join("b.toml"), "include = [\"a.toml\"]\n")?;
+        let error = load_config(&config_dir.path().join("a.toml").to_string_lossy(), None)
+            .err()
+            .ok_or("the cycle should be reported")?;
+        assert!(error.to_string().contains("include cycle"), "{}", error);
+        fs::write(
+            config_dir.path().join("c.toml"),
+            "[[scaffolds]]\nname = \"C\"\nextends = \"Missing\"\n",
+        )?;
+        let error = load_config(&config_dir.path().join("c.toml").to_string_lossy(), None)
+            .err()
+            .ok_or("the unknown base should be reported")?;
+        assert!(error.to_string().contains("extends unknown scaffold 'Missing'"), "{}", error);
+
+        // Removing an entry the base doesn't list, or without extending, is an error.
+        for (toml, expected) in [
+            (
+                "[[scaffolds]]\nname = \"B\"\n[[scaffolds]]\nname = \"D\"\nextends = \"B\"\nremove = [\"x.tera\"]\n",
+                "Scaffold 'D' removes 'x.tera', which 'B' doesn't list",
+            ),
+            (
+                "[[scaffolds]]\nname = \"D\"\nremove = [\"x.tera\"]\n",
+                "Scaffold 'D' sets `remove` but doesn't extend a scaffold",
+            ),
+        ] {
+            fs::write(config_dir.path().join("d.toml"), toml)?;
+            let error = load_config(&config_dir.path().join("d.toml").to_string_lossy(), None)
+                .err()
+                .ok_or("the removal should be rejected")?;
+            assert_eq!(error.to_string(), expected);
+        }
+        Ok(())
+    }
+
+    // Test that a profile overrides the project settings and variables, including profiles of included files.
+    #[test]
+    fn test_config_profiles() -> Result<(), Box<dyn std::error::Error>> {
+        let config_dir = TempDir::new()?;
+        fs::write(
+            config_dir.path().join("common.toml"),
+            r#"
+[profile.prod.variables]
+replicas = 2
+log_level = "warn"
+"#,
+        )?;
+        fs::write(
+            config_dir.path().join("scaffolding.toml"),
+            r#"
+include = ["common.toml"]
+
+[project]
+name = "Service"
+output = "dev-output"
+
+[variables]
+replicas = 1
+region = "eu"
+
+[profile.prod.project]
+output = "prod-output"
+
+[profile.prod.variables]
+replicas = 3
+"#,
+        )?;
+        let config_path = config_dir.path().join("scaffolding.toml").to_string_lossy().into_owned();
+        let mut config = load_config(&config_path, None)?;
+        apply_profile(&mut config, "prod")?;
+        let project = config.project.as_ref().ok_or("no project")?;
+        assert_eq!(project.name.as_deref(), Some("Service"));
+        assert_eq!(project.output.as_deref(), Some("prod-output"));
+        let variables = config.variables.as_ref().ok_or("no variables")?;
+        assert_eq!(variables["replicas"], toml::Value::Integer(3));
+        assert_eq!(variables["log_level"], toml::Value::String("warn".to_string()));
+        assert_eq!(variables["region"], toml::Value::String("eu".to_string()));
+
+        let mut config = load_config(&config_path, None)?;
+        let error = apply_profile(&mut config, "staging").err().ok_or("unknown profiles should fail")?;
+        assert_eq!(error.to_string(), "Unknown profile 'staging', available profiles: prod");
+        Ok(())
+    }
+
+    // Test that the tests have a cache of their own and no user configuration.
+    #[test]
+    fn test_env_paths() {
+        assert!(clones_dir().starts_with(std::env::temp_dir().join("scaficionado-tests")));
+        assert!(user_config_path().is_some_and(|path| !path.exists()));
+    }
+
+    // Test that the user configuration provides defaults under the project's configuration, and repository aliases.
+    #[test]
+    fn test_user_config() -> Result<(), Box<dyn std::error::Error>> {
+        let config_dir = TempDir::new()?;
+        let user_path = config_dir.path().join("config.toml");
+        fs::write(
+            &user_path,
+            r#"
+cache_dir = "/var/cache/scaficionado"
+
+[project]
+output = "user-output"
+strict = true
+
+[variables]
+author = "Jane"
+team = "users"
+
+[aliases]
+mytemplates = "git@github.com:org/templates.git"
+
+[tokens]
+"github.com" = "secret"
+"#,
+        )?;
+        let user = load_user_config(&user_path)?;
+        assert_eq!(user.tokens["github.com"], "secret");
+
+        let config: Config = from_toml(
+            r#"
+[project]
+strict = false
+
+[variables]
+team = "project"
+
+[[scaffolds]]
+repo = "mytemplates"
+
+[[scaffolds]]
+repo = "local/templates"
+"#,
+        )?;
+        let config = apply_user_config(config, &user);
+        let project = config.project.as_ref().ok_or("no project")?;
+        assert_eq!(project.output.as_deref(), Some("user-output"));
+        assert_eq!(project.strict, Some(false));
+        let variables = config.variables.as_ref().ok_or("no variables")?;
+        assert_eq!(variables["author"].as_str(), Some("Jane"));
+        assert_eq!(variables["team"].as_str(), Some("project"));
+        assert_eq!(config.scaffolds[0].repo, RepoUrls::from("git@github.com:org/templates.git"));
+        assert_eq!(config.scaffolds[1].repo, RepoUrls::from("local/templates"));
+
+        assert_eq!(repo_host("https://user@github.com:443/org/repo.git"), Some("github.com"));
+        assert_eq!(repo_host("git@gitlab.com:org/repo.git"), Some("gitlab.com"));
+
+        fs::write(&user_path, "cache_dri = \"/tmp\"\n")?;
+        let error = load_user_config(&user_path).err().ok_or("the typo should be rejected")?;
+        assert!(error.to_string().contains("did you mean `cache_dir`?"), "{}", error);
+        Ok(())
+    }
+
+    // Test downloading a configuration and its includes over HTTP, with checksum pinning.
+    #[test]
+    fn test_remote_config() -> Result<(), Box<dyn std::error::Error>> {
+        let main = "include = [\"ci.toml\"]\n\n[[scaffolds]]\nname = \"Service\"\nrepo = \"https://example.com/service.git\"\n".to_string();
+        let ci = "[[scaffolds]]\nname = \"CI\"\nrepo = \"https://example.com/ci.git\"\n".to_string();
+        let checksum = sha256_hex(main.as_bytes());
+        let base_url = serve_http(vec![("/golden/service.toml", main), ("/golden/ci.toml", ci)])?;
+        let url = format!("{}/golden/service.toml", base_url);
+
+        let config = load_config(&url, Some(&checksum))?;
+        let names: Vec<&str> = config.scaffolds.iter().map(scaffold_name).collect();
+        assert_eq!(names, ["CI", "Service"]);
+        load_config(&format!("{}#sha256={}", url, checksum.to_uppercase()), None)?;
+
+        let error = load_config(&url, Some(&"0".repeat(64)))
+            .err()
+            .ok_or("the checksum should mismatch")?;
+        assert!(error.to_string().contains("Checksum mismatch"), "{}", error);
+        let error = load_config(&format!("{}/golden/missing.toml", base_url), None)
+            .err()
+            .ok_or("the download should fail")?;
+        assert!(error.to_string().contains("Failed to download"), "{}", error);
+        Ok(())
+    }
+
+    // Test that requires_version is checked before the rest of the configuration is parsed.
+    #[test]
+    fn test_requires_version() -> Result<(), Box<dyn std::error::Error>> {
+        let config_dir = TempDir::new()?;
+        let config_path = config_dir.path().join("scaffolding.toml");
+        let location = config_path.to_string_lossy().into_owned();
+
+        fs::write(&config_path, format!("requires_version = \">={}\"\n", env!("CARGO_PKG_VERSION")))?;
+        assert!(load_config(&location, None).is_ok());
+
+        // A setting of a future version doesn't hide the version check behind an unknown field error.
+        fs::write(&config_path, "requires_version = \">=99.0\"\n[project]\nfuture_setting = true\n")?;
+        let error = load_config(&location, None).err().ok_or("the version requirement should fail")?;
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "{:?} requires scaficionado >=99.0, but this is version {}; please upgrade",
+                location,
+                env!("CARGO_PKG_VERSION")
+            )
+        );
+
+        fs::write(&config_path, "requires_version = \"latest\"\n")?;
+        let error = load_config(&location, None).err().ok_or("an invalid requirement should fail")?;
+        assert!(error.to_string().starts_with("Invalid requires_version \"latest\""));
+        Ok(())
+    }
+}
@@ -0,0 +1,1614 @@
+//! Generating a project: preparing its scaffolds, asking their prompts and rendering them into the output
+//! directory.
+
+use crate::render::{error_chain, reset_sequences, template_variables};
+use regex::Regex;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+use std::time::Instant;
+use tempfile::TempDir;
+use tera::{Context, Template};
+
+use super::*;
+
+/// Files and directories that mark the root of an existing project, for scaffolds with `mode = "augment"`.
+const PROJECT_ROOT_MARKERS: [&str; 12] = [
+    ".git",
+    "Cargo.toml",
+    "package.json",
+    "pyproject.toml",
+    "setup.py",
+    "go.mod",
+    "pom.xml",
+    "build.gradle",
+    "build.gradle.kts",
+    "Gemfile",
+    "composer.json",
+    "scaffolding.toml",
+];
+
+/// Ask the prompts of every prepared scaffold, then render each one.  Errors of a scaffold are added to `report`;
+/// with --keep-going the remaining scaffolds are still rendered.  Returns the resolved answers and written files of every scaffold.
+pub(crate) fn render_scaffolds(
+    config: &Config,
+    prepared: &[(Scaffold, PathBuf)],
+    replayed: &Answers,
+    project_name: &str,
+    output_base: &Path,
+    options: &RenderOptions,
+    report: &mut ErrorReport,
+) -> Result<RenderedScaffolds, Box<dyn Error>> {
+    // Ask every prompt up front so that nothing is generated before all answers are known.
+    let mut input: Box<dyn AnswerInput> = match options.defaults {
+        true => Box::new(io::empty()),
+        false => Box::new(io::stdin().lock()),
+    };
+    let mut answers = Vec::new();
+    let suggest = user_config().map(|user| user.suggest.clone()).unwrap_or_default();
+    for (scaffold, repo_base) in prepared {
+        let replayed_answers = replayed.scaffolds.get(scaffold_name(scaffold)).cloned().unwrap_or_default();
+        let global_variables = config.variables.as_ref();
+        // Nothing is sent without --suggest, and failing suggestions leave the configured defaults.
+        let suggested = match options.suggest {
+            true => suggest_defaults(scaffold, project_name, global_variables, &replayed_answers, &suggest)
+                .inspect_err(|e| say!("Could not get suggestions for {}: {}", scaffold_name(scaffold), e))
+                .ok(),
+            false => None,
+        };
+        let scaffold = suggested.as_ref().unwrap_or(scaffold);
+        answers.push(match scaffold.format {
+            TemplateFormat::Native => resolve_prompts(scaffold, project_name, global_variables, replayed_answers, &mut input)?,
+            TemplateFormat::Cookiecutter => {
+                let template_root = get_templates_dir(repo_base, scaffold);
+                resolve_cookiecutter_variables(
+                    scaffold,
+                    &template_root,
+                    project_name,
+                    global_variables,
+                    replayed_answers,
+                    &mut input,
+                )?
+            }
+            TemplateFormat::CargoGenerate => {
+                let template_root = get_templates_dir(repo_base, scaffold);
+                resolve_cargo_generate_placeholders(
+                    scaffold,
+                    &template_root,
+                    project_name,
+                    global_variables,
+                    replayed_answers,
+                    &mut input,
+                )?
+            }
+        });
+        if let Some(scaffold_answers) = answers.last_mut() {
+            coerce_variables(scaffold, global_variables, scaffold_answers)
+                .map_err(|e| RunError::new(scaffold_name(scaffold), ErrorStage::Validation, None, e.as_ref()))?;
+        }
+    }
+
+    // Nothing is written when two scaffolds would write the same file.
+    report
+        .errors
+        .extend(check_collisions(config, prepared, &answers, project_name, output_base, options));
+    let mut scaffolds = Vec::new();
+    for ((scaffold, repo_base), scaffold_answers) in prepared.iter().zip(&answers) {
+        if !report.errors.is_empty() && !options.keep_going {
+            break;
+        }
+        if let Err(e) = check_cancelled() {
+            report
+                .errors
+                .push(RunError::new(scaffold_name(scaffold), ErrorStage::Render, None, &e));
+            break;
+        }
+        say!("Processing scaffold: {}", scaffold_name(scaffold));
+        match process_scaffold(
+            scaffold,
+            repo_base,
+            project_name,
+            config.variables.as_ref(),
+            scaffold_answers,
+            output_base,
+            options,
+        ) {
+            Ok(outcome) => {
+                scaffolds.push(ScaffoldRun {
+                    name: scaffold_name(scaffold).to_string(),
+                    written: outcome.written,
+                    hooks_run: outcome.hooks_run,
+                });
+                report.errors.extend(outcome.errors);
+            }
+            Err(e) => report.errors.push(e),
+        }
+        // A cancelled run stops even with --keep-going.
+        if !report.errors.is_empty() && (!options.keep_going || cancelled()) {
+            break;
+        }
+    }
+    Ok(RenderedScaffolds { answers, scaffolds })
+}
+
+/// Generate the project described by the configuration.
+pub(crate) fn generate(args: &Args) -> Result<(), Box<dyn Error>> {
+    // Set before the configuration is loaded, to keep its messages off stdout too.
+    if args.output == STDOUT_OUTPUT {
+        send_messages_to_stderr();
+    }
+    let config = timed("load configuration", None, || load_config_with_args(args))?;
+    generate_config(args, config)
+}
+
+/// Generate the project described by an already loaded configuration.
+pub(crate) fn generate_config(args: &Args, mut config: Config) -> Result<(), Box<dyn Error>> {
+    let mut replayed = match &args.answers {
+        Some(path) => load_answers(Path::new(path))?,
+        None => Answers::default(),
+    };
+    // Variables set on the command line or in the environment replace the replayed answers.
+    for name in variable_overrides(args).keys() {
+        for answers in replayed.scaffolds.values_mut() {
+            answers.remove(name);
+        }
+    }
+    apply_replayed_project_name(args, &replayed, &mut config);
+
+    let project_name = get_project_name(args, &config);
+    let options = get_render_options(args, &config);
+    // The output directory may use the project name and global variables, e.g. "services/{{ project_name }}".
+    let output_context = build_context(&project_name, config.variables.as_ref(), None);
+    let output = native_separators(&render_string(
+        &get_output_directory(args, &config),
+        "project output",
+        &output_context,
+        &options,
+    )?);
+    // With `--output -`, the project is generated into a temporary directory and one of its files is written to stdout.
+    let stdout_dir = match output == STDOUT_OUTPUT {
+        true => {
+            send_messages_to_stderr();
+            Some(TempDir::new()?)
+        }
+        false => None,
+    };
+    let output = match &stdout_dir {
+        Some(dir) => dir.path().to_string_lossy().into_owned(),
+        None => output,
+    };
+    let output_base = Path::new(&output);
+    say!(
+        "Scaffolding project '{}' to: {:?}, overwrite={}, strict={}",
+        project_name,
+        output_base,
+        options.overwrite,
+        options.strict
+    );
+
+    // Missing tools are reported before anything is cloned, rather than by a hook failing halfway through.
+    check_requirements(&config.scaffolds, &std::env::var_os("PATH").unwrap_or_default()).map_err(ConfigError)?;
+    if let Some(format) = config.project.as_ref().and_then(|project| project.format.as_ref()) {
+        format.formatters().map_err(ConfigError)?;
+    }
+    if args.offline {
+        check_offline_cache(&config.scaffolds).map_err(ConfigError)?;
+    }
+
+    // Obtain every repository before asking prompts: cookiecutter templates define their prompts in the repository.
+    let mut report = ErrorReport::default();
+    let check_required = |scaffold: &Scaffold| {
+        let replayed_answers = replayed.scaffolds.get(scaffold_name(scaffold));
+        check_required_variables(scaffold, &project_name, config.variables.as_ref(), replayed_answers)
+            .map_err(|e| RunError::new(scaffold_name(scaffold), ErrorStage::Validation, None, e.as_ref()))
+    };
+    // Required variables are checked before anything is cloned, and again with those of the repository manifests.
+    let mut scaffolds = Vec::new();
+    for scaffold in &config.scaffolds {
+        match check_required(scaffold) {
+            Ok(()) => scaffolds.push(scaffold),
+            Err(e) => report.errors.push(e),
+        }
+    }
+    if !report.errors.is_empty() && !options.keep_going {
+        scaffolds.clear();
+    }
+
+    let mut prepared = Vec::new();
+    let mut clones = TemporaryClones::default();
+    let hooks_defaults = config.project.as_ref().and_then(|project| project.hooks_defaults.as_ref());
+    for scaffold in scaffolds {
+        match timed("clone", Some(scaffold_name(scaffold)), || {
+            load_scaffold(scaffold, &get_git_options(args, &config))
+        }) {
+            Ok((mut loaded, repo_base)) => {
+                if let (Some(hooks), Some(defaults)) = (&mut loaded.hooks, hooks_defaults) {
+                    apply_hooks_defaults(hooks, defaults);
+                }
+                clones.add(&repo_base);
+                // Modes are checked against the output directory as it was before this run.
+                match check_scaffold_mode(&loaded, output_base) {
+                    Ok(()) => match check_required(&loaded) {
+                        Ok(()) => prepared.push((loaded, repo_base)),
+                        Err(e) => report.errors.push(e),
+                    },
+                    Err(e) => report
+                        .errors
+                        .push(RunError::new(scaffold_name(&loaded), ErrorStage::Validation, None, e.as_ref())),
+                }
+            }
+            Err(e) => report.errors.push(e),
+        }
+        // A cancelled run stops even with --keep-going.
+        if !report.errors.is_empty() && (!options.keep_going || cancelled()) {
+            break;
+        }
+    }
+
+    if let Some(dir) = &args.stage {
+        begin_staging(Path::new(dir), is_writable);
+    }
+    // The clones are kept while sibling projects are generated from them.
+    let mut result = generate_project(args, &config, &prepared, &replayed, &project_name, output_base, report);
+    let mut previous_output = output.clone();
+    while let Ok(answers) = &result {
+        if stdout_dir.is_some() || previewing() || options.defaults {
+            break;
+        }
+        let recorded = record_answers(&config, &project_name, answers);
+        let (name, output) = match ask_another_project(args, &config, &previous_output, &mut io::stdin().lock()) {
+            Ok(Some(next)) => next,
+            Ok(None) => break,
+            Err(e) => {
+                result = Err(e);
+                break;
+            }
+        };
+        let output_base = Path::new(&output);
+        say!("Scaffolding project '{}' to: {:?} with the same answers", name, output_base);
+        let mut report = ErrorReport::default();
+        for (scaffold, _) in &prepared {
+            if let Err(e) = check_scaffold_mode(scaffold, output_base) {
+                report
+                    .errors
+                    .push(RunError::new(scaffold_name(scaffold), ErrorStage::Validation, None, e.as_ref()));
+            }
+        }
+        result = generate_project(args, &config, &prepared, &recorded, &name, output_base, report);
+        previous_output = output;
+    }
+    if let Some(staging) = finish_staging() {
+        write_install_script(&staging)?;
+    }
+    clones.clean_up()?;
+    result?;
+    if let Some(dir) = &stdout_dir {
+        write_selected_file(dir.path(), args.select_file.as_deref(), &mut io::stdout().lock())?;
+    }
+    Ok(())
+}
+
+/// Write one generated file of `dir` to `out`: the `selected` one, or else the only one.  The answers file doesn't count.
+fn write_selected_file(dir: &Path, selected: Option<&str>, out: &mut impl Write) -> Result<(), Box<dyn Error>> {
+    let files = snapshot_files(dir)?;
+    let generated = || files.keys().map(|path| path.display().to_string()).collect::<Vec<_>>().join(", ");
+    let content = match selected {
+        Some(selected) => files.get(Path::new(selected)).ok_or_else(|| {
+            format!(
+                "--select-file {:?} wasn't generated; the generated files are: {}",
+                selected,
+                generated()
+            )
+        })?,
+        None => files.values().next().filter(|_| files.len() == 1).ok_or_else(|| {
+            format!(
+                "--output - writes a single file, but {} were generated ({}); choose one with --select-file",
+                files.len(),
+                generated()
+            )
+        })?,
+    };
+    out.write_all(content)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Render the prepared scaffolds into one project and report the run.  `report` holds the errors found while
+/// preparing the scaffolds.  Returns the resolved answers of every scaffold.
+fn generate_project(
+    args: &Args,
+    config: &Config,
+    prepared: &[(Scaffold, PathBuf)],
+    replayed: &Answers,
+    project_name: &str,
+    output_base: &Path,
+    mut report: ErrorReport,
+) -> Result<Vec<BTreeMap<String, toml::Value>>, Box<dyn Error>> {
+    let options = get_render_options(args, config);
+    // Nothing is written while previewing, and --output - writes to a directory of its own.
+    let _lock = match previewing() || messages_to_stderr() {
+        true => None,
+        false => Some(lock_output(output_base, args.wait)?),
+    };
+    // Without --keep-going, a failure undoes every file the run wrote; --keep-going keeps what succeeded.
+    if !options.keep_going {
+        begin_transaction()?;
+    }
+    begin_limits(options.limits);
+    let rendered = if report.errors.is_empty() || options.keep_going {
+        render_scaffolds(config, prepared, replayed, project_name, output_base, &options, &mut report)
+    } else {
+        Ok(RenderedScaffolds::default())
+    };
+    let rendered = match rendered {
+        Ok(rendered) if report.errors.is_empty() => {
+            commit_transaction();
+            rendered
+        }
+        Ok(rendered) => {
+            roll_back_run()?;
+            rendered
+        }
+        Err(e) => {
+            roll_back_run()?;
+            return Err(e);
+        }
+    };
+
+    let elapsed = RUN_STARTED.get_or_init(Instant::now).elapsed();
+    let mut summary = RunSummary::new(project_name, output_base, &rendered.scaffolds, report.errors.len(), elapsed);
+    say!("\n{}", summary.table());
+    if args.timings {
+        summary.timings = take_timings();
+        say!("{}", summary.timings_table());
+    }
+    if let Some(path) = &args.report {
+        summary.write_json(Path::new(path))?;
+    }
+    LAST_SUMMARY.with(|last| *last.borrow_mut() = Some(summary));
+    if let Some(path) = &args.error_report {
+        report.write_json(Path::new(path))?;
+    }
+    if github_actions_enabled(args) {
+        report_to_github_actions(project_name, &rendered.scaffolds, &report)?;
+    }
+    if !report.errors.is_empty() {
+        if !options.keep_going {
+            return Err(Box::new(report.errors.remove(0)));
+        }
+        report.print();
+        return Err(Box::new(PartialFailure {
+            errors: report.errors.len(),
+            project_name: project_name.to_string(),
+        }));
+    }
+
+    if previewing() {
+        return Ok(rendered.answers);
+    }
+    say!("Scaffolding for project '{}' created successfully!", project_name);
+    let mut answers = record_answers(config, project_name, &rendered.answers);
+    answers.sources = locked_sources(prepared);
+    omit_secrets(&mut answers, prepared.iter().map(|(scaffold, _)| scaffold));
+    write_answers(output_base, &answers)?;
+    if let Some(format) = config.project.as_ref().and_then(|project| project.format.as_ref()) {
+        let written: Vec<PathBuf> = rendered
+            .scaffolds
+            .iter()
+            .flat_map(|scaffold| &scaffold.written)
+            .filter(|(_, status)| matches!(status, WriteStatus::Created | WriteStatus::Overwritten))
+            .map(|(path, _)| path.clone())
+            .collect();
+        run_formatters(output_base, &written, format)?;
+    }
+    if let Some(git) = get_git_init(args, config) {
+        init_git_repository(output_base, &git)?;
+    }
+
+    if args.open {
+        let editor = get_open_with(config).ok_or("--open requires project.open_with, $VISUAL or $EDITOR to be set")?;
+        open_in_editor(&editor, output_base)?;
+    }
+
+    Ok(rendered.answers)
+}
+
+/// Undo the files written by a failed run.
+fn roll_back_run() -> Result<(), Box<dyn Error>> {
+    let files = rollback()?;
+    if files > 0 {
+        say!("Rolled back {} file(s) written before the error", files);
+    }
+    Ok(())
+}
+
+/// Get the name of a scaffold used for logging and answers files.
+pub(crate) fn scaffold_name(scaffold: &Scaffold) -> &str {
+    scaffold.name.as_deref().unwrap_or("unnamed")
+}
+
+/// Get the directory holding the scaffold's templates (defaults to "templates", or the repository root
+/// for cookiecutter and cargo-generate templates).
+pub(crate) fn get_templates_dir(scaffold_repo_base: &Path, scaffold: &Scaffold) -> PathBuf {
+    match (&scaffold.template_dir, scaffold.format) {
+        (Some(template_dir), _) => scaffold_repo_base.join(template_dir),
+        (None, TemplateFormat::Native) => scaffold_repo_base.join("templates"),
+        (None, TemplateFormat::Cookiecutter | TemplateFormat::CargoGenerate) => scaffold_repo_base.to_path_buf(),
+    }
+}
+
+/// Process a single scaffold.  The render `options` (overwrite, strict) are passed
+/// through to render_templates.
+pub(crate) fn process_scaffold(
+    scaffold: &Scaffold,
+    scaffold_repo_base: &Path,
+    project_name: &str,
+    global_variables: Option<&HashMap<String, toml::Value>>,
+    answers: &BTreeMap<String, toml::Value>,
+    output_base: &Path,
+    options: &RenderOptions,
+) -> Result<ScaffoldOutcome, RunError> {
+    let error = |stage, file: Option<&str>, e: Box<dyn Error>| RunError::new(scaffold_name(scaffold), stage, file, e.as_ref());
+    // Observers hear of every hook before it runs, and a cancelled run stops before it.
+    let start_hook = |stage: &str, hook: &str| {
+        notify(|observer| observer.on_hook(scaffold_name(scaffold), stage, hook));
+        check_cancelled().map_err(|e| error(ErrorStage::Hook, Some(hook), e.into()))
+    };
+
+    let mut outcome = ScaffoldOutcome::default();
+
+    // --- Determine the Templates Directory ---
+    let templates_dir = get_templates_dir(scaffold_repo_base, scaffold);
+    say!("Rendering templates from: {:?}", templates_dir);
+    notify(|observer| observer.on_scaffold_start(scaffold_name(scaffold), output_base));
+
+    // --- Set Up the Templating Context ---
+    let mut context = scaffold_context(scaffold, project_name, global_variables, answers, output_base);
+    for warning in variable_warnings(scaffold, &templates_dir, project_name, global_variables) {
+        say!("Warning: {}", warning);
+    }
+
+    let result = (|| {
+        // --- Run a Capturing Pre Hook (if any), whose output becomes a variable ---
+        if let Some((hooks, Hook::Command(pre))) = scaffold.hooks.as_ref().and_then(|hooks| hooks.pre.as_ref().map(|pre| (hooks, pre))) {
+            if let Some(name) = &pre.capture {
+                say!("Running pre-generation hook command: {} (captured as {})", pre.command, name);
+                start_hook("pre", &pre.command)?;
+                let captured = timed("pre hook", Some(scaffold_name(scaffold)), || {
+                    run_command_hook(pre, hooks, output_base)
+                })
+                .map_err(|e| error(ErrorStage::Hook, Some(&pre.command), e))?;
+                let captured = toml::Value::String(captured.unwrap_or_default());
+                let captured = match declared_types(scaffold).get(name.as_str()) {
+                    Some(kind) => kind
+                        .coerce(&captured)
+                        .map_err(|e| error(ErrorStage::Hook, Some(&pre.command), format!("`{}`: {}", name, e).into()))?,
+                    None => captured,
+                };
+                context.insert(name, &captured);
+                outcome.hooks_run += 1;
+            }
+        }
+
+        // --- Compute the Computed Variables ---
+        apply_computed_variables(scaffold, &mut context, options).map_err(|e| error(ErrorStage::Render, None, e))?;
+
+        // --- Run the Plan Hook (if any), which may change the files and variables ---
+        let planned;
+        let scaffold = match scaffold
+            .hooks
+            .as_ref()
+            .and_then(|hooks| hooks.plan.as_ref().map(|plan| (hooks, plan)))
+        {
+            Some((hooks, plan_script)) => {
+                let plan_hook_path = resolve_hook_path(&scaffold_repo_base.join(native_separators(plan_script)), hooks, cfg!(windows));
+                say!("Running plan hook: {:?}", plan_hook_path);
+                start_hook("plan", plan_script)?;
+                let plan_hook = render_hook_script(&plan_hook_path, &context, output_base, options)
+                    .map_err(|e| error(ErrorStage::Hook, Some(plan_script), e))?;
+                let plan = Plan {
+                    scaffold: scaffold_name(scaffold).to_string(),
+                    project_name: project_name.to_string(),
+                    output: output_base.to_string_lossy().to_string(),
+                    format: scaffold.format,
+                    variables: match context.clone().into_json() {
+                        serde_json::Value::Object(variables) => variables,
+                        _ => Default::default(),
+                    },
+                    files: scaffold.template.files.clone(),
+                };
+                let plan = timed("plan hook", Some(scaffold_name(scaffold)), || {
+                    run_plan_hook(&plan_hook.path, hooks, output_base, plan)
+                })
+                .map_err(|e| error(ErrorStage::Hook, Some(plan_script), e))?;
+                outcome.hooks_run += 1;
+                context = Context::from_value(serde_json::Value::Object(plan.variables))
+                    .map_err(|e| error(ErrorStage::Hook, Some(plan_script), e.into()))?;
+                planned = Scaffold {
+                    template: TemplateConfig {
+                        files: plan.files,
+                        ..scaffold.template.clone()
+                    },
+                    ..scaffold.clone()
+                };
+                &planned
+            }
+            None => scaffold,
+        };
+
+        // --- Render Templates / Copy Files (with overwrite and strict flags) ---
+        let rendered = timed("render", Some(scaffold_name(scaffold)), || {
+            render_scaffold_templates(scaffold, &templates_dir, output_base, &context, answers, options)
+        })
+        .map_err(|e| match e.downcast::<FileError>() {
+            Ok(file_error) => RunError::from_file_error(scaffold_name(scaffold), &file_error),
+            Err(e) => error(ErrorStage::Render, None, e),
+        })?;
+        outcome.errors.extend(
+            rendered
+                .errors
+                .iter()
+                .map(|file_error| RunError::from_file_error(scaffold_name(scaffold), file_error)),
+        );
+        outcome.written = rendered.written;
+
+        // --- Decide Whether Hooks Should Run ---
+        let hooks = scaffold.hooks.as_ref().filter(|hooks| {
+            if previewing() {
+                say!("Skipping hooks because nothing is written (diff)");
+                return false;
+            }
+            if !outcome.errors.is_empty() {
+                say!("Skipping hooks because rendering failed (keep-going)");
+                return false;
+            }
+            let changed = outcome.written.iter().any(|(_, status)| status.is_change());
+            if hooks.run_when == HookRunWhen::OnChanges && !changed {
+                say!("Skipping hooks because no files were created or changed (run_when=on_changes)");
+                return false;
+            }
+            true
+        });
+
+        // --- Run Pre-Generation Hook (if any) ---
+        if let Some(hooks) = hooks {
+            // A capturing pre hook already ran before rendering.
+            if let Some(pre) = hooks.pre.as_ref().filter(|pre| pre.capture().is_none()) {
+                start_hook("pre", pre.path())?;
+                timed("pre hook", Some(scaffold_name(scaffold)), || {
+                    run_generation_hook("pre-generation", pre, hooks, scaffold_repo_base, &context, output_base, options)
+                })
+                .map_err(|e| error(ErrorStage::Hook, Some(pre.path()), e))?;
+                outcome.hooks_run += 1;
+            }
+        }
+
+        // --- Run Post-Generation Hook (if any) ---
+        if let Some(hooks) = hooks {
+            if let Some(post) = &hooks.post {
+                start_hook("post", post.path())?;
+                timed("post hook", Some(scaffold_name(scaffold)), || {
+                    run_generation_hook("post-generation", post, hooks, scaffold_repo_base, &context, output_base, options)
+                })
+                .map_err(|e| error(ErrorStage::Hook, Some(post.path()), e))?;
+                outcome.hooks_run += 1;
+            }
+        }
+        Ok(())
+    })();
+
+    let first_error = result.as_ref().err().or(outcome.errors.first());
+    notify(|observer| observer.on_scaffold_finish(scaffold_name(scaffold), first_error.map(|e| e as &dyn Error)));
+    match result {
+        Ok(()) => Ok(outcome),
+        // With --keep-going, the error is reported with the rest.
+        Err(e) if options.keep_going => {
+            outcome.errors.push(e);
+            Ok(outcome)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// The templating context of a scaffold: the project name, global and scaffold variables, the scaffold's answers
+/// and the metadata of the target repository.
+pub(crate) fn scaffold_context(
+    scaffold: &Scaffold,
+    project_name: &str,
+    global_variables: Option<&HashMap<String, toml::Value>>,
+    answers: &BTreeMap<String, toml::Value>,
+    output_base: &Path,
+) -> Context {
+    let mut context = build_context(project_name, global_variables, scaffold.variables.as_ref());
+    for (key, value) in answers {
+        context.insert(key, value);
+    }
+    if let Some(target_repo) = target_repo_metadata(output_base).filter(|_| !context.contains_key("target_repo")) {
+        context.insert("target_repo", &target_repo);
+    }
+    context
+}
+
+/// Render the `computed` variables of a scaffold into the context, each after the computed variables it uses.
+pub(crate) fn apply_computed_variables(scaffold: &Scaffold, context: &mut Context, options: &RenderOptions) -> Result<(), Box<dyn Error>> {
+    let mut pending = BTreeMap::new();
+    for (name, expression) in &scaffold.computed {
+        let template =
+            Template::new(name, None, expression).map_err(|e| format!("Invalid computed variable `{}`: {}", name, error_chain(&e)))?;
+        let uses: BTreeSet<String> = template_variables(&template)
+            .into_keys()
+            .filter(|used| scaffold.computed.contains_key(used))
+            .collect();
+        pending.insert(name.as_str(), (expression, uses));
+    }
+    while !pending.is_empty() {
+        let ready: Vec<(&str, &String)> = pending
+            .iter()
+            .filter(|(_, (_, uses))| uses.iter().all(|used| !pending.contains_key(used.as_str())))
+            .map(|(name, (expression, _))| (*name, *expression))
+            .collect();
+        if ready.is_empty() {
+            let names: Vec<&str> = pending.keys().copied().collect();
+            return Err(format!("Computed variables use each other in a cycle: {}", names.join(", ")).into());
+        }
+        for (name, expression) in ready {
+            pending.remove(name);
+            let value = render_string(expression, &format!("computed variable `{}`", name), context, options)?;
+            context.insert(name, &value);
+        }
+    }
+    Ok(())
+}
+
+/// Find the files that more than one scaffold would write, and destinations that differ only in case.  Every
+/// collision is an error, or a warning with --allow-collisions.
+fn check_collisions(
+    config: &Config,
+    prepared: &[(Scaffold, PathBuf)],
+    answers: &[BTreeMap<String, toml::Value>],
+    project_name: &str,
+    output_base: &Path,
+    options: &RenderOptions,
+) -> Vec<RunError> {
+    let mut writers: BTreeMap<PathBuf, Vec<&str>> = BTreeMap::new();
+    for ((scaffold, repo_base), scaffold_answers) in prepared.iter().zip(answers) {
+        let mut context = scaffold_context(scaffold, project_name, config.variables.as_ref(), scaffold_answers, output_base);
+        // Errors are reported by the actual run.
+        apply_computed_variables(scaffold, &mut context, options).ok();
+        let templates_dir = get_templates_dir(repo_base, scaffold);
+        for dest in planned_destinations(scaffold, &templates_dir, output_base, &context, scaffold_answers, options) {
+            writers.entry(dest).or_default().push(scaffold_name(scaffold));
+        }
+    }
+    let mut errors = Vec::new();
+    for (dest, scaffolds) in writers.iter().filter(|(_, scaffolds)| scaffolds.len() > 1) {
+        let names = scaffolds.iter().map(|name| format!("'{}'", name)).collect::<Vec<_>>().join(", ");
+        if options.allow_collisions {
+            say!("Warning: {:?} is written by more than one scaffold: {}", dest, names);
+            continue;
+        }
+        errors.push(RunError {
+            scaffold: scaffolds[scaffolds.len() - 1].to_string(),
+            stage: ErrorStage::Validation,
+            file: None,
+            message: format!("{:?} is written by more than one scaffold: {}", dest, names),
+        });
+    }
+    // Destinations that differ only in case are one file on macOS and Windows: an error there (a warning with
+    // --allow-collisions), and a warning elsewhere, as the project may be checked out there later.
+    let case_insensitive = cfg!(any(windows, target_os = "macos"));
+    for (dests, scaffolds) in case_collisions(&writers) {
+        let dests = dests.iter().map(|dest| format!("{:?}", dest)).collect::<Vec<_>>().join(" and ");
+        let names = scaffolds.iter().map(|name| format!("'{}'", name)).collect::<Vec<_>>().join(", ");
+        let message = format!(
+            "{} differ only in case and overwrite each other on case-insensitive file systems (written by {})",
+            dests, names
+        );
+        if !case_insensitive || options.allow_collisions {
+            say!("Warning: {}", message);
+            continue;
+        }
+        errors.push(RunError {
+            scaffold: scaffolds[scaffolds.len() - 1].to_string(),
+            stage: ErrorStage::Validation,
+            file: None,
+            message,
+        });
+    }
+    errors
+}
+
+/// Group the planned destinations that differ only in case, e.g. "README.md" and "readme.md", with the scaffolds
+/// writing them.
+fn case_collisions<'a>(writers: &'a BTreeMap<PathBuf, Vec<&'a str>>) -> Vec<(Vec<&'a PathBuf>, Vec<&'a str>)> {
+    let mut folded: BTreeMap<String, (Vec<&PathBuf>, Vec<&str>)> = BTreeMap::new();
+    for (dest, scaffolds) in writers {
+        let (dests, names) = folded.entry(dest.to_string_lossy().to_lowercase()).or_default();
+        dests.push(dest);
+        for name in scaffolds {
+            if !names.contains(name) {
+                names.push(name);
+            }
+        }
+    }
+    folded.into_values().filter(|(dests, _)| dests.len() > 1).collect()
+}
+
+/// Render the templates of a scaffold in its format.  Cookiecutter templates read their variables from `answers`.
+pub(crate) fn render_scaffold_templates(
+    scaffold: &Scaffold,
+    templates_dir: &Path,
+    output_base: &Path,
+    context: &Context,
+    answers: &BTreeMap<String, toml::Value>,
+    options: &RenderOptions,
+) -> Result<RenderResult, Box<dyn Error>> {
+    reset_sequences();
+    match scaffold.format {
+        TemplateFormat::Native => render_templates(templates_dir, output_base, scaffold, context, options),
+        TemplateFormat::Cookiecutter => render_cookiecutter(templates_dir, output_base, context, answers, options),
+        TemplateFormat::CargoGenerate => render_cargo_generate(templates_dir, output_base, context, options),
+    }
+}
+
+/// Prepare a scaffold and apply the manifest its repository ships, if any.  Returns the combined scaffold and the
+/// repository's base path; remote repositories are cloned into a temporary directory that the caller must clean up.
+pub(crate) fn load_scaffold(scaffold: &Scaffold, git: &GitOptions) -> Result<(Scaffold, PathBuf), RunError> {
+    let (repo_base, url) = prepare_scaffold(scaffold, git)?;
+    // From here on, the repository is the mirror that was obtained.
+    let scaffold = &Scaffold {
+        repo: url.into(),
+        ..scaffold.clone()
+    };
+    match apply_repo_manifest(scaffold, &repo_base) {
+        Ok(merged) => Ok((merged, repo_base)),
+        Err(e) => {
+            if is_temporary_clone(&repo_base) {
+                clean_up_persistent_dirs(vec![repo_base]).ok();
+            }
+            Err(RunError::new(
+                scaffold_name(scaffold),
+                ErrorStage::Validation,
+                Some(REPO_MANIFEST_FILE),
+                e.as_ref(),
+            ))
+        }
+    }
+}
+
+/// Validate a scaffold and obtain its repository, returning its base path and URL.  Remote repositories are cloned
+/// into a temporary directory that the caller must clean up.
+pub(crate) fn prepare_scaffold(scaffold: &Scaffold, git: &GitOptions) -> Result<(PathBuf, String), RunError> {
+    let error = |stage, e: Box<dyn Error>| RunError::new(scaffold_name(scaffold), stage, None, e.as_ref());
+    validate_scaffold(scaffold).map_err(|e| error(ErrorStage::Validation, e))?;
+    acquire_scaffold_repo(scaffold, git).map_err(|e| error(ErrorStage::Clone, e))
+}
+
+/// Check that a scaffold's configuration is usable before anything is cloned or written.
+pub(crate) fn validate_scaffold(scaffold: &Scaffold) -> Result<(), Box<dyn Error>> {
+    if scaffold.repo.is_empty() || scaffold.repo.urls().iter().any(|url| url.trim().is_empty()) {
+        return Err("`repo` must not be empty".into());
+    }
+    if let Some(version) = &scaffold.version {
+        semver::VersionReq::parse(version).map_err(|e| format!("Invalid `version = {:?}`: {}", version, e))?;
+    }
+    if let (Some(_), Some(url)) = (&scaffold.sha256, scaffold.repo.urls().iter().find(|url| !is_archive(url))) {
+        return Err(format!("`sha256` verifies archives, but {} isn't a .tar or .tar.gz archive", url).into());
+    }
+    for file in &scaffold.template.files {
+        if file.dir.is_none() && (file.src.is_empty() || scaffold.template.dest(file).is_empty()) {
+            return Err("Template file entries must set both `src` and `dest` (or `auto_dest = true`), or `dir`".into());
+        }
+    }
+    for pattern in &scaffold.template.raw {
+        glob::Pattern::new(pattern).map_err(|e| format!("Invalid raw pattern `{}`: {}", pattern, e))?;
+    }
+    if scaffold
+        .hooks
+        .as_ref()
+        .and_then(|hooks| hooks.post.as_ref())
+        .is_some_and(|post| post.capture().is_some())
+    {
+        return Err("`capture` is only supported on pre hooks, which run before the templates are rendered".into());
+    }
+    for prompt in scaffold.prompts.iter().flatten() {
+        let Some(kind) = prompt.kind else { continue };
+        // Answers are compared with the choices, so they must already have the type.
+        if let Some(value) = prompt
+            .default
+            .iter()
+            .chain(prompt.choices.iter().flatten())
+            .find(|value| VariableType::of(value) != Some(kind))
+        {
+            return Err(format!(
+                "Prompt '{}' has `type = \"{}\"`, but {} isn't {}",
+                prompt.name,
+                kind.name(),
+                value,
+                kind.described()
+            )
+            .into());
+        }
+    }
+    Ok(())
+}
+
+/// Check that every required variable of a scaffold is set and non-empty in the global or scaffold variables or the
+/// replayed answers, or is asked by a prompt, reporting all missing variables at once.
+fn check_required_variables(
+    scaffold: &Scaffold,
+    project_name: &str,
+    global_variables: Option<&HashMap<String, toml::Value>>,
+    replayed_answers: Option<&BTreeMap<String, toml::Value>>,
+) -> Result<(), Box<dyn Error>> {
+    let is_set = |value: &toml::Value| match value {
+        toml::Value::String(value) => !value.trim().is_empty(),
+        toml::Value::Array(values) => !values.is_empty(),
+        toml::Value::Table(table) => !table.is_empty(),
+        _ => true,
+    };
+    let missing: Vec<&str> = scaffold
+        .required_variables
+        .iter()
+        .filter(|name| {
+            let value = scaffold
+                .variables
+                .as_ref()
+                .and_then(|variables| variables.get(*name))
+                .or_else(|| replayed_answers.and_then(|answers| answers.get(*name)))
+                .or_else(|| global_variables.and_then(|variables| variables.get(*name)));
+            let set = match name.as_str() {
+                "project_name" => !project_name.trim().is_empty(),
+                _ => value.is_some_and(is_set),
+            };
+            !set && !scaffold.prompts.iter().flatten().any(|prompt| &prompt.name == *name)
+        })
+        .map(String::as_str)
+        .collect();
+    if !missing.is_empty() {
+        return Err(format!("Missing required variables: {}", missing.join(", ")).into());
+    }
+    Ok(())
+}
+
+/// Warn about scaffold variables that no template of the scaffold references, and about scaffold variables that
+/// shadow a global variable or the project name with a different value.  Unused variables aren't reported for
+/// scaffolds whose plan, Rhai or templated hooks see every variable, nor for cookiecutter and cargo-generate templates.
+fn variable_warnings(
+    scaffold: &Scaffold,
+    templates_dir: &Path,
+    project_name: &str,
+    global_variables: Option<&HashMap<String, toml::Value>>,
+) -> Vec<String> {
+    let Some(variables) = &scaffold.variables else {
+        return Vec::new();
+    };
+    let mut warnings = Vec::new();
+    let mut names: Vec<&String> = variables.keys().collect();
+    names.sort();
+
+    for name in &names {
+        let shadowed = match name.as_str() {
+            "project_name" => Some(toml::Value::String(project_name.to_string())),
+            _ => global_variables.and_then(|globals| globals.get(*name)).cloned(),
+        };
+        if let Some(shadowed) = shadowed.filter(|shadowed| shadowed != &variables[*name]) {
+            let shadowed_kind = match name.as_str() {
+                "project_name" => "project name",
+                _ => "global variable",
+            };
+            warnings.push(format!(
+                "variable `{}` of scaffold '{}' ({}) shadows the {} ({})",
+                name,
+                scaffold_name(scaffold),
+                display_value(&variables[*name]),
+                shadowed_kind,
+                display_value(&shadowed)
+            ));
+        }
+    }
+
+    let hooks_see_variables = scaffold.hooks.as_ref().is_some_and(|hooks| {
+        hooks.plan.is_some()
+            || [&hooks.pre, &hooks.post]
+                .into_iter()
+                .flatten()
+                .any(|hook| matches!(hook, Hook::Rhai(_)) || hook.path().ends_with(".tera"))
+    });
+    if scaffold.format != TemplateFormat::Native || hooks_see_variables {
+        return warnings;
+    }
+    // Warnings are best effort: templates that don't parse are reported when they are rendered.
+    let Ok(mut sources) = scaffold_template_sources(templates_dir, scaffold) else {
+        return warnings;
+    };
+    sources.extend(template_library(templates_dir, &scaffold.template).unwrap_or_default());
+    sources.extend(scaffold.computed.clone());
+    let referenced: HashSet<String> = sources
+        .iter()
+        .filter_map(|(location, source)| Template::new(location, None, source).ok())
+        .flat_map(|template| template_variables(&template).into_keys())
+        .collect();
+    for name in names.into_iter().filter(|name| !referenced.contains(*name)) {
+        warnings.push(format!(
+            "variable `{}` of scaffold '{}' isn't used by any of its templates",
+            name,
+            scaffold_name(scaffold)
+        ));
+    }
+    warnings
+}
+
+/// Find a program in the directories of `path` (the PATH variable), trying the PATHEXT extensions on Windows.
+fn find_program(name: &str, path: &std::ffi::OsStr) -> Option<PathBuf> {
+    let extensions: Vec<String> = match cfg!(windows) {
+        true => std::env::var("PATHEXT")
+            .unwrap_or_else(|_| ".EXE;.CMD;.BAT".to_string())
+            .split(';')
+            .map(str::to_string)
+            .collect(),
+        false => Vec::new(),
+    };
+    std::env::split_paths(path).find_map(|dir| {
+        std::iter::once(dir.join(name))
+            .chain(extensions.iter().map(|extension| dir.join(format!("{}{}", name, extension))))
+            .find(|candidate| candidate.is_file())
+    })
+}
+
+/// The first version number in a program's output, e.g. [1, 27, 3] for "Client Version: v1.27.3".
+fn parse_tool_version(output: &str) -> Option<Vec<u64>> {
+    static VERSION: OnceLock<Regex> = OnceLock::new();
+    let version = VERSION.get_or_init(|| Regex::new(r"\d+(?:\.\d+)*").unwrap());
+    let found = version.find(output)?;
+    found.as_str().split('.').map(|part| part.parse().ok()).collect()
+}
+
+/// Compare two versions component by component, treating missing components as 0.
+fn compare_versions(a: &[u64], b: &[u64]) -> std::cmp::Ordering {
+    (0..a.len().max(b.len()))
+        .map(|i| a.get(i).unwrap_or(&0).cmp(b.get(i).unwrap_or(&0)))
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(std::cmp::Ordering::Equal)
+}
+
+/// Check one `binaries` entry of `[scaffolds.requires]`, e.g. "kubectl >= 1.27", against the programs on `path`.
+fn check_binary_requirement(requirement: &str, path: &std::ffi::OsStr) -> Result<(), String> {
+    static REQUIREMENT: OnceLock<Regex> = OnceLock::new();
+    let pattern = REQUIREMENT.get_or_init(|| Regex::new(r"^\s*([^\s<>=]+)\s*(?:(>=|<=|==|=|>|<)\s*(\S+))?\s*$").unwrap());
+    let captures = pattern
+        .captures(requirement)
+        .ok_or_else(|| format!("invalid requirement `{}`, expected e.g. `kubectl >= 1.27`", requirement))?;
+    let name = &captures[1];
+    let program = find_program(name, path).ok_or_else(|| format!("`{}` is not installed or not on the PATH", name))?;
+    let (Some(operator), Some(required)) = (captures.get(2), captures.get(3)) else {
+        return Ok(());
+    };
+    let required_version =
+        parse_tool_version(required.as_str()).ok_or_else(|| format!("invalid version `{}` in `{}`", required.as_str(), requirement))?;
+    let output = Command::new(&program)
+        .arg("--version")
+        .output()
+        .map_err(|e| format!("could not run `{} --version`: {}", name, e))?;
+    let reported = format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let version = parse_tool_version(&reported).ok_or_else(|| format!("`{} --version` printed no version number", name))?;
+    let ordering = compare_versions(&version, &required_version);
+    let satisfied = match operator.as_str() {
+        ">=" => ordering.is_ge(),
+        "<=" => ordering.is_le(),
+        ">" => ordering.is_gt(),
+        "<" => ordering.is_lt(),
+        _ => ordering.is_eq(),
+    };
+    if !satisfied {
+        let version: Vec<String> = version.iter().map(u64::to_string).collect();
+        return Err(format!(
+            "`{}` {} doesn't satisfy {} {}",
+            name,
+            version.join("."),
+            operator.as_str(),
+            required.as_str()
+        ));
+    }
+    Ok(())
+}
+
+/// Check the `[scaffolds.requires]` of every scaffold against the programs on `path` and the environment, reporting
+/// every unmet requirement at once.
+fn check_requirements(scaffolds: &[Scaffold], path: &std::ffi::OsStr) -> Result<(), Box<dyn Error>> {
+    let mut unmet = Vec::new();
+    for scaffold in scaffolds {
+        let Some(requires) = &scaffold.requires else {
+            continue;
+        };
+        for requirement in &requires.binaries {
+            if let Err(reason) = check_binary_requirement(requirement, path) {
+                unmet.push(format!("scaffold '{}': {}", scaffold_name(scaffold), reason));
+            }
+        }
+        for name in &requires.env {
+            if std::env::var_os(name).is_none_or(|value| value.is_empty()) {
+                unmet.push(format!(
+                    "scaffold '{}': environment variable {} is not set",
+                    scaffold_name(scaffold),
+                    name
+                ));
+            }
+        }
+    }
+    if !unmet.is_empty() {
+        return Err(format!("Unmet prerequisites:\n  {}", unmet.join("\n  ")).into());
+    }
+    Ok(())
+}
+
+/// Check that the output directory suits the scaffold's mode, before anything is generated.
+pub(crate) fn check_scaffold_mode(scaffold: &Scaffold, output_base: &Path) -> Result<(), Box<dyn Error>> {
+    match scaffold.mode {
+        ScaffoldMode::Any => Ok(()),
+        ScaffoldMode::Create => {
+            let non_empty = output_base.is_dir() && fs::read_dir(output_base)?.next().is_some();
+            if non_empty {
+                return Err(format!(
+                    "mode = \"create\" refuses to generate into the non-empty directory {:?}",
+                    output_base
+                )
+                .into());
+            }
+            Ok(())
+        }
+        ScaffoldMode::Augment => {
+            if !PROJECT_ROOT_MARKERS.iter().any(|marker| output_base.join(marker).exists()) {
+                return Err(format!(
+                    "mode = \"augment\" requires an existing project in {:?} (none of {} found)",
+                    output_base,
+                    PROJECT_ROOT_MARKERS.join(", ")
+                )
+                .into());
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use std::collections::{BTreeMap, HashMap};
+    use std::fs;
+    use std::path::PathBuf;
+    use tempfile::TempDir;
+    use tera::Tera;
+
+    // Test that computed variables are rendered after the computed variables they use, and that cycles are rejected.
+    #[test]
+    fn test_computed_variables() -> Result<(), Box<dyn std::error::Error>> {
+        let toml_content = r#"
+[[scaffolds]]
+name = "Computed"
+repo = "local_repo"
+
+[scaffolds.template]
+files = []
+
+[scaffolds.variables]
+registry = "ghcr.io/acme"
+
+[scaffolds.computed]
+tagged = "{{ image }}:latest"
+image = "{{ registry }}/{{ project_name | kebab_case }}"
+"#;
+        let config: Config = toml::from_str(toml_content)?;
+        let scaffold = &config.scaffolds[0];
+        let mut context = build_context("MyService", None, scaffold.variables.as_ref());
+        apply_computed_variables(scaffold, &mut context, &RenderOptions::default())?;
+        assert_eq!(Tera::one_off("{{ tagged }}", &context, false)?, "ghcr.io/acme/my-service:latest");
+        assert!(
+            scaffold_description(scaffold, None).contains("| `image` | `{{ registry }}/{{ project_name \\| kebab_case }}` | computed |")
+        );
+
+        let mut cyclic = scaffold.clone();
+        cyclic.computed.insert("image".to_string(), "{{ tagged }}".to_string());
+        let err = apply_computed_variables(&cyclic, &mut build_context("MyService", None, None), &RenderOptions::default()).unwrap_err();
+        assert!(err.to_string().contains("cycle: image, tagged"), "{}", err);
+        Ok(())
+    }
+
+    // Test process_scaffold with a simulated local repository.
+    #[test]
+    fn test_process_scaffold_local() -> Result<(), Box<dyn std::error::Error>> {
+        // Create a temporary directory to simulate a local repository.
+        let local_repo_dir = TempDir::new()?;
+        // Create the "templates" directory within it.
+        let templates_subdir = local_repo_dir.path().join("templates");
+        fs::create_dir_all(&templates_subdir)?;
+
+        // Write a sample template into the local repo.
+        let template_content = "Hello, {{ project_name }}!";
+        let template_file_path = templates_subdir.join("greeting.txt.tera");
+        fs::write(&template_file_path, template_content)?;
+
+        // Build a Scaffold instance referring to the local repository.
+        let scaffold = Scaffold {
+            name: Some("LocalTest".to_string()),
+            repo: local_repo_dir.path().to_string_lossy().to_string().into(),
+            template_dir: Some("templates".to_string()),
+            template: TemplateConfig {
+                files: vec![TemplateFile {
+                    src: "greeting.txt.tera".to_string(),
+                    dest: "greeting.txt".to_string(),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        // Create a temporary output directory.
+        let output_dir = TempDir::new()?;
+
+        // Local repositories are used in place, so there is no temporary clone to clean up.
+        let (repo_base, _) = prepare_scaffold(&scaffold, &GitOptions::default())?;
+        assert_eq!(repo_base, fs::canonicalize(local_repo_dir.path())?);
+
+        // Process the scaffold.
+        process_scaffold(
+            &scaffold,
+            &repo_base,
+            "LocalProject",
+            None,
+            &BTreeMap::new(),
+            output_dir.path(),
+            &overwrite_options(),
+        )?;
+
+        // Verify that the rendered file has been created.
+        let output_file_path = output_dir.path().join("greeting.txt");
+        let rendered_content = fs::read_to_string(output_file_path)?;
+        assert_eq!(rendered_content, "Hello, LocalProject!");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dest_file_expands_variables_from_scaffold_variables() -> Result<(), Box<dyn std::error::Error>> {
+        // Create a temporary directory to simulate a local repository.
+        let local_repo_dir = TempDir::new()?;
+        let templates_dir = local_repo_dir.path().join("templates");
+        fs::create_dir_all(&templates_dir)?;
+
+        // Create necessary sub-directory and template file.
+        let kind_cluster_dir = templates_dir.join("kind-cluster");
+        fs::create_dir_all(&kind_cluster_dir)?;
+        let template_file_path = kind_cluster_dir.join("kind_config.yaml.tera");
+        fs::write(
+            &template_file_path,
+            "config: {{project_name}}, workers: {{kind_workers}}, env: {{environment}}",
+        )?;
+
+        // Build a Scaffold instance referring to the local repository.
+        let scaffold = Scaffold {
+            name: Some("LocalTest".to_string()),
+            repo: local_repo_dir.path().to_string_lossy().to_string().into(),
+            template_dir: Some("templates".to_string()),
+            template: TemplateConfig {
+                files: vec![TemplateFile {
+                    src: "kind-cluster/kind_config.yaml.tera".to_string(),
+                    dest: "{{project_name}}-{{environment}}-kind_config{{kind_workers}}.yaml".to_string(),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+            hooks: None,
+            variables: Some({
+                let mut map = HashMap::new();
+                map.insert("kind_workers".to_string(), toml::Value::Integer(3));
+                map.insert("environment".to_string(), toml::Value::String("development".to_string()));
+                map
+            }),
+            ..Default::default()
+        };
+
+        // Create a temporary output directory.
+        let output_dir = TempDir::new()?;
+        process_scaffold(
+            &scaffold,
+            &prepare_scaffold(&scaffold, &GitOptions::default())?.0,
+            "MyProject",
+            None,
+            &BTreeMap::new(),
+            output_dir.path(),
+            &overwrite_options(),
+        )?;
+
+        // Verify that the destination filename has expanded variables.
+        let expected_output_file = output_dir.path().join("MyProject-development-kind_config3.yaml");
+        assert!(expected_output_file.exists());
+        assert!(expected_output_file
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .contains("MyProject-development-kind_config3.yaml"));
+        let output_content = fs::read_to_string(expected_output_file)?;
+        assert!(output_content.contains("config: MyProject, workers: 3, env: development"));
+        Ok(())
+    }
+
+    // Test that --keep-going renders the good files and collects every broken one.
+    #[test]
+    fn test_keep_going_collects_errors() -> Result<(), Box<dyn std::error::Error>> {
+        let repo_dir = TempDir::new()?;
+        let templates = repo_dir.path().join("templates");
+        fs::create_dir_all(&templates)?;
+        fs::write(templates.join("good.txt.tera"), "Hello, {{ project_name }}!")?;
+        fs::write(templates.join("broken.txt.tera"), "{{ missing }}")?;
+        fs::write(templates.join("unclosed.txt.tera"), "{% if true %}")?;
+
+        let file = |name: &str| TemplateFile {
+            src: format!("{}.txt.tera", name),
+            dest: format!("{}.txt", name),
+            ..Default::default()
+        };
+        let scaffold = Scaffold {
+            name: Some("Broken".to_string()),
+            repo: repo_dir.path().to_string_lossy().to_string().into(),
+            template: TemplateConfig {
+                files: vec![file("broken"), file("good"), file("unclosed")],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let output_dir = TempDir::new()?;
+        let options = RenderOptions {
+            keep_going: true,
+            ..overwrite_options()
+        };
+
+        let outcome = process_scaffold(
+            &scaffold,
+            &prepare_scaffold(&scaffold, &GitOptions::default())?.0,
+            "KeepGoing",
+            None,
+            &BTreeMap::new(),
+            output_dir.path(),
+            &options,
+        )?;
+        assert_eq!(fs::read_to_string(output_dir.path().join("good.txt"))?, "Hello, KeepGoing!");
+        // Parse errors are reported while registering, before any file is rendered.
+        let files: Vec<_> = outcome.errors.iter().map(|e| e.file.as_deref()).collect();
+        assert_eq!(files, [Some("unclosed.txt.tera"), Some("broken.txt.tera")]);
+        assert!(outcome
+            .errors
+            .iter()
+            .all(|e| e.scaffold == "Broken" && e.stage == ErrorStage::Render));
+
+        // Without --keep-going the first broken file stops the scaffold.
+        let error = process_scaffold(
+            &scaffold,
+            &prepare_scaffold(&scaffold, &GitOptions::default())?.0,
+            "KeepGoing",
+            None,
+            &BTreeMap::new(),
+            output_dir.path(),
+            &overwrite_options(),
+        )
+        .unwrap_err();
+        assert_eq!(error.stage, ErrorStage::Render);
+        assert!(error.to_string().starts_with("scaffold 'Broken': render error"));
+
+        // An invalid file entry is reported before anything is cloned.
+        let invalid = Scaffold {
+            repo: "https://example.invalid/repo.git".into(),
+            template: TemplateConfig {
+                files: vec![TemplateFile {
+                    src: "only-src.tera".to_string(),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let Err(error) = prepare_scaffold(&invalid, &GitOptions::default()) else {
+            panic!("expected a validation error");
+        };
+        assert_eq!(error.stage, ErrorStage::Validation);
+        Ok(())
+    }
+
+    // Test that a failed run restores the files and directories written before the error.
+    #[test]
+    fn test_rollback_failed_run() -> Result<(), Box<dyn std::error::Error>> {
+        let repo_dir = TempDir::new()?;
+        let templates = repo_dir.path().join("templates");
+        fs::create_dir_all(&templates)?;
+        fs::write(templates.join("new.txt.tera"), "new {{ project_name }}")?;
+        fs::write(templates.join("existing.txt.tera"), "replaced")?;
+        fs::write(templates.join("broken.txt.tera"), "{{ missing }}")?;
+        let output_dir = TempDir::new()?;
+        fs::write(output_dir.path().join("existing.txt"), "original")?;
+
+        let repo = repo_dir.path().to_string_lossy().replace('\\', "/");
+        let config = || {
+            from_toml::<Config>(&format!(
+                r#"
+[project]
+overwrite = true
+
+[[scaffolds]]
+name = "Good"
+repo = "{repo}"
+template.files = [
+  {{ src = "new.txt.tera", dest = "nested/dir/new.txt" }},
+  {{ src = "existing.txt.tera", dest = "existing.txt" }},
+]
+
+[[scaffolds]]
+name = "Broken"
+repo = "{repo}"
+template.files = [{{ src = "broken.txt.tera", dest = "broken.txt" }}]
+"#
+            ))
+        };
+        let output = output_dir.path().to_string_lossy().into_owned();
+        let args = Args::try_parse_from(["scaficionado", "-o", &output])?;
+        let error = generate_config(&args, config()?)
+            .err()
+            .ok_or("the broken scaffold should fail the run")?;
+        assert!(error.to_string().starts_with("scaffold 'Broken': render error"));
+        assert_eq!(fs::read_to_string(output_dir.path().join("existing.txt"))?, "original");
+        assert!(!output_dir.path().join("nested").exists());
+
+        // --keep-going keeps the files of the scaffolds that succeeded.
+        let args = Args::try_parse_from(["scaficionado", "-o", &output, "--keep-going"])?;
+        assert!(generate_config(&args, config()?).is_err());
+        assert_eq!(fs::read_to_string(output_dir.path().join("existing.txt"))?, "replaced");
+        assert!(output_dir.path().join("nested/dir/new.txt").exists());
+        Ok(())
+    }
+
+    // Test that two scaffolds writing the same file fail the run before anything is written.
+    #[test]
+    fn test_scaffold_collisions() -> Result<(), Box<dyn std::error::Error>> {
+        let repo_dir = TempDir::new()?;
+        let templates = repo_dir.path().join("templates");
+        fs::create_dir_all(templates.join("docs"))?;
+        fs::write(templates.join("readme.md.tera"), "# {{ project_name }}")?;
+        fs::write(templates.join("docs/README.md"), "docs")?;
+        fs::write(templates.join("other.md"), "other")?;
+        let output_dir = TempDir::new()?;
+
+        let repo = repo_dir.path().to_string_lossy().replace('\\', "/");
+        let config = || {
+            from_toml::<Config>(&format!(
+                r#"
+[[scaffolds]]
+name = "Docs"
+repo = "{repo}"
+template.files = [{{ src = "docs", dest = "." }}, {{ src = "other.md", dest = "other.md" }}]
+
+[[scaffolds]]
+name = "Readme"
+repo = "{repo}"
+template.files = [{{ src = "readme.md.tera", dest = "{{{{ 'README' }}}}.md" }}]
+"#
+            ))
+        };
+        let output = output_dir.path().to_string_lossy().into_owned();
+        let args = Args::try_parse_from(["scaficionado", "-o", &output])?;
+        let error = generate_config(&args, config()?).err().ok_or("the collision should fail the run")?;
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "scaffold 'Readme': validation error: {:?} is written by more than one scaffold: 'Docs', 'Readme'",
+                output_dir.path().join("README.md")
+            )
+        );
+        assert!(!output_dir.path().join("other.md").exists());
+
+        // --allow-collisions only warns; with --overwrite the last scaffold wins.
+        let args = Args::try_parse_from(["scaficionado", "-o", &output, "--allow-collisions", "--overwrite"])?;
+        generate_config(&args, config()?)?;
+        assert_eq!(fs::read_to_string(output_dir.path().join("README.md"))?, "# MyExampleProject");
+
+        // Destinations that differ only in case are grouped with the scaffolds writing them.
+        let (upper, lower) = (PathBuf::from("out/README.md"), PathBuf::from("out/readme.md"));
+        let writers = BTreeMap::from([
+            (upper.clone(), vec!["Docs"]),
+            (PathBuf::from("out/notes.md"), vec!["Docs"]),
+            (lower.clone(), vec!["Docs", "Readme"]),
+        ]);
+        assert_eq!(case_collisions(&writers), vec![(vec![&upper, &lower], vec!["Docs", "Readme"])]);
+        Ok(())
+    }
+
+    // Test that create and augment modes check the output directory before generating.
+    #[test]
+    fn test_scaffold_mode() -> Result<(), Box<dyn std::error::Error>> {
+        let output_dir = TempDir::new()?;
+        let create = Scaffold {
+            mode: ScaffoldMode::Create,
+            ..Default::default()
+        };
+        let augment = Scaffold {
+            mode: ScaffoldMode::Augment,
+            ..Default::default()
+        };
+
+        // A missing or empty directory is a new project.
+        assert!(check_scaffold_mode(&create, &output_dir.path().join("missing")).is_ok());
+        assert!(check_scaffold_mode(&create, output_dir.path()).is_ok());
+        let error = check_scaffold_mode(&augment, output_dir.path()).unwrap_err();
+        assert!(error.to_string().contains("requires an existing project"));
+
+        fs::write(output_dir.path().join("Cargo.toml"), "[package]")?;
+        assert!(check_scaffold_mode(&create, output_dir.path()).is_err());
+        assert!(check_scaffold_mode(&augment, output_dir.path()).is_ok());
+        assert!(check_scaffold_mode(&Scaffold::default(), output_dir.path()).is_ok());
+        Ok(())
+    }
+
+    // Test that missing required variables fail the run with one error before anything is cloned.
+    #[test]
+    fn test_required_variables() -> Result<(), Box<dyn std::error::Error>> {
+        let output_dir = TempDir::new()?;
+        let config: Config = from_toml(
+            r#"
+[variables]
+team = ""
+
+[[scaffolds]]
+name = "Api"
+repo = "git://127.0.0.1:1/unreachable.git"
+required_variables = ["project_name", "team", "region", "port", "owner"]
+variables = { owner = "platform" }
+prompts = [{ name = "region", message = "Region?" }]
+"#,
+        )?;
+        let output = output_dir.path().join("out").to_string_lossy().into_owned();
+        let args = Args::try_parse_from(["scaficionado", "-o", &output])?;
+        let error = generate_config(&args, config)
+            .err()
+            .ok_or("missing variables should fail the run")?;
+        assert_eq!(
+            error.to_string(),
+            "scaffold 'Api': validation error: Missing required variables: team, port"
+        );
+        assert!(!output_dir.path().join("out").exists());
+
+        // Requirements of an extended scaffold or manifest are combined with the scaffold's own.
+        let base = Scaffold {
+            required_variables: vec!["team".to_string(), "port".to_string()],
+            ..Default::default()
+        };
+        let scaffold = Scaffold {
+            required_variables: vec!["port".to_string(), "owner".to_string()],
+            ..Default::default()
+        };
+        let merged = merge_scaffold(&scaffold, RepoManifest::from(base));
+        assert_eq!(merged.required_variables, ["team", "port", "owner"]);
+        Ok(())
+    }
+
+    // Test that [scaffolds.requires] reports every missing program, too old version and unset variable before cloning.
+    #[cfg(unix)]
+    #[test]
+    fn test_requirements() -> Result<(), Box<dyn std::error::Error>> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let bin_dir = TempDir::new()?;
+        let tool = bin_dir.path().join("kubectl");
+        fs::write(&tool, "#!/bin/sh\necho 'Client Version: v1.25.3'\n")?;
+        fs::set_permissions(&tool, fs::Permissions::from_mode(0o755))?;
+        let path = bin_dir.path().as_os_str();
+
+        assert_eq!(parse_tool_version("Docker version 24.0.7, build afdd53b"), Some(vec![24, 0, 7]));
+        assert!(check_binary_requirement("kubectl", path).is_ok());
+        assert!(check_binary_requirement("kubectl >= 1.25", path).is_ok());
+        assert!(check_binary_requirement("kubectl<2", path).is_ok());
+
+        let config = from_toml::<Config>(
+            r#"
+[[scaffolds]]
+name = "Cluster"
+repo = "https://example.invalid/never-cloned.git"
+requires = { binaries = ["kubectl >= 1.27", "docker"], env = ["SCAFICIONADO_TEST_UNSET_VARIABLE"] }
+"#,
+        )?;
+        let error = check_requirements(&config.scaffolds, path)
+            .err()
+            .ok_or("the requirements should be unmet")?;
+        assert_eq!(
+            error.to_string(),
+            "Unmet prerequisites:\n  \
+             scaffold 'Cluster': `kubectl` 1.25.3 doesn't satisfy >= 1.27\n  \
+             scaffold 'Cluster': `docker` is not installed or not on the PATH\n  \
+             scaffold 'Cluster': environment variable SCAFICIONADO_TEST_UNSET_VARIABLE is not set"
+        );
+        Ok(())
+    }
+
+    // Test that --output - writes the only generated file, or the one chosen with --select-file.
+    #[test]
+    fn test_write_selected_file() -> Result<(), Box<dyn std::error::Error>> {
+        let output_dir = TempDir::new()?;
+        fs::create_dir_all(output_dir.path().join(".scaficionado"))?;
+        fs::write(output_dir.path().join(ANSWERS_FILE), "[scaffolds]")?;
+        fs::write(output_dir.path().join("deployment.yaml"), "kind: Deployment\n")?;
+
+        let mut out = Vec::new();
+        write_selected_file(output_dir.path(), None, &mut out)?;
+        assert_eq!(out, b"kind: Deployment\n");
+
+        fs::create_dir_all(output_dir.path().join("k8s"))?;
+        fs::write(output_dir.path().join("k8s/service.yaml"), "kind: Service\n")?;
+        let error = write_selected_file(output_dir.path(), None, &mut Vec::new())
+            .err()
+            .ok_or("two files need --select-file")?;
+        assert_eq!(
+            error.to_string(),
+            "--output - writes a single file, but 2 were generated (deployment.yaml, k8s/service.yaml); choose one with --select-file"
+        );
+        let mut out = Vec::new();
+        write_selected_file(output_dir.path(), Some("k8s/service.yaml"), &mut out)?;
+        assert_eq!(out, b"kind: Service\n");
+        assert!(write_selected_file(output_dir.path(), Some("missing.yaml"), &mut Vec::new()).is_err());
+        Ok(())
+    }
+
+    // Test that unused scaffold variables and variables shadowing global ones with another value are reported.
+    #[test]
+    fn test_variable_warnings() -> Result<(), Box<dyn std::error::Error>> {
+        let repo_dir = TempDir::new()?;
+        let templates = repo_dir.path().join("templates");
+        fs::create_dir_all(&templates)?;
+        fs::write(templates.join("main.tera"), "{{ port }} {% include \"partial.tera\" %}")?;
+        fs::write(templates.join("partial.tera"), "{{ region }}")?;
+
+        let config = from_toml::<Config>(&format!(
+            r#"
+[variables]
+region = "eu"
+team = "web"
+
+[[scaffolds]]
+name = "Api"
+repo = "{}"
+template.files = [{{ src = "main.tera", dest = "{{{{ project_name }}}}.txt" }}]
+variables = {{ port = 8080, region = "us", team = "web", stale = true, project_name = "Other" }}
+"#,
+            repo_dir.path().to_string_lossy().replace('\\', "/")
+        ))?;
+        let scaffold = &config.scaffolds[0];
+        let warnings = variable_warnings(scaffold, &templates, "Demo", config.variables.as_ref());
+        assert_eq!(
+            warnings,
+            [
+                "variable `project_name` of scaffold 'Api' (Other) shadows the project name (Demo)",
+                "variable `region` of scaffold 'Api' (us) shadows the global variable (eu)",
+                "variable `stale` of scaffold 'Api' isn't used by any of its templates",
+                "variable `team` of scaffold 'Api' isn't used by any of its templates",
+            ]
+        );
+
+        // A plan hook receives every variable, so none of them is reported as unused.
+        let scaffold = Scaffold {
+            hooks: Some(HooksConfig {
+                plan: Some("plan.sh".to_string()),
+                ..Default::default()
+            }),
+            ..scaffold.clone()
+        };
+        assert_eq!(variable_warnings(&scaffold, &templates, "Demo", config.variables.as_ref()).len(), 2);
+        Ok(())
+    }
+}
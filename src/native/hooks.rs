@@ -0,0 +1,1018 @@
+//! Hooks run around generation: scripts, commands, Rhai scripts, plan hooks, formatters and the git repository
+//! initialized in the output directory.
+
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+use tera::Context;
+
+use super::*;
+
+/// Sibling hook extensions tried on Windows for ".sh" hooks, in order.
+pub(crate) const DEFAULT_WINDOWS_HOOK_EXTENSIONS: [&str; 3] = ["ps1", "bat", "cmd"];
+
+/// Build the command that runs a hook script with the hook settings, using the configured interpreter, or one for
+/// scripts Windows can't execute directly.
+fn hook_command(script_path: &Path, hooks: &HooksConfig, output_base: &Path) -> Command {
+    let mut command = match hooks.interpreter.as_deref().map(str::split_whitespace) {
+        Some(mut words) if words.clone().next().is_some() => {
+            let mut command = Command::new(words.next().unwrap_or_default());
+            command.args(words).arg(script_path);
+            command
+        }
+        _ => script_command(script_path, cfg!(windows)),
+    };
+    apply_hook_settings(&mut command, hooks, output_base);
+    command
+}
+
+/// Give a hook's command the environment and working directory of the hook settings.
+fn apply_hook_settings(command: &mut Command, hooks: &HooksConfig, output_base: &Path) {
+    command.envs(hooks.env.iter().flatten());
+    if messages_to_stderr() {
+        command.stdout(io::stderr());
+    }
+    if let Some(cwd) = &hooks.cwd {
+        command.current_dir(output_base.join(native_separators(cwd)));
+    }
+}
+
+/// Run a command hook with the hook settings.  Returns what it printed, trimmed, when it captures its output.
+pub(crate) fn run_command_hook(hook: &CommandHook, hooks: &HooksConfig, output_base: &Path) -> Result<Option<String>, Box<dyn Error>> {
+    let mut words = hook.command.split_whitespace();
+    let program = words.next().ok_or("Hook command is empty")?;
+    let mut command = Command::new(program);
+    command.args(words);
+    apply_hook_settings(&mut command, hooks, output_base);
+    if hook.capture.is_some() {
+        command.stdout(std::process::Stdio::piped());
+    }
+    let mut child = command
+        .spawn()
+        .map_err(|e| format!("Failed to run hook command `{}`: {}", hook.command, e))?;
+    let stdout = read_pipe(child.stdout.take());
+    let status = wait_for_hook(&mut child, hooks)?;
+    let stdout = stdout.join().unwrap_or_default();
+    if !status.success() {
+        return Err(format!("Hook command `{}` failed with {}", hook.command, status).into());
+    }
+    Ok(hook.capture.as_ref().map(|_| String::from_utf8_lossy(&stdout).trim().to_string()))
+}
+
+/// Wait for a hook to exit, stopping it after the hook timeout.
+fn wait_for_hook(child: &mut std::process::Child, hooks: &HooksConfig) -> io::Result<std::process::ExitStatus> {
+    let Some(timeout) = hooks.timeout else {
+        return child.wait();
+    };
+    let deadline = Instant::now() + Duration::from_secs(timeout);
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        if Instant::now() >= deadline {
+            child.kill().ok();
+            child.wait().ok();
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("Hook timed out after {}s", timeout),
+            ));
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+}
+
+/// The command that runs a script by itself, or with the interpreter its extension needs where the platform can't
+/// run it directly: PowerShell for `.ps1`, cmd for `.bat` and `.cmd`, and on Windows `sh` (e.g. Git Bash's) for `.sh`.
+pub(crate) fn script_command(script_path: &Path, windows: bool) -> Command {
+    let mut command = match script_path.extension().and_then(|ext| ext.to_str()) {
+        Some("ps1") => {
+            let mut command = Command::new(if windows { "powershell" } else { "pwsh" });
+            command.args(["-NoProfile", "-ExecutionPolicy", "Bypass", "-File"]);
+            command
+        }
+        Some("bat") | Some("cmd") => {
+            let mut command = Command::new("cmd");
+            command.arg("/C");
+            command
+        }
+        Some("sh") if windows => Command::new("sh"),
+        _ => return Command::new(script_path),
+    };
+    command.arg(script_path);
+    command
+}
+
+/// What a scaffold is about to generate, handed to its `plan` hook as JSON.  Only `variables` and `files` of the plan
+/// the hook prints are applied; the other fields are for information.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(default)]
+pub(crate) struct Plan {
+    pub(crate) scaffold: String,
+    pub(crate) project_name: String,
+    pub(crate) output: String,
+    pub(crate) format: TemplateFormat,
+    /// Every variable of the template context.
+    pub(crate) variables: serde_json::Map<String, serde_json::Value>,
+    /// The template entries of a native scaffold.  Cookiecutter and cargo-generate templates have none.
+    pub(crate) files: Vec<TemplateFile>,
+}
+
+/// Run a plan hook located at `script_path` with `plan` on stdin.  Returns the plan the hook printed, or `plan`
+/// itself when it printed nothing.  A non-zero exit rejects the plan with the hook's stderr as the reason.
+pub(crate) fn run_plan_hook(script_path: &Path, hooks: &HooksConfig, output_base: &Path, plan: Plan) -> Result<Plan, Box<dyn Error>> {
+    let mut child = hook_command(script_path, hooks, output_base)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()?;
+    // The output is read while waiting, so that a hook printing more than a pipe holds doesn't block.
+    let stdout = read_pipe(child.stdout.take());
+    let stderr = read_pipe(child.stderr.take());
+    let input = serde_json::to_vec_pretty(&plan)?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A hook that rejects without reading its input closes the pipe early; its exit status tells why.
+        stdin.write_all(&input).ok();
+    }
+    let status = wait_for_hook(&mut child, hooks)?;
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr = stderr.join().unwrap_or_default();
+    if !status.success() {
+        let reason = String::from_utf8_lossy(&stderr).trim().to_string();
+        return Err(match reason.is_empty() {
+            true => format!("Plan rejected by hook ({})", status).into(),
+            false => format!("Plan rejected by hook: {}", reason).into(),
+        });
+    }
+    if stdout.iter().all(u8::is_ascii_whitespace) {
+        return Ok(plan);
+    }
+    serde_json::from_slice(&stdout).map_err(|e| format!("Plan hook printed an invalid plan: {}", e).into())
+}
+
+/// Read a pipe of a child process to the end in a thread.
+fn read_pipe(pipe: Option<impl io::Read + Send + 'static>) -> std::thread::JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut content = Vec::new();
+        if let Some(mut pipe) = pipe {
+            pipe.read_to_end(&mut content).ok();
+        }
+        content
+    })
+}
+
+/// Run a hook script located at `script_path`.
+fn run_hook(script_path: &Path, hooks: &HooksConfig, output_base: &Path) -> io::Result<()> {
+    let status = wait_for_hook(&mut hook_command(script_path, hooks, output_base).spawn()?, hooks)?;
+    if !status.success() {
+        Err(io::Error::other("Hook script failed"))
+    } else {
+        Ok(())
+    }
+}
+
+/// Find the hook script to run.  On Windows, a hook such as "scripts/pre.sh" is replaced by the first
+/// existing sibling with one of the mapped extensions (e.g. "scripts/pre.ps1"), so cross-platform
+/// template repositories can ship both variants without conditional configuration.  A rendered hook such as
+/// "scripts/pre.sh.tera" is replaced the same way, by e.g. "scripts/pre.ps1.tera".
+pub(crate) fn resolve_hook_path(script_path: &Path, hooks: &HooksConfig, windows: bool) -> PathBuf {
+    if !windows {
+        return script_path.to_path_buf();
+    }
+    let (script, suffix) = match script_path.extension() {
+        Some(ext) if ext == "tera" => (script_path.with_extension(""), ".tera"),
+        _ => (script_path.to_path_buf(), ""),
+    };
+    let Some(extension) = script.extension().and_then(|ext| ext.to_str()) else {
+        return script_path.to_path_buf();
+    };
+    let alternatives = match &hooks.windows_extensions {
+        Some(mapping) => mapping.get(extension).cloned().unwrap_or_default(),
+        None if extension == "sh" => DEFAULT_WINDOWS_HOOK_EXTENSIONS.iter().map(|ext| ext.to_string()).collect(),
+        None => Vec::new(),
+    };
+    alternatives
+        .iter()
+        .map(|alternative| {
+            let mut candidate = script.with_extension(alternative).into_os_string();
+            candidate.push(suffix);
+            PathBuf::from(candidate)
+        })
+        .find(|candidate| candidate.is_file())
+        .unwrap_or_else(|| script_path.to_path_buf())
+}
+
+/// Run a pre- or post-generation hook: a script or command as a process, or a Rhai script in the embedded engine.
+pub(crate) fn run_generation_hook(
+    kind: &str,
+    hook: &Hook,
+    hooks: &HooksConfig,
+    scaffold_repo_base: &Path,
+    context: &Context,
+    output_base: &Path,
+    options: &RenderOptions,
+) -> Result<(), Box<dyn Error>> {
+    match hook {
+        Hook::Script(script) => {
+            let hook_path = resolve_hook_path(&scaffold_repo_base.join(native_separators(script)), hooks, cfg!(windows));
+            say!("Running {} hook: {:?}", kind, hook_path);
+            let rendered = render_hook_script(&hook_path, context, output_base, options)?;
+            run_hook(&rendered.path, hooks, output_base)?;
+        }
+        Hook::Rhai(RhaiHook { rhai }) => {
+            let hook_path = scaffold_repo_base.join(native_separators(rhai));
+            say!("Running {} Rhai hook: {:?}", kind, hook_path);
+            let rendered = render_hook_script(&hook_path, context, output_base, options)?;
+            run_rhai_hook(&rendered.path, hooks, context, output_base)?;
+        }
+        Hook::Command(command) => {
+            say!("Running {} hook command: {}", kind, command.command);
+            run_command_hook(command, hooks, output_base)?;
+        }
+    }
+    Ok(())
+}
+
+/// Resolve a path given to a Rhai hook's file functions against the output directory.  Absolute paths and paths
+/// leaving the output directory are rejected, also through symbolic links.
+fn rhai_path(output_base: &Path, path: &str) -> Result<PathBuf, Box<rhai::EvalAltResult>> {
+    let outside = || -> Box<rhai::EvalAltResult> { format!("Path {:?} is outside the output directory", path).into() };
+    if Path::new(path)
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(outside());
+    }
+    let root = resolve_links(output_base).map_err(|_| outside())?;
+    let target = resolve_links(&output_base.join(path)).map_err(|_| outside())?;
+    match target.starts_with(&root) {
+        true => Ok(target),
+        false => Err(outside()),
+    }
+}
+
+/// `path` with the symbolic links of the part of it that exists resolved, followed by the part that doesn't exist
+/// yet.  A dangling link is an error, as writing through it would create its target.
+pub(crate) fn resolve_links(path: &Path) -> io::Result<PathBuf> {
+    for existing in path.ancestors() {
+        if existing.as_os_str().is_empty() || existing.symlink_metadata().is_err() {
+            continue;
+        }
+        let canonical = fs::canonicalize(existing)?;
+        // Joining an empty path would append a separator.
+        return match path.strip_prefix(existing).map_err(io::Error::other)? {
+            rest if rest.as_os_str().is_empty() => Ok(canonical),
+            rest => Ok(canonical.join(rest)),
+        };
+    }
+    Ok(std::env::current_dir()?.join(path))
+}
+
+/// Run a Rhai hook script.  The script sees the scaffold context as `context`, the output directory as `output_dir`
+/// and the hook environment variables as `env`, and can only touch files inside the output directory through
+/// `read_file`, `write_file`, `exists`, `remove_file`, `create_dir` and `list_dir`; it can't `import` modules.  The
+/// hook timeout applies.
+fn run_rhai_hook(script_path: &Path, hooks: &HooksConfig, context: &Context, output_base: &Path) -> Result<(), Box<dyn Error>> {
+    type RhaiResult<T> = Result<T, Box<rhai::EvalAltResult>>;
+    let io_error = |path: &str, e: io::Error| -> Box<rhai::EvalAltResult> { format!("{}: {}", path, e).into() };
+
+    let mut engine = rhai::Engine::new();
+    // `import` would load scripts from anywhere on the filesystem.
+    engine.set_module_resolver(rhai::module_resolvers::DummyModuleResolver::new());
+    let root = output_base.to_path_buf();
+    engine.register_fn("read_file", move |path: &str| -> RhaiResult<String> {
+        fs::read_to_string(rhai_path(&root, path)?).map_err(|e| io_error(path, e))
+    });
+    let root = output_base.to_path_buf();
+    engine.register_fn("write_file", move |path: &str, content: &str| -> RhaiResult<()> {
+        let target = rhai_path(&root, path)?;
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(|e| io_error(path, e))?;
+        }
+        fs::write(target, content).map_err(|e| io_error(path, e))
+    });
+    let root = output_base.to_path_buf();
+    engine.register_fn("exists", move |path: &str| -> RhaiResult<bool> {
+        Ok(rhai_path(&root, path)?.exists())
+    });
+    let root = output_base.to_path_buf();
+    engine.register_fn("remove_file", move |path: &str| -> RhaiResult<()> {
+        fs::remove_file(rhai_path(&root, path)?).map_err(|e| io_error(path, e))
+    });
+    let root = output_base.to_path_buf();
+    engine.register_fn("create_dir", move |path: &str| -> RhaiResult<()> {
+        fs::create_dir_all(rhai_path(&root, path)?).map_err(|e| io_error(path, e))
+    });
+    let root = output_base.to_path_buf();
+    engine.register_fn("list_dir", move |path: &str| -> RhaiResult<rhai::Array> {
+        let mut names = fs::read_dir(rhai_path(&root, path)?)
+            .and_then(|entries| {
+                entries
+                    .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+                    .collect::<io::Result<Vec<_>>>()
+            })
+            .map_err(|e| io_error(path, e))?;
+        names.sort();
+        Ok(names.into_iter().map(rhai::Dynamic::from).collect())
+    });
+    if let Some(timeout) = hooks.timeout {
+        let deadline = Instant::now() + Duration::from_secs(timeout);
+        engine.on_progress(move |_| (Instant::now() > deadline).then_some(rhai::Dynamic::UNIT));
+    }
+
+    let mut scope = rhai::Scope::new();
+    scope.push_constant("context", rhai::serde::to_dynamic(context.clone().into_json())?);
+    scope.push_constant("output_dir", output_base.to_string_lossy().into_owned());
+    let env: rhai::Map = hooks.env.iter().flatten().map(|(key, value)| (key.into(), value.into())).collect();
+    scope.push_constant("env", env);
+    let script = read_text_file(script_path)?;
+    engine
+        .run_with_scope(&mut scope, &script)
+        .map_err(|e| format!("Rhai hook {:?} failed: {}", script_path, e).into())
+}
+
+/// A hook script ready to run: the script itself, or a rendered copy of a `.tera` script.
+pub(crate) struct HookScript {
+    pub(crate) path: PathBuf,
+    /// Holds the rendered copy until the hook has run.
+    _rendered: Option<TempDir>,
+}
+
+/// Render a hook script ending in `.tera` with the scaffold context, plus `output_dir`, into a temporary file
+/// named without the suffix and with the permissions of the original.  Other scripts are run as they are.
+pub(crate) fn render_hook_script(
+    script_path: &Path,
+    context: &Context,
+    output_base: &Path,
+    options: &RenderOptions,
+) -> Result<HookScript, Box<dyn Error>> {
+    if script_path.extension().is_none_or(|ext| ext != "tera") {
+        return Ok(HookScript {
+            path: script_path.to_path_buf(),
+            _rendered: None,
+        });
+    }
+    let mut context = context.clone();
+    context.insert("output_dir", &output_base.to_string_lossy());
+    let rendered = render_string(&read_text_file(script_path)?, &script_path.to_string_lossy(), &context, options)?;
+    let dir = TempDir::new()?;
+    let path = dir.path().join(script_path.file_stem().unwrap_or_default());
+    fs::write(&path, rendered).map_err(|source| FsError::Write {
+        path: path.clone(),
+        source,
+    })?;
+    fs::set_permissions(&path, fs::metadata(script_path)?.permissions())?;
+    Ok(HookScript {
+        path,
+        _rendered: Some(dir),
+    })
+}
+
+/// Run the formatters of `format` over the `files` written to `output_base`, each once with all of its matching
+/// files as arguments, in the output directory.
+pub(crate) fn run_formatters(output_base: &Path, files: &[PathBuf], format: &FormatConfig) -> Result<(), Box<dyn Error>> {
+    for (pattern, command) in format.formatters()? {
+        let matching: Vec<&Path> = files
+            .iter()
+            .filter_map(|file| file.strip_prefix(output_base).ok())
+            .filter(|file| pattern.matches(&file.to_string_lossy().replace('\\', "/")))
+            .collect();
+        if matching.is_empty() {
+            continue;
+        }
+        let mut parts = command.split_whitespace();
+        let program = parts
+            .next()
+            .ok_or_else(|| format!("Formatter command for `{}` is empty", pattern))?;
+        say!("Formatting {} file(s) with: {}", matching.len(), command);
+        let failure = match Command::new(program).args(parts).args(&matching).current_dir(output_base).output() {
+            Ok(output) if output.status.success() => continue,
+            Ok(output) => format!(
+                "Formatter '{}' failed with {}: {}",
+                command,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+            Err(e) => format!("Failed to run formatter '{}': {}", command, e),
+        };
+        match format.on_failure {
+            FormatFailure::Warn => say!("Warning: {}", failure),
+            FormatFailure::Fail => return Err(failure.into()),
+        }
+    }
+    Ok(())
+}
+
+/// Initialize a git repository in `output_base`, extend its `.gitignore`, stage everything and optionally commit.
+/// Nothing is done when the output directory already is inside a git repository.
+pub(crate) fn init_git_repository(output_base: &Path, git: &GitInitConfig) -> Result<(), Box<dyn Error>> {
+    let library = git_library()?;
+    if let Some(existing) = library.discover(output_base) {
+        say!(
+            "Skipping git init: {:?} is already inside the git repository {:?}",
+            output_base,
+            existing
+        );
+        return Ok(());
+    }
+
+    if !git.gitignore.is_empty() {
+        let path = output_base.join(".gitignore");
+        let mut gitignore = match fs::read_to_string(&path) {
+            Ok(existing) => existing,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(source) => return Err(FsError::Read { path, source }.into()),
+        };
+        let listed: Vec<String> = gitignore.lines().map(|line| line.trim().to_string()).collect();
+        for pattern in git.gitignore.iter().filter(|pattern| !listed.contains(pattern)) {
+            if !gitignore.is_empty() && !gitignore.ends_with('\n') {
+                gitignore.push('\n');
+            }
+            gitignore.push_str(pattern);
+            gitignore.push('\n');
+        }
+        fs::write(&path, gitignore).map_err(|source| FsError::Write { path, source })?;
+    }
+
+    let message = git.commit.then(|| git.message.as_deref().unwrap_or("Initial commit"));
+    let staged = library.init(output_base, git.branch.as_deref(), message)?;
+    say!("Initialized a git repository in {:?}", output_base);
+    if let Some(message) = message {
+        say!("Committed {} file(s): {}", staged, message);
+    }
+    Ok(())
+}
+
+/// The git user name and "name <email>" author string, as cargo-generate's `username` and `authors`.
+pub(crate) fn git_author() -> (String, String) {
+    let library = git_library().ok();
+    let get = |key: &str| library.and_then(|library| library.config_value(key));
+    let name = get("user.name").or_else(|| std::env::var("USER").ok()).unwrap_or_default();
+    let authors = match get("user.email") {
+        Some(email) => format!("{} <{}>", name, email),
+        None => name.clone(),
+    };
+    (name, authors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use std::collections::{BTreeMap, HashMap};
+    use std::fs;
+    use std::io;
+    use std::path::PathBuf;
+    use tempfile::TempDir;
+
+    // Test the run_hook function with a hook that succeeds.
+    #[test]
+    fn test_run_hook_success() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let script_path = temp_dir.path().join("hook.sh");
+
+        // Create a simple script that exits successfully.
+        #[cfg(unix)]
+        fs::write(&script_path, "#!/bin/sh\nexit 0")?;
+        #[cfg(windows)]
+        fs::write(&script_path, "@echo off\r\nexit 0")?;
+
+        // On Unix, ensure the script is executable.
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mut perms = fs::metadata(&script_path)?.permissions();
+            perms.set_mode(0o755);
+            fs::set_permissions(&script_path, perms)?;
+        }
+
+        // run_hook should complete without error.
+        run_hook(&script_path, &HooksConfig::default(), temp_dir.path())?;
+        Ok(())
+    }
+
+    // Test the run_hook function with a hook that fails.
+    #[test]
+    fn test_run_hook_failure() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let script_path = temp_dir.path().join("hook_fail.sh");
+
+        // Create a simple script that exits with a non-zero status.
+        #[cfg(unix)]
+        fs::write(&script_path, "#!/bin/sh\nexit 1")?;
+        #[cfg(windows)]
+        fs::write(&script_path, "@echo off\r\nexit 1")?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mut perms = fs::metadata(&script_path)?.permissions();
+            perms.set_mode(0o755);
+            fs::set_permissions(&script_path, perms)?;
+        }
+
+        // run_hook should return an error.
+        let result = run_hook(&script_path, &HooksConfig::default(), temp_dir.path());
+        assert!(result.is_err());
+        Ok(())
+    }
+
+    // Test that project hook defaults are inherited and overridden, and that hooks are stopped after their timeout.
+    #[cfg(unix)]
+    #[test]
+    fn test_hooks_defaults() -> Result<(), Box<dyn std::error::Error>> {
+        let config: Config = from_toml(
+            r#"
+[project.hooks_defaults]
+interpreter = "sh -e"
+env = { GREETING = "hello", TEAM = "platform" }
+cwd = "work"
+timeout = 30
+
+[[scaffolds]]
+repo = "."
+hooks = { post = "post.sh", env = { TEAM = "payments" }, timeout = 1 }
+"#,
+        )?;
+        let defaults = config
+            .project
+            .as_ref()
+            .and_then(|project| project.hooks_defaults.as_ref())
+            .ok_or("no defaults")?;
+        let mut hooks = config.scaffolds[0].hooks.clone().ok_or("no hooks")?;
+        apply_hooks_defaults(&mut hooks, defaults);
+        assert_eq!(hooks.interpreter.as_deref(), Some("sh -e"));
+        assert_eq!(hooks.timeout, Some(1));
+
+        // The script isn't executable: the interpreter runs it, in the working directory below the output.
+        let repo_dir = TempDir::new()?;
+        let output_dir = TempDir::new()?;
+        fs::create_dir_all(output_dir.path().join("work"))?;
+        let script = repo_dir.path().join("post.sh");
+        fs::write(&script, "echo \"$GREETING $TEAM\" > hook.txt\n")?;
+        run_hook(&script, &hooks, output_dir.path())?;
+        assert_eq!(fs::read_to_string(output_dir.path().join("work/hook.txt"))?, "hello payments\n");
+
+        fs::write(&script, "sleep 5\n")?;
+        let error = run_hook(&script, &hooks, output_dir.path()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(error.to_string(), "Hook timed out after 1s");
+        Ok(())
+    }
+
+    // Test that Windows hook shims pick the first existing sibling script.
+    #[test]
+    fn test_resolve_hook_path_windows_shims() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let hook = temp_dir.path().join("pre.sh");
+        fs::write(&hook, "")?;
+        fs::write(temp_dir.path().join("pre.bat"), "")?;
+
+        let defaults = HooksConfig::default();
+        // Other platforms always run the configured hook.
+        assert_eq!(resolve_hook_path(&hook, &defaults, false), hook);
+        // On Windows, pre.ps1 doesn't exist so pre.bat is used.
+        assert_eq!(resolve_hook_path(&hook, &defaults, true), temp_dir.path().join("pre.bat"));
+
+        // A custom mapping without a matching sibling falls back to the configured hook.
+        let custom = HooksConfig {
+            windows_extensions: Some(HashMap::from([("sh".to_string(), vec!["ps1".to_string()])])),
+            ..Default::default()
+        };
+        assert_eq!(resolve_hook_path(&hook, &custom, true), hook);
+
+        // Rendered hooks keep their `.tera` suffix.
+        let rendered = temp_dir.path().join("post.sh.tera");
+        fs::write(&rendered, "")?;
+        fs::write(temp_dir.path().join("post.ps1.tera"), "")?;
+        assert_eq!(resolve_hook_path(&rendered, &defaults, true), temp_dir.path().join("post.ps1.tera"));
+        Ok(())
+    }
+
+    // Test that hooks with run_when = "on_changes" are skipped when nothing changed.
+    #[cfg(unix)]
+    #[test]
+    fn test_hooks_run_when_on_changes() -> Result<(), Box<dyn std::error::Error>> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let local_repo_dir = TempDir::new()?;
+        let templates_subdir = local_repo_dir.path().join("templates");
+        fs::create_dir_all(&templates_subdir)?;
+        fs::write(templates_subdir.join("greeting.txt.tera"), "Hello, {{ project_name }}!")?;
+
+        // The hook appends a line to a counter file next to itself every time it runs.
+        let hook_path = local_repo_dir.path().join("post.sh");
+        fs::write(&hook_path, "#!/bin/sh\necho ran >> \"$(dirname \"$0\")/count.txt\"\n")?;
+        let mut perms = fs::metadata(&hook_path)?.permissions();
+        perms.set_mode(0o755);
+        fs::set_permissions(&hook_path, perms)?;
+
+        let scaffold = Scaffold {
+            name: Some("HookTest".to_string()),
+            repo: local_repo_dir.path().to_string_lossy().to_string().into(),
+            template_dir: Some("templates".to_string()),
+            template: TemplateConfig {
+                files: vec![TemplateFile {
+                    src: "greeting.txt.tera".to_string(),
+                    dest: "greeting.txt".to_string(),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+            hooks: Some(HooksConfig {
+                pre: None,
+                post: Some(Hook::Script("post.sh".to_string())),
+                run_when: HookRunWhen::OnChanges,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let output_dir = TempDir::new()?;
+        let first = process_scaffold(
+            &scaffold,
+            &prepare_scaffold(&scaffold, &GitOptions::default())?.0,
+            "HookProject",
+            None,
+            &BTreeMap::new(),
+            output_dir.path(),
+            &RenderOptions::default(),
+        )?;
+        assert_eq!(first.hooks_run, 1);
+        let second = process_scaffold(
+            &scaffold,
+            &prepare_scaffold(&scaffold, &GitOptions::default())?.0,
+            "HookProject",
+            None,
+            &BTreeMap::new(),
+            output_dir.path(),
+            &RenderOptions::default(),
+        )?;
+        assert_eq!(second.hooks_run, 0);
+        process_scaffold(
+            &scaffold,
+            &prepare_scaffold(&scaffold, &GitOptions::default())?.0,
+            "HookProject",
+            None,
+            &BTreeMap::new(),
+            output_dir.path(),
+            &overwrite_options(),
+        )?;
+
+        // Only the first run created a file, so the hook should have run exactly once.
+        let count = fs::read_to_string(local_repo_dir.path().join("count.txt"))?;
+        assert_eq!(count.lines().count(), 1);
+        Ok(())
+    }
+
+    // Test that a hook ending in .tera is rendered with the scaffold context and keeps its permissions.
+    #[cfg(unix)]
+    #[test]
+    fn test_templated_hook() -> Result<(), Box<dyn std::error::Error>> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let local_repo_dir = TempDir::new()?;
+        let templates_subdir = local_repo_dir.path().join("templates");
+        fs::create_dir_all(&templates_subdir)?;
+        fs::write(templates_subdir.join("greeting.txt.tera"), "Hello, {{ project_name }}!")?;
+
+        let hook_path = local_repo_dir.path().join("post.sh.tera");
+        fs::write(
+            &hook_path,
+            "#!/bin/sh\necho \"{{ project_name }} {{ license }} $(basename \"$0\")\" > '{{ output_dir }}/hook.txt'\n",
+        )?;
+        let mut perms = fs::metadata(&hook_path)?.permissions();
+        perms.set_mode(0o755);
+        fs::set_permissions(&hook_path, perms)?;
+
+        let scaffold = Scaffold {
+            name: Some("HookTest".to_string()),
+            repo: local_repo_dir.path().to_string_lossy().to_string().into(),
+            template_dir: Some("templates".to_string()),
+            template: TemplateConfig {
+                files: vec![TemplateFile {
+                    src: "greeting.txt.tera".to_string(),
+                    dest: "greeting.txt".to_string(),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+            hooks: Some(HooksConfig {
+                post: Some(Hook::Script("post.sh.tera".to_string())),
+                ..Default::default()
+            }),
+            variables: Some(HashMap::from([("license".to_string(), toml::Value::String("MIT".to_string()))])),
+            ..Default::default()
+        };
+
+        let output_dir = TempDir::new()?;
+        let result = process_scaffold(
+            &scaffold,
+            &prepare_scaffold(&scaffold, &GitOptions::default())?.0,
+            "HookProject",
+            None,
+            &BTreeMap::new(),
+            output_dir.path(),
+            &RenderOptions::default(),
+        )?;
+        assert_eq!(result.hooks_run, 1);
+        assert_eq!(fs::read_to_string(output_dir.path().join("hook.txt"))?, "HookProject MIT post.sh\n");
+        Ok(())
+    }
+
+    // Test that a plan hook can filter the files and change the variables of a scaffold, or reject its plan.
+    #[cfg(unix)]
+    #[test]
+    fn test_plan_hook() -> Result<(), Box<dyn std::error::Error>> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let repo_dir = TempDir::new()?;
+        fs::create_dir_all(repo_dir.path().join("templates"))?;
+        fs::write(repo_dir.path().join("templates/a.txt.tera"), "{{ greeting }}")?;
+        fs::write(repo_dir.path().join("templates/b.txt"), "b")?;
+        let plan_input = repo_dir.path().join("plan.json");
+        let hook = repo_dir.path().join("plan.sh");
+        fs::write(
+            &hook,
+            format!(
+                "#!/bin/sh\ncat > {:?}\necho '{{\"variables\": {{\"greeting\": \"Howdy\"}}, \"files\": [{{\"src\": \"a.txt.tera\", \"dest\": \"a.txt\"}}]}}'\n",
+                plan_input
+            ),
+        )?;
+        fs::set_permissions(&hook, fs::Permissions::from_mode(0o755))?;
+        let file = |src: &str, dest: &str| TemplateFile {
+            src: src.to_string(),
+            dest: dest.to_string(),
+            ..Default::default()
+        };
+        let mut scaffold = Scaffold {
+            repo: repo_dir.path().to_string_lossy().to_string().into(),
+            template: TemplateConfig {
+                files: vec![file("a.txt.tera", "a.txt"), file("b.txt", "b.txt")],
+                ..Default::default()
+            },
+            hooks: Some(HooksConfig {
+                plan: Some("plan.sh".to_string()),
+                ..Default::default()
+            }),
+            variables: Some(HashMap::from([("greeting".to_string(), toml::Value::String("Hello".to_string()))])),
+            ..Default::default()
+        };
+        let output_dir = TempDir::new()?;
+        process_scaffold(
+            &scaffold,
+            repo_dir.path(),
+            "Planned",
+            None,
+            &BTreeMap::new(),
+            output_dir.path(),
+            &overwrite_options(),
+        )?;
+
+        let plan: Plan = serde_json::from_str(&fs::read_to_string(&plan_input)?)?;
+        assert_eq!(plan.project_name, "Planned");
+        assert_eq!(plan.variables["greeting"], "Hello");
+        assert_eq!(plan.files.len(), 2);
+        assert_eq!(fs::read_to_string(output_dir.path().join("a.txt"))?, "Howdy");
+        assert!(!output_dir.path().join("b.txt").exists());
+
+        // A hook exiting non-zero rejects the plan before anything is written.
+        fs::write(&hook, "#!/bin/sh\necho 'b.txt is not allowed' >&2\nexit 1\n")?;
+        scaffold.variables = None;
+        let output_dir = TempDir::new()?;
+        let error = process_scaffold(
+            &scaffold,
+            repo_dir.path(),
+            "Planned",
+            None,
+            &BTreeMap::new(),
+            output_dir.path(),
+            &overwrite_options(),
+        )
+        .err()
+        .ok_or("the plan should be rejected")?;
+        assert_eq!(error.stage, ErrorStage::Hook);
+        assert!(error.message.contains("b.txt is not allowed"));
+        assert_eq!(fs::read_dir(output_dir.path())?.count(), 0);
+        Ok(())
+    }
+
+    // Test that a pre hook command captures its output into a variable used by the templates.
+    #[cfg(unix)]
+    #[test]
+    fn test_capture_hook_output() -> Result<(), Box<dyn std::error::Error>> {
+        let repo_dir = TempDir::new()?;
+        fs::create_dir_all(repo_dir.path().join("templates"))?;
+        fs::write(repo_dir.path().join("templates/AUTHORS.tera"), "{{ author_name }} <{{ team }}>")?;
+        let output_dir = TempDir::new()?;
+        let config = |post: &str| {
+            from_toml::<Config>(&format!(
+                r#"
+[[scaffolds]]
+name = "Authors"
+repo = "{}"
+template.files = [{{ src = "AUTHORS.tera", dest = "AUTHORS" }}]
+variables = {{ team = "platform" }}
+hooks = {{ cwd = ".", pre = {{ command = "echo   Ada Lovelace ", capture = "author_name" }}, post = {} }}
+"#,
+                repo_dir.path().display(),
+                post
+            ))
+        };
+        let output = output_dir.path().to_string_lossy().into_owned();
+        let args = Args::try_parse_from(["scaficionado", "-o", &output])?;
+        generate_config(&args, config(r#"{ command = "touch done" }"#)?)?;
+        assert_eq!(fs::read_to_string(output_dir.path().join("AUTHORS"))?, "Ada Lovelace <platform>");
+        assert!(output_dir.path().join("done").exists());
+
+        // Only pre hooks can capture, and a failing command fails the run.
+        let error = load_scaffold(
+            &config(r#"{ command = "true", capture = "x" }"#)?.scaffolds[0],
+            &GitOptions::default(),
+        )
+        .err()
+        .ok_or("the post capture should be rejected")?;
+        assert!(error.to_string().contains("`capture` is only supported on pre hooks"), "{}", error);
+        let hook = CommandHook {
+            command: "false".to_string(),
+            capture: Some("x".to_string()),
+        };
+        let error = run_command_hook(&hook, &HooksConfig::default(), output_dir.path()).unwrap_err();
+        assert!(error.to_string().starts_with("Hook command `false` failed"), "{}", error);
+        Ok(())
+    }
+
+    // Test that [project.format] runs each formatter once over the written files matching its pattern.
+    #[cfg(unix)]
+    #[test]
+    fn test_run_formatters() -> Result<(), Box<dyn std::error::Error>> {
+        use std::os::unix::fs::PermissionsExt;
+        let output_dir = TempDir::new()?;
+        let tools = TempDir::new()?;
+        let formatter = tools.path().join("fmt.sh");
+        fs::write(&formatter, "#!/bin/sh\nfor file in \"$@\"; do echo formatted > \"$file\"; done\n")?;
+        fs::set_permissions(&formatter, fs::Permissions::from_mode(0o755))?;
+        let written: Vec<PathBuf> = ["main.rs", "src/lib.rs", "README.md"]
+            .iter()
+            .map(|file| output_dir.path().join(file))
+            .collect();
+        for file in &written {
+            fs::create_dir_all(file.parent().unwrap())?;
+            fs::write(file, "fn  main(){}")?;
+        }
+
+        let config: Config = toml::from_str(&format!(
+            "[project.format]\non_failure = \"fail\"\n\"**/*.rs\" = \"{}\"\n",
+            formatter.display()
+        ))?;
+        let format = config.project.unwrap().format.unwrap();
+        assert_eq!(format.on_failure, FormatFailure::Fail);
+        run_formatters(output_dir.path(), &written, &format)?;
+        assert_eq!(fs::read_to_string(&written[0])?, "formatted\n");
+        assert_eq!(fs::read_to_string(&written[1])?, "formatted\n");
+        assert_eq!(fs::read_to_string(&written[2])?, "fn  main(){}");
+
+        // A failing formatter only warns, unless on_failure = "fail".
+        let failing = FormatConfig {
+            on_failure: FormatFailure::Warn,
+            formatters: BTreeMap::from([("*.md".to_string(), "false".to_string())]),
+        };
+        run_formatters(output_dir.path(), &written, &failing)?;
+        let failing = FormatConfig {
+            on_failure: FormatFailure::Fail,
+            ..failing
+        };
+        let error = run_formatters(output_dir.path(), &written, &failing).unwrap_err();
+        assert!(error.to_string().starts_with("Formatter 'false' failed"), "{}", error);
+        Ok(())
+    }
+
+    // Test that a Rhai hook sees the context and can only write inside the output directory.
+    #[test]
+    fn test_rhai_hook() -> Result<(), Box<dyn std::error::Error>> {
+        let repo_dir = TempDir::new()?;
+        let templates = repo_dir.path().join("templates");
+        fs::create_dir_all(&templates)?;
+        fs::create_dir_all(repo_dir.path().join("hooks"))?;
+        fs::write(templates.join("README.md.tera"), "# {{ project_name }}")?;
+        fs::write(
+            repo_dir.path().join("hooks/setup.rhai"),
+            r#"
+create_dir("docs");
+write_file("docs/" + context.project_name + ".txt", `${context.license} ${env.STAGE} ${read_file("README.md")}`);
+if list_dir("docs") != [context.project_name + ".txt"] || !exists("README.md") {
+    throw "unexpected files";
+}
+"#,
+        )?;
+        fs::write(repo_dir.path().join("hooks/escape.rhai"), r#"write_file("../escaped.txt", "x");"#)?;
+        let output_dir = TempDir::new()?;
+
+        let repo = repo_dir.path().to_string_lossy().replace('\\', "/");
+        let config = |post: &str| {
+            from_toml::<Config>(&format!(
+                r#"
+[[scaffolds]]
+name = "Readme"
+repo = "{repo}"
+template.files = [{{ src = "README.md.tera", dest = "README.md" }}]
+variables = {{ license = "MIT" }}
+hooks = {{ post = {{ rhai = "{post}" }}, env = {{ STAGE = "dev" }} }}
+"#
+            ))
+        };
+        let output = output_dir.path().join("out").to_string_lossy().into_owned();
+        let args = Args::try_parse_from(["scaficionado", "-o", &output, "-p", "Demo"])?;
+        generate_config(&args, config("hooks/setup.rhai")?)?;
+        assert_eq!(fs::read_to_string(output_dir.path().join("out/docs/Demo.txt"))?, "MIT dev # Demo");
+
+        let args = Args::try_parse_from(["scaficionado", "-o", &output, "-p", "Demo", "-w"])?;
+        let error = generate_config(&args, config("hooks/escape.rhai")?)
+            .err()
+            .ok_or("writing outside the output directory should fail")?;
+        assert!(error.to_string().contains("is outside the output directory"), "{}", error);
+        assert!(!output_dir.path().join("escaped.txt").exists());
+
+        // Scripts can't import modules, which could be anywhere.
+        let module = output_dir.path().join("module.rhai");
+        fs::write(&module, r#"write_file("imported.txt", "x");"#)?;
+        fs::write(
+            repo_dir.path().join("hooks/import.rhai"),
+            format!("import {:?} as m;", module.to_string_lossy()),
+        )?;
+        let error = generate_config(&args, config("hooks/import.rhai")?)
+            .err()
+            .ok_or("importing a module should fail")?;
+        assert!(error.to_string().contains("Module not found"), "{}", error);
+        assert!(!output_dir.path().join("out/imported.txt").exists());
+
+        // Nor leave the output directory through a symbolic link.
+        #[cfg(unix)]
+        {
+            let outside = TempDir::new()?;
+            std::os::unix::fs::symlink(outside.path(), output_dir.path().join("out/link"))?;
+            std::os::unix::fs::symlink(outside.path().join("missing.txt"), output_dir.path().join("out/dangling"))?;
+            for (name, script) in [
+                ("through_link", r#"write_file("link/escaped.txt", "x");"#),
+                ("read_link", r#"list_dir("link");"#),
+                ("dangling", r#"write_file("dangling", "x");"#),
+            ] {
+                fs::write(repo_dir.path().join(format!("hooks/{}.rhai", name)), script)?;
+                let error = generate_config(&args, config(&format!("hooks/{}.rhai", name))?)
+                    .err()
+                    .ok_or(format!("{} should fail", name))?;
+                assert!(error.to_string().contains("is outside the output directory"), "{}", error);
+            }
+            assert!(!outside.path().join("escaped.txt").exists());
+            assert!(!outside.path().join("missing.txt").exists());
+        }
+        Ok(())
+    }
+
+    // Test that [project.git] initializes a repository, extends .gitignore and commits the generated files.
+    #[test]
+    fn test_git_init() -> Result<(), Box<dyn std::error::Error>> {
+        let repo_dir = TempDir::new()?;
+        let templates = repo_dir.path().join("templates");
+        fs::create_dir_all(&templates)?;
+        fs::write(templates.join("README.md.tera"), "# {{ project_name }}")?;
+        fs::write(templates.join("gitignore.tera"), "target/\n")?;
+        let output_dir = TempDir::new()?;
+
+        let repo = repo_dir.path().to_string_lossy().replace('\\', "/");
+        let config = from_toml::<Config>(&format!(
+            r#"
+[project.git]
+gitignore = ["target/", "*.log"]
+commit = true
+branch = "trunk"
+
+[[scaffolds]]
+name = "Readme"
+repo = "{repo}"
+template.files = [
+  {{ src = "README.md.tera", dest = "README.md" }},
+  {{ src = "gitignore.tera", dest = ".gitignore" }},
+]
+"#
+        ))?;
+        let output = output_dir.path().to_string_lossy().into_owned();
+        fs::write(output_dir.path().join("debug.log"), "ignored")?;
+        let args = Args::try_parse_from(["scaficionado", "-o", &output])?;
+        generate_config(&args, config)?;
+
+        assert_eq!(fs::read_to_string(output_dir.path().join(".gitignore"))?, "target/\n*.log\n");
+        assert_eq!(git(output_dir.path(), &["symbolic-ref", "--short", "HEAD"])?, "trunk");
+        assert_eq!(
+            git(output_dir.path(), &["ls-tree", "--name-only", "HEAD"])?,
+            ".gitignore\n.scaficionado\nREADME.md"
+        );
+        // The ignored debug.log is left out, and the index matches the commit.
+        assert_eq!(git(output_dir.path(), &["status", "--porcelain"])?, "");
+        Ok(())
+    }
+}
@@ -0,0 +1,541 @@
+//! The API of applications embedding scaficionado: runs from code, observers and cancellation.
+
+use clap::Parser;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use super::*;
+
+/// A run configured with command-line arguments, for applications embedding scaficionado, e.g. a web service that
+/// generates projects on demand.
+///
+/// ```no_run
+/// let scaffolder = scaficionado::Scaffolder::new(["--config", "service.toml", "--defaults", "--output", "out"])?;
+/// scaffolder.execute()?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct Scaffolder {
+    pub(crate) args: Args,
+    cancellation_token: Option<std::sync::Arc<AtomicBool>>,
+    #[cfg(feature = "async")]
+    run_limit: Option<RunLimit>,
+}
+
+impl Scaffolder {
+    /// A run with these command-line arguments, without the program name.
+    pub fn new<I, T>(args: I) -> Result<Self, Box<dyn Error>>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<std::ffi::OsString>,
+    {
+        let args = std::iter::once("scaficionado".into()).chain(args.into_iter().map(Into::into));
+        Ok(Scaffolder {
+            args: Args::try_parse_from(args)?,
+            cancellation_token: None,
+            #[cfg(feature = "async")]
+            run_limit: None,
+        })
+    }
+
+    /// Cancel the run once `token` is set to true, as with [`set_cancellation_token`].
+    pub fn cancellation_token(mut self, token: std::sync::Arc<AtomicBool>) -> Self {
+        self.cancellation_token = Some(token);
+        self
+    }
+
+    /// Take turns with the other runs of `limit` in [`execute_async`](Self::execute_async), instead of with those of
+    /// the default limit of DEFAULT_ASYNC_RUNS runs at the same time.
+    #[cfg(feature = "async")]
+    pub fn run_limit(mut self, limit: RunLimit) -> Self {
+        self.run_limit = Some(limit);
+        self
+    }
+
+    /// Run on the calling thread.
+    pub fn execute(&self) -> Result<(), Box<dyn Error>> {
+        // Also without a token, so that a token set for another run of the thread doesn't apply.
+        set_cancellation_token(self.cancellation_token.clone());
+        run_with_args(&self.args)
+    }
+
+    /// Run the blocking scaffolder on tokio's blocking thread pool, so that an async service can await runs without
+    /// blocking its workers.  Cloning, downloads, rendering and hooks stay blocking work: each run keeps one pool
+    /// thread while it lasts.  Runs take turns with the other runs of their [`RunLimit`], so that a burst of requests
+    /// doesn't fill the pool.  The error is returned as its message.
+    #[cfg(feature = "async")]
+    pub async fn execute_async(self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let limit = self.run_limit.clone().unwrap_or_else(|| {
+            static DEFAULT: std::sync::OnceLock<RunLimit> = std::sync::OnceLock::new();
+            DEFAULT.get_or_init(|| RunLimit::new(DEFAULT_ASYNC_RUNS)).clone()
+        });
+        let permit = limit.0.acquire_owned().await?;
+        tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            // Pool threads are reused, so a token of an earlier run mustn't carry over.
+            set_cancellation_token(self.cancellation_token.clone());
+            run_with_args(&self.args).map_err(|e| crate::render::error_chain(e.as_ref()).into())
+        })
+        .await?
+    }
+}
+
+/// Runs of [`Scaffolder::execute_async`] at the same time, unless given another [`RunLimit`].
+#[cfg(feature = "async")]
+pub(crate) const DEFAULT_ASYNC_RUNS: usize = 8;
+
+/// How many runs of [`Scaffolder::execute_async`] go at the same time, shared by the scaffolders it's given to with
+/// [`Scaffolder::run_limit`]; further runs wait for their turn.
+///
+/// ```no_run
+/// # async fn generate() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+/// let limit = scaficionado::RunLimit::new(2);
+/// let scaffolder = scaficionado::Scaffolder::new(["--defaults", "--output", "out"]).map_err(|e| e.to_string())?;
+/// scaffolder.run_limit(limit.clone()).execute_async().await?;
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "async")]
+#[derive(Clone)]
+pub struct RunLimit(std::sync::Arc<tokio::sync::Semaphore>);
+
+#[cfg(feature = "async")]
+impl RunLimit {
+    /// At most `runs` runs at the same time, and at least one.
+    pub fn new(runs: usize) -> Self {
+        RunLimit(std::sync::Arc::new(tokio::sync::Semaphore::new(runs.max(1))))
+    }
+}
+
+/// Generate the project of the configuration file `config` into `output` without prompting, like a row of
+/// `batch`: `variables` override the global variables and those of every scaffold, and prompts take their defaults.
+/// The project is named by a `project_name` variable, or else by the configuration.  Returns the summary of the run,
+/// as written by --report.
+///
+/// ```no_run
+/// let variables = serde_json::json!({ "project_name": "billing", "team": "payments" });
+/// let summary = scaficionado::scaffold_project("scaffolding.toml", variables.as_object().unwrap(), "services/billing")?;
+/// println!("{}", summary["scaffolds"]);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn scaffold_project(
+    config: &str,
+    variables: &serde_json::Map<String, serde_json::Value>,
+    output: &str,
+) -> Result<serde_json::Value, Box<dyn Error>> {
+    let (args, config) = library_project(config, variables, output)?;
+    LAST_SUMMARY.with(|last| last.borrow_mut().take());
+    generate_config(&args, config)?;
+    let summary = LAST_SUMMARY.with(|last| last.borrow_mut().take());
+    Ok(serde_json::to_value(summary)?)
+}
+
+/// Check that [`scaffold_project`] would succeed with these arguments, by rendering the project without writing anything or
+/// running hooks.
+pub fn validate_project(config: &str, variables: &serde_json::Map<String, serde_json::Value>, output: &str) -> Result<(), Box<dyn Error>> {
+    let (args, config) = library_project(config, variables, output)?;
+    begin_preview();
+    let result = generate_config(&args, config);
+    finish_preview();
+    result
+}
+
+/// The scaffolds of the configuration file `config` and their prompts, as listed by `serve`'s `GET /scaffolds`:
+/// `{"scaffolds": [{"name": ..., "repo": [...], "prompts": [{"name": ..., "type": ..., "default": ...}]}]}`.
+pub fn list_scaffolds(config: &str) -> Result<serde_json::Value, Box<dyn Error>> {
+    let args = Args::try_parse_from(["scaficionado", "--config", config])?;
+    Ok(scaffold_listing(&load_config_with_args(&args)?))
+}
+
+/// The arguments and configuration of a run of [`scaffold_project`] or [`validate_project`].
+fn library_project(
+    config: &str,
+    variables: &serde_json::Map<String, serde_json::Value>,
+    output: &str,
+) -> Result<(Args, Config), Box<dyn Error>> {
+    let mut variables: BTreeMap<String, toml::Value> =
+        serde_json::from_value(serde_json::Value::Object(variables.clone())).map_err(|e| format!("Invalid variables: {}", e))?;
+    let args = Args::try_parse_from(["scaficionado", "--config", config, "--output", output, "--defaults"])?;
+    let config = load_config_with_args(&args)?;
+    let project_name = match variables.remove("project_name") {
+        Some(toml::Value::String(name)) => name,
+        Some(value) => return Err(format!("The project_name variable must be a string, not {}", value).into()),
+        None => config
+            .project
+            .as_ref()
+            .and_then(|project| project.name.clone())
+            .unwrap_or(args.project_name.clone()),
+    };
+    let row = BatchRow {
+        project_name,
+        output: Some(output.to_string()),
+        variables,
+    };
+    let args = Args {
+        project_name: row.project_name.clone(),
+        ..args
+    };
+    let config = apply_batch_row(&args, config, &row);
+    Ok((args, config))
+}
+
+/// Receives the progress of a run, for applications embedding scaficionado that show it their own way instead of
+/// reading its output.  Every method does nothing by default.  Observers are added with [`register_observer`].
+#[allow(unused_variables)]
+pub trait ScaffoldObserver: Send + Sync {
+    /// A scaffold is about to be rendered into `output`.
+    fn on_scaffold_start(&self, scaffold: &str, output: &Path) {}
+
+    /// A scaffold is done, with the (first) error if it failed.
+    fn on_scaffold_finish(&self, scaffold: &str, error: Option<&dyn Error>) {}
+
+    /// A file was written or an empty directory created, `overwritten` if the file existed.  Files written to the
+    /// --stage directory are reported with their staged path.
+    fn on_file_written(&self, path: &Path, overwritten: bool) {}
+
+    /// A file was left alone: it existed and overwriting is off, or it already had the rendered content.
+    fn on_file_skipped(&self, path: &Path) {}
+
+    /// A hook of a scaffold is about to run: `stage` is "pre", "plan" or "post", and `hook` its script or command.
+    fn on_hook(&self, scaffold: &str, stage: &str, hook: &str) {}
+}
+
+/// Observers added with [`register_observer`], told about every run of the process.
+static OBSERVERS: std::sync::RwLock<Vec<Box<dyn ScaffoldObserver>>> = std::sync::RwLock::new(Vec::new());
+
+/// Add an observer of the progress of runs, e.g. to update the progress bar of a GUI.
+pub fn register_observer(observer: Box<dyn ScaffoldObserver>) {
+    OBSERVERS.write().unwrap_or_else(|e| e.into_inner()).push(observer);
+}
+
+/// Call `event` on every registered observer, in the order they were registered.
+pub(crate) fn notify(event: impl Fn(&dyn ScaffoldObserver)) {
+    for observer in OBSERVERS.read().unwrap_or_else(|e| e.into_inner()).iter() {
+        event(observer.as_ref());
+    }
+}
+
+thread_local! {
+    /// The token of [`set_cancellation_token`] for the runs of this thread, if any.
+    static CANCELLATION_TOKEN: RefCell<Option<std::sync::Arc<AtomicBool>>> = const { RefCell::new(None) };
+}
+
+/// Let an application embedding scaficionado cancel the next run of the calling thread, e.g. from the Cancel button
+/// of its GUI on another thread: once `token` is set to true, the run stops before its next file or hook, or during a
+/// clone, and the files it wrote are removed (unless it runs with --keep-going).  The token is cleared when the run
+/// ends, so that it doesn't cancel the next job of a thread pool; `None` clears it before then.
+pub fn set_cancellation_token(token: Option<std::sync::Arc<AtomicBool>>) {
+    CANCELLATION_TOKEN.with(|current| *current.borrow_mut() = token);
+}
+
+/// Clears the cancellation token of the thread when the run holding it ends, however it ends.
+pub(crate) struct CancellationScope;
+
+impl Drop for CancellationScope {
+    fn drop(&mut self) {
+        set_cancellation_token(None);
+    }
+}
+
+/// Whether the cancellation token of this thread is set.
+pub(crate) fn cancelled() -> bool {
+    CANCELLATION_TOKEN.with(|token| token.borrow().as_ref().is_some_and(|token| token.load(Ordering::Relaxed)))
+}
+
+/// Fail with [`Cancelled`] if the cancellation token is set.
+pub(crate) fn check_cancelled() -> Result<(), Cancelled> {
+    match cancelled() {
+        true => Err(Cancelled),
+        false => Ok(()),
+    }
+}
+
+/// The run was cancelled with the cancellation token.  Like an exceeded limit, it stops the run even with
+/// --keep-going.
+#[derive(Debug)]
+pub(crate) struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("The run was cancelled")
+    }
+}
+
+impl Error for Cancelled {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::native::testing::git;
+    use std::collections::BTreeMap;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use tempfile::TempDir;
+    use tera::Context;
+
+    // Test that a Scaffolder runs with its arguments, on the calling thread or (with `async`) on the blocking pool.
+    #[test]
+    fn test_scaffolder() -> Result<(), Box<dyn std::error::Error>> {
+        let repo_dir = TempDir::new()?;
+        fs::create_dir_all(repo_dir.path().join("templates"))?;
+        fs::write(repo_dir.path().join("templates/readme.md.tera"), "# {{ project_name }}")?;
+        let work_dir = TempDir::new()?;
+        let config_path = work_dir.path().join("scaffolding.toml");
+        fs::write(
+            &config_path,
+            format!(
+                "[[scaffolds]]\nrepo = \"{}\"\ntemplate.files = [{{ src = \"readme.md.tera\", dest = \"README.md\" }}]\n",
+                repo_dir.path().to_string_lossy().replace('\\', "/")
+            ),
+        )?;
+        let scaffolder = |name: &str| {
+            let output = work_dir.path().join(name);
+            let args = [
+                "--config".as_ref(),
+                config_path.as_os_str(),
+                "--project-name".as_ref(),
+                name.as_ref(),
+                "--output".as_ref(),
+                output.as_os_str(),
+                "--defaults".as_ref(),
+            ];
+            Scaffolder::new(args.map(|arg: &std::ffi::OsStr| arg.to_os_string()))
+        };
+        scaffolder("first")?.execute()?;
+        assert_eq!(fs::read_to_string(work_dir.path().join("first/README.md"))?, "# first");
+        assert!(Scaffolder::new(["--no-such-flag"]).is_err());
+
+        // A cancelled run fails before writing anything.
+        let token = std::sync::Arc::new(AtomicBool::new(true));
+        let cancelled = scaffolder("cancelled")?.cancellation_token(token);
+        assert!(cancelled.execute().unwrap_err().to_string().contains("The run was cancelled"));
+        assert!(!work_dir.path().join("cancelled/README.md").exists());
+        // The token ends with its run, so the next run of the thread isn't cancelled.
+        scaffolder("after")?.execute()?;
+        assert!(work_dir.path().join("after/README.md").exists());
+        // A token set for the thread doesn't apply to a Scaffolder without one.
+        set_cancellation_token(Some(std::sync::Arc::new(AtomicBool::new(true))));
+        scaffolder("own")?.execute()?;
+        assert!(work_dir.path().join("own/README.md").exists());
+
+        #[cfg(feature = "async")]
+        {
+            // More runs than may run at the same time, which wait for their turn.
+            let names: Vec<String> = (0..DEFAULT_ASYNC_RUNS + 2).map(|run| format!("async{}", run)).collect();
+            let scaffolders = names.iter().map(|name| scaffolder(name)).collect::<Result<Vec<_>, _>>()?;
+            // Runs of a limit of their own take turns with each other only.
+            let limit = RunLimit::new(1);
+            let limited = ["limited1", "limited2"].map(|name| scaffolder(name).map(|run| run.run_limit(limit.clone())));
+            let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+            runtime
+                .block_on(async {
+                    let mut jobs: tokio::task::JoinSet<_> = scaffolders.into_iter().map(Scaffolder::execute_async).collect();
+                    for scaffolder in limited {
+                        jobs.spawn(scaffolder.map_err(|e| e.to_string())?.execute_async());
+                    }
+                    while let Some(job) = jobs.join_next().await {
+                        job??;
+                    }
+                    Ok::<(), Box<dyn std::error::Error + Send + Sync>>(())
+                })
+                .map_err(|e| e.to_string())?;
+            for name in names.iter().map(String::as_str).chain(["limited1", "limited2"]) {
+                assert_eq!(
+                    fs::read_to_string(work_dir.path().join(name).join("README.md"))?,
+                    format!("# {}", name)
+                );
+            }
+        }
+        Ok(())
+    }
+
+    // Test the library functions generating, validating and listing the project of a configuration file.
+    #[test]
+    fn test_library_api() -> Result<(), Box<dyn std::error::Error>> {
+        let repo_dir = TempDir::new()?;
+        fs::create_dir_all(repo_dir.path().join("templates"))?;
+        fs::write(
+            repo_dir.path().join("templates/readme.md.tera"),
+            "{{ project_name }}: {{ replicas + 1 }}",
+        )?;
+        let work_dir = TempDir::new()?;
+        let config_path = work_dir.path().join("scaffolding.toml");
+        fs::write(
+            &config_path,
+            format!(
+                r#"
+[project]
+name = "configured"
+
+[[scaffolds]]
+name = "Service"
+repo = "{}"
+template.files = [{{ src = "readme.md.tera", dest = "README.md" }}]
+prompts = [{{ name = "replicas", type = "integer" }}]
+"#,
+                repo_dir.path().to_string_lossy().replace('\\', "/")
+            ),
+        )?;
+        let config = config_path.to_string_lossy();
+        let output = work_dir.path().join("out");
+        let output = output.to_string_lossy();
+        let variables = |value: serde_json::Value| value.as_object().cloned().unwrap_or_default();
+
+        let Err(e) = validate_project(&config, &variables(serde_json::json!({})), &output) else {
+            panic!("validated a project without a value for a prompt without a default");
+        };
+        assert!(e.to_string().contains("replicas"), "{}", e);
+        validate_project(&config, &variables(serde_json::json!({ "replicas": "2" })), &output)?;
+        assert!(!Path::new(output.as_ref()).exists());
+
+        let summary = scaffold_project(&config, &variables(serde_json::json!({ "replicas": 2 })), &output)?;
+        assert_eq!(summary["project"], "configured");
+        assert_eq!(summary["errors"], 0);
+        assert_eq!(fs::read_to_string(work_dir.path().join("out/README.md"))?, "configured: 3");
+        let named = variables(serde_json::json!({ "project_name": "billing", "replicas": 1 }));
+        let summary = scaffold_project(&config, &named, &output)?;
+        assert_eq!(summary["project"], "billing");
+        let Err(e) = scaffold_project(&config, &variables(serde_json::json!({ "project_name": 1 })), &output) else {
+            panic!("generated a project with a numeric name");
+        };
+        assert!(e.to_string().contains("must be a string"), "{}", e);
+
+        let listing = list_scaffolds(&config)?;
+        assert_eq!(listing["scaffolds"][0]["prompts"][0]["type"], "integer");
+        Ok(())
+    }
+
+    // Test that registered observers are told about scaffolds, files and hooks.
+    #[test]
+    fn test_observers() -> Result<(), Box<dyn std::error::Error>> {
+        struct Recorder(std::sync::Arc<std::sync::Mutex<Vec<String>>>);
+        impl Recorder {
+            fn record(&self, event: String) {
+                self.0.lock().unwrap().push(event);
+            }
+        }
+        impl ScaffoldObserver for Recorder {
+            fn on_scaffold_start(&self, scaffold: &str, _output: &Path) {
+                self.record(format!("start {}", scaffold));
+            }
+            fn on_scaffold_finish(&self, scaffold: &str, error: Option<&dyn std::error::Error>) {
+                self.record(format!("finish {} {}", scaffold, error.is_some()));
+            }
+            fn on_file_written(&self, path: &Path, overwritten: bool) {
+                self.record(format!("written {} {}", path.display(), overwritten));
+            }
+            fn on_file_skipped(&self, path: &Path) {
+                self.record(format!("skipped {}", path.display()));
+            }
+            fn on_hook(&self, scaffold: &str, stage: &str, hook: &str) {
+                self.record(format!("hook {} {} {}", scaffold, stage, hook));
+            }
+        }
+        let repo_dir = TempDir::new()?;
+        let output_dir = TempDir::new()?;
+        fs::create_dir_all(repo_dir.path().join("templates"))?;
+        fs::write(repo_dir.path().join("templates/a.txt"), "a")?;
+        fs::write(repo_dir.path().join("templates/b.txt"), "b")?;
+        fs::write(output_dir.path().join("b.txt"), "old")?;
+        let scaffold: Scaffold = from_toml(&format!(
+            r#"
+name = "ObservedService"
+repo = {:?}
+template.files = [{{ src = "a.txt", dest = "a.txt" }}, {{ src = "b.txt", dest = "b.txt" }}]
+hooks.post = {{ command = "git --version" }}
+"#,
+            repo_dir.path().to_string_lossy()
+        ))?;
+        let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        register_observer(Box::new(Recorder(events.clone())));
+
+        let (repo_base, _) = prepare_scaffold(&scaffold, &GitOptions::default())?;
+        for options in [overwrite_options(), RenderOptions::default()] {
+            process_scaffold(&scaffold, &repo_base, "Demo", None, &BTreeMap::new(), output_dir.path(), &options)?;
+        }
+        let (a, b) = (output_dir.path().join("a.txt"), output_dir.path().join("b.txt"));
+        // Other tests run at the same time, so only the events of this one are compared.
+        let events: Vec<String> = events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|event| event.contains("ObservedService") || event.contains(&*output_dir.path().to_string_lossy()))
+            .cloned()
+            .collect();
+        assert_eq!(
+            events,
+            [
+                "start ObservedService".to_string(),
+                format!("written {} false", a.display()),
+                format!("written {} true", b.display()),
+                "hook ObservedService post git --version".to_string(),
+                "finish ObservedService false".to_string(),
+                "start ObservedService".to_string(),
+                format!("skipped {}", a.display()),
+                format!("skipped {}", b.display()),
+                "hook ObservedService post git --version".to_string(),
+                "finish ObservedService false".to_string(),
+            ]
+        );
+        Ok(())
+    }
+
+    // Test that a cancelled run stops between files and during clones, and can be rolled back.
+    #[test]
+    fn test_cancellation() -> Result<(), Box<dyn std::error::Error>> {
+        // Cancels the run when the second file is written, as a GUI would from another thread.
+        struct CancelAfter(PathBuf, std::sync::Arc<AtomicBool>);
+        impl ScaffoldObserver for CancelAfter {
+            fn on_file_written(&self, path: &Path, _overwritten: bool) {
+                if path == self.0 {
+                    self.1.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+        let templates_dir = TempDir::new()?;
+        let output_dir = TempDir::new()?;
+        fs::write(templates_dir.path().join("item.txt.tera"), "{{ item }}")?;
+        let scaffold: Scaffold = from_toml(
+            r#"
+repo = "."
+template.files = [{ src = "item.txt.tera", dest = "items/{{ item }}.txt", for_each = "items" }]
+"#,
+        )?;
+        let mut context = Context::new();
+        context.insert("items", &["a", "b", "c", "d"]);
+        let token = std::sync::Arc::new(AtomicBool::new(false));
+        register_observer(Box::new(CancelAfter(output_dir.path().join("items/b.txt"), token.clone())));
+        set_cancellation_token(Some(token.clone()));
+        let options = RenderOptions {
+            keep_going: true,
+            ..overwrite_options()
+        };
+        begin_transaction()?;
+        let error = render_templates(templates_dir.path(), output_dir.path(), &scaffold, &context, &options).unwrap_err();
+        assert!(error.is::<Cancelled>(), "{}", error);
+        assert!(output_dir.path().join("items/b.txt").exists());
+        assert!(!output_dir.path().join("items/c.txt").exists());
+        assert_eq!(rollback()?, 2);
+        assert!(!output_dir.path().join("items/a.txt").exists());
+
+        // A cancelled clone isn't retried.
+        let upstream = TempDir::new()?;
+        git(upstream.path(), &["init", "-q"])?;
+        git(upstream.path(), &["commit", "-q", "--allow-empty", "-m", "Empty"])?;
+        let url = format!("file://{}", upstream.path().display());
+        let Err(error) = clone_repo_with_retries(&url, &output_dir.path().join("clone"), &GitOptions::default()) else {
+            return Err("the clone should be cancelled".into());
+        };
+        assert!(error.is::<Cancelled>(), "{}", error);
+
+        token.store(false, Ordering::Relaxed);
+        let again = output_dir.path().join("again");
+        render_templates(templates_dir.path(), &again, &scaffold, &context, &options)?;
+        assert!(again.join("items/d.txt").exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,1038 @@
+//! Writing the output: files and directories, previews, limits, rollback, staging, locks and progress messages.
+
+use crate::render::new_tera;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Component, Path, PathBuf};
+use tempfile::TempDir;
+use tera::{Context, Template};
+
+use super::*;
+
+/// Largest file, number of files and total bytes a run writes, and largest file it renders as a template, unless
+/// configured with `[project.limits]`.
+pub(crate) const DEFAULT_MAX_FILE_SIZE: u64 = 100_000_000;
+pub(crate) const DEFAULT_MAX_FILES: u64 = 10_000;
+pub(crate) const DEFAULT_MAX_TOTAL_BYTES: u64 = 1_000_000_000;
+pub(crate) const DEFAULT_MAX_TEMPLATE_SIZE: u64 = 10_000_000;
+
+/// The lock of an output directory, released when dropped.
+pub(crate) struct OutputLock {
+    pub(crate) file: fs::File,
+}
+
+/// Lock the output directory for this run, so that two runs don't interleave their writes.  Fails when another run
+/// holds the lock, or waits for it with `wait`.  The lock file is kept in the cache directory, so nothing of it ends
+/// up in the generated project.
+pub(crate) fn lock_output(output_base: &Path, wait: bool) -> Result<OutputLock, Box<dyn Error>> {
+    let path = output_lock_path(output_base)?;
+    // The lock file stays, so that a run waiting on it can't end up locking a replaced file.
+    let file = open_lock_file(&path).map_err(|source| FsError::Write { path, source })?;
+    let lock = OutputLock { file };
+    match lock.file.try_lock() {
+        Ok(()) => Ok(lock),
+        Err(fs::TryLockError::WouldBlock) if wait => {
+            say!("Waiting for another scaficionado run on {:?} to finish...", output_base);
+            lock.file.lock()?;
+            Ok(lock)
+        }
+        Err(fs::TryLockError::WouldBlock) => Err(format!(
+            "Another scaficionado run is in progress on {:?}; use --wait to wait for it to finish",
+            output_base
+        )
+        .into()),
+        Err(fs::TryLockError::Error(e)) => Err(e.into()),
+    }
+}
+
+/// Whether progress messages go to stderr, see [`RunContext::messages_to_stderr`].
+pub(crate) fn messages_to_stderr() -> bool {
+    run_context().messages_to_stderr
+}
+
+/// Send the progress messages of the run on this thread to stderr, e.g. while stdout carries a generated file.
+pub(crate) fn send_messages_to_stderr() {
+    update_run_context(|context| context.messages_to_stderr = true);
+}
+
+/// Print a message without a newline, e.g. a prompt, on the stream used for progress messages.
+pub(crate) fn print_partial(text: &str) -> io::Result<()> {
+    if messages_to_stderr() {
+        eprint!("{}", text);
+        io::stderr().flush()
+    } else {
+        print!("{}", text);
+        io::stdout().flush()
+    }
+}
+
+/// How a line of output is highlighted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Style {
+    Created,
+    Overwritten,
+    Unchanged,
+    Skipped,
+    Removed,
+    Error,
+    Heading,
+}
+
+impl Style {
+    /// Color `text` for `stream`, unless colors are disabled or the stream isn't a terminal.
+    pub(crate) fn paint(self, text: &str, stream: &impl IsTerminal) -> String {
+        self.paint_if(run_context().colors && stream.is_terminal(), text)
+    }
+
+    pub(crate) fn paint_if(self, enabled: bool, text: &str) -> String {
+        if !enabled {
+            return text.to_string();
+        }
+        let code = match self {
+            Style::Created => "32",
+            Style::Overwritten => "33",
+            Style::Unchanged | Style::Skipped => "2",
+            Style::Removed => "31",
+            Style::Error => "1;31",
+            Style::Heading => "1",
+        };
+        format!("\x1b[{}m{}\x1b[0m", code, text)
+    }
+}
+
+/// Print the status of a destination file or directory, with a symbol and color per status.
+/// Print the status of a written file or created directory, and pass it on to the registered observers.
+pub(crate) fn report_write_status(path: &Path, status: WriteStatus) {
+    notify(|observer| match status {
+        WriteStatus::Created | WriteStatus::Staged => observer.on_file_written(path, false),
+        WriteStatus::Overwritten => observer.on_file_written(path, true),
+        WriteStatus::Unchanged | WriteStatus::Skipped => observer.on_file_skipped(path),
+    });
+    let (label, style) = match status {
+        WriteStatus::Created => ("+ created", Style::Created),
+        WriteStatus::Overwritten => ("~ overwritten", Style::Overwritten),
+        WriteStatus::Unchanged => ("= unchanged", Style::Unchanged),
+        WriteStatus::Skipped => ("- skipped", Style::Skipped),
+        WriteStatus::Staged => ("> staged", Style::Overwritten),
+    };
+    let label = format!("{:<13}", label);
+    let label = match messages_to_stderr() {
+        true => style.paint(&label, &io::stderr()),
+        false => style.paint(&label, &io::stdout()),
+    };
+    say!("{} {:?}", label, path);
+}
+
+/// The result of writing a single destination file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum WriteStatus {
+    Created,
+    Overwritten,
+    /// The destination already had identical content.
+    Unchanged,
+    /// The destination existed and overwrite=false.
+    Skipped,
+    /// The destination isn't writable and was written to the --stage directory instead.
+    Staged,
+}
+
+impl WriteStatus {
+    /// Whether this write created or modified a file on disk.
+    pub(crate) fn is_change(self) -> bool {
+        matches!(self, WriteStatus::Created | WriteStatus::Overwritten | WriteStatus::Staged)
+    }
+}
+
+pub(crate) fn write_file(dest: &Path, content: &[u8], overwrite: bool) -> Result<WriteStatus, Box<dyn Error>> {
+    check_cancelled()?;
+    if record_planned(dest, overwrite) {
+        return Ok(WriteStatus::Created);
+    }
+    check_limits(dest, content.len() as u64)?;
+    if previewing() {
+        record_preview(dest, content.to_vec());
+        return Ok(WriteStatus::Created);
+    }
+    let status = if dest.exists() {
+        if !overwrite {
+            report_write_status(dest, WriteStatus::Skipped);
+            return Ok(WriteStatus::Skipped);
+        }
+        if fs::metadata(dest).is_ok_and(|metadata| metadata.len() == content.len() as u64) && read_file(dest)? == content {
+            report_write_status(dest, WriteStatus::Unchanged);
+            return Ok(WriteStatus::Unchanged);
+        }
+        WriteStatus::Overwritten
+    } else {
+        WriteStatus::Created
+    };
+    let staged = stage_file(dest, |staged| {
+        fs::write(staged, content).map_err(|source| FsError::Write {
+            path: staged.to_path_buf(),
+            source,
+        })
+    })?;
+    if staged {
+        return Ok(WriteStatus::Staged);
+    }
+    record_write(dest, status)?;
+    if let Some(parent) = dest.parent() {
+        create_dir(parent)?;
+    }
+    fs::write(dest, content).map_err(|source| FsError::Write {
+        path: dest.to_path_buf(),
+        source,
+    })?;
+    report_write_status(dest, status);
+    Ok(status)
+}
+
+pub(crate) fn copy_file(src: &Path, dest: &Path, overwrite: bool) -> Result<WriteStatus, Box<dyn Error>> {
+    check_cancelled()?;
+    if record_planned(dest, overwrite) {
+        return Ok(WriteStatus::Created);
+    }
+    check_limits(dest, fs::metadata(src).map(|metadata| metadata.len()).unwrap_or_default())?;
+    if previewing() {
+        record_preview(dest, read_file(src)?);
+        return Ok(WriteStatus::Created);
+    }
+    let status = if dest.exists() {
+        if !overwrite {
+            report_write_status(dest, WriteStatus::Skipped);
+            return Ok(WriteStatus::Skipped);
+        }
+        if same_contents(src, dest)? {
+            report_write_status(dest, WriteStatus::Unchanged);
+            return Ok(WriteStatus::Unchanged);
+        }
+        WriteStatus::Overwritten
+    } else {
+        WriteStatus::Created
+    };
+    let staged = stage_file(dest, |staged| {
+        fs::copy(src, staged).map(|_| ()).map_err(|source| FsError::Copy {
+            src: src.to_path_buf(),
+            dest: staged.to_path_buf(),
+            source,
+        })
+    })?;
+    if staged {
+        return Ok(WriteStatus::Staged);
+    }
+    record_write(dest, status)?;
+    if let Some(parent) = dest.parent() {
+        create_dir(parent)?;
+    }
+    fs::copy(src, dest).map_err(|source| FsError::Copy {
+        src: src.to_path_buf(),
+        dest: dest.to_path_buf(),
+        source,
+    })?;
+    report_write_status(dest, status);
+    Ok(status)
+}
+
+/// Create an empty directory (and its parents) in the output.
+pub(crate) fn create_empty_dir(dir_path: &Path) -> Result<(PathBuf, WriteStatus), Box<dyn Error>> {
+    if planning() || previewing() {
+        return Ok((dir_path.to_path_buf(), WriteStatus::Created));
+    }
+    if dir_path.is_dir() {
+        report_write_status(dir_path, WriteStatus::Unchanged);
+        return Ok((dir_path.to_path_buf(), WriteStatus::Unchanged));
+    }
+    if stage_dir(dir_path)? {
+        return Ok((dir_path.to_path_buf(), WriteStatus::Staged));
+    }
+    record_new_dirs(dir_path);
+    create_dir(dir_path)?;
+    report_write_status(dir_path, WriteStatus::Created);
+    Ok((dir_path.to_path_buf(), WriteStatus::Created))
+}
+
+thread_local! {
+    /// The destinations collected by `planned_destinations` instead of being written.
+    static PLANNED: RefCell<Option<Vec<PathBuf>>> = const { RefCell::new(None) };
+}
+
+/// Whether writes are being collected by `planned_destinations`.
+fn planning() -> bool {
+    PLANNED.with(|planned| planned.borrow().is_some())
+}
+
+/// While planning, collect a destination that would be written and return true.
+fn record_planned(dest: &Path, overwrite: bool) -> bool {
+    PLANNED.with(|planned| match planned.borrow_mut().as_mut() {
+        Some(planned) => {
+            if overwrite || !dest.exists() {
+                planned.push(dest.components().collect());
+            }
+            true
+        }
+        None => false,
+    })
+}
+
+thread_local! {
+    /// The content of every file a `diff` run would write, collected instead of writing it.
+    static PREVIEW: RefCell<Option<BTreeMap<PathBuf, Vec<u8>>>> = const { RefCell::new(None) };
+}
+
+/// Collect the files written by this thread in memory instead of writing them, until `finish_preview`.
+pub(crate) fn begin_preview() {
+    PREVIEW.with(|preview| *preview.borrow_mut() = Some(BTreeMap::new()));
+}
+
+/// Stop collecting writes and return the collected files.
+pub(crate) fn finish_preview() -> BTreeMap<PathBuf, Vec<u8>> {
+    PREVIEW.with(|preview| preview.borrow_mut().take()).unwrap_or_default()
+}
+
+/// Whether writes are being collected by `begin_preview`.
+pub(crate) fn previewing() -> bool {
+    PREVIEW.with(|preview| preview.borrow().is_some())
+}
+
+/// Collect the content a file would be written with.  A later write of the same file replaces it.
+fn record_preview(dest: &Path, content: Vec<u8>) {
+    PREVIEW.with(|preview| {
+        if let Some(preview) = preview.borrow_mut().as_mut() {
+            preview.insert(dest.components().collect(), content);
+        }
+    });
+}
+
+/// The largest file, number of files and total bytes a run writes, and the largest template it renders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Limits {
+    pub(crate) max_file_size: u64,
+    pub(crate) max_files: u64,
+    pub(crate) max_total_bytes: u64,
+    pub(crate) max_template_size: u64,
+}
+
+/// The files and bytes written so far against the limits of the run.
+struct LimitUsage {
+    limits: Limits,
+    files: u64,
+    bytes: u64,
+}
+
+thread_local! {
+    /// The usage of the limits of the current run, if it has limits.
+    static LIMITS: RefCell<Option<LimitUsage>> = const { RefCell::new(None) };
+}
+
+/// Count the files written by this thread against `limits` (none when `None`), until the next call.
+pub(crate) fn begin_limits(limits: Option<Limits>) {
+    LIMITS.with(|usage| {
+        *usage.borrow_mut() = limits.map(|limits| LimitUsage {
+            limits,
+            files: 0,
+            bytes: 0,
+        })
+    });
+}
+
+/// A write that would exceed a limit of the run.  Unlike other file errors, it stops the run even with --keep-going.
+#[derive(Debug)]
+pub(crate) struct LimitExceeded(pub(crate) String);
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (set [project.limits], or run with --no-limits)", self.0)
+    }
+}
+
+impl Error for LimitExceeded {}
+
+/// Count a file of `size` bytes about to be written to `dest`, failing when it exceeds a limit of the run.
+fn check_limits(dest: &Path, size: u64) -> Result<(), LimitExceeded> {
+    LIMITS.with(|usage| {
+        let mut usage = usage.borrow_mut();
+        let Some(usage) = usage.as_mut() else {
+            return Ok(());
+        };
+        let limits = usage.limits;
+        if size > limits.max_file_size {
+            return Err(LimitExceeded(format!(
+                "{:?} would be {} bytes, more than max_file_size ({} bytes)",
+                dest, size, limits.max_file_size
+            )));
+        }
+        usage.files += 1;
+        usage.bytes += size;
+        if usage.files > limits.max_files {
+            return Err(LimitExceeded(format!(
+                "The run would write more than max_files ({} files)",
+                limits.max_files
+            )));
+        }
+        if usage.bytes > limits.max_total_bytes {
+            return Err(LimitExceeded(format!(
+                "The run would write more than max_total_bytes ({} bytes)",
+                limits.max_total_bytes
+            )));
+        }
+        Ok(())
+    })
+}
+
+/// The max_template_size of the current run, if it has limits.
+pub(crate) fn max_template_size() -> Option<u64> {
+    LIMITS.with(|usage| usage.borrow().as_ref().map(|usage| usage.limits.max_template_size))
+}
+
+/// Read a file to render as a template, refusing files larger than the max_template_size of the run.
+pub(crate) fn read_template_file(path: &Path) -> Result<String, Box<dyn Error>> {
+    if let Some(max) = max_template_size() {
+        let size = fs::metadata(path).map(|metadata| metadata.len()).unwrap_or_default();
+        if size > max {
+            return Err(format!(
+                "{:?} is {} bytes, too large to render as a template (max_template_size is {} bytes); add it to `raw` \
+                 to copy it as-is",
+                path, size, max
+            )
+            .into());
+        }
+    }
+    Ok(read_text_file(path)?)
+}
+
+/// The files a scaffold would write, found by rendering it without writing anything.  Destinations of files
+/// that fail to render, and files a plan hook adds, aren't known.
+pub(crate) fn planned_destinations(
+    scaffold: &Scaffold,
+    templates_dir: &Path,
+    output_base: &Path,
+    context: &Context,
+    answers: &BTreeMap<String, toml::Value>,
+    options: &RenderOptions,
+) -> BTreeSet<PathBuf> {
+    PLANNED.with(|planned| *planned.borrow_mut() = Some(Vec::new()));
+    // Errors are reported by the actual run; planning stops at the first one.
+    let options = RenderOptions {
+        keep_going: false,
+        ..*options
+    };
+    let _ = render_scaffold_templates(scaffold, templates_dir, output_base, context, answers, &options);
+    PLANNED
+        .with(|planned| planned.borrow_mut().take())
+        .unwrap_or_default()
+        .into_iter()
+        .collect()
+}
+
+/// The writes of a run, to undo them when it fails.  Overwritten files are backed up to a temporary directory
+/// before they are replaced.  Kept per thread like SEQUENCES.
+struct Transaction {
+    backups: TempDir,
+    entries: Vec<TransactionEntry>,
+}
+
+enum TransactionEntry {
+    Created(PathBuf),
+    Overwritten { path: PathBuf, backup: PathBuf },
+    CreatedDir(PathBuf),
+}
+
+thread_local! {
+    static TRANSACTION: RefCell<Option<Transaction>> = const { RefCell::new(None) };
+}
+
+/// Start recording the writes of this thread so that `rollback` can undo them.
+pub(crate) fn begin_transaction() -> Result<(), Box<dyn Error>> {
+    let transaction = Transaction {
+        backups: TempDir::new()?,
+        entries: Vec::new(),
+    };
+    TRANSACTION.with(|current| *current.borrow_mut() = Some(transaction));
+    Ok(())
+}
+
+/// Keep the writes of the transaction and remove its backups.
+pub(crate) fn commit_transaction() {
+    TRANSACTION.with(|current| current.borrow_mut().take());
+}
+
+/// Record the directories `create_dir(dir)` is about to create.
+fn record_new_dirs(dir: &Path) {
+    TRANSACTION.with(|current| {
+        if let Some(transaction) = current.borrow_mut().as_mut() {
+            let new_dirs = dir.ancestors().take_while(|dir| !dir.as_os_str().is_empty() && !dir.exists());
+            // Outermost first, so that they're removed innermost first.
+            let new_dirs: Vec<_> = new_dirs.map(|dir| TransactionEntry::CreatedDir(dir.to_path_buf())).collect();
+            transaction.entries.extend(new_dirs.into_iter().rev());
+        }
+    });
+}
+
+/// Record a file that's about to be written with `status`, backing up the file it overwrites.
+fn record_write(dest: &Path, status: WriteStatus) -> Result<(), Box<dyn Error>> {
+    if let Some(parent) = dest.parent() {
+        record_new_dirs(parent);
+    }
+    TRANSACTION.with(|current| {
+        let mut current = current.borrow_mut();
+        let Some(transaction) = current.as_mut() else {
+            return Ok(());
+        };
+        let entry = if status == WriteStatus::Overwritten {
+            let backup = transaction.backups.path().join(transaction.entries.len().to_string());
+            fs::copy(dest, &backup).map_err(|source| FsError::Copy {
+                src: dest.to_path_buf(),
+                dest: backup.clone(),
+                source,
+            })?;
+            TransactionEntry::Overwritten {
+                path: dest.to_path_buf(),
+                backup,
+            }
+        } else {
+            TransactionEntry::Created(dest.to_path_buf())
+        };
+        transaction.entries.push(entry);
+        Ok(())
+    })
+}
+
+/// Undo the writes of the transaction, newest first: remove the created files and directories and restore the
+/// overwritten files.  Directories that are no longer empty, e.g. because a hook wrote to them, are kept.
+/// Returns the number of files that were rolled back.
+pub(crate) fn rollback() -> Result<usize, Box<dyn Error>> {
+    let Some(transaction) = TRANSACTION.with(|current| current.borrow_mut().take()) else {
+        return Ok(0);
+    };
+    let mut files = 0;
+    for entry in transaction.entries.iter().rev() {
+        match entry {
+            TransactionEntry::Created(path) => {
+                fs::remove_file(path).map_err(|source| FsError::Write {
+                    path: path.clone(),
+                    source,
+                })?;
+                files += 1;
+            }
+            TransactionEntry::Overwritten { path, backup } => {
+                fs::copy(backup, path).map_err(|source| FsError::Copy {
+                    src: backup.clone(),
+                    dest: path.clone(),
+                    source,
+                })?;
+                files += 1;
+            }
+            TransactionEntry::CreatedDir(dir) => {
+                let _ = fs::remove_dir(dir);
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Destinations the current user can't write, collected with --stage.  Their content is written to the staging
+/// directory and an install script performs the final placement with elevated privileges.  Like SEQUENCES, it's
+/// kept per thread because the writes happen deep in rendering.
+pub(crate) struct Staging {
+    pub(crate) dir: PathBuf,
+    pub(crate) entries: Vec<StagedEntry>,
+    /// Whether a destination can be written directly; replaceable in tests, where root can write anywhere.
+    writable: fn(&Path) -> bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum StagedEntry {
+    File { staged: PathBuf, dest: PathBuf },
+    Dir(PathBuf),
+}
+
+thread_local! {
+    static STAGING: RefCell<Option<Staging>> = const { RefCell::new(None) };
+}
+
+/// Start staging the unwritable destinations of this thread's writes in `dir`.
+pub(crate) fn begin_staging(dir: &Path, writable: fn(&Path) -> bool) {
+    STAGING.with(|staging| {
+        *staging.borrow_mut() = Some(Staging {
+            dir: dir.to_path_buf(),
+            entries: Vec::new(),
+            writable,
+        })
+    });
+}
+
+/// Stop staging and return what was staged.
+pub(crate) fn finish_staging() -> Option<Staging> {
+    STAGING.with(|staging| staging.borrow_mut().take())
+}
+
+/// Whether the current user can write `path`: an existing file must open for appending, otherwise the closest
+/// existing directory must accept a new file.
+pub(crate) fn is_writable(path: &Path) -> bool {
+    if path.is_file() {
+        return fs::OpenOptions::new().append(true).open(path).is_ok();
+    }
+    let mut dir = path;
+    loop {
+        if dir.as_os_str().is_empty() {
+            dir = Path::new(".");
+        }
+        if dir.exists() {
+            return dir.is_dir() && tempfile::tempfile_in(dir).is_ok();
+        }
+        match dir.parent() {
+            Some(parent) => dir = parent,
+            None => return false,
+        }
+    }
+}
+
+/// With --stage, write an unwritable destination to the staging directory using `write` and record it for the
+/// install script.  Returns false when the destination is written directly.
+fn stage_file(dest: &Path, write: impl FnOnce(&Path) -> Result<(), FsError>) -> Result<bool, Box<dyn Error>> {
+    STAGING.with(|staging| {
+        let mut staging = staging.borrow_mut();
+        let Some(staging) = staging.as_mut().filter(|staging| !(staging.writable)(dest)) else {
+            return Ok(false);
+        };
+        let absolute = std::path::absolute(dest)?;
+        // The staged copy mirrors the absolute destination, e.g. <stage>/files/etc/app/app.conf.
+        let relative: PathBuf = absolute
+            .components()
+            .filter(|component| matches!(component, Component::Normal(_)))
+            .collect();
+        let staged = staging.dir.join("files").join(relative);
+        if let Some(parent) = staged.parent() {
+            create_dir(parent)?;
+        }
+        write(&staged)?;
+        staging.entries.push(StagedEntry::File { staged, dest: absolute });
+        report_write_status(dest, WriteStatus::Staged);
+        Ok(true)
+    })
+}
+
+/// With --stage, record an unwritable directory for the install script.  Returns false when it's created directly.
+fn stage_dir(dir: &Path) -> Result<bool, Box<dyn Error>> {
+    STAGING.with(|staging| {
+        let mut staging = staging.borrow_mut();
+        let Some(staging) = staging.as_mut().filter(|staging| !(staging.writable)(dir)) else {
+            return Ok(false);
+        };
+        staging.entries.push(StagedEntry::Dir(std::path::absolute(dir)?));
+        report_write_status(dir, WriteStatus::Staged);
+        Ok(true)
+    })
+}
+
+/// Quote `value` for a POSIX shell.
+fn shell_quote(value: &Path) -> String {
+    format!("'{}'", value.to_string_lossy().replace('\'', "'\\''"))
+}
+
+/// A shell script that creates the staged directories and copies the staged files to their destinations.  The
+/// privilege command defaults to sudo and can be replaced with $SUDO, e.g. SUDO=doas or SUDO= when run as root.
+fn install_script(entries: &[StagedEntry]) -> String {
+    let mut script = String::from(
+        "#!/bin/sh\n# Generated by scaficionado: places the files that need elevated privileges.  Review before running.\nset -e\nSUDO=${SUDO-sudo}\n",
+    );
+    for entry in entries {
+        match entry {
+            StagedEntry::File { staged, dest } => {
+                if let Some(parent) = dest.parent() {
+                    script.push_str(&format!("$SUDO mkdir -p {}\n", shell_quote(parent)));
+                }
+                script.push_str(&format!("$SUDO cp {} {}\n", shell_quote(staged), shell_quote(dest)));
+            }
+            StagedEntry::Dir(dir) => script.push_str(&format!("$SUDO mkdir -p {}\n", shell_quote(dir))),
+        }
+    }
+    script
+}
+
+/// Write the install script of the staged destinations, if any, and tell the user how to run it.
+pub(crate) fn write_install_script(staging: &Staging) -> Result<(), Box<dyn Error>> {
+    if staging.entries.is_empty() {
+        return Ok(());
+    }
+    let path = staging.dir.join("install.sh");
+    create_dir(&staging.dir)?;
+    fs::write(&path, install_script(&staging.entries)).map_err(|source| FsError::Write {
+        path: path.clone(),
+        source,
+    })?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+    }
+    say!(
+        "{} destination(s) need elevated privileges; review and run: sh {}",
+        staging.entries.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+/// A failed file system operation while generating, naming the paths involved: a bare "No such file or directory"
+/// doesn't tell which of a scaffold's files it is about.
+#[derive(Debug)]
+pub(crate) enum FsError {
+    Read { path: PathBuf, source: io::Error },
+    ReadDir { path: PathBuf, source: io::Error },
+    Write { path: PathBuf, source: io::Error },
+    Copy { src: PathBuf, dest: PathBuf, source: io::Error },
+    CreateDir { path: PathBuf, source: io::Error },
+}
+
+impl fmt::Display for FsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FsError::Read { path, .. } => write!(f, "Failed to read {:?}", path),
+            FsError::ReadDir { path, .. } => write!(f, "Failed to list directory {:?}", path),
+            FsError::Write { path, .. } => write!(f, "Failed to write {:?}", path),
+            FsError::Copy { src, dest, .. } => write!(f, "Failed to copy {:?} to {:?}", src, dest),
+            FsError::CreateDir { path, .. } => write!(f, "Failed to create directory {:?}", path),
+        }
+    }
+}
+
+impl Error for FsError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            FsError::Read { source, .. }
+            | FsError::ReadDir { source, .. }
+            | FsError::Write { source, .. }
+            | FsError::Copy { source, .. }
+            | FsError::CreateDir { source, .. } => Some(source),
+        }
+    }
+}
+
+/// Whether two files have the same contents, compared a chunk at a time instead of reading them whole.
+fn same_contents(a: &Path, b: &Path) -> Result<bool, FsError> {
+    let read_error = |path: &Path| {
+        let path = path.to_path_buf();
+        move |source| FsError::Read { path, source }
+    };
+    let len = |path: &Path| fs::metadata(path).map(|metadata| metadata.len()).map_err(read_error(path));
+    if len(a)? != len(b)? {
+        return Ok(false);
+    }
+    let open = |path: &Path| fs::File::open(path).map(io::BufReader::new).map_err(read_error(path));
+    let (mut a_reader, mut b_reader) = (open(a)?, open(b)?);
+    loop {
+        let a_chunk = a_reader.fill_buf().map_err(read_error(a))?;
+        let b_chunk = b_reader.fill_buf().map_err(read_error(b))?;
+        let n = a_chunk.len().min(b_chunk.len());
+        if n == 0 {
+            return Ok(a_chunk.len() == b_chunk.len());
+        }
+        if a_chunk[..n] != b_chunk[..n] {
+            return Ok(false);
+        }
+        a_reader.consume(n);
+        b_reader.consume(n);
+    }
+}
+
+/// fs::read, with the path in the error.
+pub(crate) fn read_file(path: &Path) -> Result<Vec<u8>, FsError> {
+    fs::read(path).map_err(|source| FsError::Read {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// fs::read_to_string, with the path in the error.
+pub(crate) fn read_text_file(path: &Path) -> Result<String, FsError> {
+    fs::read_to_string(path).map_err(|source| FsError::Read {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// fs::read_dir, with the path in the error.
+pub(crate) fn read_dir(path: &Path) -> Result<fs::ReadDir, FsError> {
+    fs::read_dir(path).map_err(|source| FsError::ReadDir {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// fs::create_dir_all, with the path in the error.
+pub(crate) fn create_dir(path: &Path) -> Result<(), FsError> {
+    if planning() || previewing() {
+        return Ok(());
+    }
+    fs::create_dir_all(path).map_err(|source| FsError::CreateDir {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Render a one-off template string.  In strict mode, the template is checked for undefined
+/// variables first; `location` describes where the template came from for error messages.
+pub(crate) fn render_string(input: &str, location: &str, context: &Context, options: &RenderOptions) -> Result<String, Box<dyn Error>> {
+    if options.strict {
+        let template = Template::new(location, None, input)?;
+        check_undefined_variables(&template, input, location, context)?;
+    }
+    let mut tera = new_tera();
+    tera.add_raw_template(location, input)?;
+    Ok(tera.render(location, context)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+    use tempfile::TempDir;
+    use tera::Context;
+
+    // Test that output is only colored when enabled, and never for streams that aren't terminals.
+    #[test]
+    fn test_output_colors() -> Result<(), Box<dyn std::error::Error>> {
+        assert_eq!(Style::Created.paint_if(true, "+ created"), "\x1b[32m+ created\x1b[0m");
+        assert_eq!(Style::Error.paint_if(true, "! error"), "\x1b[1;31m! error\x1b[0m");
+        assert_eq!(Style::Skipped.paint_if(false, "- skipped"), "- skipped");
+        let dir = TempDir::new()?;
+        let file = fs::File::create(dir.path().join("output.log"))?;
+        assert_eq!(Style::Overwritten.paint("~ overwritten", &file), "~ overwritten");
+        Ok(())
+    }
+
+    // Test that write_file reports created, unchanged, overwritten and skipped files.
+    #[test]
+    fn test_write_file_status() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let dest = temp_dir.path().join("nested").join("file.txt");
+
+        assert_eq!(write_file(&dest, b"one", false)?, WriteStatus::Created);
+        assert_eq!(write_file(&dest, b"two", false)?, WriteStatus::Skipped);
+        assert_eq!(write_file(&dest, b"one", true)?, WriteStatus::Unchanged);
+        assert_eq!(write_file(&dest, b"two", true)?, WriteStatus::Overwritten);
+        assert_eq!(fs::read_to_string(&dest)?, "two");
+        Ok(())
+    }
+
+    // Test that --stage writes unwritable destinations to the staging directory and lists them in install.sh.
+    #[test]
+    #[cfg(unix)]
+    fn test_stage_unwritable_destinations() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let stage = temp_dir.path().join("stage");
+        let privileged = temp_dir.path().join("etc");
+        let public = temp_dir.path().join("home").join("notes.txt");
+        fn writable(path: &Path) -> bool {
+            !path.components().any(|component| component.as_os_str() == "etc")
+        }
+
+        begin_staging(&stage, writable);
+        let conf = privileged.join("app").join("it's.conf");
+        assert_eq!(write_file(&conf, b"port = 80", false)?, WriteStatus::Staged);
+        assert_eq!(create_empty_dir(&privileged.join("app.d"))?.1, WriteStatus::Staged);
+        assert_eq!(write_file(&public, b"notes", false)?, WriteStatus::Created);
+        let staging = finish_staging().ok_or("staging was not started")?;
+        assert!(finish_staging().is_none());
+
+        assert!(!conf.exists());
+        assert_eq!(fs::read_to_string(&public)?, "notes");
+        let staged = stage.join("files").join(conf.strip_prefix("/")?);
+        assert_eq!(fs::read_to_string(&staged)?, "port = 80");
+        assert_eq!(
+            staging.entries,
+            vec![
+                StagedEntry::File {
+                    staged: staged.clone(),
+                    dest: conf.clone()
+                },
+                StagedEntry::Dir(privileged.join("app.d")),
+            ]
+        );
+
+        write_install_script(&staging)?;
+        let script = fs::read_to_string(stage.join("install.sh"))?;
+        assert!(script.starts_with("#!/bin/sh\n"));
+        let quote = |path: &Path| format!("'{}'", path.display().to_string().replace('\'', "'\\''"));
+        assert!(script.contains(&format!("$SUDO cp {} {}\n", quote(&staged), quote(&conf))));
+        assert!(script.contains(&format!("$SUDO mkdir -p '{}'\n", privileged.join("app.d").display())));
+        assert!(script.contains("it'\\''s.conf"));
+        Ok(())
+    }
+
+    // Test that a run stops at its limits, even with --keep-going, and that the defaults apply unless --no-limits.
+    #[test]
+    fn test_limits() -> Result<(), Box<dyn std::error::Error>> {
+        let templates_dir = TempDir::new()?;
+        let output_dir = TempDir::new()?;
+        fs::write(templates_dir.path().join("item.txt.tera"), "{{ item }}\n")?;
+        let scaffold = Scaffold {
+            template: TemplateConfig {
+                files: vec![TemplateFile {
+                    src: "item.txt.tera".to_string(),
+                    dest: "items/{{ item }}.txt".to_string(),
+                    for_each: Some("items".to_string()),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut context = Context::new();
+        context.insert("items", &["a", "b", "c", "d"]);
+        let limits = Limits {
+            max_file_size: 100,
+            max_files: 3,
+            max_total_bytes: 100,
+            max_template_size: 100,
+        };
+        let options = RenderOptions {
+            keep_going: true,
+            ..overwrite_options()
+        };
+        begin_limits(Some(limits));
+        let error = render_templates(templates_dir.path(), output_dir.path(), &scaffold, &context, &options).unwrap_err();
+        assert!(
+            error.to_string().starts_with("The run would write more than max_files (3 files)"),
+            "{}",
+            error
+        );
+        assert!(!output_dir.path().join("items/d.txt").exists());
+
+        context.insert("items", &["a".repeat(60), "b".repeat(60)]);
+        begin_limits(Some(Limits { max_files: 10, ..limits }));
+        let error = render_templates(templates_dir.path(), output_dir.path(), &scaffold, &context, &options).unwrap_err();
+        assert!(error.to_string().contains("more than max_total_bytes (100 bytes)"), "{}", error);
+        begin_limits(Some(Limits {
+            max_file_size: 10,
+            ..limits
+        }));
+        let error = render_templates(templates_dir.path(), output_dir.path(), &scaffold, &context, &options).unwrap_err();
+        assert!(
+            error.to_string().contains("would be 61 bytes, more than max_file_size (10 bytes)"),
+            "{}",
+            error
+        );
+        begin_limits(None);
+        render_templates(templates_dir.path(), output_dir.path(), &scaffold, &context, &options)?;
+
+        let config: Config = toml::from_str("[project.limits]\nmax_files = 5")?;
+        let limits = get_limits(&Args::default(), &config).ok_or("limits should apply by default")?;
+        assert_eq!((limits.max_files, limits.max_file_size), (5, DEFAULT_MAX_FILE_SIZE));
+        let no_limits = Args {
+            no_limits: true,
+            ..Default::default()
+        };
+        assert_eq!(get_limits(&no_limits, &config), None);
+        Ok(())
+    }
+
+    // Test that files are compared a chunk at a time, and that templates above max_template_size are refused.
+    #[test]
+    fn test_large_files() -> Result<(), Box<dyn std::error::Error>> {
+        let templates_dir = TempDir::new()?;
+        let output_dir = TempDir::new()?;
+        let path = |name: &str| templates_dir.path().join(name);
+        let data = "x".repeat(20_000);
+        fs::write(path("data.csv"), &data)?;
+        fs::write(path("same.csv"), &data)?;
+        fs::write(path("other.csv"), data.replacen('x', "y", 1))?;
+        fs::write(path("short.csv"), "x")?;
+        assert!(same_contents(&path("data.csv"), &path("same.csv"))?);
+        assert!(!same_contents(&path("data.csv"), &path("other.csv"))?);
+        assert!(!same_contents(&path("data.csv"), &path("short.csv"))?);
+
+        fs::write(path("big.txt.tera"), format!("{{{{ name }}}}{}", data))?;
+        fs::write(path("model.bin"), [b"{{ name }}\0".as_slice(), data.as_bytes()].concat())?;
+        fs::write(path("small.txt.tera"), "{{ name }}")?;
+        let limits = Limits {
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+            max_files: DEFAULT_MAX_FILES,
+            max_total_bytes: DEFAULT_MAX_TOTAL_BYTES,
+            max_template_size: 1000,
+        };
+        begin_limits(Some(limits));
+        let file = |src: &str| TemplateFile {
+            src: src.to_string(),
+            dest: src.to_string(),
+            ..Default::default()
+        };
+        let scaffold = Scaffold {
+            template: TemplateConfig {
+                files: vec![file("data.csv"), file("big.txt.tera"), file("small.txt.tera")],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut context = Context::new();
+        context.insert("name", "demo");
+        let options = RenderOptions {
+            keep_going: true,
+            ..overwrite_options()
+        };
+        let result = render_templates(templates_dir.path(), output_dir.path(), &scaffold, &context, &options)?;
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].file, "big.txt.tera");
+        assert!(
+            result.errors[0].message.contains("20010 bytes, too large to render as a template"),
+            "{}",
+            result.errors[0].message
+        );
+        assert_eq!(fs::read_to_string(output_dir.path().join("data.csv"))?, data);
+        assert_eq!(fs::read_to_string(output_dir.path().join("small.txt.tera"))?, "demo");
+
+        // In template trees, large binary files are still copied, and large text files refused.
+        assert_eq!(read_tree_text(&path("model.bin"))?, None);
+        assert_eq!(read_tree_text(&path("small.txt.tera"))?.as_deref(), Some("{{ name }}"));
+        assert!(read_tree_text(&path("big.txt.tera")).is_err());
+        begin_limits(None);
+        assert!(read_tree_text(&path("big.txt.tera"))?.is_some());
+        Ok(())
+    }
+
+    // Test that a second run on the same output directory fails while the first one holds the lock
+    #[test]
+    fn test_output_lock() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = TempDir::new()?;
+        let output = dir.path().join("out");
+        let first = lock_output(&output, false)?;
+        let error = lock_output(&output, false).err().ok_or("the second lock should fail")?;
+        assert!(error.to_string().starts_with("Another scaficionado run is in progress on"));
+        assert!(error.to_string().ends_with("use --wait to wait for it to finish"));
+
+        // The lock is kept out of the output directory, which locking doesn't even create.
+        drop(first);
+        assert!(!output.exists());
+        let lock = lock_output(&output, false)?;
+        fs::create_dir_all(output.join(".scaficionado"))?;
+        fs::write(output.join("README.md"), "# Demo")?;
+        fs::write(output.join(ANSWERS_FILE), "")?;
+        drop(lock);
+        let names = |dir: &Path| -> io::Result<Vec<std::ffi::OsString>> {
+            let mut names: Vec<_> = fs::read_dir(dir)?
+                .map(|entry| entry.map(|entry| entry.file_name()))
+                .collect::<Result<_, _>>()?;
+            names.sort();
+            Ok(names)
+        };
+        assert_eq!(names(&output)?, [".scaficionado", "README.md"]);
+        assert_eq!(names(&output.join(".scaficionado"))?, ["answers.toml"]);
+        assert!(output_lock_path(&output)?.exists());
+
+        // Stale clones are left alone while another run uses the cache.
+        let cache = dir.path().join("clones");
+        fs::create_dir(&cache)?;
+        let shared = share_clone_cache(&cache);
+        assert!(shared.is_some());
+        assert_eq!(remove_stale_clones(&cache, false)?, None);
+        drop(shared);
+        assert_eq!(remove_stale_clones(&cache, false)?, Some((0, 0)));
+        Ok(())
+    }
+}
@@ -0,0 +1,97 @@
+//! Path helpers for the platform's separators and the forms of Windows paths.
+
+use regex::Regex;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Check for a Windows drive-letter path ("C:\templates", "C:/templates") or a UNC path ("\\server\share"), which
+/// is local whatever else it contains.
+pub(crate) fn is_windows_path(path: &str) -> bool {
+    static WINDOWS_PATH: OnceLock<Regex> = OnceLock::new();
+    WINDOWS_PATH
+        .get_or_init(|| Regex::new(r"^([A-Za-z]:[\\/]|\\\\[^\\/?])").unwrap())
+        .is_match(path)
+}
+
+/// Use the platform's separator throughout a path from the configuration or a template, so that "src/main.rs" and
+/// "src\main.rs" name the same file everywhere and joins onto extended-length Windows paths stay valid.
+pub(crate) fn native_separators(path: &str) -> String {
+    path.replace(['/', '\\'], std::path::MAIN_SEPARATOR_STR)
+}
+
+/// Windows' limit on path length, unless a path is given in the extended-length form "\\?\C:\...".
+const WINDOWS_MAX_PATH: usize = 260;
+
+/// Canonicalize a path.  On Windows this yields an extended-length path, which most tools (cmd, PowerShell, many
+/// compilers) reject, so the plain form is used whenever it's short enough to work.
+pub(crate) fn canonical_path(path: impl AsRef<Path>) -> io::Result<PathBuf> {
+    let canonical = fs::canonicalize(path)?;
+    if !cfg!(windows) {
+        return Ok(canonical);
+    }
+    Ok(strip_extended_length(&canonical.to_string_lossy()).map_or(canonical, PathBuf::from))
+}
+
+/// The plain form of an extended-length Windows path ("\\?\C:\x" or "\\?\UNC\server\share\x"), if it has one
+/// within the length limit.
+fn strip_extended_length(path: &str) -> Option<String> {
+    let rest = path.strip_prefix(r"\\?\")?;
+    let plain = match rest.strip_prefix(r"UNC\") {
+        Some(unc) => format!(r"\\{}", unc),
+        None if is_windows_path(rest) => rest.to_string(),
+        None => return None,
+    };
+    (plain.len() < WINDOWS_MAX_PATH).then_some(plain)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::native::{render_dest_path, script_command, RenderOptions};
+    use std::path::Path;
+    use tera::Context;
+
+    // Test the Windows path helpers: separators, extended-length paths and script interpreters.
+    #[test]
+    fn test_windows_paths() -> Result<(), Box<dyn std::error::Error>> {
+        let separator = std::path::MAIN_SEPARATOR_STR;
+        assert_eq!(
+            native_separators(r"src/nested\main.rs"),
+            ["src", "nested", "main.rs"].join(separator)
+        );
+
+        assert_eq!(strip_extended_length(r"\\?\C:\work\app").as_deref(), Some(r"C:\work\app"));
+        assert_eq!(
+            strip_extended_length(r"\\?\UNC\server\share\app").as_deref(),
+            Some(r"\\server\share\app")
+        );
+        assert_eq!(strip_extended_length(r"C:\work\app"), None);
+        // Paths too long for the plain form stay extended.
+        assert_eq!(strip_extended_length(&format!(r"\\?\C:\{}", "a".repeat(WINDOWS_MAX_PATH))), None);
+
+        let program = |path: &str, windows: bool| script_command(Path::new(path), windows).get_program().to_owned();
+        assert_eq!(program("pre.ps1", true), "powershell");
+        assert_eq!(program("pre.ps1", false), "pwsh");
+        assert_eq!(program("pre.cmd", true), "cmd");
+        assert_eq!(program("pre.sh", true), "sh");
+        assert_eq!(program("pre.sh", false), "pre.sh");
+        let args: Vec<_> = script_command(Path::new("pre.bat"), true)
+            .get_args()
+            .map(|arg| arg.to_owned())
+            .collect();
+        assert_eq!(args, ["/C", "pre.bat"]);
+
+        // Destinations may use either separator.
+        let output_base = Path::new("out");
+        let dest = render_dest_path(
+            r"docs\{{ name }}.md",
+            output_base,
+            &Context::from_serialize(serde_json::json!({ "name": "guide" }))?,
+            &RenderOptions::default(),
+        )?;
+        assert_eq!(dest, output_base.join("docs").join("guide.md"));
+        Ok(())
+    }
+}
@@ -0,0 +1,1046 @@
+//! Variables asked by prompts or set on the command line: their types, answers files and suggested defaults.
+
+use crate::render::error_chain;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal};
+use std::path::Path;
+use tera::Context;
+
+use super::*;
+
+/// Where the resolved variables of a run are recorded, relative to the output directory.
+pub(crate) const ANSWERS_FILE: &str = ".scaficionado/answers.toml";
+
+/// Shown instead of the answers and defaults of secret prompts.
+pub(crate) const REDACTED: &str = "<secret>";
+
+/// Prefix of the environment variables that set template variables, e.g. `SCAFICIONADO_VAR_team`.
+const ENV_VARIABLE_PREFIX: &str = "SCAFICIONADO_VAR_";
+
+/// The declared type of a variable.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum VariableType {
+    String,
+    Integer,
+    Float,
+    Bool,
+    List,
+    Table,
+}
+
+impl VariableType {
+    /// The type of a TOML value, if it has one of the declarable types.
+    pub(crate) fn of(value: &toml::Value) -> Option<Self> {
+        match value {
+            toml::Value::String(_) => Some(VariableType::String),
+            toml::Value::Integer(_) => Some(VariableType::Integer),
+            toml::Value::Float(_) => Some(VariableType::Float),
+            toml::Value::Boolean(_) => Some(VariableType::Bool),
+            toml::Value::Array(_) => Some(VariableType::List),
+            toml::Value::Table(_) => Some(VariableType::Table),
+            toml::Value::Datetime(_) => None,
+        }
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            VariableType::String => "string",
+            VariableType::Integer => "integer",
+            VariableType::Float => "float",
+            VariableType::Bool => "bool",
+            VariableType::List => "list",
+            VariableType::Table => "table",
+        }
+    }
+
+    pub(crate) fn described(self) -> &'static str {
+        match self {
+            VariableType::String => "a string",
+            VariableType::Integer => "an integer",
+            VariableType::Float => "a float",
+            VariableType::Bool => "a boolean",
+            VariableType::List => "a list",
+            VariableType::Table => "a table",
+        }
+    }
+
+    /// Convert a value to this type.  Strings (answers, CSV cells and command output) are parsed: "yes" and "no" as
+    /// booleans, TOML arrays or comma-separated items as lists and inline TOML tables as tables.  Scalars become
+    /// strings and integers floats; any other conversion is an error.
+    pub(crate) fn coerce(self, value: &toml::Value) -> Result<toml::Value, String> {
+        let inline = |text: &str| {
+            toml::from_str::<toml::Table>(&format!("value = {}", text))
+                .ok()
+                .and_then(|mut t| t.remove("value"))
+        };
+        let coerced = match (self, value) {
+            _ if VariableType::of(value) == Some(self) => Some(value.clone()),
+            (VariableType::String, toml::Value::Integer(_) | toml::Value::Float(_) | toml::Value::Boolean(_)) => {
+                Some(toml::Value::String(value.to_string()))
+            }
+            (VariableType::String, toml::Value::Datetime(datetime)) => Some(toml::Value::String(datetime.to_string())),
+            (VariableType::Integer, toml::Value::String(text)) => text.trim().parse().ok().map(toml::Value::Integer),
+            (VariableType::Float, toml::Value::Integer(number)) => Some(toml::Value::Float(*number as f64)),
+            (VariableType::Float, toml::Value::String(text)) => text.trim().parse().ok().map(toml::Value::Float),
+            (VariableType::Bool, toml::Value::String(text)) => match text.trim().to_lowercase().as_str() {
+                "y" | "yes" | "true" => Some(toml::Value::Boolean(true)),
+                "n" | "no" | "false" => Some(toml::Value::Boolean(false)),
+                _ => None,
+            },
+            (VariableType::List, toml::Value::String(text)) => match inline(text) {
+                Some(list @ toml::Value::Array(_)) => Some(list),
+                _ => Some(toml::Value::Array(
+                    text.split(',')
+                        .map(str::trim)
+                        .filter(|item| !item.is_empty())
+                        .map(|item| toml::Value::String(item.to_string()))
+                        .collect(),
+                )),
+            },
+            (VariableType::Table, toml::Value::String(text)) => inline(text).filter(toml::Value::is_table),
+            _ => None,
+        };
+        coerced.ok_or_else(|| format!("expected {}, got {}", self.described(), display_value(value)))
+    }
+}
+
+/// A question asked interactively when its variable has no value from the configuration or an answers file.
+#[derive(Deserialize, JsonSchema, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Prompt {
+    /// Name of the variable the answer is stored in.
+    pub(crate) name: String,
+    /// Question shown to the user (defaults to the variable name).
+    pub(crate) message: Option<String>,
+    /// Value used when the answer is left empty.  Its type decides how answers are parsed, unless `type` is set.
+    #[schemars(with = "Option<serde_json::Value>")]
+    pub(crate) default: Option<toml::Value>,
+    /// Type the answer is parsed as: "string", "integer", "float", "bool", "list" (comma-separated) or "table".
+    #[serde(rename = "type")]
+    pub(crate) kind: Option<VariableType>,
+    /// Allowed answers, if the answer must be one of a fixed set.
+    #[schemars(with = "Option<Vec<serde_json::Value>>")]
+    pub(crate) choices: Option<Vec<toml::Value>>,
+    /// Tera expression, e.g. "use_database == true": the prompt is only asked when it holds for the variables and
+    /// earlier answers.  Otherwise its variable takes the default, if any.
+    pub(crate) when: Option<String>,
+    /// Section the prompt is shown in.  A heading is printed before the first prompt of each section.
+    pub(crate) group: Option<String>,
+    /// Read the answer without echoing it, and leave it out of the answers file.
+    #[serde(default)]
+    pub(crate) secret: bool,
+}
+
+/// The resolved variables of a run, written to the answers file and replayed with --answers.
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub(crate) struct Answers {
+    pub(crate) project_name: Option<String>,
+    /// Resolved variables per scaffold name.
+    #[serde(default)]
+    pub(crate) scaffolds: BTreeMap<String, BTreeMap<String, toml::Value>>,
+    /// The commit every scaffold cloned from a git repository was generated from, checked by `outdated`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) sources: BTreeMap<String, LockedSource>,
+}
+
+/// The repository and commit a scaffold was generated from.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub(crate) struct LockedSource {
+    pub(crate) repo: String,
+    pub(crate) commit: String,
+    /// A tag pointing at the commit, e.g. "v1.2.0".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) tag: Option<String>,
+}
+
+/// Load an answers file written by a previous run.
+pub(crate) fn load_answers(path: &Path) -> Result<Answers, Box<dyn Error>> {
+    say!("Replaying answers from: {:?}", path);
+    let answers_str = fs::read_to_string(path)?;
+    Ok(toml::from_str(&answers_str)?)
+}
+
+/// Write the answers file into the output directory.
+pub(crate) fn write_answers(output_base: &Path, answers: &Answers) -> Result<(), Box<dyn Error>> {
+    let path = output_base.join(ANSWERS_FILE);
+    if let Some(parent) = path.parent() {
+        create_dir(parent)?;
+    }
+    let content = format!(
+        "# Variables resolved by scaficionado.  Replay them with --answers {}\n{}",
+        path.display(),
+        toml::to_string(answers)?
+    );
+    fs::write(&path, content).map_err(|source| FsError::Write {
+        path: path.clone(),
+        source,
+    })?;
+    say!("Recorded answers to: {:?}", path);
+    Ok(())
+}
+
+/// Use the replayed project name unless one was given on the command line.
+pub(crate) fn apply_replayed_project_name(args: &Args, replayed: &Answers, config: &mut Config) {
+    if let Some(name) = &replayed.project_name {
+        if args.project_name == DEFAULT_PROJECT_NAME {
+            config.project.get_or_insert_with(Default::default).name = Some(name.clone());
+        }
+    }
+}
+
+/// Leave the secret variables and answers of the `scaffolds` out of the answers file, so that they are asked again
+/// when it is replayed.
+pub(crate) fn omit_secrets<'a>(answers: &mut Answers, scaffolds: impl IntoIterator<Item = &'a Scaffold>) {
+    for scaffold in scaffolds {
+        if let Some(resolved) = answers.scaffolds.get_mut(scaffold_name(scaffold)) {
+            resolved.retain(|name, _| !is_secret(scaffold, name));
+        }
+    }
+}
+
+/// Whether the variable `name` of a scaffold holds a secret: it is listed in `secret_variables` or answers a secret prompt.
+pub(crate) fn is_secret(scaffold: &Scaffold, name: &str) -> bool {
+    scaffold.secret_variables.iter().any(|secret| secret == name)
+        || scaffold.prompts.iter().flatten().any(|prompt| prompt.secret && prompt.name == name)
+}
+
+/// Collect every resolved variable of the run (global, scaffold and answered) for the answers file.
+pub(crate) fn record_answers(config: &Config, project_name: &str, answers: &[BTreeMap<String, toml::Value>]) -> Answers {
+    let mut recorded = Answers {
+        project_name: Some(project_name.to_string()),
+        ..Default::default()
+    };
+    for (scaffold, scaffold_answers) in config.scaffolds.iter().zip(answers) {
+        let resolved = recorded.scaffolds.entry(scaffold_name(scaffold).to_string()).or_default();
+        for vars in [config.variables.as_ref(), scaffold.variables.as_ref()].into_iter().flatten() {
+            resolved.extend(vars.iter().map(|(key, value)| (key.clone(), value.clone())));
+        }
+        resolved.extend(scaffold_answers.clone());
+    }
+    recorded
+}
+
+/// Resolve the prompts of a scaffold.  Replayed answers are kept (they override configured variables);
+/// prompts whose variable is still undefined are asked on `input`.
+pub(crate) fn resolve_prompts(
+    scaffold: &Scaffold,
+    project_name: &str,
+    global_variables: Option<&HashMap<String, toml::Value>>,
+    answers: BTreeMap<String, toml::Value>,
+    input: &mut impl AnswerInput,
+) -> Result<BTreeMap<String, toml::Value>, Box<dyn Error>> {
+    let context = build_context(project_name, global_variables, scaffold.variables.as_ref());
+    ask_missing_prompts(scaffold.prompts.iter().flatten(), &context, answers, input)
+}
+
+/// Ask the prompts whose variable is neither in `context` nor already answered, skipping those whose `when`
+/// condition doesn't hold, and print the heading of each `group` before its first prompt.
+pub(crate) fn ask_missing_prompts<'a>(
+    prompts: impl IntoIterator<Item = &'a Prompt>,
+    context: &Context,
+    mut answers: BTreeMap<String, toml::Value>,
+    input: &mut impl AnswerInput,
+) -> Result<BTreeMap<String, toml::Value>, Box<dyn Error>> {
+    let mut group = None;
+    for prompt in prompts {
+        if context.contains_key(&prompt.name) || answers.contains_key(&prompt.name) {
+            continue;
+        }
+        if let Some(condition) = &prompt.when {
+            if !prompt_condition_holds(prompt, condition, context, &answers)? {
+                if let Some(default) = &prompt.default {
+                    answers.insert(prompt.name.clone(), default.clone());
+                }
+                continue;
+            }
+        }
+        if prompt.group.is_some() && prompt.group != group {
+            group = prompt.group.clone();
+            let heading = group.as_deref().unwrap_or_default();
+            let heading = match messages_to_stderr() {
+                true => Style::Heading.paint(heading, &io::stderr()),
+                false => Style::Heading.paint(heading, &io::stdout()),
+            };
+            say!("\n{}", heading);
+        }
+        let answer = ask_prompt(prompt, input)?;
+        answers.insert(prompt.name.clone(), answer);
+    }
+    Ok(answers)
+}
+
+/// Evaluate the `when` condition of a prompt against the variables and the answers so far.  Undefined variables
+/// are an error, except with `is defined` tests or the `default` filter.
+fn prompt_condition_holds(
+    prompt: &Prompt,
+    condition: &str,
+    context: &Context,
+    answers: &BTreeMap<String, toml::Value>,
+) -> Result<bool, Box<dyn Error>> {
+    let mut context = context.clone();
+    for (name, answer) in answers {
+        context.insert(name, answer);
+    }
+    let location = format!("`when` of prompt '{}'", prompt.name);
+    let template = format!("{{% if {} %}}true{{% endif %}}", condition);
+    render_string(&template, &location, &context, &RenderOptions::default())
+        .map(|rendered| rendered == "true")
+        .map_err(|e| format!("Invalid {}: {}", location, error_chain(e.as_ref())).into())
+}
+
+/// Where prompts read their answers from.
+pub(crate) trait AnswerInput: BufRead {
+    /// Read the answer to a secret prompt.  Only a terminal can hide it.
+    fn read_secret_line(&mut self, line: &mut String) -> io::Result<usize> {
+        self.read_line(line)
+    }
+}
+
+impl AnswerInput for io::StdinLock<'_> {
+    fn read_secret_line(&mut self, line: &mut String) -> io::Result<usize> {
+        if !io::stdin().is_terminal() {
+            return self.read_line(line);
+        }
+        let secret = rpassword::read_password()?;
+        line.push_str(&secret);
+        line.push('\n');
+        Ok(line.len())
+    }
+}
+
+impl AnswerInput for io::Empty {}
+
+impl<T: AsRef<[u8]>> AnswerInput for io::Cursor<T> {}
+
+impl AnswerInput for Box<dyn AnswerInput> {
+    fn read_secret_line(&mut self, line: &mut String) -> io::Result<usize> {
+        (**self).read_secret_line(line)
+    }
+}
+
+/// Ask a single prompt until a valid answer is given.  An empty answer, or the end of the input, selects the default.
+/// The answers and default of secret prompts are never shown.
+pub(crate) fn ask_prompt(prompt: &Prompt, input: &mut impl AnswerInput) -> Result<toml::Value, Box<dyn Error>> {
+    let mut message = prompt.message.clone().unwrap_or_else(|| prompt.name.clone());
+    if let Some(choices) = &prompt.choices {
+        let choices: Vec<String> = choices.iter().map(display_value).collect();
+        message = format!("{} ({})", message, choices.join(", "));
+    }
+    let shown = |value: &toml::Value| match prompt.secret {
+        true => REDACTED.to_string(),
+        false => display_value(value),
+    };
+    loop {
+        match &prompt.default {
+            Some(default) => print_partial(&format!("{} [{}]: ", message, shown(default)))?,
+            None => print_partial(&format!("{}: ", message))?,
+        }
+
+        let mut line = String::new();
+        let read = match prompt.secret {
+            true => input.read_secret_line(&mut line)?,
+            false => input.read_line(&mut line)?,
+        };
+        if read == 0 {
+            let default = prompt.default.as_ref().ok_or_else(|| {
+                format!(
+                    "No answer given for prompt '{}', which has no default: set the variable, or replay it with --answers",
+                    prompt.name
+                )
+            })?;
+            say!("{}", shown(default));
+            return Ok(default.clone());
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            match &prompt.default {
+                Some(default) => return Ok(default.clone()),
+                None => continue,
+            }
+        }
+        match parse_answer(line, prompt) {
+            Ok(value) if prompt.choices.as_ref().is_some_and(|choices| !choices.contains(&value)) => {
+                say!("Invalid answer: '{}' is not one of the choices", shown(&value))
+            }
+            Ok(value) => return Ok(value),
+            // Parse errors can quote the answer.
+            Err(_) if prompt.secret => say!("Invalid answer for a secret"),
+            Err(e) => say!("Invalid answer: {}", e),
+        }
+    }
+}
+
+/// Parse an answer as the prompt's declared type, or the type of its default value (strings otherwise).
+fn parse_answer(answer: &str, prompt: &Prompt) -> Result<toml::Value, Box<dyn Error>> {
+    let kind = prompt.kind.or_else(|| prompt.default.as_ref().and_then(VariableType::of));
+    Ok(kind
+        .unwrap_or(VariableType::String)
+        .coerce(&toml::Value::String(answer.to_string()))?)
+}
+
+/// The declared types of a scaffold's variables: the `type` of its prompts, overridden by its `types`.
+pub(crate) fn declared_types(scaffold: &Scaffold) -> BTreeMap<&str, VariableType> {
+    let mut declared: BTreeMap<&str, VariableType> = scaffold
+        .prompts
+        .iter()
+        .flatten()
+        .filter_map(|prompt| prompt.kind.map(|kind| (prompt.name.as_str(), kind)))
+        .collect();
+    declared.extend(scaffold.types.iter().map(|(name, kind)| (name.as_str(), *kind)));
+    declared
+}
+
+/// Convert the variables of a scaffold that have a declared type to it, whether they come from the global or
+/// scaffold variables, an answers file, a batch row or a prompt.  The converted values are added to `answers`,
+/// which take precedence in the template context.
+pub(crate) fn coerce_variables(
+    scaffold: &Scaffold,
+    global_variables: Option<&HashMap<String, toml::Value>>,
+    answers: &mut BTreeMap<String, toml::Value>,
+) -> Result<(), Box<dyn Error>> {
+    let mut errors = Vec::new();
+    for (name, kind) in declared_types(scaffold) {
+        let Some(value) = answers
+            .get(name)
+            .or_else(|| scaffold.variables.as_ref().and_then(|variables| variables.get(name)))
+            .or_else(|| global_variables.and_then(|variables| variables.get(name)))
+        else {
+            continue;
+        };
+        match kind.coerce(value) {
+            Ok(coerced) if &coerced == value => {}
+            Ok(coerced) => {
+                answers.insert(name.to_string(), coerced);
+            }
+            Err(e) => errors.push(format!("`{}`: {}", name, e)),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(format!("Variables of the wrong type: {}", errors.join("; ")).into());
+    }
+    Ok(())
+}
+
+/// Parse a `--var` argument: a variable name, `=` and its value.
+pub(crate) fn parse_variable_assignment(assignment: &str) -> Result<(String, String), String> {
+    match assignment.split_once('=') {
+        Some((name, value)) if !name.trim().is_empty() => Ok((name.trim().to_string(), value.to_string())),
+        _ => Err(format!("invalid variable '{}': expected NAME=VALUE, e.g. replicas=3", assignment)),
+    }
+}
+
+/// The variables set outside the configuration: `SCAFICIONADO_VAR_<name>` environment variables, overridden by
+/// `--var` arguments.
+pub(crate) fn variable_overrides(args: &Args) -> BTreeMap<String, String> {
+    let mut overrides = env_variables();
+    overrides.extend(args.vars.iter().cloned());
+    overrides
+}
+
+/// The variables set in the environment as `SCAFICIONADO_VAR_<name>`.  Unit tests get none, whatever the environment
+/// of the test run.
+fn env_variables() -> BTreeMap<String, String> {
+    #[cfg(test)]
+    return BTreeMap::new();
+    #[cfg(not(test))]
+    variables_in_environment(std::env::vars_os())
+}
+
+/// The variables among environment variables, named after their `SCAFICIONADO_VAR_` prefix.  Variables that aren't
+/// valid Unicode are left out.
+fn variables_in_environment(vars: impl IntoIterator<Item = (std::ffi::OsString, std::ffi::OsString)>) -> BTreeMap<String, String> {
+    vars.into_iter()
+        .filter_map(|(name, value)| {
+            let name = name.to_str()?.strip_prefix(ENV_VARIABLE_PREFIX)?.to_string();
+            (!name.is_empty()).then_some((name, value.into_string().ok()?))
+        })
+        .collect()
+}
+
+/// Set the variables of `overrides` in the global variables and in the variables of every scaffold that has them, like
+/// the variables of a `batch` row.  A value given to a variable without a declared type takes the type of the value it
+/// replaces, so that `--var replicas=3` stays an integer; declared types are converted by `coerce_variables`.
+pub(crate) fn apply_variable_overrides(overrides: &BTreeMap<String, String>, config: &mut Config) -> Result<(), Box<dyn Error>> {
+    let declared: HashSet<String> = config
+        .scaffolds
+        .iter()
+        .flat_map(|scaffold| declared_types(scaffold).into_keys().map(str::to_string))
+        .collect();
+    let typed = |name: &str, value: &str, replaced: Option<&toml::Value>| -> Result<toml::Value, String> {
+        let value = toml::Value::String(value.to_string());
+        match replaced.and_then(VariableType::of).filter(|_| !declared.contains(name)) {
+            Some(kind) => kind.coerce(&value).map_err(|e| format!("Variable `{}`: {}", name, e)),
+            None => Ok(value),
+        }
+    };
+    for (name, value) in overrides {
+        let variables = config.variables.get_or_insert_with(Default::default);
+        let global = typed(name, value, variables.get(name))?;
+        variables.insert(name.clone(), global);
+        for variables in config.scaffolds.iter_mut().filter_map(|scaffold| scaffold.variables.as_mut()) {
+            if let Some(variable) = variables.get_mut(name) {
+                *variable = typed(name, value, Some(variable))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Display a TOML value without quoting strings.
+pub(crate) fn display_value(value: &toml::Value) -> String {
+    match value {
+        toml::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// `[suggest]` of the user configuration: the OpenAI-compatible chat completions endpoint asked by --suggest.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct SuggestConfig {
+    /// URL of the chat completions endpoint.
+    pub(crate) endpoint: String,
+    /// Model asked for the suggestions.
+    pub(crate) model: String,
+    /// Environment variable holding the API key, sent as a bearer token.
+    api_key_env: String,
+}
+
+impl Default for SuggestConfig {
+    fn default() -> Self {
+        SuggestConfig {
+            endpoint: "https://api.openai.com/v1/chat/completions".to_string(),
+            model: "gpt-4o-mini".to_string(),
+            api_key_env: "OPENAI_API_KEY".to_string(),
+        }
+    }
+}
+
+/// With --suggest, ask the LLM of `settings` for defaults of the prompts that will be asked, and return the scaffold
+/// with the suggestions as the prompts' defaults, to be accepted or edited.  Only the project name, the scaffold
+/// name and the names, messages, defaults and choices of the prompts are sent; secret prompts are left out.
+pub(crate) fn suggest_defaults(
+    scaffold: &Scaffold,
+    project_name: &str,
+    global_variables: Option<&HashMap<String, toml::Value>>,
+    answers: &BTreeMap<String, toml::Value>,
+    settings: &SuggestConfig,
+) -> Result<Scaffold, Box<dyn Error>> {
+    let context = build_context(project_name, global_variables, scaffold.variables.as_ref());
+    let pending: Vec<&Prompt> = scaffold
+        .prompts
+        .iter()
+        .flatten()
+        .filter(|prompt| !prompt.secret && !context.contains_key(&prompt.name) && !answers.contains_key(&prompt.name))
+        .collect();
+    if pending.is_empty() {
+        return Ok(scaffold.clone());
+    }
+    if run_context().offline {
+        return Err("--offline forbids asking for suggestions".into());
+    }
+    let api_key = std::env::var(&settings.api_key_env)
+        .ok()
+        .filter(|key| !key.trim().is_empty())
+        .ok_or_else(|| format!("--suggest needs an API key in ${}", settings.api_key_env))?;
+    say!(
+        "Asking {} to suggest defaults for {} variable(s) of {}",
+        repo_host(&settings.endpoint).unwrap_or(&settings.endpoint),
+        pending.len(),
+        scaffold_name(scaffold)
+    );
+    let suggestions = request_suggestions(settings, &api_key, scaffold_name(scaffold), project_name, &pending)?;
+    Ok(apply_suggestions(scaffold, &suggestions))
+}
+
+/// Ask the chat completions endpoint for a JSON object of suggested values by variable name.
+fn request_suggestions(
+    settings: &SuggestConfig,
+    api_key: &str,
+    scaffold: &str,
+    project_name: &str,
+    prompts: &[&Prompt],
+) -> Result<serde_json::Map<String, serde_json::Value>, Box<dyn Error>> {
+    let variables: Vec<serde_json::Value> = prompts
+        .iter()
+        .map(|prompt| {
+            serde_json::json!({
+                "name": prompt.name,
+                "description": prompt.message,
+                "default": prompt.default,
+                "choices": prompt.choices,
+            })
+        })
+        .collect();
+    let question = serde_json::json!({
+        "project_name": project_name,
+        "scaffold": scaffold,
+        "variables": variables,
+    });
+    let body = serde_json::json!({
+        "model": settings.model,
+        "response_format": { "type": "json_object" },
+        "messages": [
+            {
+                "role": "system",
+                "content": "You suggest values for the variables of a project template.  Reply with a JSON object \
+                    mapping each variable name to a suggested value of the same type as its default, picked from its \
+                    choices if it has any.  Leave out variables you have no good suggestion for.",
+            },
+            { "role": "user", "content": question.to_string() },
+        ],
+    });
+    let failed = |e: &dyn std::fmt::Display| format!("Failed to get suggestions from {}: {}", settings.endpoint, e);
+    let mut response = http_agent(true)?
+        .post(&settings.endpoint)
+        .header("Authorization", &format!("Bearer {}", api_key))
+        .header("Content-Type", "application/json")
+        .send(body.to_string())
+        .map_err(|e| failed(&e))?;
+    let reply: serde_json::Value = serde_json::from_str(&response.body_mut().read_to_string().map_err(|e| failed(&e))?)?;
+    let content = reply["choices"][0]["message"]["content"]
+        .as_str()
+        .ok_or_else(|| failed(&"the reply has no message"))?;
+    match serde_json::from_str(content) {
+        Ok(serde_json::Value::Object(suggestions)) => Ok(suggestions),
+        _ => Err(failed(&format!("expected a JSON object, got {:?}", content)).into()),
+    }
+}
+
+/// Make the suggested values the defaults of the scaffold's prompts.  Suggestions are parsed like answers, by the
+/// type of the prompt's default; those that don't parse or aren't one of the prompt's choices are ignored.
+fn apply_suggestions(scaffold: &Scaffold, suggestions: &serde_json::Map<String, serde_json::Value>) -> Scaffold {
+    let mut scaffold = scaffold.clone();
+    for prompt in scaffold.prompts.iter_mut().flatten().filter(|prompt| !prompt.secret) {
+        let Some(suggestion) = suggestions.get(&prompt.name) else {
+            continue;
+        };
+        let text = match suggestion {
+            serde_json::Value::String(text) => text.clone(),
+            serde_json::Value::Null | serde_json::Value::Array(_) | serde_json::Value::Object(_) => continue,
+            other => other.to_string(),
+        };
+        let Ok(value) = parse_answer(&text, prompt) else {
+            continue;
+        };
+        if prompt.choices.as_ref().is_none_or(|choices| choices.contains(&value)) {
+            prompt.default = Some(value);
+        }
+    }
+    scaffold
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use std::collections::{BTreeMap, HashMap};
+    use std::fs;
+    use std::io;
+    use std::path::Path;
+    use tempfile::TempDir;
+    use tera::Tera;
+
+    // Test that prompts are asked only for undefined variables and parsed by the default's type.
+    #[test]
+    fn test_resolve_prompts() -> Result<(), Box<dyn std::error::Error>> {
+        let toml_content = r#"
+[[scaffolds]]
+name = "Prompted"
+repo = "local_repo"
+
+[scaffolds.template]
+files = []
+
+[scaffolds.variables]
+environment = "development"
+
+[[scaffolds.prompts]]
+name = "environment"
+
+[[scaffolds.prompts]]
+name = "team"
+message = "Which team owns this project?"
+
+[[scaffolds.prompts]]
+name = "replicas"
+default = 2
+
+[[scaffolds.prompts]]
+name = "use_db"
+default = false
+
+[[scaffolds.prompts]]
+name = "region"
+"#;
+        let config: Config = toml::from_str(toml_content)?;
+        let mut replayed = BTreeMap::new();
+        replayed.insert("region".to_string(), toml::Value::String("us-west-2".into()));
+
+        // "environment" is configured and "region" is replayed, so only three questions are asked.
+        // The first answer for "replicas" is invalid and asked again.
+        let mut input = io::Cursor::new("platform\nmany\n3\n\n");
+        let answers = resolve_prompts(&config.scaffolds[0], "Project", None, replayed, &mut input)?;
+
+        assert!(!answers.contains_key("environment"));
+        assert_eq!(answers["team"], toml::Value::String("platform".into()));
+        assert_eq!(answers["replicas"], toml::Value::Integer(3));
+        assert_eq!(answers["use_db"], toml::Value::Boolean(false));
+        assert_eq!(answers["region"], toml::Value::String("us-west-2".into()));
+
+        // Without input (--defaults), prompts take their default; prompts without one are an error rather than a hang.
+        let mut empty = io::empty();
+        let error = resolve_prompts(&config.scaffolds[0], "Project", None, BTreeMap::new(), &mut empty).unwrap_err();
+        assert!(error.to_string().starts_with("No answer given for prompt 'team'"));
+        let mut config = config;
+        config.scaffolds[0]
+            .prompts
+            .as_mut()
+            .unwrap()
+            .retain(|prompt| prompt.default.is_some());
+        let answers = resolve_prompts(&config.scaffolds[0], "Project", None, BTreeMap::new(), &mut empty)?;
+        assert_eq!(answers["replicas"], toml::Value::Integer(2));
+        assert_eq!(answers["use_db"], toml::Value::Boolean(false));
+        Ok(())
+    }
+
+    // Test that variables with a declared type are converted to it from any source, and that mismatches are errors.
+    #[test]
+    fn test_variable_types() -> Result<(), Box<dyn std::error::Error>> {
+        let toml_content = r#"
+[variables]
+replicas = "3"
+
+[[scaffolds]]
+name = "Typed"
+repo = "local_repo"
+
+[scaffolds.template]
+files = []
+
+[scaffolds.variables]
+debug = "yes"
+labels = { team = "platform" }
+
+[scaffolds.types]
+replicas = "integer"
+debug = "bool"
+labels = "table"
+ratio = "float"
+
+[[scaffolds.prompts]]
+name = "regions"
+type = "list"
+"#;
+        let config: Config = toml::from_str(toml_content)?;
+        let scaffold = &config.scaffolds[0];
+        validate_scaffold(scaffold)?;
+        let mut input = io::Cursor::new("us-east-1, eu-west-1\n");
+        let mut answers = resolve_prompts(scaffold, "Project", config.variables.as_ref(), BTreeMap::new(), &mut input)?;
+        answers.insert("ratio".to_string(), toml::Value::Integer(1));
+        coerce_variables(scaffold, config.variables.as_ref(), &mut answers)?;
+        assert_eq!(answers["replicas"], toml::Value::Integer(3));
+        assert_eq!(answers["debug"], toml::Value::Boolean(true));
+        assert_eq!(answers["ratio"], toml::Value::Float(1.0));
+        assert!(!answers.contains_key("labels"));
+
+        // Lists and tables are lists and maps in templates.
+        let context = scaffold_context(scaffold, "Project", config.variables.as_ref(), &answers, Path::new("."));
+        let rendered = Tera::one_off(
+            "{% for region in regions %}{{ region }};{% endfor %}{{ labels.team }} {{ replicas + 1 }}",
+            &context,
+            false,
+        )?;
+        assert_eq!(rendered, "us-east-1;eu-west-1;platform 4");
+        assert_eq!(
+            VariableType::List.coerce(&toml::Value::String("[1, 2]".to_string()))?,
+            toml::Value::Array(vec![toml::Value::Integer(1), toml::Value::Integer(2)])
+        );
+
+        answers.insert("replicas".to_string(), toml::Value::String("many".to_string()));
+        answers.insert("labels".to_string(), toml::Value::Boolean(true));
+        let error = coerce_variables(scaffold, None, &mut answers).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Variables of the wrong type: `labels`: expected a table, got true; `replicas`: expected an integer, got many"
+        );
+
+        // Prompt defaults and choices must have the prompt's type.
+        let mut mistyped = scaffold.clone();
+        mistyped.prompts.as_mut().unwrap()[0].default = Some(toml::Value::String("us-east-1".to_string()));
+        let error = validate_scaffold(&mistyped).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Prompt 'regions' has `type = \"list\"`, but \"us-east-1\" isn't a list"
+        );
+        Ok(())
+    }
+
+    // Test that --var and SCAFICIONADO_VAR_ variables override the configuration and the answers file, converted to the
+    // declared type or the type of the value they replace.
+    #[test]
+    fn test_variable_overrides() -> Result<(), Box<dyn std::error::Error>> {
+        assert_eq!(parse_variable_assignment("tags=a=b")?, ("tags".to_string(), "a=b".to_string()));
+        assert_eq!(
+            parse_variable_assignment("=3").unwrap_err(),
+            "invalid variable '=3': expected NAME=VALUE, e.g. replicas=3"
+        );
+        let environment = variables_in_environment([
+            ("SCAFICIONADO_VAR_team".into(), "payments".into()),
+            ("SCAFICIONADO_VAR_".into(), "nameless".into()),
+            ("HOME".into(), "/home/jane".into()),
+        ]);
+        assert_eq!(environment, BTreeMap::from([("team".to_string(), "payments".to_string())]));
+
+        let repo_dir = TempDir::new()?;
+        fs::create_dir_all(repo_dir.path().join("templates"))?;
+        fs::write(
+            repo_dir.path().join("templates/values.txt.tera"),
+            "{{ replicas + 1 }} {{ debug }} {% for region in regions %}{{ region }};{% endfor %} {{ team }}",
+        )?;
+        let work_dir = TempDir::new()?;
+        let config_path = work_dir.path().join("scaffolding.toml");
+        fs::write(
+            &config_path,
+            format!(
+                r#"
+[variables]
+debug = true
+team = "platform"
+
+[[scaffolds]]
+name = "Typed"
+repo = "{}"
+template.files = [{{ src = "values.txt.tera", dest = "values.txt" }}]
+variables = {{ replicas = 1 }}
+types = {{ regions = "list" }}
+"#,
+                repo_dir.path().to_string_lossy().replace('\\', "/")
+            ),
+        )?;
+        let answers = work_dir.path().join("answers.toml");
+        fs::write(&answers, "[scaffolds.Typed]\nreplicas = 5\nregions = [\"us-east-1\"]\n")?;
+        let output = work_dir.path().join("out");
+        let run = |vars: &[&str]| -> Result<(), Box<dyn std::error::Error>> {
+            let mut arguments = vec!["scaficionado", "-c", config_path.to_str().unwrap_or_default()];
+            arguments.extend(["-o", output.to_str().unwrap_or_default(), "-w", "--defaults"]);
+            arguments.extend(["--answers", answers.to_str().unwrap_or_default()]);
+            for var in vars {
+                arguments.extend(["--var", var]);
+            }
+            generate(&Args::try_parse_from(arguments)?)
+        };
+        run(&["replicas=3", "debug=no", "regions=eu-west-1, us-west-2"])?;
+        assert_eq!(
+            fs::read_to_string(output.join("values.txt"))?,
+            "4 false eu-west-1;us-west-2; platform"
+        );
+
+        let error = run(&["debug=maybe"]).err().ok_or("debug is a boolean")?;
+        assert_eq!(error.to_string(), "Variable `debug`: expected a boolean, got maybe");
+        Ok(())
+    }
+
+    // Test that prompts are skipped when their `when` condition doesn't hold for the earlier answers.
+    #[test]
+    fn test_conditional_prompts() -> Result<(), Box<dyn std::error::Error>> {
+        let toml_content = r#"
+[[scaffolds]]
+name = "Conditional"
+repo = "local_repo"
+
+[scaffolds.template]
+files = []
+
+[[scaffolds.prompts]]
+name = "use_database"
+default = false
+group = "Database"
+
+[[scaffolds.prompts]]
+name = "database_engine"
+default = "postgres"
+choices = ["postgres", "mysql"]
+group = "Database"
+when = "use_database"
+
+[[scaffolds.prompts]]
+name = "database_replicas"
+when = "use_database and database_engine == 'postgres'"
+group = "Database"
+
+[[scaffolds.prompts]]
+name = "owner"
+when = "team is defined"
+"#;
+        let config: Config = toml::from_str(toml_content)?;
+        let scaffold = &config.scaffolds[0];
+
+        // Without a database, its prompts are skipped: the engine takes its default, the replicas stay unset.
+        let mut input = io::Cursor::new("no\n");
+        let answers = resolve_prompts(scaffold, "Project", None, BTreeMap::new(), &mut input)?;
+        assert_eq!(answers["use_database"], toml::Value::Boolean(false));
+        assert_eq!(answers["database_engine"], toml::Value::String("postgres".into()));
+        assert!(!answers.contains_key("database_replicas"));
+        assert!(!answers.contains_key("owner"));
+
+        let mut input = io::Cursor::new("yes\n\n3\n");
+        let answers = resolve_prompts(scaffold, "Project", None, BTreeMap::new(), &mut input)?;
+        assert_eq!(answers["database_replicas"], toml::Value::String("3".into()));
+
+        // Conditions see configured variables, and broken conditions name the prompt.
+        let mut variables = HashMap::new();
+        variables.insert("team".to_string(), toml::Value::String("platform".into()));
+        let mut input = io::Cursor::new("no\nalice\n");
+        let answers = resolve_prompts(scaffold, "Project", Some(&variables), BTreeMap::new(), &mut input)?;
+        assert_eq!(answers["owner"], toml::Value::String("alice".into()));
+
+        let mut broken = scaffold.clone();
+        broken.prompts.as_mut().unwrap()[1].when = Some("use_database ==".to_string());
+        let mut input = io::Cursor::new("yes\n");
+        let error = resolve_prompts(&broken, "Project", None, BTreeMap::new(), &mut input).unwrap_err();
+        assert!(
+            error.to_string().starts_with("Invalid `when` of prompt 'database_engine'"),
+            "{}",
+            error
+        );
+        Ok(())
+    }
+
+    // Test that recorded answers can be written and replayed.
+    #[test]
+    fn test_answers_round_trip() -> Result<(), Box<dyn std::error::Error>> {
+        let output_dir = TempDir::new()?;
+        let config: Config = toml::from_str(
+            r#"
+[variables]
+team = "platform"
+
+[[scaffolds]]
+name = "Service"
+repo = "local_repo"
+
+[scaffolds.template]
+files = []
+
+[scaffolds.variables]
+port = 8080
+"#,
+        )?;
+        let mut prompted = BTreeMap::new();
+        prompted.insert("owner".to_string(), toml::Value::String("alice".into()));
+
+        write_answers(output_dir.path(), &record_answers(&config, "AnsweredProject", &[prompted]))?;
+        let replayed = load_answers(&output_dir.path().join(ANSWERS_FILE))?;
+
+        assert_eq!(replayed.project_name.as_deref(), Some("AnsweredProject"));
+        let service = &replayed.scaffolds["Service"];
+        assert_eq!(service["team"], toml::Value::String("platform".into()));
+        assert_eq!(service["port"], toml::Value::Integer(8080));
+        assert_eq!(service["owner"], toml::Value::String("alice".into()));
+        Ok(())
+    }
+
+    // Test that secret variables and the answers of secret prompts are left out of the answers file.
+    #[test]
+    fn test_secret_answers() -> Result<(), Box<dyn std::error::Error>> {
+        let output_dir = TempDir::new()?;
+        let config: Config = toml::from_str(
+            r#"
+[variables]
+registry_password = "hunter2"
+
+[[scaffolds]]
+name = "Service"
+repo = "local_repo"
+secret_variables = ["registry_password"]
+
+[scaffolds.template]
+files = []
+
+[[scaffolds.prompts]]
+name = "api_token"
+secret = true
+
+[[scaffolds.prompts]]
+name = "owner"
+"#,
+        )?;
+        let scaffold = &config.scaffolds[0];
+        assert!(is_secret(scaffold, "api_token") && is_secret(scaffold, "registry_password"));
+        assert!(!is_secret(scaffold, "owner"));
+
+        let mut input = io::Cursor::new("s3cr3t\nalice\n");
+        let answers = resolve_prompts(scaffold, "Project", config.variables.as_ref(), BTreeMap::new(), &mut input)?;
+        assert_eq!(answers["api_token"], toml::Value::String("s3cr3t".into()));
+
+        let mut recorded = record_answers(&config, "SecretProject", &[answers]);
+        omit_secrets(&mut recorded, &config.scaffolds);
+        write_answers(output_dir.path(), &recorded)?;
+        let content = fs::read_to_string(output_dir.path().join(ANSWERS_FILE))?;
+        assert!(!content.contains("s3cr3t") && !content.contains("hunter2"), "{}", content);
+        let replayed = load_answers(&output_dir.path().join(ANSWERS_FILE))?;
+        assert_eq!(replayed.scaffolds["Service"].keys().collect::<Vec<_>>(), ["owner"]);
+        Ok(())
+    }
+
+    // Test that suggestions of the LLM endpoint become the defaults of the prompts, typed like the configured defaults.
+    #[test]
+    fn test_suggest_defaults() -> Result<(), Box<dyn std::error::Error>> {
+        let config: Config = toml::from_str(
+            r#"
+[[scaffolds]]
+name = "Service"
+repo = "local_repo"
+
+[scaffolds.template]
+files = []
+
+[[scaffolds.prompts]]
+name = "description"
+message = "What does the service do?"
+
+[[scaffolds.prompts]]
+name = "replicas"
+default = 1
+
+[[scaffolds.prompts]]
+name = "language"
+default = "rust"
+choices = ["rust", "go"]
+
+[[scaffolds.prompts]]
+name = "api_token"
+secret = true
+"#,
+        )?;
+        let suggestions = r#"{"description": "Payments API", "replicas": "3", "language": "cobol", "api_token": "x"}"#;
+        let reply = serde_json::json!({ "choices": [{ "message": { "content": suggestions } }] }).to_string();
+        let settings = SuggestConfig {
+            endpoint: format!("{}/v1/chat/completions", serve_http(vec![("/v1/chat/completions", reply)])?),
+            ..Default::default()
+        };
+        let scaffold = &config.scaffolds[0];
+        let prompts: Vec<&Prompt> = scaffold.prompts.iter().flatten().collect();
+        let suggested = request_suggestions(&settings, "key", "Service", "payments", &prompts)?;
+        let suggested = apply_suggestions(scaffold, &suggested);
+
+        let defaults: Vec<Option<toml::Value>> = suggested.prompts.iter().flatten().map(|p| p.default.clone()).collect();
+        assert_eq!(
+            defaults,
+            [
+                Some(toml::Value::String("Payments API".into())),
+                Some(toml::Value::Integer(3)),
+                // Not one of the choices, and secrets are never suggested.
+                Some(toml::Value::String("rust".into())),
+                None,
+            ]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,202 @@
+//! Scaffold registry indexes, for `search` and `add`.
+
+use serde::Deserialize;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use super::*;
+
+/// A scaffold registry index: a TOML or JSON file listing scaffolds to find with `search` and add with `add`.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RegistryIndex {
+    #[serde(default)]
+    pub(crate) scaffolds: Vec<RegistryEntry>,
+}
+
+/// A scaffold of a registry index.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RegistryEntry {
+    pub(crate) name: String,
+    #[serde(default)]
+    pub(crate) description: String,
+    pub(crate) repo: RepoUrls,
+    pub(crate) template_dir: Option<String>,
+    #[serde(default)]
+    pub(crate) tags: Vec<String>,
+}
+
+/// Load the registry index at `location`, or at `registry` of the user configuration.  Files ending in `.json` are
+/// JSON, others TOML.
+pub(crate) fn load_registry_index(location: Option<&str>) -> Result<RegistryIndex, Box<dyn Error>> {
+    let location = match location {
+        Some(location) => location.to_string(),
+        None => user_config()?
+            .registry
+            .clone()
+            .ok_or("No registry index: pass --registry or set `registry` in the user configuration")?,
+    };
+    let content = read_config_source(&location, None)?;
+    let index = match split_checksum(&location).0.ends_with(".json") {
+        true => serde_json::from_str(&content).map_err(|e| e.to_string()),
+        false => from_toml(&content).map_err(|e| e.to_string()),
+    };
+    index.map_err(|e| format!("Failed to parse the registry index {}: {}", location, e).into())
+}
+
+/// The scaffolds of `index` whose name, description or one of whose tags contains `query`, ignoring case.
+fn search_index<'a>(index: &'a RegistryIndex, query: &str) -> Vec<&'a RegistryEntry> {
+    let query = query.to_lowercase();
+    index
+        .scaffolds
+        .iter()
+        .filter(|entry| {
+            entry.name.to_lowercase().contains(&query)
+                || entry.description.to_lowercase().contains(&query)
+                || entry.tags.iter().any(|tag| tag.to_lowercase().contains(&query))
+        })
+        .collect()
+}
+
+/// `search` subcommand: print the scaffolds of the registry index matching `query`.
+pub(crate) fn search_registry(registry: Option<&str>, query: &str) -> Result<(), Box<dyn Error>> {
+    let index = load_registry_index(registry)?;
+    let matches = search_index(&index, query);
+    if matches.is_empty() {
+        println!("No scaffold matches {:?}", query);
+        return Ok(());
+    }
+    let width = matches.iter().map(|entry| entry.name.len()).max().unwrap_or(0);
+    for entry in matches {
+        let tags = match entry.tags.is_empty() {
+            true => String::new(),
+            false => format!(" [{}]", entry.tags.join(", ")),
+        };
+        println!("{:width$}  {}{}", entry.name, entry.description, tags, width = width);
+    }
+    println!("\nAdd one to {} with `scaficionado add <name>`", DEFAULT_CONFIG_PATH);
+    Ok(())
+}
+
+/// `add` subcommand: append the scaffold `name` of the registry index to the configuration file at `config_path`.
+/// The entry is appended as text, so the rest of the file keeps its formatting and comments.
+pub(crate) fn add_from_registry(index: &RegistryIndex, name: &str, config_path: &Path) -> Result<(), Box<dyn Error>> {
+    let entry = index.scaffolds.iter().find(|entry| entry.name == name).ok_or_else(|| {
+        match closest_name(name, index.scaffolds.iter().map(|entry| entry.name.clone())) {
+            Some(closest) => format!("No scaffold named '{}' in the registry index, did you mean '{}'?", name, closest),
+            None => format!("No scaffold named '{}' in the registry index", name),
+        }
+    })?;
+    let existing = match config_path.exists() {
+        true => fs::read_to_string(config_path)?,
+        false => String::new(),
+    };
+    let config: Config = from_toml(&existing).map_err(|e| format!("Failed to parse {:?}: {}", config_path, e))?;
+    if config.scaffolds.iter().any(|scaffold| scaffold.name.as_deref() == Some(name)) {
+        return Err(format!("{:?} already has a scaffold named '{}'", config_path, name).into());
+    }
+
+    let quote = |value: &str| toml::Value::String(value.to_string()).to_string();
+    let mut section = String::from("[[scaffolds]]\n");
+    section.push_str(&format!("name = {}\n", quote(&entry.name)));
+    let repo = match &entry.repo {
+        RepoUrls::One(url) => quote(url),
+        RepoUrls::Mirrors(urls) => format!("[{}]", urls.iter().map(|url| quote(url)).collect::<Vec<_>>().join(", ")),
+    };
+    section.push_str(&format!("repo = {}\n", repo));
+    if let Some(template_dir) = &entry.template_dir {
+        section.push_str(&format!("template_dir = {}\n", quote(template_dir)));
+    }
+    let separator = match existing.as_str() {
+        "" => "",
+        text if text.ends_with("\n\n") => "",
+        text if text.ends_with('\n') => "\n",
+        _ => "\n\n",
+    };
+    fs::write(config_path, format!("{}{}{}", existing, separator, section)).map_err(|source| FsError::Write {
+        path: config_path.to_path_buf(),
+        source,
+    })?;
+    say!(
+        "Added scaffold '{}' ({}) to {:?}",
+        entry.name,
+        entry.repo.urls().join(", "),
+        config_path
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    // Test searching a registry index and adding one of its scaffolds to a configuration file.
+    #[test]
+    fn test_registry_index() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = TempDir::new()?;
+        let index_path = dir.path().join("index.toml");
+        fs::write(
+            &index_path,
+            r#"
+[[scaffolds]]
+name = "kafka-consumer"
+description = "A Kafka consumer service"
+repo = "https://example.com/kafka-consumer.git"
+tags = ["kafka", "java"]
+
+[[scaffolds]]
+name = "stream-worker"
+description = "Processes event streams"
+repo = ["git@git.internal:stream.git", "https://example.com/stream.git"]
+template_dir = "templates/worker"
+tags = ["Kafka"]
+
+[[scaffolds]]
+name = "web"
+repo = "https://example.com/web.git"
+"#,
+        )?;
+        let index = load_registry_index(Some(&index_path.to_string_lossy()))?;
+        let names = |entries: Vec<&RegistryEntry>| entries.into_iter().map(|entry| entry.name.clone()).collect::<Vec<_>>();
+        assert_eq!(names(search_index(&index, "KAFKA")), vec!["kafka-consumer", "stream-worker"]);
+        assert_eq!(names(search_index(&index, "event")), vec!["stream-worker"]);
+        assert!(search_index(&index, "python").is_empty());
+
+        let json_path = dir.path().join("index.json");
+        fs::write(
+            &json_path,
+            r#"{"scaffolds": [{"name": "web", "repo": "https://example.com/web.git"}]}"#,
+        )?;
+        assert_eq!(load_registry_index(Some(&json_path.to_string_lossy()))?.scaffolds[0].name, "web");
+
+        // The entry is appended, keeping the rest of the file as it is.
+        let config_path = dir.path().join("scaffolding.toml");
+        fs::write(&config_path, "# Services\n[project]\nname = \"demo\"\n")?;
+        add_from_registry(&index, "stream-worker", &config_path)?;
+        assert_eq!(
+            fs::read_to_string(&config_path)?,
+            "# Services\n[project]\nname = \"demo\"\n\n[[scaffolds]]\nname = \"stream-worker\"\n\
+             repo = [\"git@git.internal:stream.git\", \"https://example.com/stream.git\"]\ntemplate_dir = \"templates/worker\"\n"
+        );
+        add_from_registry(&index, "web", &config_path)?;
+        let config: Config = from_toml(&fs::read_to_string(&config_path)?)?;
+        assert_eq!(config.scaffolds.len(), 2);
+
+        let error = add_from_registry(&index, "web", &config_path)
+            .err()
+            .ok_or("web was already added")?;
+        assert!(error.to_string().ends_with("already has a scaffold named 'web'"));
+        let error = add_from_registry(&index, "kafka-consumr", &config_path)
+            .err()
+            .ok_or("the name is misspelled")?;
+        assert_eq!(
+            error.to_string(),
+            "No scaffold named 'kafka-consumr' in the registry index, did you mean 'kafka-consumer'?"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,599 @@
+//! What a run reports: its summary, timings, errors, exit codes and GitHub Actions output.
+
+use crate::render::error_chain;
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use super::*;
+
+/// The result of processing a single scaffold.
+#[derive(Debug, Default)]
+pub(crate) struct ScaffoldOutcome {
+    /// Status of every destination file.
+    pub(crate) written: Vec<(PathBuf, WriteStatus)>,
+    /// Number of hooks that ran successfully.
+    pub(crate) hooks_run: usize,
+    /// Errors collected with --keep-going.
+    pub(crate) errors: Vec<RunError>,
+}
+
+/// What render_scaffolds produced for the scaffolds it processed.
+#[derive(Debug, Default)]
+pub(crate) struct RenderedScaffolds {
+    /// Resolved answers of every scaffold, in the order of the configuration.
+    pub(crate) answers: Vec<BTreeMap<String, toml::Value>>,
+    /// The files and hooks of every scaffold that was processed.
+    pub(crate) scaffolds: Vec<ScaffoldRun>,
+}
+
+/// The files written and hooks run for a scaffold.
+#[derive(Debug, Default)]
+pub(crate) struct ScaffoldRun {
+    pub(crate) name: String,
+    pub(crate) written: Vec<(PathBuf, WriteStatus)>,
+    pub(crate) hooks_run: usize,
+}
+
+/// Start of the run, for the elapsed time in the summary.
+pub(crate) static RUN_STARTED: OnceLock<Instant> = OnceLock::new();
+
+thread_local! {
+    /// The summary of the last project generated, for the report of `batch`.
+    pub(crate) static LAST_SUMMARY: RefCell<Option<RunSummary>> = const { RefCell::new(None) };
+}
+
+/// The end-of-run summary, printed after every run and written as JSON with --report.
+#[derive(Debug, Serialize)]
+pub(crate) struct RunSummary {
+    pub(crate) project: String,
+    pub(crate) output: String,
+    pub(crate) elapsed_seconds: f64,
+    pub(crate) errors: usize,
+    pub(crate) scaffolds: Vec<ScaffoldSummary>,
+    /// The duration of each phase, with --timings.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) timings: Vec<Timing>,
+}
+
+/// How long one phase of a run took: loading the configuration, or a scaffold's clone, render or hook.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub(crate) struct Timing {
+    phase: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) scaffold: Option<String>,
+    pub(crate) seconds: f64,
+}
+
+thread_local! {
+    /// The phases timed so far, kept per thread like SEQUENCES.
+    static TIMINGS: RefCell<Vec<Timing>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Run `phase` (of `scaffold`) and record how long it took for --timings.
+pub(crate) fn timed<T>(phase: &str, scaffold: Option<&str>, run: impl FnOnce() -> T) -> T {
+    let started = Instant::now();
+    let result = run();
+    let timing = Timing {
+        phase: phase.to_string(),
+        scaffold: scaffold.map(str::to_string),
+        seconds: started.elapsed().as_secs_f64(),
+    };
+    TIMINGS.with(|timings| timings.borrow_mut().push(timing));
+    result
+}
+
+/// Return the recorded timings and start over.
+pub(crate) fn take_timings() -> Vec<Timing> {
+    TIMINGS.with(|timings| timings.take())
+}
+
+/// The number of files per write status and of hooks run for a scaffold.
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub(crate) struct ScaffoldSummary {
+    pub(crate) name: String,
+    pub(crate) created: usize,
+    pub(crate) overwritten: usize,
+    pub(crate) unchanged: usize,
+    pub(crate) skipped: usize,
+    pub(crate) staged: usize,
+    pub(crate) hooks_run: usize,
+}
+
+impl RunSummary {
+    pub(crate) fn new(project_name: &str, output_base: &Path, scaffolds: &[ScaffoldRun], errors: usize, elapsed: Duration) -> Self {
+        let scaffolds = scaffolds
+            .iter()
+            .map(|run| {
+                let count = |status: WriteStatus| run.written.iter().filter(|(_, written)| *written == status).count();
+                ScaffoldSummary {
+                    name: run.name.clone(),
+                    created: count(WriteStatus::Created),
+                    overwritten: count(WriteStatus::Overwritten),
+                    unchanged: count(WriteStatus::Unchanged),
+                    skipped: count(WriteStatus::Skipped),
+                    staged: count(WriteStatus::Staged),
+                    hooks_run: run.hooks_run,
+                }
+            })
+            .collect();
+        RunSummary {
+            project: project_name.to_string(),
+            output: output_base.to_string_lossy().into_owned(),
+            elapsed_seconds: elapsed.as_secs_f64(),
+            errors,
+            scaffolds,
+            timings: Vec::new(),
+        }
+    }
+
+    /// A table with a row per timed phase, in the order they ran.
+    pub(crate) fn timings_table(&self) -> String {
+        let width = self
+            .timings
+            .iter()
+            .map(|timing| timing.scaffold.as_deref().unwrap_or_default().len())
+            .chain(["Scaffold".len()])
+            .max()
+            .unwrap_or(0);
+        let mut table = format!("Timings:\n  {:<20}  {:<width$}  {:>9}\n", "Phase", "Scaffold", "Seconds");
+        for timing in &self.timings {
+            table.push_str(&format!(
+                "  {:<20}  {:<width$}  {:>9.3}\n",
+                timing.phase,
+                timing.scaffold.as_deref().unwrap_or_default(),
+                timing.seconds
+            ));
+        }
+        table
+    }
+
+    /// A table with a row per scaffold, e.g. for the end of the output.
+    pub(crate) fn table(&self) -> String {
+        let width = self
+            .scaffolds
+            .iter()
+            .map(|scaffold| scaffold.name.len())
+            .chain(["Scaffold".len()])
+            .max()
+            .unwrap_or(0);
+        let mut table = format!(
+            "Summary of project '{}' ({:.2}s, {} error(s)):\n  {:<width$}  Created  Overwritten  Unchanged  Skipped  Hooks\n",
+            self.project, self.elapsed_seconds, self.errors, "Scaffold"
+        );
+        for scaffold in &self.scaffolds {
+            table.push_str(&format!(
+                "  {:<width$}  {:>7}  {:>11}  {:>9}  {:>7}  {:>5}\n",
+                scaffold.name, scaffold.created, scaffold.overwritten, scaffold.unchanged, scaffold.skipped, scaffold.hooks_run
+            ));
+        }
+        table
+    }
+
+    /// Write the summary as JSON, e.g. as a CI artifact.
+    pub(crate) fn write_json(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        say!("Wrote run report to: {:?}", path);
+        Ok(())
+    }
+}
+
+/// The stage of a run an error happened in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ErrorStage {
+    Validation,
+    Clone,
+    Render,
+    Hook,
+}
+
+impl fmt::Display for ErrorStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let stage = match self {
+            ErrorStage::Validation => "validation",
+            ErrorStage::Clone => "clone",
+            ErrorStage::Render => "render",
+            ErrorStage::Hook => "hook",
+        };
+        f.write_str(stage)
+    }
+}
+
+/// An error of a run, attributed to a scaffold and, where known, a file.
+#[derive(Debug, Serialize)]
+pub(crate) struct RunError {
+    pub(crate) scaffold: String,
+    pub(crate) stage: ErrorStage,
+    pub(crate) file: Option<String>,
+    pub(crate) message: String,
+}
+
+impl RunError {
+    pub(crate) fn new(scaffold: &str, stage: ErrorStage, file: Option<&str>, error: &dyn Error) -> Self {
+        RunError {
+            scaffold: scaffold.to_string(),
+            stage,
+            file: file.map(str::to_string),
+            message: error_chain(error),
+        }
+    }
+
+    pub(crate) fn from_file_error(scaffold: &str, file_error: &FileError) -> Self {
+        RunError {
+            scaffold: scaffold.to_string(),
+            stage: ErrorStage::Render,
+            file: Some(file_error.file.clone()),
+            message: file_error.message.clone(),
+        }
+    }
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "scaffold '{}': {} error", self.scaffold, self.stage)?;
+        if let Some(file) = &self.file {
+            write!(f, " in {}", file)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl Error for RunError {}
+
+/// An error loading the configuration, including included files and the user configuration.
+#[derive(Debug)]
+pub(crate) struct ConfigError(pub(crate) Box<dyn Error>);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Error for ConfigError {}
+
+/// The error of a --keep-going run that reported errors for some of its files or scaffolds.
+#[derive(Debug)]
+pub(crate) struct PartialFailure {
+    pub(crate) errors: usize,
+    pub(crate) project_name: String,
+}
+
+impl fmt::Display for PartialFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} error(s) occurred while scaffolding project '{}'",
+            self.errors, self.project_name
+        )
+    }
+}
+
+impl Error for PartialFailure {}
+
+/// The process exit code for an error returned by [`run`], so that wrappers can tell failures apart:
+/// 2 for configuration errors, 3 for clone failures, 4 for render failures, 5 for hook failures,
+/// 6 for a --keep-going run with errors, and 1 for anything else.
+pub fn exit_code(error: &(dyn Error + 'static)) -> i32 {
+    if let Some(error) = error.downcast_ref::<RunError>() {
+        return match error.stage {
+            ErrorStage::Validation => 2,
+            ErrorStage::Clone => 3,
+            ErrorStage::Render => 4,
+            ErrorStage::Hook => 5,
+        };
+    }
+    if error.is::<ConfigError>() {
+        2
+    } else if error.is::<PartialFailure>() {
+        6
+    } else {
+        1
+    }
+}
+
+/// Every error of a run, printed at the end and optionally written as JSON with --error-report.
+#[derive(Debug, Default, Serialize)]
+pub(crate) struct ErrorReport {
+    pub(crate) errors: Vec<RunError>,
+}
+
+impl ErrorReport {
+    /// Print the errors grouped by scaffold, in the order the scaffolds were processed.
+    pub(crate) fn print(&self) {
+        let header = format!("{} error(s) occurred:", self.errors.len());
+        eprintln!("\n{}", Style::Error.paint(&header, &io::stderr()));
+        let mut scaffolds: Vec<&str> = Vec::new();
+        for error in &self.errors {
+            if !scaffolds.contains(&error.scaffold.as_str()) {
+                scaffolds.push(&error.scaffold);
+            }
+        }
+        for scaffold in scaffolds {
+            eprintln!("  Scaffold '{}':", scaffold);
+            for error in self.errors.iter().filter(|error| error.scaffold == scaffold) {
+                match &error.file {
+                    Some(file) => eprintln!("    [{}] {}: {}", error.stage, file, error.message),
+                    None => eprintln!("    [{}] {}", error.stage, error.message),
+                }
+            }
+        }
+    }
+
+    /// Write the report as JSON for CI annotation tools.
+    pub(crate) fn write_json(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        say!("Wrote error report to: {:?}", path);
+        Ok(())
+    }
+}
+
+/// Whether to report to GitHub Actions: with --gha, or when running in a workflow (GITHUB_ACTIONS=true).
+pub(crate) fn github_actions_enabled(args: &Args) -> bool {
+    args.gha || std::env::var("GITHUB_ACTIONS").is_ok_and(|value| value == "true")
+}
+
+/// Escape the message of a workflow command.
+fn escape_workflow_data(value: &str) -> String {
+    value.replace('%', "%25").replace('\r', "%0D").replace('\n', "%0A")
+}
+
+/// Escape a property (e.g. `file=`) of a workflow command.
+fn escape_workflow_property(value: &str) -> String {
+    escape_workflow_data(value).replace(':', "%3A").replace(',', "%2C")
+}
+
+/// The workflow commands of a run: an `::error` per error, attributed to its file where known, and a closing `::notice`.
+fn workflow_commands(project_name: &str, scaffolds: &[ScaffoldRun], report: &ErrorReport) -> Vec<String> {
+    let mut commands = Vec::new();
+    for error in &report.errors {
+        let mut properties = Vec::new();
+        if let Some(file) = &error.file {
+            properties.push(format!("file={}", escape_workflow_property(file)));
+        }
+        let title = format!("scaficionado: {} ({})", error.scaffold, error.stage);
+        properties.push(format!("title={}", escape_workflow_property(&title)));
+        commands.push(format!(
+            "::error {}::{}",
+            properties.join(","),
+            escape_workflow_data(&error.message)
+        ));
+    }
+    let files = scaffolds.iter().flat_map(|scaffold| &scaffold.written);
+    let created = files.clone().filter(|(_, status)| *status == WriteStatus::Created).count();
+    let overwritten = files.filter(|(_, status)| *status == WriteStatus::Overwritten).count();
+    let summary = format!(
+        "Project '{}': {} file(s) created, {} changed, {} error(s)",
+        project_name,
+        created,
+        overwritten,
+        report.errors.len()
+    );
+    commands.push(format!("::notice title=scaficionado::{}", escape_workflow_data(&summary)));
+    commands
+}
+
+/// The job summary of a run: a Markdown table of the files it created or changed.
+fn job_summary(project_name: &str, scaffolds: &[ScaffoldRun], report: &ErrorReport) -> String {
+    let mut summary = format!("### Scaffolded project `{}`\n\n", project_name);
+    let changes: Vec<_> = scaffolds
+        .iter()
+        .flat_map(|scaffold| scaffold.written.iter().map(move |(path, status)| (&scaffold.name, path, status)))
+        .filter(|(_, _, status)| status.is_change())
+        .collect();
+    if changes.is_empty() {
+        summary.push_str("No files were created or changed.\n");
+    } else {
+        summary.push_str("| Scaffold | File | Status |\n| --- | --- | --- |\n");
+        for (scaffold, path, status) in changes {
+            let status = match status {
+                WriteStatus::Created => "created",
+                WriteStatus::Staged => "staged",
+                _ => "changed",
+            };
+            summary.push_str(&format!("| {} | `{}` | {} |\n", scaffold, path.display(), status));
+        }
+    }
+    if !report.errors.is_empty() {
+        summary.push_str(&format!(
+            "\n{} error(s) occurred, see the annotations of this run.\n",
+            report.errors.len()
+        ));
+    }
+    summary
+}
+
+/// Print the workflow commands of a run and append its job summary to $GITHUB_STEP_SUMMARY, when set.
+pub(crate) fn report_to_github_actions(project_name: &str, scaffolds: &[ScaffoldRun], report: &ErrorReport) -> Result<(), Box<dyn Error>> {
+    for command in workflow_commands(project_name, scaffolds, report) {
+        println!("{}", command);
+    }
+    if let Some(path) = std::env::var_os("GITHUB_STEP_SUMMARY") {
+        let mut file = fs::OpenOptions::new().create(true).append(true).open(&path)?;
+        writeln!(file, "{}", job_summary(project_name, scaffolds, report))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    // Test the GitHub Actions workflow commands and job summary of a run.
+    #[test]
+    fn test_github_actions_report() -> Result<(), Box<dyn std::error::Error>> {
+        let written = vec![ScaffoldRun {
+            name: "api".to_string(),
+            written: vec![
+                (PathBuf::from("out/README.md"), WriteStatus::Created),
+                (PathBuf::from("out/main.rs"), WriteStatus::Overwritten),
+                (PathBuf::from("out/lib.rs"), WriteStatus::Unchanged),
+            ],
+            hooks_run: 0,
+        }];
+        let report = ErrorReport {
+            errors: vec![RunError::new(
+                "api",
+                ErrorStage::Render,
+                Some("src/a,b.rs"),
+                &*Box::<dyn std::error::Error>::from("line one\nline two: 100%"),
+            )],
+        };
+        let commands = workflow_commands("demo", &written, &report);
+        assert_eq!(
+            commands,
+            vec![
+                "::error file=src/a%2Cb.rs,title=scaficionado%3A api (render)::line one%0Aline two: 100%25".to_string(),
+                "::notice title=scaficionado::Project 'demo': 1 file(s) created, 1 changed, 1 error(s)".to_string(),
+            ]
+        );
+
+        let summary = job_summary("demo", &written, &report);
+        assert!(summary.contains("| api | `out/README.md` | created |"), "{}", summary);
+        assert!(summary.contains("| api | `out/main.rs` | changed |"), "{}", summary);
+        assert!(!summary.contains("lib.rs"), "{}", summary);
+        assert!(summary.contains("1 error(s) occurred"), "{}", summary);
+        assert!(job_summary("demo", &[], &ErrorReport::default()).contains("No files were created or changed."));
+        Ok(())
+    }
+
+    // Test that each failure class has its own exit code.
+    #[test]
+    fn test_exit_codes() -> Result<(), Box<dyn std::error::Error>> {
+        let config_dir = TempDir::new()?;
+        let missing = config_dir.path().join("missing.toml").to_string_lossy().into_owned();
+        let args = Args::try_parse_from(["scaficionado", "-c", &missing])?;
+        let error = load_config_with_args(&args).err().ok_or("a missing configuration should fail")?;
+        assert_eq!(exit_code(error.as_ref()), 2);
+
+        let cause: Box<dyn std::error::Error> = "failed".into();
+        let stages = [
+            (ErrorStage::Validation, 2),
+            (ErrorStage::Clone, 3),
+            (ErrorStage::Render, 4),
+            (ErrorStage::Hook, 5),
+        ];
+        for (stage, code) in stages {
+            let error: Box<dyn std::error::Error> = Box::new(RunError::new("Api", stage, None, cause.as_ref()));
+            assert_eq!(exit_code(error.as_ref()), code);
+        }
+        let partial: Box<dyn std::error::Error> = Box::new(PartialFailure {
+            errors: 2,
+            project_name: "Demo".to_string(),
+        });
+        assert_eq!(exit_code(partial.as_ref()), 6);
+        assert_eq!(partial.to_string(), "2 error(s) occurred while scaffolding project 'Demo'");
+        assert_eq!(exit_code(cause.as_ref()), 1);
+        Ok(())
+    }
+
+    // Test the end-of-run summary table and its JSON report.
+    #[test]
+    fn test_run_summary() -> Result<(), Box<dyn std::error::Error>> {
+        let scaffolds = vec![
+            ScaffoldRun {
+                name: "Api".to_string(),
+                written: vec![
+                    (PathBuf::from("a"), WriteStatus::Created),
+                    (PathBuf::from("b"), WriteStatus::Created),
+                    (PathBuf::from("c"), WriteStatus::Skipped),
+                ],
+                hooks_run: 2,
+            },
+            ScaffoldRun {
+                name: "Kubernetes Service".to_string(),
+                written: vec![
+                    (PathBuf::from("d"), WriteStatus::Overwritten),
+                    (PathBuf::from("e"), WriteStatus::Unchanged),
+                ],
+                hooks_run: 0,
+            },
+        ];
+        let summary = RunSummary::new("Demo", Path::new("out"), &scaffolds, 1, Duration::from_millis(1500));
+        assert_eq!(
+            summary.table(),
+            "Summary of project 'Demo' (1.50s, 1 error(s)):\n\
+             \x20 Scaffold            Created  Overwritten  Unchanged  Skipped  Hooks\n\
+             \x20 Api                       2            0          0        1      2\n\
+             \x20 Kubernetes Service        0            1          1        0      0\n"
+        );
+
+        let report_dir = TempDir::new()?;
+        let report_path = report_dir.path().join("report.json");
+        summary.write_json(&report_path)?;
+        let report: serde_json::Value = serde_json::from_str(&fs::read_to_string(&report_path)?)?;
+        assert_eq!(report["elapsed_seconds"], 1.5);
+        assert_eq!(report["scaffolds"][0]["created"], 2);
+        assert_eq!(report["scaffolds"][0]["hooks_run"], 2);
+        assert_eq!(report["scaffolds"][1]["name"], "Kubernetes Service");
+        Ok(())
+    }
+
+    // Test that --timings records each phase of a run and adds it to the report.
+    #[test]
+    fn test_timings() -> Result<(), Box<dyn std::error::Error>> {
+        let repo_dir = TempDir::new()?;
+        let templates = repo_dir.path().join("templates");
+        fs::create_dir_all(&templates)?;
+        fs::write(templates.join("README.md.tera"), "# {{ project_name }}")?;
+        let output_dir = TempDir::new()?;
+        let report_path = output_dir.path().join("report.json");
+
+        let config = from_toml::<Config>(&format!(
+            r#"
+[[scaffolds]]
+name = "Docs"
+repo = "{}"
+template.files = [{{ src = "README.md.tera", dest = "README.md" }}]
+"#,
+            repo_dir.path().to_string_lossy().replace('\\', "/")
+        ))?;
+        let output = output_dir.path().join("out").to_string_lossy().into_owned();
+        let report = report_path.to_string_lossy().into_owned();
+        let args = Args::try_parse_from(["scaficionado", "-o", &output, "--timings", "--report", &report])?;
+        take_timings();
+        generate_config(&args, config)?;
+
+        let report: serde_json::Value = serde_json::from_str(&fs::read_to_string(&report_path)?)?;
+        let phases: Vec<(&str, &str)> = report["timings"]
+            .as_array()
+            .ok_or("the report should list the timings")?
+            .iter()
+            .map(|timing| {
+                (
+                    timing["phase"].as_str().unwrap_or_default(),
+                    timing["scaffold"].as_str().unwrap_or_default(),
+                )
+            })
+            .collect();
+        assert_eq!(phases, [("clone", "Docs"), ("render", "Docs")]);
+        assert!(report["timings"][0]["seconds"].as_f64().is_some());
+
+        let summary = RunSummary {
+            timings: vec![Timing {
+                phase: "load configuration".to_string(),
+                scaffold: None,
+                seconds: 0.25,
+            }],
+            ..RunSummary::new("Demo", Path::new("out"), &[], 0, Duration::ZERO)
+        };
+        assert_eq!(
+            summary.timings_table(),
+            "Timings:\n  Phase                 Scaffold    Seconds\n  load configuration                  0.250\n"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,646 @@
+//! The `serve` subcommand: an HTTP API and form over the configured scaffolds.
+
+use crate::render::error_chain;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::io::{self, BufRead, Read, Write};
+use std::path::{Component, Path};
+use std::time::Duration;
+use tempfile::TempDir;
+use tera::escape_html;
+
+use super::*;
+
+/// Largest request line and headers, and request body, `serve` reads, and how long it waits for a request.
+const MAX_REQUEST_HEAD: u64 = 64 * 1024;
+const MAX_REQUEST_BODY: usize = 1_000_000;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Connections `serve` answers at the same time; further connections wait to be accepted.
+pub(crate) const SERVE_THREADS: usize = 8;
+
+/// Answer the requests of every connection to `listener` on SERVE_THREADS threads, so that a slow client or a long
+/// generation doesn't hold up the others.  Connections are accepted as threads become free.  The threads run with
+/// the settings of the run serving them, e.g. --offline.
+pub(crate) fn serve_requests(args: &Args, listener: &std::net::TcpListener) {
+    let (sender, receiver) = std::sync::mpsc::sync_channel::<std::net::TcpStream>(0);
+    let receiver = std::sync::Mutex::new(receiver);
+    let context = run_context();
+    std::thread::scope(|scope| {
+        for _ in 0..SERVE_THREADS {
+            scope.spawn(|| {
+                let _run = RunScope::enter(context);
+                loop {
+                    let stream = receiver.lock().unwrap_or_else(|e| e.into_inner()).recv();
+                    match stream {
+                        Ok(stream) => serve_connection(args, stream),
+                        Err(_) => return,
+                    }
+                }
+            });
+        }
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    if sender.send(stream).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => say!("Failed to accept a connection: {}", e),
+            }
+        }
+        drop(sender);
+    });
+}
+
+/// Answer the request of a connection to `serve`.
+fn serve_connection(args: &Args, mut stream: std::net::TcpStream) {
+    let response = match read_http_request(&mut stream) {
+        Ok(request) => {
+            say!("{} {}", request.method, request.path);
+            serve_request(args, &request)
+        }
+        Err(e) => HttpResponse::error(400, &e.to_string()),
+    };
+    if let Err(e) = response.write_to(&mut stream) {
+        say!("Failed to send a response: {}", e);
+    }
+}
+
+/// A request to `serve`.
+struct HttpRequest {
+    method: String,
+    path: String,
+    body: Vec<u8>,
+}
+
+/// A response of `serve`.
+struct HttpResponse {
+    status: u16,
+    content_type: &'static str,
+    /// Extra headers, e.g. Content-Disposition.
+    headers: Vec<(&'static str, String)>,
+    body: Vec<u8>,
+}
+
+impl HttpResponse {
+    fn json(status: u16, value: &serde_json::Value) -> Self {
+        HttpResponse {
+            status,
+            content_type: "application/json",
+            headers: Vec::new(),
+            body: value.to_string().into_bytes(),
+        }
+    }
+
+    fn html(page: String) -> Self {
+        HttpResponse {
+            status: 200,
+            content_type: "text/html; charset=utf-8",
+            headers: Vec::new(),
+            body: page.into_bytes(),
+        }
+    }
+
+    /// An error response, with the message in the `errors` list of a JSON body.
+    fn error(status: u16, message: &str) -> Self {
+        HttpResponse::json(status, &serde_json::json!({ "errors": [message] }))
+    }
+
+    fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        let reason = match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            422 => "Unprocessable Entity",
+            _ => "Internal Server Error",
+        };
+        let mut head = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+            self.status,
+            reason,
+            self.content_type,
+            self.body.len()
+        );
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str("\r\n");
+        out.write_all(head.as_bytes())?;
+        out.write_all(&self.body)?;
+        out.flush()
+    }
+}
+
+/// Read an HTTP/1.1 request: its request line and headers, up to MAX_REQUEST_HEAD bytes, and a body of
+/// Content-Length bytes.
+fn read_http_request(stream: &mut std::net::TcpStream) -> Result<HttpRequest, Box<dyn Error>> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut reader = io::BufReader::new(io::Read::take(&*stream, MAX_REQUEST_HEAD));
+    let mut line = String::new();
+    let mut read_line = |line: &mut String| -> Result<usize, Box<dyn Error>> {
+        let read = reader.read_line(line)?;
+        if !line.ends_with('\n') && reader.get_ref().limit() == 0 {
+            return Err(format!("The request headers are larger than {} bytes", MAX_REQUEST_HEAD).into());
+        }
+        Ok(read)
+    };
+    read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Err(format!("Invalid request line {:?}", line.trim_end()).into());
+    };
+    let (method, path) = (method.to_string(), path.to_string());
+    let mut length = 0;
+    loop {
+        line.clear();
+        if read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                length = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("Invalid Content-Length {:?}", value.trim()))?;
+            }
+        }
+    }
+    if length > MAX_REQUEST_BODY {
+        return Err(format!("The request body is larger than {} bytes", MAX_REQUEST_BODY).into());
+    }
+    // What's left of the body after the part read along with the headers.
+    let buffered = reader.buffer().len().min(length);
+    reader.get_mut().set_limit((length - buffered) as u64);
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok(HttpRequest { method, path, body })
+}
+
+/// The body of `POST /validate` and `POST /generate`: a project as in a `batch` row.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ServedProject {
+    project_name: String,
+    #[serde(default)]
+    variables: BTreeMap<String, toml::Value>,
+}
+
+/// Answer a request to `serve`.
+fn serve_request(args: &Args, request: &HttpRequest) -> HttpResponse {
+    let path = request.path.split('?').next().unwrap_or_default();
+    let project = || -> Result<ServedProject, String> {
+        let project: ServedProject = serde_json::from_slice(&request.body).map_err(|e| format!("Invalid request body: {}", e))?;
+        // The project is generated into a directory named after it, which must stay in the temporary directory.
+        let mut components = Path::new(&project.project_name).components();
+        if !matches!((components.next(), components.next()), (Some(Component::Normal(_)), None)) {
+            return Err(format!("Invalid project_name {:?}", project.project_name));
+        }
+        Ok(project)
+    };
+    match (request.method.as_str(), path) {
+        ("GET", "/") => match load_config_with_args(args) {
+            Ok(config) => HttpResponse::html(scaffold_form(&config)),
+            Err(e) => HttpResponse::error(500, &error_chain(e.as_ref())),
+        },
+        ("GET", "/scaffolds") => match load_config_with_args(args) {
+            Ok(config) => HttpResponse::json(200, &scaffold_listing(&config)),
+            Err(e) => HttpResponse::error(500, &error_chain(e.as_ref())),
+        },
+        ("POST", "/validate" | "/generate") => {
+            let project = match project() {
+                Ok(project) => project,
+                Err(e) => return HttpResponse::error(400, &e),
+            };
+            let dir = match TempDir::new() {
+                Ok(dir) => dir,
+                Err(e) => return HttpResponse::error(500, &e.to_string()),
+            };
+            if path == "/validate" {
+                begin_preview();
+                let result = generate_served_project(args, &project, dir.path());
+                finish_preview();
+                return match result {
+                    Ok(()) => HttpResponse::json(200, &serde_json::json!({ "valid": true })),
+                    Err(e) => HttpResponse::error(422, &error_chain(e.as_ref())),
+                };
+            }
+            let archive = generate_served_project(args, &project, dir.path()).and_then(|()| pack_archive(dir.path()));
+            match archive {
+                Ok(archive) => HttpResponse {
+                    status: 200,
+                    content_type: "application/gzip",
+                    headers: vec![(
+                        "Content-Disposition",
+                        format!("attachment; filename=\"{}.tar.gz\"", project.project_name),
+                    )],
+                    body: archive,
+                },
+                Err(e) => HttpResponse::error(422, &error_chain(e.as_ref())),
+            }
+        }
+        (_, "/" | "/scaffolds" | "/validate" | "/generate") => HttpResponse::error(405, "Method not allowed"),
+        _ => HttpResponse::error(404, "Not found"),
+    }
+}
+
+/// Generate a served project into `dir`, in a directory named after it, like a `batch` row.
+fn generate_served_project(args: &Args, project: &ServedProject, dir: &Path) -> Result<(), Box<dyn Error>> {
+    let row = BatchRow {
+        project_name: project.project_name.clone(),
+        output: Some(dir.join(&project.project_name).to_string_lossy().into_owned()),
+        variables: project.variables.clone(),
+    };
+    let project_args = Args {
+        project_name: row.project_name.clone(),
+        defaults: true,
+        open: false,
+        answers: None,
+        report: None,
+        error_report: None,
+        ..args.clone()
+    };
+    let config = apply_batch_row(&project_args, load_config_with_args(&project_args)?, &row);
+    generate_config(&project_args, config)
+}
+
+/// The configured scaffolds and their prompts, for `GET /scaffolds`.  Defaults of secret prompts are left out.
+pub(crate) fn scaffold_listing(config: &Config) -> serde_json::Value {
+    let scaffolds: Vec<serde_json::Value> = config
+        .scaffolds
+        .iter()
+        .map(|scaffold| {
+            let prompts: Vec<serde_json::Value> = scaffold
+                .prompts
+                .iter()
+                .flatten()
+                .map(|prompt| {
+                    serde_json::json!({
+                        "name": prompt.name,
+                        "message": prompt.message.as_deref().unwrap_or(&prompt.name),
+                        "description": scaffold.descriptions.get(&prompt.name),
+                        "type": prompt.kind.map(VariableType::name),
+                        "default": prompt.default.as_ref().filter(|_| !prompt.secret),
+                        "choices": prompt.choices,
+                        "group": prompt.group,
+                        "secret": prompt.secret,
+                    })
+                })
+                .collect();
+            serde_json::json!({
+                "name": scaffold_name(scaffold),
+                "repo": scaffold.repo.urls(),
+                "prompts": prompts,
+            })
+        })
+        .collect();
+    serde_json::json!({ "scaffolds": scaffolds })
+}
+
+/// The page `serve` answers `GET /` with: a form with a field per prompt of the configured scaffolds, grouped by
+/// scaffold and `group`, whose input depends on the prompt's type and choices.  Its script sends the filled-in
+/// variables to `/validate` or `/generate` and downloads the archive.
+fn scaffold_form(config: &Config) -> String {
+    let text = |value: &toml::Value| match value {
+        toml::Value::String(text) => text.clone(),
+        toml::Value::Array(items) => items.iter().map(|item| item.to_string()).collect::<Vec<_>>().join(", "),
+        toml::Value::Table(_) => serde_json::to_string(value).unwrap_or_default(),
+        _ => value.to_string(),
+    };
+    let mut fields = String::new();
+    let mut seen = HashSet::new();
+    for scaffold in &config.scaffolds {
+        let types = declared_types(scaffold);
+        let prompts: Vec<&Prompt> = scaffold
+            .prompts
+            .iter()
+            .flatten()
+            .filter(|prompt| seen.insert(&prompt.name))
+            .collect();
+        if prompts.is_empty() {
+            continue;
+        }
+        fields.push_str(&format!("<fieldset><legend>{}</legend>\n", escape_html(scaffold_name(scaffold))));
+        let mut group = None;
+        for prompt in prompts {
+            if prompt.group.is_some() && prompt.group != group {
+                group = prompt.group.clone();
+                fields.push_str(&format!("<h3>{}</h3>\n", escape_html(group.as_deref().unwrap_or_default())));
+            }
+            let kind = types
+                .get(prompt.name.as_str())
+                .copied()
+                .or_else(|| prompt.default.as_ref().and_then(VariableType::of))
+                .unwrap_or(VariableType::String);
+            let name = escape_html(&prompt.name);
+            let default = prompt.default.as_ref().filter(|_| !prompt.secret);
+            let value = escape_html(&default.map(text).unwrap_or_default());
+            // Prompts without a default must be answered; the others take it when left empty.
+            let required = if prompt.default.is_none() { " required" } else { "" };
+            let input = match (&prompt.choices, kind) {
+                (Some(choices), _) => {
+                    let options: String = choices
+                        .iter()
+                        .map(|choice| {
+                            let selected = if Some(choice) == default { " selected" } else { "" };
+                            format!("<option{}>{}</option>", selected, escape_html(&text(choice)))
+                        })
+                        .collect();
+                    format!("<select name=\"{}\">{}</select>", name, options)
+                }
+                (None, VariableType::Bool) => {
+                    let checked = if default == Some(&toml::Value::Boolean(true)) {
+                        " checked"
+                    } else {
+                        ""
+                    };
+                    format!("<input type=\"checkbox\" name=\"{}\"{}>", name, checked)
+                }
+                (None, VariableType::Integer | VariableType::Float) => {
+                    let step = if kind == VariableType::Integer { "1" } else { "any" };
+                    format!(
+                        "<input type=\"number\" step=\"{}\" name=\"{}\" value=\"{}\"{}>",
+                        step, name, value, required
+                    )
+                }
+                (None, VariableType::Table) => {
+                    format!(
+                        "<textarea name=\"{}\" placeholder=\"JSON object\"{}>{}</textarea>",
+                        name, required, value
+                    )
+                }
+                (None, VariableType::String | VariableType::List) => {
+                    let input_type = if prompt.secret { "password" } else { "text" };
+                    let placeholder = if kind == VariableType::List {
+                        " placeholder=\"comma-separated\""
+                    } else {
+                        ""
+                    };
+                    format!(
+                        "<input type=\"{}\" name=\"{}\" value=\"{}\"{}{}>",
+                        input_type, name, value, placeholder, required
+                    )
+                }
+            };
+            let description = scaffold
+                .descriptions
+                .get(&prompt.name)
+                .map(|description| format!("<small>{}</small>", escape_html(description)))
+                .unwrap_or_default();
+            fields.push_str(&format!(
+                "<label data-type=\"{}\">{}\n{}{}</label>\n",
+                kind.name(),
+                escape_html(prompt.message.as_deref().unwrap_or(&prompt.name)),
+                input,
+                description
+            ));
+        }
+        fields.push_str("</fieldset>\n");
+    }
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>scaficionado</title>
+<style>
+body {{ font-family: sans-serif; max-width: 40em; margin: 2em auto; }}
+label {{ display: block; margin: 0.8em 0; }}
+input[type=text], input[type=password], input[type=number], select, textarea {{ display: block; width: 100%; }}
+small {{ display: block; color: #666; }}
+#errors {{ color: #b00; white-space: pre-wrap; }}
+</style>
+</head>
+<body>
+<h1>Generate a project</h1>
+<form id="project">
+<label data-type="string">Project name
+<input type="text" name="project_name" value="{}" required></label>
+{}<button type="submit" name="action" value="validate">Validate</button>
+<button type="submit" name="action" value="generate">Generate</button>
+</form>
+<p id="status"></p>
+<pre id="errors"></pre>
+<script>
+const form = document.getElementById("project");
+form.addEventListener("submit", async (event) => {{
+  event.preventDefault();
+  const action = event.submitter.value;
+  const status = document.getElementById("status");
+  const errors = document.getElementById("errors");
+  errors.textContent = "";
+  const body = {{ project_name: form.elements.project_name.value, variables: {{}} }};
+  try {{
+    for (const label of form.querySelectorAll("fieldset label")) {{
+      const field = label.querySelector("[name]");
+      const type = label.dataset.type;
+      let value = field.type === "checkbox" ? field.checked : field.value.trim();
+      if (value === "") continue;
+      if (type === "integer" || type === "float") value = Number(value);
+      if (type === "list" && field.tagName !== "SELECT") value = value.split(",").map((item) => item.trim()).filter((item) => item);
+      if (type === "table") value = JSON.parse(value);
+      body.variables[field.name] = value;
+    }}
+  }} catch (e) {{
+    errors.textContent = e.message;
+    return;
+  }}
+  status.textContent = action === "generate" ? "Generating..." : "Validating...";
+  const response = await fetch(action, {{ method: "POST", body: JSON.stringify(body) }});
+  if (!response.ok) {{
+    status.textContent = "";
+    errors.textContent = (await response.json()).errors.join("\n");
+  }} else if (action === "validate") {{
+    status.textContent = "The project is valid.";
+  }} else {{
+    const link = document.createElement("a");
+    link.href = URL.createObjectURL(await response.blob());
+    link.download = body.project_name + ".tar.gz";
+    link.click();
+    status.textContent = "Generated " + link.download + ".";
+  }}
+}});
+</script>
+</body>
+</html>
+"#,
+        escape_html(
+            config
+                .project
+                .as_ref()
+                .and_then(|project| project.name.as_deref())
+                .unwrap_or_default()
+        ),
+        fields
+    )
+}
+
+/// Print a Markdown description of the scaffold `name`, including what the manifest of its repository adds.
+/// Messages go to stderr.
+pub(crate) fn describe_scaffold(args: &Args, name: &str) -> Result<(), Box<dyn Error>> {
+    send_messages_to_stderr();
+    let config = load_config_with_args(args)?;
+    let scaffold = config
+        .scaffolds
+        .iter()
+        .find(|scaffold| scaffold_name(scaffold) == name)
+        .ok_or_else(|| format!("No scaffold named '{}' in {}", name, args.config))?;
+    let (scaffold, repo_base) = load_scaffold(scaffold, &get_git_options(args, &config))?;
+    if is_temporary_clone(&repo_base) {
+        clean_up_persistent_dirs(vec![repo_base])?;
+    }
+    print!("{}", scaffold_description(&scaffold, config.variables.as_ref()));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::{self, Write};
+    use std::time::Instant;
+    use tempfile::TempDir;
+
+    // Test the endpoints of `serve`: listing the scaffolds, validating a project and generating it as an archive.
+    #[test]
+    fn test_serve() -> Result<(), Box<dyn std::error::Error>> {
+        let repo_dir = TempDir::new()?;
+        fs::create_dir_all(repo_dir.path().join("templates"))?;
+        fs::write(
+            repo_dir.path().join("templates/readme.md.tera"),
+            "{{ project_name }} by {{ owner }}",
+        )?;
+        let work_dir = TempDir::new()?;
+        let config_path = work_dir.path().join("scaffolding.toml");
+        fs::write(
+            &config_path,
+            format!(
+                r#"
+[[scaffolds]]
+name = "Service"
+repo = "{}"
+template.files = [{{ src = "readme.md.tera", dest = "README.md" }}]
+prompts = [{{ name = "owner", message = "Who owns it?" }}, {{ name = "token", default = "hunter2", secret = true }}]
+"#,
+                repo_dir.path().to_string_lossy().replace('\\', "/")
+            ),
+        )?;
+        let args = Args {
+            config: config_path.to_string_lossy().into_owned(),
+            ..Args::default()
+        };
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let address = listener.local_addr()?;
+        let url = format!("http://{}", address);
+        std::thread::spawn(move || serve_requests(&args, &listener));
+
+        // A client that doesn't send its request doesn't hold up the others.
+        let _idle = std::net::TcpStream::connect(address)?;
+        let started = Instant::now();
+        let agent = http_agent(false)?;
+        let mut response = agent.get(&url).call()?;
+        assert_eq!(response.headers()["content-type"], "text/html; charset=utf-8");
+        assert!(response.body_mut().read_to_string()?.contains("Who owns it?"));
+        assert!(started.elapsed() < REQUEST_TIMEOUT);
+        let json = |response: &mut ureq::http::Response<ureq::Body>| -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+            Ok(serde_json::from_str(&response.body_mut().read_to_string()?)?)
+        };
+        let mut response = agent.get(format!("{}/scaffolds", url)).call()?;
+        let listing = json(&mut response)?;
+        assert_eq!(listing["scaffolds"][0]["name"], "Service");
+        assert_eq!(listing["scaffolds"][0]["prompts"][0]["message"], "Who owns it?");
+        assert_eq!(listing["scaffolds"][0]["prompts"][1]["default"], serde_json::Value::Null);
+
+        let post = |path: &str, body: &str| agent.post(format!("{}{}", url, path)).send(body);
+        let mut response = post("/validate", r#"{"project_name": "api", "variables": {"owner": "alice"}}"#)?;
+        assert_eq!(response.status(), 200);
+        assert_eq!(json(&mut response)?["valid"], true);
+        // Nothing is written while validating.
+        assert!(!config_path.with_file_name("api").exists());
+        let mut response = post(
+            "/validate",
+            r#"{"project_name": "api", "variables": {"owner": ["not", "a", "string"]"#,
+        )?;
+        assert_eq!(response.status(), 400);
+        assert!(json(&mut response)?["errors"][0]
+            .as_str()
+            .unwrap_or_default()
+            .starts_with("Invalid request body"));
+        let response = post("/generate", r#"{"project_name": "../escape"}"#)?;
+        assert_eq!(response.status(), 400);
+
+        let mut response = post("/generate", r#"{"project_name": "api", "variables": {"owner": "alice"}}"#)?;
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()["content-type"], "application/gzip");
+        let archive = response.body_mut().read_to_vec()?;
+        let unpacked = TempDir::new()?;
+        unpack_archive(archive.as_slice(), unpacked.path())?;
+        assert_eq!(fs::read_to_string(unpacked.path().join("api/README.md"))?, "api by alice");
+        assert!(unpacked.path().join("api").join(ANSWERS_FILE).exists());
+        assert_eq!(fs::read_dir(unpacked.path().join("api/.scaficionado"))?.count(), 1);
+
+        assert_eq!(agent.get(format!("{}/generate", url)).call()?.status(), 405);
+        assert_eq!(agent.get(format!("{}/nothing", url)).call()?.status(), 404);
+
+        // Headers are only read up to a limit.
+        let mut stream = std::net::TcpStream::connect(address)?;
+        let mut request = b"GET / HTTP/1.1\r\nX-Padding: ".to_vec();
+        request.resize(MAX_REQUEST_HEAD as usize, b'a');
+        stream.write_all(&request)?;
+        let mut response = String::new();
+        io::Read::read_to_string(&mut stream, &mut response)?;
+        assert!(response.starts_with("HTTP/1.1 400"));
+        assert!(response.contains("The request headers are larger than 65536 bytes"));
+        Ok(())
+    }
+
+    // Test that the form of `serve` has an input per prompt matching its type, choices and default.
+    #[test]
+    fn test_scaffold_form() -> Result<(), Box<dyn std::error::Error>> {
+        let config: Config = from_toml(
+            r#"
+[project]
+name = "billing"
+
+[[scaffolds]]
+name = "Service"
+repo = "."
+descriptions = { team = "The team on call for <the service>" }
+types = { replicas = "integer" }
+prompts = [
+  { name = "team", message = "Owning team" },
+  { name = "replicas", default = "2", group = "Deployment" },
+  { name = "language", choices = ["rust", "go"], default = "go", group = "Deployment" },
+  { name = "metrics", default = true },
+  { name = "token", default = "hunter2", secret = true },
+]
+
+[[scaffolds]]
+name = "Docs"
+repo = "."
+prompts = [{ name = "team" }]
+"#,
+        )?;
+        let form = scaffold_form(&config);
+        assert!(form.contains(r#"name="project_name" value="billing" required"#));
+        assert!(form.contains("<legend>Service</legend>"));
+        // A variable asked by several scaffolds gets a single field.
+        assert!(!form.contains("<legend>Docs</legend>"));
+        assert!(form.contains(
+            "<label data-type=\"string\">Owning team\n<input type=\"text\" name=\"team\" value=\"\" required><small>The team on call for &lt;the service&gt;</small></label>"
+        ));
+        assert_eq!(form.matches("<h3>Deployment</h3>").count(), 1);
+        assert!(form.contains(r#"<input type="number" step="1" name="replicas" value="2">"#));
+        assert!(form.contains(r#"<select name="language"><option>rust</option><option selected>go</option></select>"#));
+        assert!(form.contains(r#"<label data-type="bool">metrics"#));
+        assert!(form.contains(r#"<input type="checkbox" name="metrics" checked>"#));
+        assert!(form.contains(r#"<input type="password" name="token" value="">"#));
+        assert!(!form.contains("hunter2"));
+        // The script submitting the form is plain JavaScript.
+        assert!(form.contains("const form = document.getElementById(\"project\");"));
+        assert!(!form.contains("pub(crate)"));
+        Ok(())
+    }
+}
@@ -0,0 +1,576 @@
+//! Where the templates of scaffolds come from: git repositories, local directories, archives, OCI artifacts and
+//! registered sources.
+
+use regex::Regex;
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::OnceLock;
+
+use super::*;
+
+mod git;
+mod oci;
+
+pub(crate) use git::*;
+pub(crate) use oci::*;
+
+/// Check if the given repository URL is local.
+/// We assume it is local unless it has a scheme, such as "https://" or "git://", or is an scp-like SSH URL.
+pub(crate) fn is_local_repo(repo_url: &str) -> bool {
+    is_windows_path(repo_url) || (!repo_url.contains("://") && !is_scp_like_url(repo_url))
+}
+
+/// Check for the scp-like syntax of SSH URLs, e.g. "git@github.com:org/templates.git".
+fn is_scp_like_url(repo_url: &str) -> bool {
+    static SCP_LIKE: OnceLock<Regex> = OnceLock::new();
+    SCP_LIKE
+        .get_or_init(|| Regex::new(r"^[\w.-]+@[\w.-]+:").unwrap())
+        .is_match(repo_url)
+}
+
+/// The host of a remote repository URL, e.g. "github.com" for "https://user@github.com/org/repo.git".
+pub(crate) fn repo_host(repo_url: &str) -> Option<&str> {
+    let authority = match repo_url.split_once("://") {
+        Some((_, rest)) => rest.split('/').next()?,
+        None => repo_url.split(':').next()?,
+    };
+    let host = authority.rsplit('@').next()?;
+    Some(host.split(':').next().unwrap_or(host)).filter(|host| !host.is_empty())
+}
+
+/// Where a scaffold's templates come from.  The built-in sources are git repositories, local directories, tar
+/// archives and OCI artifacts; applications embedding scaficionado add others, e.g. S3 buckets or OCI registries, with
+/// [`register_source`].
+pub trait ScaffoldSource {
+    /// Obtain the templates into `dest`, a path in a temporary directory that is cleaned up after the run, and return
+    /// their base path.  Sources used in place return their own path instead.
+    fn fetch(&self, dest: &Path) -> Result<PathBuf, Box<dyn Error>>;
+
+    /// Identifies the source in messages, e.g. its URL.
+    fn identity(&self) -> String;
+
+    /// Whether the templates are used where they are, so that `fetch` needs no temporary directory.
+    fn in_place(&self) -> bool {
+        false
+    }
+}
+
+/// Creates the source for a scaffold's `repo` URL, or returns None for URLs it doesn't handle.
+pub type SourceFactory = fn(&str) -> Option<Box<dyn ScaffoldSource>>;
+
+/// Sources added with [`register_source`], asked in order before the built-in ones.
+static SOURCES: std::sync::RwLock<Vec<SourceFactory>> = std::sync::RwLock::new(Vec::new());
+
+/// Add a source of templates, e.g. for `s3://` URLs.  Registered sources take precedence over the built-in ones,
+/// the earliest registered first.
+pub fn register_source(factory: SourceFactory) {
+    SOURCES.write().unwrap_or_else(|e| e.into_inner()).push(factory);
+}
+
+/// The source of the templates at `url`: a registered source that handles it, or an OCI artifact, a tar archive, a
+/// local directory or a git repository.
+pub(crate) fn scaffold_source(url: &str, git: &GitOptions) -> Box<dyn ScaffoldSource> {
+    let registered = SOURCES
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .find_map(|factory| factory(url));
+    if let Some(source) = registered {
+        return source;
+    }
+    let location = url.to_string();
+    if url.starts_with(BUNDLE_SCHEME) {
+        Box::new(BundleSource { location })
+    } else if url.starts_with("oci://") {
+        Box::new(OciSource {
+            url: location,
+            offline: git.offline,
+            max_size: git.max_download,
+        })
+    } else if is_archive(url) {
+        Box::new(ArchiveSource {
+            location,
+            offline: git.offline,
+            sha256: None,
+            max_size: git.max_download,
+        })
+    } else if is_local_repo(url) {
+        Box::new(LocalSource { path: location })
+    } else {
+        Box::new(GitSource { url: location, git: *git })
+    }
+}
+
+/// A git repository, cloned with the clone options.
+struct GitSource {
+    url: String,
+    git: GitOptions,
+}
+
+impl ScaffoldSource for GitSource {
+    fn fetch(&self, dest: &Path) -> Result<PathBuf, Box<dyn Error>> {
+        say!("Cloning repo {:?}", self.url);
+        clone_repo(&self.url, dest, &self.git)?;
+        Ok(dest.to_path_buf())
+    }
+
+    fn identity(&self) -> String {
+        self.url.clone()
+    }
+}
+
+/// A local directory, used in place.
+struct LocalSource {
+    path: String,
+}
+
+impl ScaffoldSource for LocalSource {
+    fn fetch(&self, _dest: &Path) -> Result<PathBuf, Box<dyn Error>> {
+        let path = canonical_path(&self.path).map_err(|e| format!("Failed to open {}: {}", self.path, e))?;
+        say!("Using local scaffold repository at: {:?}", path);
+        Ok(path)
+    }
+
+    fn identity(&self) -> String {
+        self.path.clone()
+    }
+
+    fn in_place(&self) -> bool {
+        true
+    }
+}
+
+/// A `.tar`, `.tar.gz` or `.tgz` archive, local or downloaded over HTTP(S).
+struct ArchiveSource {
+    location: String,
+    offline: bool,
+    /// Expected SHA-256 checksum (hex) of the archive.
+    sha256: Option<String>,
+    /// Bytes of the largest archive downloaded, if limited.
+    max_size: Option<u64>,
+}
+
+impl ScaffoldSource for ArchiveSource {
+    fn fetch(&self, dest: &Path) -> Result<PathBuf, Box<dyn Error>> {
+        let (archive, sha256) = match is_url(&self.location) {
+            true if self.offline => return Err(format!("Cannot download {} with --offline", self.location).into()),
+            true => {
+                say!("Downloading archive {}", self.location);
+                let response = http_agent(true)?.get(&self.location).call()?;
+                spool(&self.location, response.into_body().into_reader(), self.max_size)?
+            }
+            false => {
+                let file = fs::File::open(&self.location).map_err(|e| format!("Failed to read {}: {}", self.location, e))?;
+                spool(&self.location, file, None)?
+            }
+        };
+        if let Some(expected) = &self.sha256 {
+            check_sha256(&self.location, &sha256, expected)?;
+        }
+        say!("Unpacking archive {}", self.location);
+        unpack_archive(archive, dest).map_err(|e| format!("Failed to unpack {}: {}", self.location, e))?;
+        // Archives of a repository usually hold a single top-level directory, e.g. "templates-main/".
+        let entries: Vec<PathBuf> = fs::read_dir(dest)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<_, _>>()?;
+        match entries.as_slice() {
+            [single] if single.is_dir() => Ok(single.clone()),
+            _ => Ok(dest.to_path_buf()),
+        }
+    }
+
+    fn identity(&self) -> String {
+        self.location.clone()
+    }
+}
+
+/// Copy `content` from `location` into a temporary file, a chunk at a time, failing once it's larger than
+/// `max_size`.  Returns the file, ready to be read from the start, and the SHA-256 checksum (hex) of the content.
+pub(crate) fn spool(location: &str, mut content: impl io::Read, max_size: Option<u64>) -> Result<(fs::File, String), Box<dyn Error>> {
+    use sha2::Digest;
+    let mut file = tempfile::tempfile()?;
+    let mut sha256 = sha2::Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    let mut size = 0;
+    loop {
+        let read = content.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        size += read as u64;
+        if let Some(max_size) = max_size.filter(|max_size| size > *max_size) {
+            return Err(LimitExceeded(format!("{} is larger than {} bytes (max_total_bytes)", location, max_size)).into());
+        }
+        sha256.update(&buffer[..read]);
+        file.write_all(&buffer[..read])?;
+    }
+    io::Seek::rewind(&mut file)?;
+    Ok((file, format!("{:x}", sha256.finalize())))
+}
+
+/// Unpack a tar archive, gzip-compressed or not, into `dest`.  Regular files (with their permissions) and directories
+/// are unpacked; links and other special entries are skipped, and entries that would end up outside `dest` are
+/// rejected.
+pub(crate) fn unpack_archive(data: impl io::Read, dest: &Path) -> Result<(), Box<dyn Error>> {
+    let mut data = io::BufReader::new(data);
+    let gzip = io::BufRead::fill_buf(&mut data)?.starts_with(&[0x1f, 0x8b]);
+    let data: Box<dyn io::Read> = match gzip {
+        true => Box::new(flate2::read::GzDecoder::new(data)),
+        false => Box::new(data),
+    };
+    fs::create_dir_all(dest)?;
+    let mut archive = tar::Archive::new(data);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let kind = entry.header().entry_type();
+        if !kind.is_file() && !kind.is_dir() {
+            continue;
+        }
+        let relative = entry.path()?.into_owned();
+        if relative
+            .components()
+            .any(|component| !matches!(component, Component::Normal(_) | Component::CurDir))
+        {
+            return Err(format!("entry {:?} is outside the archive", relative.to_string_lossy()).into());
+        }
+        let path = dest.join(&relative);
+        if kind.is_dir() {
+            fs::create_dir_all(&path)?;
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        io::copy(&mut entry, &mut fs::File::create(&path)?)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = entry.header().mode().unwrap_or(0o644);
+            fs::set_permissions(&path, fs::Permissions::from_mode(mode & 0o777))?;
+        }
+    }
+    Ok(())
+}
+
+/// Pack the files below `dir` into a gzip-compressed tar archive, skipping `.git`.  Entries are sorted and have no
+/// timestamps, so the same files always give the same archive.
+pub(crate) fn pack_archive(dir: &Path) -> Result<Vec<u8>, Box<dyn Error>> {
+    fn collect(dir: &Path, root: &Path, archive: &mut tar::Builder<impl Write>) -> Result<(), Box<dyn Error>> {
+        let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<_, _>>()?;
+        paths.sort();
+        for path in paths {
+            if path.file_name().is_some_and(|name| name == ".git") {
+                continue;
+            }
+            let name = path.strip_prefix(root)?.to_string_lossy().replace('\\', "/");
+            let mut header = tar::Header::new_gnu();
+            if path.is_dir() {
+                header.set_entry_type(tar::EntryType::Directory);
+                header.set_mode(0o755);
+                header.set_size(0);
+                archive.append_data(&mut header, format!("{}/", name), io::empty())?;
+                collect(&path, root, archive)?;
+            } else {
+                let content = fs::read(&path)?;
+                header.set_entry_type(tar::EntryType::Regular);
+                header.set_mode(file_mode(&path));
+                header.set_size(content.len() as u64);
+                archive.append_data(&mut header, name, content.as_slice())?;
+            }
+        }
+        Ok(())
+    }
+    let encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    let mut archive = tar::Builder::new(encoder);
+    collect(dir, dir, &mut archive)?;
+    Ok(archive.into_inner()?.finish()?)
+}
+
+/// The permissions of a file for an archive: executable or not, as other platforms don't have modes.
+fn file_mode(path: &Path) -> u32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if fs::metadata(path).is_ok_and(|metadata| metadata.permissions().mode() & 0o111 != 0) {
+            return 0o755;
+        }
+    }
+    let _ = path;
+    0o644
+}
+
+/// Whether a scaffold's `repo` is a tar archive, judging by its extension.
+pub(crate) fn is_archive(url: &str) -> bool {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    [".tar", ".tar.gz", ".tgz"].iter().any(|extension| path.ends_with(extension))
+}
+
+/// Obtain the scaffold's repository from the first of its URLs that works, and return its base path and that URL.
+/// Local repositories are used in place; remote repositories are cloned into a temporary directory that the caller
+/// must clean up.
+pub(crate) fn acquire_scaffold_repo(scaffold: &Scaffold, git: &GitOptions) -> Result<(PathBuf, String), Box<dyn Error>> {
+    let git = GitOptions {
+        retry: scaffold.retry.unwrap_or_default().or(git.retry),
+        // With a version, the submodules are checked out at the commits recorded by the resolved tag instead.
+        submodules: scaffold.submodules && scaffold.version.is_none(),
+        ..*git
+    };
+    let urls = scaffold.repo.urls();
+    let mut failures = Vec::new();
+    for (i, url) in urls.iter().enumerate() {
+        let source: Box<dyn ScaffoldSource> = match &scaffold.sha256 {
+            Some(sha256) => Box::new(ArchiveSource {
+                location: url.clone(),
+                offline: git.offline,
+                sha256: Some(sha256.clone()),
+                max_size: git.max_download,
+            }),
+            None => scaffold_source(url, &git),
+        };
+        let acquired = acquire_repo(source.as_ref(), scaffold_name(scaffold)).and_then(|path| match pin_repo(&path, scaffold, &git) {
+            Ok(()) => Ok(path),
+            Err(e) => {
+                if let Some(clone_dir) = clone_dir_of(&path) {
+                    fs::remove_dir_all(clone_dir).ok();
+                }
+                Err(e)
+            }
+        });
+        match acquired {
+            Ok(path) => return Ok((path, url.clone())),
+            Err(e) if i + 1 < urls.len() => {
+                say!("{}\nTrying the next mirror of the repository", e);
+                failures.push(e.to_string());
+            }
+            Err(e) if failures.is_empty() => return Err(e),
+            Err(e) => failures.push(e.to_string()),
+        }
+    }
+    Err(format!(
+        "None of the {} mirrors of the repository could be obtained:\n{}",
+        urls.len(),
+        failures.join("\n")
+    )
+    .into())
+}
+
+/// Check out the scaffold's `version` and verify the `signature` of the checked-out commit, if configured.
+fn pin_repo(repo_base: &Path, scaffold: &Scaffold, git: &GitOptions) -> Result<(), Box<dyn Error>> {
+    if let Some(version) = &scaffold.version {
+        let git = GitOptions {
+            submodules: scaffold.submodules,
+            ..*git
+        };
+        checkout_version(repo_base, version, &git)?;
+    }
+    if let Some(signature) = &scaffold.signature {
+        let verified = verify_signature(repo_base, signature)?;
+        say!("Verified the signature of {}", verified);
+    }
+    Ok(())
+}
+
+/// Obtain the templates from `source`: sources used in place return their own path, the others fetch into a
+/// temporary clone directory.
+pub(crate) fn acquire_repo(source: &dyn ScaffoldSource, name: &str) -> Result<PathBuf, Box<dyn Error>> {
+    if source.in_place() {
+        return source.fetch(Path::new(""));
+    }
+    let clone_dir = new_clone_dir()?;
+    match source.fetch(&clone_dir.join(name)) {
+        Ok(path) => Ok(path),
+        Err(e) => {
+            fs::remove_dir_all(&clone_dir).ok();
+            Err(e)
+        }
+    }
+}
+
+/// Whether `repo_base` was fetched into a temporary clone directory, which the run must clean up, rather than used
+/// in place.
+pub(crate) fn is_temporary_clone(repo_base: &Path) -> bool {
+    clone_dir_of(repo_base).is_some()
+}
+
+/// The temporary clone directory holding `dir`: the closest ancestor with a clone lock file.
+pub(crate) fn clone_dir_of(dir: &Path) -> Option<&Path> {
+    dir.ancestors().find(|ancestor| ancestor.join(CLONE_LOCK_FILE).is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Write;
+    use std::path::{Path, PathBuf};
+    use tempfile::TempDir;
+
+    // Test the is_local_repo helper function.
+    #[test]
+    fn test_is_local_repo() {
+        // Local path should return true.
+        assert!(is_local_repo("some/local/path"));
+        // URLs should return false.
+        assert!(!is_local_repo("https://github.com/example/repo.git"));
+        assert!(!is_local_repo("http://example.com/repo"));
+        assert!(!is_local_repo("git://example.com/repo"));
+        assert!(!is_local_repo("ssh://git@example.com/repo.git"));
+        assert!(!is_local_repo("git@github.com:org/templates.git"));
+        // Windows drive-letter and UNC paths are local.
+        assert!(is_local_repo(r"C:\Users\me@corp\templates"));
+        assert!(is_local_repo("D:/templates"));
+        assert!(is_local_repo(r"\\server\share\templates"));
+    }
+
+    // Test that a scaffold falls back to the next mirror of its repository when one can't be obtained.
+    #[test]
+    fn test_repo_mirrors() -> Result<(), Box<dyn std::error::Error>> {
+        let repo_dir = TempDir::new()?;
+        let missing = repo_dir.path().join("missing").to_string_lossy().into_owned();
+        let mirror = repo_dir.path().to_string_lossy().into_owned();
+        let config: Config = from_toml(&format!(
+            r#"
+[[scaffolds]]
+name = "Api"
+repo = ["{}", "{}"]
+"#,
+            missing, mirror
+        ))?;
+        let (loaded, repo_base) = load_scaffold(&config.scaffolds[0], &GitOptions::default())?;
+        assert_eq!(repo_base, fs::canonicalize(repo_dir.path())?);
+        assert_eq!(loaded.repo, RepoUrls::One(mirror.clone()));
+
+        // When no mirror works, every failure is reported.
+        let scaffold = Scaffold {
+            repo: RepoUrls::Mirrors(vec![missing.clone(), format!("{}-too", missing)]),
+            ..Default::default()
+        };
+        let Err(error) = load_scaffold(&scaffold, &GitOptions::default()) else {
+            return Err("no mirror exists".into());
+        };
+        let message = error.to_string();
+        assert!(
+            message.contains("None of the 2 mirrors of the repository could be obtained"),
+            "{}",
+            message
+        );
+        assert!(message.contains(&format!("Failed to open {}-too", missing)), "{}", message);
+
+        let empty = Scaffold {
+            repo: RepoUrls::Mirrors(vec![mirror, String::new()]),
+            ..Default::default()
+        };
+        assert!(validate_scaffold(&empty).is_err());
+        Ok(())
+    }
+
+    // Test the built-in archive source and a registered source.
+    #[test]
+    fn test_scaffold_sources() -> Result<(), Box<dyn std::error::Error>> {
+        fn tar_entry(archive: &mut tar::Builder<impl Write>, name: &str, mode: u32, content: &[u8]) -> io::Result<()> {
+            let mut header = tar::Header::new_gnu();
+            header.set_mode(mode);
+            header.set_size(content.len() as u64);
+            archive.append_data(&mut header, name, content)
+        }
+        let dir = TempDir::new()?;
+        let path = dir.path().join("templates.tar.gz");
+        let encoder = flate2::write::GzEncoder::new(fs::File::create(&path)?, flate2::Compression::default());
+        let mut archive = tar::Builder::new(encoder);
+        tar_entry(
+            &mut archive,
+            "templates-main/templates/README.md.tera",
+            0o644,
+            b"# {{ project_name }}",
+        )?;
+        tar_entry(&mut archive, "templates-main/hooks/post.sh", 0o755, b"#!/bin/sh\n")?;
+        archive.into_inner()?.finish()?;
+
+        // The single top-level directory of the archive is the base.
+        let base = acquire_repo(scaffold_source(&path.to_string_lossy(), &GitOptions::default()).as_ref(), "Archive")?;
+        assert!(base.ends_with("templates-main"));
+        assert_eq!(fs::read_to_string(base.join("templates/README.md.tera"))?, "# {{ project_name }}");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(base.join("hooks/post.sh"))?.permissions().mode() & 0o777, 0o755);
+        }
+        assert!(is_temporary_clone(&base));
+        let clone_dir = clone_dir_of(&base)
+            .ok_or("the archive is unpacked into a clone directory")?
+            .to_path_buf();
+        clean_up_persistent_dirs(vec![base])?;
+        assert!(!clone_dir.exists());
+
+        // Downloads stop at the limit of the run, before anything is unpacked.
+        let base_url = serve_http(vec![("/large.tar", "x".repeat(100))])?;
+        let limited = GitOptions {
+            max_download: Some(10),
+            ..GitOptions::default()
+        };
+        let source = scaffold_source(&format!("{}/large.tar", base_url), &limited);
+        let error = acquire_repo(source.as_ref(), "Large")
+            .err()
+            .ok_or("the archive is larger than the limit")?;
+        assert!(error.to_string().contains("is larger than 10 bytes (max_total_bytes)"), "{}", error);
+        let (mut spooled, sha256) = spool("content", "x".repeat(100).as_bytes(), Some(100))?;
+        assert_eq!(sha256, sha256_hex("x".repeat(100).as_bytes()));
+        assert_eq!(io::Read::read_to_end(&mut spooled, &mut Vec::new())?, 100);
+
+        // The tar crate refuses to write such paths, so the name goes into the header as it is.
+        let mut escaping = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.as_old_mut().name[..14].copy_from_slice(b"../escaped.txt");
+        header.set_size(0);
+        header.set_cksum();
+        escaping.append(&header, io::empty())?;
+        let error = unpack_archive(escaping.into_inner()?.as_slice(), dir.path())
+            .err()
+            .ok_or("the entry is outside the archive")?;
+        assert_eq!(error.to_string(), "entry \"../escaped.txt\" is outside the archive");
+
+        // Names longer than a tar header holds are kept.
+        let long = dir.path().join("long");
+        let nested = long.join("d".repeat(60)).join("e".repeat(60));
+        fs::create_dir_all(&nested)?;
+        fs::write(nested.join("f".repeat(60)), "long")?;
+        let unpacked = TempDir::new()?;
+        unpack_archive(pack_archive(&long)?.as_slice(), unpacked.path())?;
+        let name = Path::new(&"d".repeat(60)).join("e".repeat(60)).join("f".repeat(60));
+        assert_eq!(fs::read_to_string(unpacked.path().join(name))?, "long");
+        assert_eq!(pack_archive(&long)?, pack_archive(&long)?);
+
+        // Registered sources take precedence for the URLs they handle.
+        struct FixedSource(String);
+        impl ScaffoldSource for FixedSource {
+            fn fetch(&self, _dest: &Path) -> Result<PathBuf, Box<dyn std::error::Error>> {
+                Ok(PathBuf::from(&self.0))
+            }
+            fn identity(&self) -> String {
+                self.0.clone()
+            }
+            fn in_place(&self) -> bool {
+                true
+            }
+        }
+        register_source(|url| {
+            let path = url.strip_prefix("fixed-test://")?;
+            Some(Box::new(FixedSource(path.to_string())))
+        });
+        let url = format!("fixed-test://{}", dir.path().display());
+        assert_eq!(
+            scaffold_source(&url, &GitOptions::default()).identity(),
+            dir.path().display().to_string()
+        );
+        assert_eq!(
+            acquire_repo(scaffold_source(&url, &GitOptions::default()).as_ref(), "Fixed")?,
+            dir.path()
+        );
+        assert!(!is_temporary_clone(dir.path()));
+        Ok(())
+    }
+}
@@ -0,0 +1,1129 @@
+//! Template repositories in git: cloning with retries and timeouts, pinning versions, verifying signatures and
+//! reading the repository metadata that templates use.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::*;
+
+#[cfg(feature = "gix")]
+mod gitoxide;
+#[cfg(feature = "git2")]
+mod libgit2;
+
+/// Attempts and seconds before the first retry of a failed clone, unless configured with `[project.retry]`.
+const DEFAULT_CLONE_ATTEMPTS: u32 = 3;
+const DEFAULT_CLONE_BACKOFF: f64 = 1.0;
+
+/// Seconds an attempt to clone may take, and may go without receiving anything, unless configured.
+const DEFAULT_CLONE_TIMEOUT: f64 = 600.0;
+const DEFAULT_CLONE_STALL_TIMEOUT: f64 = 60.0;
+
+/// Longest wait between two attempts to clone.
+const MAX_CLONE_BACKOFF: Duration = Duration::from_secs(60);
+
+/// How often the progress of a clone is checked against its timeouts.
+#[cfg_attr(not(any(feature = "git2", feature = "gix")), allow(dead_code))]
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Who commits the generated project when git has no user.name and user.email configured.
+#[cfg_attr(not(any(feature = "git2", feature = "gix")), allow(dead_code))]
+const FALLBACK_COMMITTER: (&str, &str) = ("scaficionado", "scaficionado@localhost");
+
+/// How remote template repositories are cloned.
+#[cfg_attr(not(any(feature = "git2", feature = "gix")), allow(dead_code))]
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct GitOptions {
+    /// Print the remote's progress messages and transfer statistics.
+    pub(crate) verbose: bool,
+    /// Trust SSH host keys that aren't in known_hosts.
+    pub(crate) accept_hostkey: bool,
+    /// How often failed clones are retried.
+    pub(crate) retry: RetryConfig,
+    /// Take repositories from the clone cache instead of the network.
+    pub(crate) offline: bool,
+    /// Check out the submodules of cloned repositories.
+    pub(crate) submodules: bool,
+    /// Bytes of the largest archive or OCI layer downloaded: the max_total_bytes of the run, unbounded with
+    /// --no-limits.
+    pub(crate) max_download: Option<u64>,
+}
+
+/// Clone `repo_url` into `dest`, retrying transient failures such as timeouts, dropped connections and rate limits
+/// with exponential backoff.  The clone is kept in the clone cache for --offline; offline, the cached clone is used.
+pub(crate) fn clone_repo(repo_url: &str, dest: &Path, git: &GitOptions) -> Result<(), Box<dyn Error>> {
+    let cached = cached_repo_path(repo_url);
+    if git.offline {
+        if !cached.is_dir() {
+            return Err(format!("{} isn't in the clone cache; run once without --offline to cache it", repo_url).into());
+        }
+        say!("Using the cached clone of {}", repo_url);
+        copy_dir(&cached, dest)?;
+        mark_cache_used(&cached);
+    } else {
+        clone_repo_with_retries(repo_url, dest, git)?;
+        // A clone that can't be cached only matters offline, where the error then says what's missing.
+        if let Err(e) = cache_clone(dest, &cached) {
+            say!("Warning: could not cache the clone of {}: {}", repo_url, e);
+        }
+    }
+    if git.submodules {
+        update_submodules(dest, git)?;
+    }
+    Ok(())
+}
+
+/// Check out the submodules of a cloned repository at their recorded commits, recursively.  Each is cloned like a
+/// template repository, so retries, proxies and the clone cache apply to them too.
+fn update_submodules(repo: &Path, git: &GitOptions) -> Result<(), Box<dyn Error>> {
+    let library = git_library()?;
+    for submodule in library.submodules(repo)? {
+        // Relative URLs, e.g. "../partials.git", are relative to the repository's own.
+        let url = match library.remotes(repo).into_iter().find(|(name, _)| name == "origin") {
+            Some((_, origin)) => resolve_submodule_url(&origin, &submodule.url),
+            None => submodule.url.clone(),
+        };
+        say!("Checking out submodule {} from {}", submodule.name, url);
+        let path = repo.join(&submodule.path);
+        clone_repo(&url, &path, git).map_err(|e| format!("Failed to check out submodule {}: {}", submodule.name, e))?;
+        library.checkout(&path, &submodule.commit)?;
+    }
+    Ok(())
+}
+
+/// A submodule of a repository, as recorded in its `.gitmodules` and the commit checked out.
+pub(crate) struct Submodule {
+    pub(crate) name: String,
+    /// The URL of `.gitmodules`, which may be relative to the repository's.
+    pub(crate) url: String,
+    /// Where the submodule is checked out, relative to the repository.
+    pub(crate) path: PathBuf,
+    /// The commit of the submodule that the checked out commit of the repository records.
+    pub(crate) commit: String,
+}
+
+/// Resolve the `url` of a submodule against the `origin` URL of its repository, as git does: a URL starting with
+/// "./" or "../" is relative to the repository, and others are used as they are.
+fn resolve_submodule_url(origin: &str, url: &str) -> String {
+    if !url.starts_with("./") && !url.starts_with("../") {
+        return url.to_string();
+    }
+    let mut base = origin.trim_end_matches('/').to_string();
+    let mut relative = url;
+    loop {
+        if let Some(rest) = relative.strip_prefix("./") {
+            relative = rest;
+        } else if let Some(rest) = relative.strip_prefix("../") {
+            relative = rest;
+            // The last component goes, also after the colon of an scp-like URL such as "git@host:org/repo.git".
+            match base.rfind(['/', ':']) {
+                Some(separator) if base[separator..].starts_with(':') => base.truncate(separator + 1),
+                Some(separator) if !base[..separator].ends_with('/') => base.truncate(separator),
+                _ => {}
+            }
+        } else {
+            break;
+        }
+    }
+    let separator = if base.ends_with(':') { "" } else { "/" };
+    format!("{}{}{}", base, separator, relative)
+}
+
+/// Replace the cached clone at `cached` with a copy of the fresh clone at `source`.
+fn cache_clone(source: &Path, cached: &Path) -> Result<(), Box<dyn Error>> {
+    let parent = cached.parent().ok_or("the clone cache has no parent directory")?;
+    fs::create_dir_all(parent)?;
+    // Copied next to the cache entry and renamed, so that an interrupted run doesn't leave a broken entry behind.
+    let staging = tempfile::Builder::new().prefix(".staging-").tempdir_in(parent)?;
+    let staged = staging.path().join("repo");
+    copy_dir(source, &staged)?;
+    if cached.exists() {
+        fs::remove_dir_all(cached)?;
+    }
+    fs::rename(&staged, cached)?;
+    mark_cache_used(cached);
+    Ok(())
+}
+
+/// Copy the directory `source` to `dest` with everything in it, including the repository in `.git`.  Symbolic links
+/// are copied as links on Unix.
+fn copy_dir(source: &Path, dest: &Path) -> io::Result<()> {
+    fs::create_dir_all(dest)?;
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        let target = dest.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else if file_type.is_symlink() && cfg!(unix) {
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(fs::read_link(entry.path())?, &target)?;
+        } else {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Clone `repo_url` into `dest`, trying again after transient failures.
+pub(crate) fn clone_repo_with_retries(repo_url: &str, dest: &Path, git: &GitOptions) -> Result<(), Box<dyn Error>> {
+    let attempts = git.retry.attempts.unwrap_or(DEFAULT_CLONE_ATTEMPTS).max(1);
+    let mut delay = Duration::from_secs_f64(git.retry.backoff.unwrap_or(DEFAULT_CLONE_BACKOFF).max(0.0));
+    let library = git_library().map_err(|e| format!("Failed to clone {}: {}", repo_url, e))?;
+    let mut attempt = 1;
+    loop {
+        match library.clone(repo_url, dest, git, &CloneProgress::new(&git.retry)) {
+            Ok(()) => return Ok(()),
+            Err(_) if cancelled() => return Err(Box::new(Cancelled)),
+            Err(error) if attempt < attempts && error.is_transient() => {
+                say!(
+                    "Cloning {} failed ({}); retrying in {:.1}s (attempt {} of {})",
+                    repo_url,
+                    error.message,
+                    delay.as_secs_f64(),
+                    attempt + 1,
+                    attempts
+                );
+                // Backends refuse to clone into a non-empty directory, so start over from nothing.
+                if dest.exists() {
+                    fs::remove_dir_all(dest)?;
+                }
+                std::thread::sleep(delay);
+                delay = (delay * 2).min(MAX_CLONE_BACKOFF);
+                attempt += 1;
+            }
+            Err(error) => return Err(error.describe(repo_url).into()),
+        }
+    }
+}
+
+/// A failed attempt to clone, as the backends report it.
+#[derive(Debug)]
+pub(crate) struct CloneError {
+    pub(crate) kind: CloneErrorKind,
+    /// The library's message, e.g. with libgit2's error class and code.
+    pub(crate) message: String,
+    /// Messages the remote sent, which often hold the actual reason (e.g. "Repository not found").
+    pub(crate) remote_messages: String,
+    /// The host and fingerprint of the SSH host key the server presented, if it was checked.
+    pub(crate) host_key: Option<(String, String)>,
+}
+
+/// What made a clone fail, to decide whether to retry it and what to suggest.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(not(feature = "git2"), allow(dead_code))]
+pub(crate) enum CloneErrorKind {
+    /// The credentials were rejected.
+    Auth,
+    /// The server's certificate or SSH host key was rejected.
+    Certificate,
+    /// The repository doesn't exist.
+    NotFound,
+    /// The server answered with an HTTP error, with its status code if known.
+    Http(Option<u16>),
+    /// The connection failed or was dropped.
+    Network,
+    /// The clone took longer than `timeout`, or received nothing for `stall_timeout`.
+    TimedOut,
+    Other,
+}
+
+#[cfg_attr(not(any(feature = "git2", feature = "gix")), allow(dead_code))]
+impl CloneError {
+    pub(crate) fn new(kind: CloneErrorKind, message: impl Into<String>) -> Self {
+        CloneError {
+            kind,
+            message: message.into(),
+            remote_messages: String::new(),
+            host_key: None,
+        }
+    }
+
+    /// Whether the clone may succeed when tried again: network and HTTP errors other than client errors, but not
+    /// rejected credentials or host keys, or a missing repository.
+    pub(crate) fn is_transient(&self) -> bool {
+        match self.kind {
+            // Statuses such as 404 won't change, but 408 and 429 (rate limits) and server errors may.
+            CloneErrorKind::Http(status) => status.is_none_or(|status| !(400..500).contains(&status) || status == 408 || status == 429),
+            CloneErrorKind::Network | CloneErrorKind::TimedOut => true,
+            CloneErrorKind::Auth | CloneErrorKind::Certificate | CloneErrorKind::NotFound | CloneErrorKind::Other => false,
+        }
+    }
+
+    /// Explain the failed clone of `repo_url`: the library's message, what the remote said, and a hint for common
+    /// causes such as an untrusted SSH host key.
+    pub(crate) fn describe(&self, repo_url: &str) -> String {
+        let mut description = format!("Failed to clone {}: {}", repo_url, self.message);
+        for line in self.remote_messages.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            description.push_str(&format!("\n  remote: {}", line));
+        }
+        let hint = match (self.kind, &self.host_key) {
+            (CloneErrorKind::Certificate, Some((host, fingerprint))) => format!(
+                "SSH host key verification failed for {} ({}).  If the fingerprint is correct, add the key with \
+                 `ssh-keyscan {} >> ~/.ssh/known_hosts`, or pass --accept-hostkey to trust it",
+                host, fingerprint, host
+            ),
+            (CloneErrorKind::Certificate, None) => "the server's certificate was rejected".to_string(),
+            (CloneErrorKind::Auth, _) => {
+                "authentication failed: check your credentials or SSH agent and that you can access the repository".to_string()
+            }
+            (CloneErrorKind::TimedOut, _) => {
+                "the server stopped responding; for slow servers, raise `timeout` or `stall_timeout` in [project.retry]".to_string()
+            }
+            (CloneErrorKind::Network | CloneErrorKind::Http(_), _) => "check the repository URL and your network connection".to_string(),
+            _ => return description,
+        };
+        description.push_str(&format!("\n  hint: {}", hint));
+        description
+    }
+}
+
+/// The progress of one attempt to clone, as its backend reports it: a clone that takes longer than `timeout`, or
+/// receives nothing for `stall_timeout`, is aborted, and so is the clone of a cancelled run.
+#[cfg_attr(not(any(feature = "git2", feature = "gix")), allow(dead_code))]
+pub(crate) struct CloneProgress {
+    started: Instant,
+    timeout: Duration,
+    stall_timeout: Duration,
+    /// How much was received and indexed so far, and when that last changed.
+    last: std::cell::Cell<(usize, Instant)>,
+}
+
+#[cfg_attr(not(any(feature = "git2", feature = "gix")), allow(dead_code))]
+impl CloneProgress {
+    pub(crate) fn new(retry: &RetryConfig) -> Self {
+        let started = Instant::now();
+        CloneProgress {
+            started,
+            timeout: Duration::from_secs_f64(retry.timeout.unwrap_or(DEFAULT_CLONE_TIMEOUT).max(0.0)),
+            stall_timeout: Duration::from_secs_f64(retry.stall_timeout.unwrap_or(DEFAULT_CLONE_STALL_TIMEOUT).max(0.0)),
+            last: std::cell::Cell::new((0, started)),
+        }
+    }
+
+    /// Note that `received` was received so far, counting the messages of the remote too, and return why the clone
+    /// should stop if it should.
+    pub(crate) fn stop_reason(&self, received: usize) -> Option<String> {
+        if cancelled() {
+            return Some("the run was cancelled".to_string());
+        }
+        let (last, at) = self.last.get();
+        let progressed = received != last;
+        if progressed {
+            self.last.set((received, Instant::now()));
+        }
+        if self.started.elapsed() > self.timeout {
+            Some(format!("the clone took longer than {}s (timeout)", self.timeout.as_secs_f64()))
+        } else if !progressed && at.elapsed() > self.stall_timeout {
+            Some(format!(
+                "nothing was received for {}s (stall_timeout)",
+                self.stall_timeout.as_secs_f64()
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// Clone into `dest` with `work` on a worker thread, as neither library can be interrupted while it waits for the
+    /// server, and abandon the clone once it should stop.  `work` clones into the path it's given, a scratch
+    /// directory next to `dest` that's moved into place when it's done, runs with the settings of the calling run and
+    /// should give up soon after the flag it's given is set.  `received` is called every PROGRESS_INTERVAL, and once
+    /// more when the clone is done, with how much the worker received so far.
+    pub(crate) fn watch(
+        &self,
+        dest: &Path,
+        work: impl FnOnce(&Path, &AtomicBool) -> Result<(), CloneError> + Send + 'static,
+        mut received: impl FnMut() -> usize,
+    ) -> Result<(), CloneError> {
+        let failed = |message: String| CloneError::new(CloneErrorKind::Other, message);
+        if cancelled() {
+            return Err(failed("the run was cancelled".into()));
+        }
+        let scratch = dest
+            .parent()
+            .ok_or_else(|| io::Error::other("the clone has no parent directory"))
+            .and_then(|parent| tempfile::Builder::new().prefix(".clone-").tempdir_in(parent))
+            .map_err(|e| failed(e.to_string()))?;
+        let interrupt = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::spawn({
+            let (path, interrupt, context) = (scratch.path().join("repo"), interrupt.clone(), run_context());
+            move || {
+                let _run = RunScope::enter(context);
+                sender.send(work(&path, &interrupt))
+            }
+        });
+        loop {
+            let finished = match receiver.recv_timeout(PROGRESS_INTERVAL) {
+                Ok(result) => Some(result),
+                Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => return Err(failed("the clone stopped".into())),
+                Err(std::sync::mpsc::RecvTimeoutError::Timeout) => None,
+            };
+            // The final progress is noted too, even when the clone finished before it was first checked.
+            let stop_reason = self.stop_reason(received());
+            match finished {
+                Some(Ok(())) => break,
+                Some(Err(error)) => return Err(error),
+                None => {}
+            }
+            if let Some(reason) = stop_reason {
+                interrupt.store(true, Ordering::Relaxed);
+                // The abandoned worker may still write to the scratch directory; it's removed with the temporary
+                // directory of the clone.
+                let _ = scratch.keep();
+                return Err(CloneError::new(CloneErrorKind::TimedOut, format!("timed out: {}", reason)));
+            }
+        }
+        fs::rename(scratch.path().join("repo"), dest).map_err(|e| failed(e.to_string()))
+    }
+}
+
+/// The library that clones and reads template repositories, chosen with `backend` in the user configuration.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum GitBackend {
+    /// libgit2.  Only available with the `git2` feature.
+    Libgit2,
+    /// gitoxide, in pure Rust with rustls for HTTPS.  Only available with the `gix` feature.
+    Gix,
+}
+
+/// The backend of the user configuration, or else gix when built with the `gix` feature and libgit2 otherwise.
+fn git_backend() -> GitBackend {
+    let default = if cfg!(feature = "gix") {
+        GitBackend::Gix
+    } else {
+        GitBackend::Libgit2
+    };
+    user_config().ok().and_then(|user| user.backend).unwrap_or(default)
+}
+
+/// Whether a path of a working directory has the `export-ignore` attribute.
+pub(crate) type ExportIgnoreCheck = Box<dyn Fn(&Path) -> bool>;
+
+/// What scaficionado does with git, implemented with libgit2 (the `git2` feature) and gitoxide (the `gix` feature).
+/// Repositories are passed as the path of their working directory, so that nothing else depends on the library.
+pub(crate) trait GitLibrary: Sync {
+    /// Clone `url` into `dest` once, stopping when `progress` says so.
+    fn clone(&self, url: &str, dest: &Path, git: &GitOptions, progress: &CloneProgress) -> Result<(), CloneError>;
+    /// The remotes of `repo` with their URLs.
+    fn remotes(&self, repo: &Path) -> Vec<(String, String)>;
+    /// The commit checked out in `repo`.
+    fn head_commit(&self, repo: &Path) -> Result<String, Box<dyn Error>>;
+    /// The tags of `repo`.
+    fn tags(&self, repo: &Path) -> Result<Vec<GitTag>, Box<dyn Error>>;
+    /// The raw content of the object `id` of `repo`, e.g. of a commit or an annotated tag.
+    fn read_object(&self, repo: &Path, id: &str) -> Result<Vec<u8>, Box<dyn Error>>;
+    /// Check out `commit` in the fresh clone `repo`, detaching HEAD.
+    fn checkout(&self, repo: &Path, commit: &str) -> Result<(), Box<dyn Error>>;
+    /// The submodules of the commit checked out in `repo`.
+    fn submodules(&self, repo: &Path) -> Result<Vec<Submodule>, Box<dyn Error>>;
+    /// The working directory of the repository `path` is in, if any.
+    fn discover(&self, path: &Path) -> Option<PathBuf>;
+    /// The name of the reference that the symbolic reference `name` of `repo` points to.
+    fn symbolic_target(&self, repo: &Path, name: &str) -> Option<String>;
+    /// Initialize a repository in `dir` with `branch` as its initial branch, stage the files that aren't ignored and
+    /// commit them with `message`, if given.  Returns how many files were staged.
+    fn init(&self, dir: &Path, branch: Option<&str>, message: Option<&str>) -> Result<usize, Box<dyn Error>>;
+    /// Whether a path of the working directory `repo` has the `export-ignore` attribute, from a `.gitattributes`
+    /// file or the index.
+    fn export_ignore(&self, repo: &Path) -> Option<ExportIgnoreCheck>;
+    /// A value of the user's git configuration, e.g. "user.name".
+    fn config_value(&self, key: &str) -> Option<String>;
+    /// The references of the remote repository at `url` with the objects they point to, without fetching anything.
+    fn remote_refs(&self, url: &str) -> Result<Vec<(String, String)>, Box<dyn Error>>;
+}
+
+/// A tag of a repository.
+pub(crate) struct GitTag {
+    pub(crate) name: String,
+    /// The commit the tag points to.
+    pub(crate) commit: String,
+    /// The tag object of an annotated tag.
+    pub(crate) object: Option<String>,
+}
+
+/// The library of the configured backend, unless scaficionado was built without it.
+pub(crate) fn git_library() -> Result<&'static dyn GitLibrary, String> {
+    match git_backend() {
+        #[cfg(feature = "git2")]
+        GitBackend::Libgit2 => Ok(&libgit2::Libgit2),
+        #[cfg(feature = "gix")]
+        GitBackend::Gix => Ok(&gitoxide::Gitoxide),
+        #[allow(unreachable_patterns)]
+        _ if cfg!(not(any(feature = "git2", feature = "gix"))) => {
+            Err("git repositories need scaficionado built with the `git2` or `gix` feature".to_string())
+        }
+        #[allow(unreachable_patterns)]
+        backend => {
+            let (name, feature) = match backend {
+                GitBackend::Libgit2 => ("libgit2", "git2"),
+                GitBackend::Gix => ("gix", "gix"),
+            };
+            Err(format!(
+                "backend = \"{}\" in the user configuration needs scaficionado built with the `{}` feature",
+                name, feature
+            ))
+        }
+    }
+}
+
+/// Metadata of the git repository the project is generated into, available as `target_repo` in templates.
+#[derive(Serialize, Debug, PartialEq)]
+pub(crate) struct TargetRepo {
+    /// URL of the "origin" remote, or of the first remote.
+    pub(crate) remote_url: Option<String>,
+    /// The branch the remote's HEAD points to, or else the checked-out branch.
+    pub(crate) default_branch: Option<String>,
+    /// The output directory relative to the repository root, e.g. "services/api" (or ".").
+    pub(crate) root_relative_path: String,
+}
+
+/// Describe the git repository containing `output_base`, if any.  The output directory doesn't need to exist yet.
+pub(crate) fn target_repo_metadata(output_base: &Path) -> Option<TargetRepo> {
+    let absolute = std::path::absolute(output_base).ok()?;
+    let mut existing = absolute.as_path();
+    while !existing.exists() {
+        existing = existing.parent()?;
+    }
+    let output = canonical_path(existing).ok()?.join(absolute.strip_prefix(existing).ok()?);
+
+    let library = git_library().ok()?;
+    let workdir = library.discover(existing)?;
+    let root = canonical_path(&workdir).ok()?;
+    let relative = output.strip_prefix(&root).ok()?.to_string_lossy().replace('\\', "/");
+
+    let remotes = library.remotes(&workdir);
+    let (remote_name, remote_url) = remotes
+        .iter()
+        .find(|(name, _)| name == "origin")
+        .or(remotes.first())
+        .map(|(name, url)| (Some(name.clone()), Some(url.clone())))
+        .unwrap_or_default();
+    let symbolic_target = |name: &str, prefix: &str| {
+        let target = library.symbolic_target(&workdir, name)?;
+        target.strip_prefix(prefix).map(str::to_string)
+    };
+    let default_branch = remote_name
+        .as_deref()
+        .and_then(|remote| symbolic_target(&format!("refs/remotes/{}/HEAD", remote), &format!("refs/remotes/{}/", remote)))
+        .or_else(|| symbolic_target("HEAD", "refs/heads/"));
+
+    Some(TargetRepo {
+        remote_url,
+        default_branch,
+        root_relative_path: if relative.is_empty() { ".".to_string() } else { relative },
+    })
+}
+
+/// Lock the scaffolds cloned from remote git repositories to the commits they were cloned at.
+pub(crate) fn locked_sources(prepared: &[(Scaffold, PathBuf)]) -> BTreeMap<String, LockedSource> {
+    let mut locked = BTreeMap::new();
+    for (scaffold, repo_base) in prepared {
+        let [url] = scaffold.repo.urls() else { continue };
+        if is_local_repo(url) || is_archive(url) || url.starts_with("oci://") {
+            continue;
+        }
+        let Ok(library) = git_library() else { continue };
+        let Ok(commit) = library.head_commit(repo_base) else { continue };
+        let mut tags: Vec<String> = library
+            .tags(repo_base)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|tag| tag.commit == commit)
+            .map(|tag| tag.name)
+            .collect();
+        tags.sort_by_key(|name| tag_version(name));
+        locked.insert(
+            scaffold_name(scaffold).to_string(),
+            LockedSource {
+                repo: url.clone(),
+                commit,
+                tag: tags.pop(),
+            },
+        );
+    }
+    locked
+}
+
+/// The semantic version of a tag such as "v1.2.0" or "1.2.0", if it is one.
+fn tag_version(tag: &str) -> Option<semver::Version> {
+    semver::Version::parse(tag.strip_prefix('v').unwrap_or(tag)).ok()
+}
+
+/// Check out the highest release tag of the cloned repository at `repo_base` matching the semantic version
+/// requirement `version`, then its submodules if enabled.
+pub(crate) fn checkout_version(repo_base: &Path, version: &str, git: &GitOptions) -> Result<(), Box<dyn Error>> {
+    let requirement = semver::VersionReq::parse(version)?;
+    if !is_temporary_clone(repo_base) {
+        return Err(format!(
+            "`version = {:?}` needs a git repository to clone; local repositories are used as they are",
+            version
+        )
+        .into());
+    }
+    let library = git_library()?;
+    let tags = library
+        .tags(repo_base)
+        .map_err(|e| format!("`version = {:?}` needs a git repository: {}", version, e))?;
+    let releases: Vec<(semver::Version, &GitTag)> = tags.iter().filter_map(|tag| Some((tag_version(&tag.name)?, tag))).collect();
+    let Some((_, tag)) = releases
+        .iter()
+        .filter(|(release, _)| requirement.matches(release))
+        .max_by(|(a, _), (b, _)| a.cmp(b))
+    else {
+        let mut available: Vec<_> = releases.iter().map(|(release, _)| release).collect();
+        available.sort();
+        let available = match available.is_empty() {
+            true => "it has no release tags".to_string(),
+            false => format!(
+                "its release tags are {}",
+                available.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+            ),
+        };
+        return Err(format!("No tag of the repository matches version {:?}; {}", version, available).into());
+    };
+    library.checkout(repo_base, &tag.commit)?;
+    say!("Using tag {} for version {}", tag.name, version);
+    if git.submodules {
+        update_submodules(repo_base, git)?;
+    }
+    Ok(())
+}
+
+/// Verify the signature of the commit checked out at `repo_base`, or of the annotated tag pointing at it if the tag
+/// is signed, against the trusted keys of `config`.  Returns what was verified.
+pub(crate) fn verify_signature(repo_base: &Path, config: &SignatureConfig) -> Result<String, Box<dyn Error>> {
+    let library = git_library()?;
+    let head = library
+        .head_commit(repo_base)
+        .map_err(|e| format!("Signature verification needs a git repository: {}", e))?;
+    for tag in library.tags(repo_base)? {
+        let Some(object) = tag.object.filter(|_| tag.commit == head) else {
+            continue;
+        };
+        let object = library.read_object(repo_base, &object)?;
+        if let Some((data, signature)) = split_signature(&object) {
+            let signed = format!("tag {}", tag.name);
+            check_signature(&signed, data, signature, config)?;
+            return Ok(signed);
+        }
+    }
+    let signed = format!("commit {:.7}", head);
+    let commit = library.read_object(repo_base, &head)?;
+    let (data, signature) = split_commit_signature(&commit).ok_or_else(|| format!("The {} of the repository isn't signed", signed))?;
+    check_signature(&signed, &data, &signature, config)?;
+    Ok(signed)
+}
+
+/// Split a raw commit object into the signed content, which is the commit without its `gpgsig` header, and the
+/// signature in that header.
+fn split_commit_signature(object: &[u8]) -> Option<(Vec<u8>, Vec<u8>)> {
+    let headers = object.windows(2).position(|window| window == b"\n\n")? + 1;
+    let (mut data, mut signature) = (Vec::new(), Vec::new());
+    let mut in_signature = false;
+    for line in object[..headers].split_inclusive(|byte| *byte == b'\n') {
+        if let Some(value) = line.strip_prefix(b"gpgsig ") {
+            in_signature = true;
+            signature.extend_from_slice(value);
+        } else if let Some(continued) = line.strip_prefix(b" ").filter(|_| in_signature) {
+            signature.extend_from_slice(continued);
+        } else {
+            in_signature = false;
+            data.extend_from_slice(line);
+        }
+    }
+    data.extend_from_slice(&object[headers..]);
+    (!signature.is_empty()).then_some((data, signature))
+}
+
+/// Split a raw tag object into the signed content and the signature appended to its message.
+fn split_signature(object: &[u8]) -> Option<(&[u8], &[u8])> {
+    ["\n-----BEGIN PGP SIGNATURE-----", "\n-----BEGIN SSH SIGNATURE-----"]
+        .iter()
+        .find_map(|marker| object.windows(marker.len()).position(|window| window == marker.as_bytes()))
+        .map(|position| object.split_at(position + 1))
+}
+
+/// Verify the GPG or SSH `signature` of `data` with gpg or ssh-keygen.
+fn check_signature(signed: &str, data: &[u8], signature: &[u8], config: &SignatureConfig) -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let signature_path = dir.path().join("signature");
+    fs::write(&signature_path, signature)?;
+    let failed = |output: String| format!("Invalid signature on the {} of the repository: {}", signed, output.trim());
+    if signature.starts_with(b"-----BEGIN SSH SIGNATURE-----") {
+        let allowed_signers = config.allowed_signers.as_deref().ok_or_else(|| {
+            format!(
+                "The {} has an SSH signature; set `allowed_signers` in [scaffolds.signature]",
+                signed
+            )
+        })?;
+        let mut find = Command::new("ssh-keygen");
+        find.args(["-Y", "find-principals", "-f", allowed_signers, "-s"])
+            .arg(&signature_path);
+        let principals = run_verifier(&mut find, &[]).map_err(|output| failed(format!("no trusted key made it ({})", output.trim())))?;
+        let principal = principals.lines().next().unwrap_or_default();
+        let mut verify = Command::new("ssh-keygen");
+        verify
+            .args(["-Y", "verify", "-n", "git", "-f", allowed_signers, "-I", principal, "-s"])
+            .arg(&signature_path);
+        run_verifier(&mut verify, data).map_err(failed)?;
+    } else {
+        let mut verify = Command::new("gpg");
+        verify.args(["--batch", "--status-fd", "1"]);
+        if let Some(keyring) = &config.keyring {
+            // gpg looks up relative keyrings in its home directory.
+            let keyring = canonical_path(keyring).map_err(|e| format!("Failed to read keyring {:?}: {}", keyring, e))?;
+            verify.args(["--no-default-keyring", "--keyring"]).arg(keyring);
+        }
+        verify.arg("--verify").arg(&signature_path).arg("-");
+        let status = run_verifier(&mut verify, data).map_err(failed)?;
+        check_gpg_status(&status).map_err(|reason| failed(reason.to_string()))?;
+    }
+    Ok(())
+}
+
+/// Check the status lines gpg prints with `--status-fd`: the signature must be good and valid, and not made by an
+/// expired or revoked key.  gpg reports `VALIDSIG` for those too, so it alone isn't enough.
+fn check_gpg_status(status: &str) -> Result<(), &'static str> {
+    let keywords: Vec<&str> = status
+        .lines()
+        .filter_map(|line| line.strip_prefix("[GNUPG:] ")?.split_whitespace().next())
+        .collect();
+    let has = |keyword: &str| keywords.contains(&keyword);
+    if has("BADSIG") {
+        Err("the signature doesn't match")
+    } else if has("EXPKEYSIG") {
+        Err("the key that made it has expired")
+    } else if has("REVKEYSIG") {
+        Err("the key that made it has been revoked")
+    } else if !has("GOODSIG") || !has("VALIDSIG") {
+        Err("gpg didn't report a good and valid signature")
+    } else {
+        Ok(())
+    }
+}
+
+/// Run a signature verification command with `input` on its standard input, returning its standard output, or its
+/// output and errors if it fails.
+fn run_verifier(command: &mut Command, input: &[u8]) -> Result<String, String> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let mut child = command
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("failed to run {}: {}", program, e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input).ok();
+    }
+    let output = child.wait_with_output().map_err(|e| format!("failed to run {}: {}", program, e))?;
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    match output.status.success() {
+        true => Ok(stdout),
+        false => Err(format!("{}{}", stdout, String::from_utf8_lossy(&output.stderr))),
+    }
+}
+
+/// List the references of the remote repository at `url` with the objects they point to, without fetching anything.
+pub(crate) fn remote_refs(url: &str) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+    git_library()?.remote_refs(url)
+}
+
+/// Describe the update available for `locked` among the remote's `refs`, or None if it's up to date.  A scaffold
+/// generated from a tagged release is compared with newer release tags, otherwise with the remote's HEAD.
+pub(crate) fn available_update(locked: &LockedSource, refs: &[(String, String)]) -> Option<String> {
+    if let Some((tag, version)) = locked.tag.as_ref().and_then(|tag| Some((tag, tag_version(tag)?))) {
+        let mut newer: Vec<(semver::Version, &str)> = refs
+            .iter()
+            .filter_map(|(name, _)| name.strip_prefix("refs/tags/"))
+            .filter(|name| !name.ends_with("^{}"))
+            .filter_map(|name| Some((tag_version(name)?, name)))
+            .filter(|(candidate, _)| *candidate > version)
+            .collect();
+        newer.sort();
+        if let Some((_, latest)) = newer.last() {
+            let count = match newer.len() {
+                1 => "1 newer tag".to_string(),
+                n => format!("{} newer tags", n),
+            };
+            return Some(format!("{} → {} ({})", tag, latest, count));
+        }
+        return None;
+    }
+    let (_, head) = refs.iter().find(|(name, _)| name == "HEAD")?;
+    match *head == locked.commit {
+        true => None,
+        false => Some(format!("{:.7} → {:.7}", locked.commit, head)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::native::testing::git;
+    use std::fs;
+    use std::process::Command;
+    use std::time::{Duration, Instant};
+    use tempfile::TempDir;
+
+    // Test that the repository the output directory is in is described for templates.
+    #[test]
+    fn test_target_repo_metadata() -> Result<(), Box<dyn std::error::Error>> {
+        let repo_dir = TempDir::new()?;
+        git(repo_dir.path(), &["init", "-q"])?;
+        git(repo_dir.path(), &["remote", "add", "origin", "https://github.com/acme/app.git"])?;
+        git(
+            repo_dir.path(),
+            &["symbolic-ref", "refs/remotes/origin/HEAD", "refs/remotes/origin/trunk"],
+        )?;
+
+        // The output directory doesn't exist yet.
+        let target_repo = target_repo_metadata(&repo_dir.path().join("services").join("api")).ok_or("no repository found")?;
+        assert_eq!(
+            target_repo,
+            TargetRepo {
+                remote_url: Some("https://github.com/acme/app.git".to_string()),
+                default_branch: Some("trunk".to_string()),
+                root_relative_path: "services/api".to_string(),
+            }
+        );
+        let root = target_repo_metadata(repo_dir.path()).ok_or("no repository found")?;
+        assert_eq!(root.root_relative_path, ".");
+
+        let outside = TempDir::new()?;
+        assert!(target_repo_metadata(outside.path()).is_none());
+        Ok(())
+    }
+
+    // Test that failed clones explain the cause, including untrusted SSH host keys.
+    #[test]
+    fn test_clone_error_diagnostics() -> Result<(), Box<dyn std::error::Error>> {
+        let host_key_error = CloneError {
+            host_key: Some(("example.com".to_string(), "SHA256:abc".to_string())),
+            ..CloneError::new(CloneErrorKind::Certificate, "invalid or unknown remote ssh hostkey")
+        };
+        let message = host_key_error.describe("git@example.com:org/repo.git");
+        assert!(
+            message.contains("SSH host key verification failed for example.com (SHA256:abc)"),
+            "{}",
+            message
+        );
+        assert!(message.contains("ssh-keyscan example.com >> ~/.ssh/known_hosts"), "{}", message);
+        assert!(message.contains("--accept-hostkey"), "{}", message);
+
+        let not_found = CloneError {
+            remote_messages: "Repository not found.\n".to_string(),
+            ..CloneError::new(CloneErrorKind::Http(Some(404)), "unexpected http status code: 404")
+        };
+        let message = not_found.describe("https://example.com/missing.git");
+        assert!(message.contains("remote: Repository not found."), "{}", message);
+        assert!(message.contains("hint: check the repository URL"), "{}", message);
+
+        // Only failures that may go away are retried.
+        assert!(CloneError::new(CloneErrorKind::Http(Some(503)), "unexpected http status code: 503").is_transient());
+        assert!(CloneError::new(CloneErrorKind::Http(Some(429)), "unexpected http status code: 429").is_transient());
+        assert!(CloneError::new(CloneErrorKind::Network, "connection refused").is_transient());
+        assert!(!not_found.is_transient());
+        assert!(!host_key_error.is_transient());
+        assert!(!CloneError::new(CloneErrorKind::Auth, "authentication required").is_transient());
+
+        // Nothing listens on port 1, so every attempt fails right away with a diagnosable error.
+        let dest = TempDir::new()?;
+        let retry: RetryConfig = from_toml("attempts = 2\nbackoff = 0.0")?;
+        let git = GitOptions {
+            retry: RetryConfig {
+                backoff: Some(5.0),
+                ..RetryConfig::default()
+            }
+            .or(retry),
+            ..GitOptions::default()
+        };
+        assert_eq!((git.retry.attempts, git.retry.backoff), (Some(2), Some(5.0)));
+        let git = GitOptions { retry, ..git };
+        let Err(error) = clone_repo("git://127.0.0.1:1/repo.git", &dest.path().join("repo"), &git) else {
+            return Err("the clone should fail".into());
+        };
+        assert!(
+            error.to_string().starts_with("Failed to clone git://127.0.0.1:1/repo.git"),
+            "{}",
+            error
+        );
+
+        assert_eq!(base64(b"foobar", false), "Zm9vYmFy");
+        assert_eq!(base64(b"fooba", false), "Zm9vYmE");
+        Ok(())
+    }
+
+    // Test that a clone from a server that accepts the connection but never answers fails instead of hanging.
+    #[test]
+    fn test_clone_timeout() -> Result<(), Box<dyn std::error::Error>> {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("git://{}/repo.git", listener.local_addr()?);
+        std::thread::spawn(move || {
+            let connections: Vec<_> = listener.incoming().take(1).collect();
+            std::thread::sleep(Duration::from_secs(30));
+            drop(connections);
+        });
+        let dest = TempDir::new()?;
+        let git = GitOptions {
+            retry: from_toml("attempts = 1\nstall_timeout = 0.5")?,
+            ..GitOptions::default()
+        };
+        let started = Instant::now();
+        let Err(error) = clone_repo(&url, &dest.path().join("repo"), &git) else {
+            return Err("the clone should time out".into());
+        };
+        assert!(started.elapsed() < Duration::from_secs(10), "took {:?}", started.elapsed());
+        assert!(error.to_string().contains("raise `timeout` or `stall_timeout`"), "{}", error);
+        Ok(())
+    }
+
+    // Test that submodules of a cloned template repository are checked out with `submodules = true`.
+    #[test]
+    fn test_clone_submodules() -> Result<(), Box<dyn std::error::Error>> {
+        let shared = TempDir::new()?;
+        git(shared.path(), &["init", "-q"])?;
+        fs::write(shared.path().join("header.tera"), "# {{ project_name }}")?;
+        git(shared.path(), &["add", "-A"])?;
+        git(shared.path(), &["commit", "-q", "-m", "Add partials"])?;
+        let shared_url = shared.path().to_string_lossy().into_owned();
+
+        let upstream = TempDir::new()?;
+        git(upstream.path(), &["init", "-q"])?;
+        fs::write(upstream.path().join("README.md.tera"), "{% include \"partials/header.tera\" %}")?;
+        git(upstream.path(), &["submodule", "--quiet", "add", &shared_url, "partials"])?;
+        git(upstream.path(), &["add", "-A"])?;
+        git(upstream.path(), &["commit", "-q", "-m", "Add templates"])?;
+        let url = upstream.path().to_string_lossy().into_owned();
+
+        let dest = TempDir::new()?;
+        clone_repo(&url, &dest.path().join("without"), &GitOptions::default())?;
+        assert!(!dest.path().join("without/partials/header.tera").exists());
+        let git = GitOptions {
+            submodules: true,
+            ..GitOptions::default()
+        };
+        clone_repo(&url, &dest.path().join("with"), &git)?;
+        assert_eq!(
+            fs::read_to_string(dest.path().join("with/partials/header.tera"))?,
+            "# {{ project_name }}"
+        );
+
+        fs::remove_dir_all(cached_repo_path(&url))?;
+        fs::remove_dir_all(cached_repo_path(&shared_url))?;
+        Ok(())
+    }
+
+    // Test that the gix backend clones a repository, reporting its progress, and describes a failed clone.
+    #[cfg(feature = "gix")]
+    #[test]
+    fn test_gix_clone() -> Result<(), Box<dyn std::error::Error>> {
+        let upstream = TempDir::new()?;
+        git(upstream.path(), &["init", "-q"])?;
+        fs::create_dir_all(upstream.path().join("templates"))?;
+        fs::write(upstream.path().join("templates/readme.md.tera"), "{{ project_name }}")?;
+        git(upstream.path(), &["add", "-A"])?;
+        git(upstream.path(), &["commit", "-q", "-m", "Templates"])?;
+        let commit = git(upstream.path(), &["rev-parse", "HEAD"])?;
+
+        let clones = TempDir::new()?;
+        let url = format!("file://{}", upstream.path().display());
+        let clone = clones.path().join("clone");
+        let progress = CloneProgress::new(&RetryConfig::default());
+        gitoxide::Gitoxide
+            .clone(&url, &clone, &GitOptions::default(), &progress)
+            .map_err(|e| e.describe(&url))?;
+        assert_eq!(gitoxide::Gitoxide.head_commit(&clone)?, commit);
+        assert_eq!(fs::read_to_string(clone.join("templates/readme.md.tera"))?, "{{ project_name }}");
+        assert_eq!(gitoxide::Gitoxide.remotes(&clone), [("origin".to_string(), url)]);
+        // What gix received counts as progress, so that the clone isn't taken for stalled.
+        assert_ne!(progress.last.get().0, 0);
+
+        let missing = format!("file://{}", clones.path().join("missing").display());
+        let Err(error) = gitoxide::Gitoxide.clone(&missing, &clones.path().join("failed"), &GitOptions::default(), &progress) else {
+            return Err("cloned a missing repository".into());
+        };
+        let description = error.describe(&missing);
+        assert!(
+            description.starts_with(&format!("Failed to clone {}: ", missing)),
+            "{}",
+            description
+        );
+        assert!(!clones.path().join("failed").exists());
+        Ok(())
+    }
+
+    // Test that `version` checks out the highest release tag matching the requirement
+    #[test]
+    fn test_scaffold_version() -> Result<(), Box<dyn std::error::Error>> {
+        let upstream = TempDir::new()?;
+        git(upstream.path(), &["init", "-q"])?;
+        for tag in ["v1.0.0", "v1.2.0", "1.3.1", "v2.0.0", "latest"] {
+            fs::write(upstream.path().join("version.txt"), tag)?;
+            git(upstream.path(), &["add", "version.txt"])?;
+            git(upstream.path(), &["commit", "-q", "-m", tag])?;
+            // An annotated tag among the lightweight ones.
+            match tag {
+                "1.3.1" => git(upstream.path(), &["tag", "-a", "-m", tag, tag])?,
+                _ => git(upstream.path(), &["tag", tag])?,
+            };
+        }
+        let url = format!("file://{}", upstream.path().display());
+        let scaffold = |version: &str| Scaffold {
+            name: Some("svc".to_string()),
+            repo: url.clone().into(),
+            version: Some(version.to_string()),
+            ..Default::default()
+        };
+
+        let (repo_base, _) = acquire_scaffold_repo(&scaffold("^1.2"), &GitOptions::default())?;
+        assert_eq!(fs::read_to_string(repo_base.join("version.txt"))?, "1.3.1");
+        let locked = locked_sources(&[(scaffold("^1.2"), repo_base.clone())]);
+        assert_eq!(locked["svc"].tag.as_deref(), Some("1.3.1"));
+        fs::remove_dir_all(clone_dir_of(&repo_base).unwrap())?;
+
+        let (repo_base, _) = acquire_scaffold_repo(&scaffold("~1.0"), &GitOptions::default())?;
+        assert_eq!(fs::read_to_string(repo_base.join("version.txt"))?, "v1.0.0");
+        fs::remove_dir_all(clone_dir_of(&repo_base).unwrap())?;
+
+        let error = acquire_scaffold_repo(&scaffold(">=3"), &GitOptions::default()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "No tag of the repository matches version \">=3\"; its release tags are 1.0.0, 1.2.0, 1.3.1, 2.0.0"
+        );
+        assert!(validate_scaffold(&scaffold("one point two"))
+            .unwrap_err()
+            .to_string()
+            .starts_with("Invalid `version"));
+        let local = Scaffold {
+            repo: upstream.path().to_string_lossy().into_owned().into(),
+            ..scaffold("^1")
+        };
+        assert!(acquire_scaffold_repo(&local, &GitOptions::default())
+            .unwrap_err()
+            .to_string()
+            .contains("local repositories"));
+
+        fs::remove_dir_all(cached_repo_path(&url))?;
+        Ok(())
+    }
+
+    // Test that only good and valid gpg signatures of keys that haven't expired or been revoked are accepted
+    #[test]
+    fn test_gpg_status() {
+        let valid = "[GNUPG:] VALIDSIG 0123456789ABCDEF 2025-02-09 1739109780 0 4 0 22 8 00 0123456789ABCDEF";
+        let good = format!("[GNUPG:] NEWSIG\n[GNUPG:] GOODSIG 89ABCDEF Alice <alice@example.com>\n{}\n", valid);
+        assert_eq!(check_gpg_status(&good), Ok(()));
+        let expired = format!("[GNUPG:] EXPKEYSIG 89ABCDEF Alice <alice@example.com>\n{}\n", valid);
+        assert_eq!(check_gpg_status(&expired), Err("the key that made it has expired"));
+        let revoked = format!("[GNUPG:] REVKEYSIG 89ABCDEF Alice <alice@example.com>\n{}\n", valid);
+        assert_eq!(check_gpg_status(&revoked), Err("the key that made it has been revoked"));
+        let bad = "[GNUPG:] BADSIG 89ABCDEF Alice <alice@example.com>\n";
+        assert_eq!(check_gpg_status(bad), Err("the signature doesn't match"));
+        assert!(check_gpg_status(valid).is_err());
+        assert!(check_gpg_status("[GNUPG:] GOODSIG 89ABCDEF Alice <alice@example.com>\n").is_err());
+        // Keywords only count at the start of a status line.
+        assert!(check_gpg_status("gpg: [GNUPG:] GOODSIG\ngpg: [GNUPG:] VALIDSIG\n").is_err());
+    }
+
+    // Test the sha256 checksum of archive repositories and the signature verification of git repositories
+    #[test]
+    fn test_template_verification() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = TempDir::new()?;
+        let templates = dir.path().join("templates");
+        fs::create_dir_all(templates.join("templates"))?;
+        fs::write(templates.join("templates/README.md.tera"), "# {{ project_name }}")?;
+        fs::write(templates.join("README.md"), "templates")?;
+        let archive = dir.path().join("templates.tar.gz");
+        fs::write(&archive, pack_archive(&templates)?)?;
+        let checksum = sha256_hex(&fs::read(&archive)?);
+        let scaffold = Scaffold {
+            name: Some("Archive".to_string()),
+            repo: archive.to_string_lossy().into_owned().into(),
+            sha256: Some(checksum.to_uppercase()),
+            ..Default::default()
+        };
+        validate_scaffold(&scaffold)?;
+        let (base, _) = acquire_scaffold_repo(&scaffold, &GitOptions::default())?;
+        assert!(base.join("templates/README.md.tera").is_file());
+        fs::remove_dir_all(clone_dir_of(&base).unwrap())?;
+        let tampered = Scaffold {
+            sha256: Some("0".repeat(64)),
+            ..scaffold.clone()
+        };
+        let error = acquire_scaffold_repo(&tampered, &GitOptions::default()).unwrap_err();
+        assert!(error.to_string().starts_with("Checksum mismatch for"));
+        let not_archive = Scaffold {
+            repo: "https://github.com/org/templates.git".into(),
+            ..scaffold
+        };
+        assert!(validate_scaffold(&not_archive).is_err());
+
+        // Commits signed with an SSH key are verified against the allowed signers.
+        let keygen = |name: &str| -> Result<String, Box<dyn std::error::Error>> {
+            let key = dir.path().join(name);
+            let status = Command::new("ssh-keygen")
+                .args(["-q", "-t", "ed25519", "-N", "", "-C", name, "-f"])
+                .arg(&key)
+                .status()?;
+            assert!(status.success());
+            Ok(fs::read_to_string(key.with_extension("pub"))?)
+        };
+        let trusted = keygen("trusted")?;
+        keygen("other")?;
+        let allowed_signers = dir.path().join("allowed_signers");
+        fs::write(&allowed_signers, format!("release@example.com {}", trusted))?;
+
+        let upstream = TempDir::new()?;
+        git(upstream.path(), &["init", "-q"])?;
+        fs::write(upstream.path().join("README.md"), "templates")?;
+        git(upstream.path(), &["add", "README.md"])?;
+        let commit = |key: Option<&str>| -> Result<String, Box<dyn std::error::Error>> {
+            match key {
+                Some(key) => {
+                    let key = format!("user.signingKey={}", dir.path().join(key).display());
+                    let signing = [
+                        "-c",
+                        "gpg.format=ssh",
+                        "-c",
+                        &key,
+                        "commit",
+                        "-q",
+                        "--allow-empty",
+                        "-S",
+                        "-m",
+                        "Release",
+                    ];
+                    git(upstream.path(), &signing)?
+                }
+                None => git(upstream.path(), &["commit", "-q", "--allow-empty", "-m", "Release"])?,
+            };
+            git(upstream.path(), &["rev-parse", "HEAD"])
+        };
+        let config = SignatureConfig {
+            allowed_signers: Some(allowed_signers.to_string_lossy().into_owned()),
+            keyring: None,
+        };
+        let signed = commit(Some("trusted"))?;
+        let verified = verify_signature(upstream.path(), &config)?;
+        assert_eq!(verified, format!("commit {:.7}", signed));
+
+        commit(Some("other"))?;
+        let error = verify_signature(upstream.path(), &config).unwrap_err().to_string();
+        assert!(error.starts_with("Invalid signature on the commit"), "{}", error);
+
+        let unsigned = commit(None)?;
+        let error = verify_signature(upstream.path(), &config).unwrap_err().to_string();
+        assert_eq!(error, format!("The commit {:.7} of the repository isn't signed", unsigned));
+        Ok(())
+    }
+}
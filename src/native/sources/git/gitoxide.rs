@@ -0,0 +1,448 @@
+//! The gitoxide backend, in pure Rust, with the `gix` feature.
+
+use gix::bstr::ByteSlice;
+use gix::{Count, NestedProgress, Progress};
+use std::cell::RefCell;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use super::*;
+
+/// Clones and reads repositories with gix.
+pub(crate) struct Gitoxide;
+
+impl GitLibrary for Gitoxide {
+    /// gix reports its progress for each step of the clone, e.g. receiving or indexing objects, which --verbose
+    /// prints.
+    fn clone(&self, url: &str, dest: &Path, git: &GitOptions, progress: &CloneProgress) -> Result<(), CloneError> {
+        if git.verbose {
+            say!("Cloning {} with gix", url);
+        }
+        let steps = CloneSteps::default();
+        let work = {
+            let (url, accept_hostkey, root) = (url.to_string(), git.accept_hostkey, steps.add("clone"));
+            move |path: &Path, interrupt: &AtomicBool| {
+                gix_clone(&url, path, accept_hostkey, root, interrupt).map_err(|e| {
+                    let io =
+                        std::iter::successors(Some(e.as_ref() as &(dyn Error + 'static)), |&e| e.source()).any(|e| e.is::<io::Error>());
+                    let kind = if io { CloneErrorKind::Network } else { CloneErrorKind::Other };
+                    CloneError::new(kind, crate::render::error_chain(e.as_ref()))
+                })
+            }
+        };
+        let mut reported_percent = Vec::new();
+        progress.watch(dest, work, || {
+            let steps = steps.0.lock().unwrap_or_else(|e| e.into_inner()).clone();
+            if git.verbose {
+                reported_percent.resize(steps.len(), None);
+                for (step, reported) in steps.iter().zip(&mut reported_percent) {
+                    let (done, max) = (step.counter.load(Ordering::Relaxed), step.max.load(Ordering::Relaxed));
+                    // Report every 10%, so that logs of slow clones aren't flooded.
+                    let percent = (done * 100).checked_div(max).map(|percent| percent.min(100) / 10 * 10);
+                    if percent.is_some() && *reported != percent {
+                        *reported = percent;
+                        let name = step.name.lock().unwrap_or_else(|e| e.into_inner()).clone();
+                        say!("{}: {}% ({}/{})", name, percent.unwrap_or_default(), done, max);
+                    }
+                }
+            }
+            steps.iter().map(|step| step.counter.load(Ordering::Relaxed)).sum()
+        })?;
+        // The remote keeps the URL without the token it may have been cloned with.
+        set_origin_url(dest, url).map_err(|e| CloneError::new(CloneErrorKind::Other, e.to_string()))
+    }
+
+    fn remotes(&self, repo: &Path) -> Vec<(String, String)> {
+        let Ok(repo) = gix::open(repo) else {
+            return Vec::new();
+        };
+        repo.remote_names()
+            .iter()
+            .filter_map(|name| {
+                let remote = repo.find_remote(name.as_bstr()).ok()?;
+                let url = remote.url(gix::remote::Direction::Fetch)?.to_bstring().to_string();
+                Some((name.to_string(), url))
+            })
+            .collect()
+    }
+
+    fn head_commit(&self, repo: &Path) -> Result<String, Box<dyn Error>> {
+        Ok(gix::open(repo)?.head_id()?.to_string())
+    }
+
+    fn tags(&self, repo: &Path) -> Result<Vec<GitTag>, Box<dyn Error>> {
+        let repo = gix::open(repo)?;
+        let mut tags = Vec::new();
+        for reference in repo.references()?.tags()? {
+            let mut reference = reference.map_err(|e| e.to_string())?;
+            let name = reference.name().shorten().to_string();
+            let Some(target) = reference.try_id().map(|id| id.detach()) else {
+                continue;
+            };
+            let Ok(commit) = reference.peel_to_commit() else { continue };
+            let annotated = repo.find_object(target)?.kind == gix::object::Kind::Tag;
+            tags.push(GitTag {
+                name,
+                commit: commit.id.to_string(),
+                object: annotated.then(|| target.to_string()),
+            });
+        }
+        Ok(tags)
+    }
+
+    fn read_object(&self, repo: &Path, id: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        let repo = gix::open(repo)?;
+        let object = repo.find_object(gix::ObjectId::from_hex(id.as_bytes())?)?;
+        Ok(object.data.clone())
+    }
+
+    /// gix checks out into an empty working directory only, so everything but `.git` is removed first.
+    fn checkout(&self, repo: &Path, commit: &str) -> Result<(), Box<dyn Error>> {
+        let mut repo = gix::open(repo)?;
+        let workdir = repo.workdir().ok_or("cannot check out a bare repository")?.to_path_buf();
+        let commit = gix::ObjectId::from_hex(commit.as_bytes())?;
+        let tree = repo.find_commit(commit)?.tree_id()?.detach();
+        for entry in fs::read_dir(&workdir)? {
+            let entry = entry?;
+            if entry.file_name() == ".git" {
+                continue;
+            }
+            match entry.file_type()?.is_dir() {
+                true => fs::remove_dir_all(entry.path())?,
+                false => fs::remove_file(entry.path())?,
+            }
+        }
+        let mut index = repo.index_from_tree(&tree)?;
+        let mut options = repo.checkout_options(gix::worktree::stack::state::attributes::Source::IdMapping)?;
+        options.destination_is_initially_empty = true;
+        let objects = repo.objects.clone().into_arc()?;
+        let discard = gix::progress::Discard;
+        gix::worktree::state::checkout(&mut index, &workdir, objects, &discard, &discard, &AtomicBool::new(false), options)?;
+        index.write(Default::default())?;
+        update_reference(&mut repo, "HEAD", gix::refs::Target::Object(commit))
+    }
+
+    fn submodules(&self, repo: &Path) -> Result<Vec<Submodule>, Box<dyn Error>> {
+        let workdir = repo;
+        let repo = gix::open(repo)?;
+        let Some(submodules) = repo.submodules()? else {
+            return Ok(Vec::new());
+        };
+        // gix parses the URLs, which would lose the form of relative ones.
+        let modules = gix::config::File::from_path_no_includes(workdir.join(".gitmodules"), gix::config::Source::Worktree)?;
+        let mut list = Vec::new();
+        for submodule in submodules {
+            let name = submodule.name().to_string();
+            let commit = submodule
+                .head_id()?
+                .ok_or_else(|| format!("Submodule {} has no recorded commit", name))?;
+            let url = modules
+                .string_by("submodule", Some(submodule.name()), "url")
+                .ok_or_else(|| format!("Submodule {} has no URL", name))?;
+            list.push(Submodule {
+                url: url.to_string(),
+                path: gix::path::from_bstring(submodule.path()?)?,
+                commit: commit.to_string(),
+                name,
+            });
+        }
+        Ok(list)
+    }
+
+    fn discover(&self, path: &Path) -> Option<PathBuf> {
+        Some(gix::discover(path).ok()?.workdir()?.to_path_buf())
+    }
+
+    fn symbolic_target(&self, repo: &Path, name: &str) -> Option<String> {
+        let repo = gix::open(repo).ok()?;
+        let reference = repo.find_reference(name).ok()?;
+        reference.target().try_name().map(|target| target.as_bstr().to_string())
+    }
+
+    /// The files are staged by writing their tree, from which the index is made.
+    fn init(&self, dir: &Path, branch: Option<&str>, message: Option<&str>) -> Result<usize, Box<dyn Error>> {
+        let mut repo = gix::init(dir)?;
+        if let Some(branch) = branch {
+            let target = gix::refs::FullName::try_from(format!("refs/heads/{}", branch))?;
+            update_reference(&mut repo, "HEAD", gix::refs::Target::Symbolic(target))?;
+        }
+        let empty = gix::index::State::new(repo.object_hash());
+        let mut excludes = repo
+            .excludes(
+                &empty,
+                None,
+                gix::worktree::stack::state::ignore::Source::WorktreeThenIdMappingIfNotSkipped,
+            )?
+            .detach();
+        let mut editor = repo.edit_tree(gix::ObjectId::empty_tree(repo.object_hash()))?;
+        let staged = stage_files(&repo, dir, Path::new(""), &mut excludes, &mut editor)?;
+        let tree = editor.write()?.detach();
+        repo.index_from_tree(&tree)?.write(Default::default())?;
+        if let Some(message) = message {
+            // Without user.name and user.email configured, commit as the tool rather than failing the run.
+            let committer = repo
+                .committer_or_set_fallback(FALLBACK_COMMITTER.0, FALLBACK_COMMITTER.1)?
+                .to_owned()?;
+            repo.commit_as(
+                committer.to_ref(&mut Default::default()),
+                committer.to_ref(&mut Default::default()),
+                "HEAD",
+                message,
+                tree,
+                Vec::<gix::ObjectId>::new(),
+            )?;
+        }
+        Ok(staged)
+    }
+
+    fn export_ignore(&self, repo: &Path) -> Option<ExportIgnoreCheck> {
+        let repo = gix::open(repo).ok()?;
+        let workdir = repo.workdir()?.to_path_buf();
+        let index = repo.index_or_load_from_head_or_empty().ok()?;
+        let stack = repo
+            .attributes_only(&index, gix::worktree::stack::state::attributes::Source::WorktreeThenIdMapping)
+            .ok()?
+            .detach();
+        let stack = RefCell::new(stack);
+        Some(Box::new(move |path| {
+            let mut stack = stack.borrow_mut();
+            let mut outcome = stack.selected_attribute_matches(["export-ignore"]);
+            // Directories match patterns such as "tests/" only as directories.
+            let mode = workdir.join(path).is_dir().then_some(gix::index::entry::Mode::DIR);
+            let Ok(platform) = stack.at_path(path, mode, &repo.objects) else {
+                return false;
+            };
+            platform.matching_attributes(&mut outcome);
+            let set = outcome
+                .iter_selected()
+                .any(|matched| matched.assignment.state == gix::attrs::StateRef::Set);
+            set
+        }))
+    }
+
+    fn config_value(&self, key: &str) -> Option<String> {
+        let config = gix::config::File::from_globals().ok()?;
+        config.string(key).map(|value| value.to_string())
+    }
+
+    /// The references are listed through a temporary repository, as gix connects to remotes of a repository only.
+    fn remote_refs(&self, url: &str) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+        let scratch = tempfile::tempdir()?;
+        let repo = gix::init_bare(scratch.path())?;
+        let remote = repo.remote_at(url)?;
+        let options = gix::remote::ref_map::Options {
+            prefix_from_spec_as_filter_on_remote: false,
+            ..Default::default()
+        };
+        let (ref_map, _) = remote
+            .connect(gix::remote::Direction::Fetch)?
+            .ref_map(gix::progress::Discard, options)?;
+        let mut refs = Vec::new();
+        for reference in &ref_map.remote_refs {
+            let (name, target, peeled) = reference.unpack();
+            let Some(target) = target else { continue };
+            refs.push((name.to_string(), target.to_string()));
+            // As listed by git: the commit of an annotated tag follows it, marked with "^{}".
+            if let Some(peeled) = peeled {
+                refs.push((format!("{}^{{}}", name), peeled.to_string()));
+            }
+        }
+        Ok(refs)
+    }
+}
+
+/// Fetch `repo_url` into `dest` with gix and check out its default branch, reporting to `progress`.  The token of the
+/// host from the user configuration is sent as the URL's credentials over HTTPS; otherwise gix asks git's credential
+/// helpers.
+fn gix_clone(
+    repo_url: &str,
+    dest: &Path,
+    accept_hostkey: bool,
+    mut progress: CloneStep,
+    interrupt: &AtomicBool,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut url = gix::url::parse(repo_url.as_bytes().as_bstr())?;
+    let token = repo_host(repo_url).and_then(|host| user_config().ok()?.tokens.get(host));
+    if let Some(token) = token.filter(|_| url.scheme == gix::url::Scheme::Https && url.user().is_none()) {
+        url.set_user(Some("x-access-token".into()));
+        url.set_password(Some(token.clone()));
+    }
+    let mut overrides = Vec::new();
+    if accept_hostkey {
+        overrides.push("core.sshCommand=ssh -o StrictHostKeyChecking=accept-new");
+    }
+    if cfg!(windows) {
+        overrides.push("core.longpaths=true");
+    }
+    let mut prepare = gix::prepare_clone(url, dest)?.with_in_memory_config_overrides(overrides);
+    let (mut checkout, _) = prepare.fetch_then_checkout(progress.add_child("fetch"), interrupt)?;
+    checkout.main_worktree(progress.add_child("checkout"), interrupt)?;
+    Ok(())
+}
+
+/// Set the URL of the `origin` remote of the repository at `repo` in its configuration file.
+fn set_origin_url(repo: &Path, url: &str) -> Result<(), Box<dyn Error>> {
+    let path = repo.join(".git").join("config");
+    let mut config = gix::config::File::from_path_no_includes(path.clone(), gix::config::Source::Local)?;
+    config.set_raw_value_by("remote", Some("origin".into()), "url", url)?;
+    config.write_to(&mut fs::File::create(&path)?)?;
+    Ok(())
+}
+
+/// Point the reference `name` of `repo` at `target`, without following symbolic references.  The reflog entry
+/// needs a committer, so the fallback one is used when none is configured.
+fn update_reference(repo: &mut gix::Repository, name: &str, target: gix::refs::Target) -> Result<(), Box<dyn Error>> {
+    repo.committer_or_set_fallback(FALLBACK_COMMITTER.0, FALLBACK_COMMITTER.1)?;
+    repo.edit_reference(gix::refs::transaction::RefEdit {
+        change: gix::refs::transaction::Change::Update {
+            log: Default::default(),
+            expected: gix::refs::transaction::PreviousValue::Any,
+            new: target,
+        },
+        name: name.try_into()?,
+        deref: false,
+    })?;
+    Ok(())
+}
+
+/// Write the files below `relative` in `dir` that aren't ignored to the repository and add them to the tree of
+/// `editor`, as `git add` does, returning how many there are.
+fn stage_files(
+    repo: &gix::Repository,
+    dir: &Path,
+    relative: &Path,
+    excludes: &mut gix::worktree::Stack,
+    editor: &mut gix::object::tree::Editor<'_>,
+) -> Result<usize, Box<dyn Error>> {
+    let mut staged = 0;
+    for entry in fs::read_dir(dir.join(relative))? {
+        let entry = entry?;
+        if entry.file_name() == ".git" {
+            continue;
+        }
+        let path = relative.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        let mode = file_type.is_dir().then_some(gix::index::entry::Mode::DIR);
+        if excludes.at_path(path.as_path(), mode, &repo.objects)?.is_excluded() {
+            continue;
+        }
+        let (kind, content) = if file_type.is_dir() {
+            staged += stage_files(repo, dir, &path, excludes, editor)?;
+            continue;
+        } else if file_type.is_symlink() {
+            let target = fs::read_link(entry.path())?;
+            (gix::object::tree::EntryKind::Link, gix::path::into_bstr(target)?.to_vec())
+        } else {
+            (file_kind(&entry.metadata()?), fs::read(entry.path())?)
+        };
+        let blob = repo.write_blob(content)?;
+        editor.upsert(gix::path::into_bstr(path.as_path())?.as_ref(), kind, blob)?;
+        staged += 1;
+    }
+    Ok(staged)
+}
+
+/// Whether a file is staged as executable.
+fn file_kind(metadata: &fs::Metadata) -> gix::object::tree::EntryKind {
+    #[cfg(unix)]
+    if std::os::unix::fs::PermissionsExt::mode(&metadata.permissions()) & 0o111 != 0 {
+        return gix::object::tree::EntryKind::BlobExecutable;
+    }
+    let _ = metadata;
+    gix::object::tree::EntryKind::Blob
+}
+
+/// The steps of a clone, as gix reports them, for the thread that watches it to read.
+#[derive(Clone, Default)]
+struct CloneSteps(Arc<Mutex<Vec<Arc<StepState>>>>);
+
+/// A step of a clone, e.g. receiving or indexing objects.
+struct StepState {
+    name: Mutex<String>,
+    /// How many units there are in total, or 0 if unknown.
+    max: AtomicUsize,
+    counter: gix::progress::StepShared,
+}
+
+impl CloneSteps {
+    fn add(&self, name: impl Into<String>) -> CloneStep {
+        let state = Arc::new(StepState {
+            name: Mutex::new(name.into()),
+            max: AtomicUsize::new(0),
+            counter: Arc::new(AtomicUsize::new(0)),
+        });
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).push(state.clone());
+        CloneStep {
+            state,
+            steps: self.clone(),
+        }
+    }
+}
+
+/// The progress gix reports for a step of a clone.
+struct CloneStep {
+    state: Arc<StepState>,
+    steps: CloneSteps,
+}
+
+impl Count for CloneStep {
+    fn set(&self, step: gix::progress::Step) {
+        self.state.counter.store(step, Ordering::Relaxed);
+    }
+
+    fn step(&self) -> gix::progress::Step {
+        self.state.counter.load(Ordering::Relaxed)
+    }
+
+    fn inc_by(&self, step: gix::progress::Step) {
+        self.state.counter.fetch_add(step, Ordering::Relaxed);
+    }
+
+    fn counter(&self) -> gix::progress::StepShared {
+        self.state.counter.clone()
+    }
+}
+
+impl Progress for CloneStep {
+    fn init(&mut self, max: Option<gix::progress::Step>, _: Option<gix::progress::Unit>) {
+        self.state.max.store(max.unwrap_or(0), Ordering::Relaxed);
+    }
+
+    fn max(&self) -> Option<gix::progress::Step> {
+        Some(self.state.max.load(Ordering::Relaxed)).filter(|max| *max > 0)
+    }
+
+    fn set_max(&mut self, max: Option<gix::progress::Step>) -> Option<gix::progress::Step> {
+        Some(self.state.max.swap(max.unwrap_or(0), Ordering::Relaxed)).filter(|max| *max > 0)
+    }
+
+    fn set_name(&mut self, name: String) {
+        *self.state.name.lock().unwrap_or_else(|e| e.into_inner()) = name;
+    }
+
+    fn name(&self) -> Option<String> {
+        Some(self.state.name.lock().unwrap_or_else(|e| e.into_inner()).clone())
+    }
+
+    fn id(&self) -> gix::progress::Id {
+        gix::progress::UNKNOWN
+    }
+
+    fn message(&self, _: gix::progress::MessageLevel, _: String) {}
+}
+
+impl NestedProgress for CloneStep {
+    type SubProgress = CloneStep;
+
+    fn add_child(&mut self, name: impl Into<String>) -> Self::SubProgress {
+        self.steps.add(name)
+    }
+
+    fn add_child_with_id(&mut self, name: impl Into<String>, _: gix::progress::Id) -> Self::SubProgress {
+        self.steps.add(name)
+    }
+}
@@ -0,0 +1,415 @@
+//! The libgit2 backend, with the `git2` feature.
+
+use git2::Repository;
+use regex::Regex;
+use std::cell::{Cell, RefCell};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::*;
+
+/// How long libgit2 waits for a server, see [`set_git_server_timeout`].
+const LIBGIT2_SERVER_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Clones and reads repositories with libgit2.
+pub(crate) struct Libgit2;
+
+impl GitLibrary for Libgit2 {
+    /// Messages the remote sends are printed with --verbose and kept for the error.  The callbacks give up once the
+    /// clone is abandoned for taking too long or stalling.
+    fn clone(&self, url: &str, dest: &Path, git: &GitOptions, progress: &CloneProgress) -> Result<(), CloneError> {
+        // What the worker received and indexed so far, and how many messages the remote sent.
+        let received = Arc::new((AtomicUsize::new(0), AtomicUsize::new(0)));
+        let work = {
+            let (url, git, received) = (url.to_string(), *git, received.clone());
+            move |path: &Path, interrupt: &AtomicBool| clone_repository(&url, path, &git, &received, interrupt)
+        };
+        progress.watch(dest, work, || {
+            received.0.load(Ordering::Relaxed) + received.1.load(Ordering::Relaxed)
+        })
+    }
+
+    fn remotes(&self, repo: &Path) -> Vec<(String, String)> {
+        let Ok(repo) = Repository::open(repo) else {
+            return Vec::new();
+        };
+        let names = repo
+            .remotes()
+            .map(|names| names.iter().flatten().map(str::to_string).collect::<Vec<_>>());
+        names
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|name| {
+                let url = repo.find_remote(&name).ok()?.url()?.to_string();
+                Some((name, url))
+            })
+            .collect()
+    }
+
+    fn head_commit(&self, repo: &Path) -> Result<String, Box<dyn Error>> {
+        Ok(Repository::open(repo)?.head()?.peel_to_commit()?.id().to_string())
+    }
+
+    fn tags(&self, repo: &Path) -> Result<Vec<GitTag>, Box<dyn Error>> {
+        let repo = Repository::open(repo)?;
+        let mut tags = Vec::new();
+        for name in repo.tag_names(None)?.iter().flatten() {
+            let object = repo.revparse_single(&format!("refs/tags/{}", name))?;
+            let Ok(commit) = object.peel_to_commit() else { continue };
+            tags.push(GitTag {
+                name: name.to_string(),
+                commit: commit.id().to_string(),
+                object: object.as_tag().map(|tag| tag.id().to_string()),
+            });
+        }
+        Ok(tags)
+    }
+
+    fn read_object(&self, repo: &Path, id: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        let repo = Repository::open(repo)?;
+        let odb = repo.odb()?;
+        let object = odb.read(git2::Oid::from_str(id)?)?;
+        Ok(object.data().to_vec())
+    }
+
+    fn checkout(&self, repo: &Path, commit: &str) -> Result<(), Box<dyn Error>> {
+        let repo = Repository::open(repo)?;
+        let commit = git2::Oid::from_str(commit)?;
+        let object = repo.find_object(commit, None)?;
+        repo.checkout_tree(&object, Some(git2::build::CheckoutBuilder::new().force()))?;
+        repo.set_head_detached(commit)?;
+        Ok(())
+    }
+
+    fn submodules(&self, repo: &Path) -> Result<Vec<Submodule>, Box<dyn Error>> {
+        let repo = Repository::open(repo)?;
+        let mut submodules = Vec::new();
+        for submodule in repo.submodules()? {
+            let name = submodule.name().unwrap_or("unnamed").to_string();
+            let commit = submodule
+                .head_id()
+                .ok_or_else(|| format!("Submodule {} has no recorded commit", name))?;
+            submodules.push(Submodule {
+                url: submodule.url().ok_or_else(|| format!("Submodule {} has no URL", name))?.to_string(),
+                path: submodule.path().to_path_buf(),
+                commit: commit.to_string(),
+                name,
+            });
+        }
+        Ok(submodules)
+    }
+
+    fn discover(&self, path: &Path) -> Option<PathBuf> {
+        Some(Repository::discover(path).ok()?.workdir()?.to_path_buf())
+    }
+
+    fn symbolic_target(&self, repo: &Path, name: &str) -> Option<String> {
+        let repo = Repository::open(repo).ok()?;
+        let reference = repo.find_reference(name).ok()?;
+        reference.symbolic_target().map(str::to_string)
+    }
+
+    fn init(&self, dir: &Path, branch: Option<&str>, message: Option<&str>) -> Result<usize, Box<dyn Error>> {
+        let mut init_options = git2::RepositoryInitOptions::new();
+        if let Some(branch) = branch {
+            init_options.initial_head(branch);
+        }
+        let repo = Repository::init_opts(dir, &init_options)?;
+        let mut index = repo.index()?;
+        index.add_all(["*"], git2::IndexAddOption::DEFAULT, None)?;
+        index.write()?;
+        if let Some(message) = message {
+            let tree = repo.find_tree(index.write_tree()?)?;
+            // Without user.name and user.email configured, commit as the tool rather than failing the run.
+            let signature = repo
+                .signature()
+                .or_else(|_| git2::Signature::now(FALLBACK_COMMITTER.0, FALLBACK_COMMITTER.1))?;
+            repo.commit(Some("HEAD"), &signature, &signature, message, &tree, &[])?;
+        }
+        Ok(index.len())
+    }
+
+    fn export_ignore(&self, repo: &Path) -> Option<ExportIgnoreCheck> {
+        let repo = Repository::open(repo).ok()?;
+        Some(Box::new(move |path| {
+            repo.get_attr(path, "export-ignore", git2::AttrCheckFlags::FILE_THEN_INDEX)
+                .is_ok_and(|value| git2::AttrValue::from_string(value) == git2::AttrValue::True)
+        }))
+    }
+
+    fn config_value(&self, key: &str) -> Option<String> {
+        git2::Config::open_default().ok()?.get_string(key).ok()
+    }
+
+    fn remote_refs(&self, url: &str) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+        set_git_server_timeout();
+        let mut remote = git2::Remote::create_detached(url)?;
+        let mut callbacks = git2::RemoteCallbacks::new();
+        callbacks.credentials(git_credentials(url));
+        let mut proxy_options = git2::ProxyOptions::new();
+        if let Some(proxy) = proxy_for(url, |name| std::env::var(name).ok()) {
+            proxy_options.url(&proxy);
+        }
+        let connection = remote.connect_auth(git2::Direction::Fetch, Some(callbacks), Some(proxy_options))?;
+        let refs = connection
+            .list()?
+            .iter()
+            .map(|head| (head.name().to_string(), head.oid().to_string()))
+            .collect();
+        Ok(refs)
+    }
+}
+
+/// The proxy for an HTTP(S) repository URL from the environment (`HTTPS_PROXY` or `HTTP_PROXY`, then `ALL_PROXY`,
+/// each also lowercase), unless its host is excluded by `NO_PROXY`.  libgit2 only proxies HTTP(S), not SSH.
+fn proxy_for(repo_url: &str, env: impl Fn(&str) -> Option<String>) -> Option<String> {
+    let scheme = match repo_url.split_once("://")?.0 {
+        "https" => "HTTPS_PROXY",
+        "http" => "HTTP_PROXY",
+        _ => return None,
+    };
+    let var = |name: &str| {
+        env(name)
+            .or_else(|| env(&name.to_lowercase()))
+            .filter(|value| !value.trim().is_empty())
+    };
+    let proxy = var(scheme).or_else(|| var("ALL_PROXY"))?;
+    let host = repo_host(repo_url)?.to_lowercase();
+    let excluded = var("NO_PROXY").is_some_and(|no_proxy| {
+        no_proxy.split(',').map(str::trim).filter(|entry| !entry.is_empty()).any(|entry| {
+            let entry = entry
+                .split(':')
+                .next()
+                .unwrap_or(entry)
+                .trim_start_matches('*')
+                .trim_start_matches('.');
+            entry.is_empty() || host == entry.to_lowercase() || host.ends_with(&format!(".{}", entry.to_lowercase()))
+        })
+    });
+    (!excluded).then_some(proxy)
+}
+
+/// Classify a libgit2 error, keeping its class and code in the message.
+fn clone_error(error: &git2::Error) -> CloneError {
+    let kind = match (error.code(), error.class()) {
+        (git2::ErrorCode::Auth, _) => CloneErrorKind::Auth,
+        (git2::ErrorCode::Certificate, _) => CloneErrorKind::Certificate,
+        (git2::ErrorCode::NotFound, _) => CloneErrorKind::NotFound,
+        (_, git2::ErrorClass::Net | git2::ErrorClass::Os | git2::ErrorClass::Http) if error.message().contains("timed out") => {
+            CloneErrorKind::TimedOut
+        }
+        (_, git2::ErrorClass::Http) => {
+            let status = Regex::new(r"status code: (\d{3})")
+                .ok()
+                .and_then(|pattern| pattern.captures(error.message())?[1].parse::<u16>().ok());
+            CloneErrorKind::Http(status)
+        }
+        (_, git2::ErrorClass::Net | git2::ErrorClass::Os | git2::ErrorClass::Ssl | git2::ErrorClass::Ssh) => CloneErrorKind::Network,
+        _ => CloneErrorKind::Other,
+    };
+    let message = format!("{} (class {:?}, code {:?})", error.message(), error.class(), error.code());
+    CloneError::new(kind, message)
+}
+
+/// The credentials callback for `repo_url`: the token of its host from the user configuration over HTTPS, or the
+/// keys of the SSH agent.
+fn git_credentials(repo_url: &str) -> impl FnMut(&str, Option<&str>, git2::CredentialType) -> Result<git2::Cred, git2::Error> {
+    let mut attempts = 0;
+    let token = repo_host(repo_url).and_then(|host| user_config().ok()?.tokens.get(host));
+    move |_, username, allowed| {
+        // libgit2 asks again after rejected credentials; give up instead of retrying forever.
+        attempts += 1;
+        if attempts > 3 {
+            return Err(git2::Error::from_str("credentials were rejected"));
+        }
+        match token {
+            Some(token) if allowed.contains(git2::CredentialType::USER_PASS_PLAINTEXT) => {
+                git2::Cred::userpass_plaintext(username.unwrap_or("x-access-token"), token)
+            }
+            _ if allowed.contains(git2::CredentialType::SSH_KEY) => git2::Cred::ssh_key_from_agent(username.unwrap_or("git")),
+            _ => git2::Cred::default(),
+        }
+    }
+}
+
+/// Clone `url` into `dest` on the worker of [`CloneProgress::watch`], counting what was received in `received`.
+fn clone_repository(
+    url: &str,
+    dest: &Path,
+    git: &GitOptions,
+    received: &(AtomicUsize, AtomicUsize),
+    interrupt: &AtomicBool,
+) -> Result<(), CloneError> {
+    let remote_messages = RefCell::new(String::new());
+    let host_key: RefCell<Option<(String, String)>> = RefCell::new(None);
+    let reported_percent = Cell::new(None);
+    set_git_server_timeout();
+
+    let mut callbacks = git2::RemoteCallbacks::new();
+    callbacks.credentials(git_credentials(url));
+    callbacks.sideband_progress(|data| {
+        let message = String::from_utf8_lossy(data);
+        if git.verbose {
+            print_partial(&format!("remote: {}", message)).ok();
+        }
+        remote_messages.borrow_mut().push_str(&message);
+        received.1.fetch_add(1, Ordering::Relaxed);
+        !interrupt.load(Ordering::Relaxed)
+    });
+    callbacks.transfer_progress(|stats| {
+        // Report every 10%, so that logs of slow clones aren't flooded.
+        let percent = (stats.received_objects() * 100).checked_div(stats.total_objects()).unwrap_or(100) / 10 * 10;
+        if git.verbose && reported_percent.get() != Some(percent) {
+            reported_percent.set(Some(percent));
+            say!(
+                "Receiving objects: {}% ({}/{}), {} KiB",
+                percent,
+                stats.received_objects(),
+                stats.total_objects(),
+                stats.received_bytes() / 1024
+            );
+        }
+        received.0.store(
+            stats.received_bytes() + stats.indexed_objects() + stats.indexed_deltas(),
+            Ordering::Relaxed,
+        );
+        !interrupt.load(Ordering::Relaxed)
+    });
+    callbacks.certificate_check(|cert, host| {
+        let Some(key) = cert.as_hostkey() else {
+            return Ok(git2::CertificateCheckStatus::CertificatePassthrough);
+        };
+        let fingerprint = key.hash_sha256().map_or_else(
+            || "unknown fingerprint".to_string(),
+            |hash| format!("SHA256:{}", base64(hash, false)),
+        );
+        if git.verbose || git.accept_hostkey {
+            say!("SSH host key of {}: {}", host, fingerprint);
+        }
+        *host_key.borrow_mut() = Some((host.to_string(), fingerprint));
+        match git.accept_hostkey {
+            true => Ok(git2::CertificateCheckStatus::CertificateOk),
+            // Let libgit2 check the key against known_hosts.
+            false => Ok(git2::CertificateCheckStatus::CertificatePassthrough),
+        }
+    });
+
+    let mut fetch_options = git2::FetchOptions::new();
+    fetch_options.remote_callbacks(callbacks);
+    if let Some(proxy) = proxy_for(url, |name| std::env::var(name).ok()) {
+        if git.verbose {
+            say!("Using proxy {}", proxy);
+        }
+        let mut proxy_options = git2::ProxyOptions::new();
+        proxy_options.url(&proxy);
+        fetch_options.proxy_options(proxy_options);
+    }
+    let cloned = repo_builder().fetch_options(fetch_options).clone(url, dest);
+    cloned.map(drop).map_err(|e| {
+        let mut error = clone_error(&e);
+        error.remote_messages = remote_messages.take();
+        error.host_key = host_key.take();
+        error
+    })
+}
+
+/// Make libgit2 give up connecting to, reading from or writing to a server after LIBGIT2_SERVER_TIMEOUT, so that the
+/// worker of an abandoned clone doesn't wait for a server that stopped responding forever.  libgit2 has these
+/// settings for the whole process only, so they don't depend on the run: its `timeout` and `stall_timeout` are
+/// enforced by watching the clone.
+fn set_git_server_timeout() {
+    static SET: std::sync::Once = std::sync::Once::new();
+    SET.call_once(|| {
+        let millis = LIBGIT2_SERVER_TIMEOUT.as_millis() as i32;
+        // SAFETY: the settings are changed once, before any clone or listing of references connects to a server.
+        unsafe {
+            git2::opts::set_server_connect_timeout_in_milliseconds(millis).ok();
+            git2::opts::set_server_timeout_in_milliseconds(millis).ok();
+        }
+    });
+}
+
+/// A clone builder that turns on libgit2's long path support (`core.longpaths`) on Windows before the checkout, so
+/// that deeply nested template files can be checked out below the temporary directory.
+fn repo_builder<'cb>() -> git2::build::RepoBuilder<'cb> {
+    let mut builder = git2::build::RepoBuilder::new();
+    if cfg!(windows) {
+        builder.remote_create(|repo, name, url| {
+            repo.config()?.set_bool("core.longpaths", true)?;
+            repo.remote(name, url)
+        });
+    }
+    builder
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test that clones use the proxy from the environment, except for hosts in NO_PROXY.
+    #[test]
+    fn test_proxy_for() -> Result<(), Box<dyn std::error::Error>> {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| vars.iter().find(|(var, _)| *var == name).map(|(_, value)| value.to_string())
+        };
+        let proxied = env(&[
+            ("HTTPS_PROXY", "http://proxy.corp:3128"),
+            ("http_proxy", "http://plain.corp:3128"),
+            ("NO_PROXY", "localhost, .internal.corp,git.example.com:443"),
+        ]);
+        assert_eq!(
+            proxy_for("https://github.com/org/templates.git", proxied),
+            Some("http://proxy.corp:3128".to_string())
+        );
+        assert_eq!(
+            proxy_for("http://github.com/org/templates.git", proxied),
+            Some("http://plain.corp:3128".to_string())
+        );
+        assert_eq!(proxy_for("https://git.internal.corp/templates.git", proxied), None);
+        assert_eq!(proxy_for("https://git.example.com/templates.git", proxied), None);
+        assert_eq!(proxy_for("git@github.com:org/templates.git", proxied), None);
+
+        let everything = env(&[("ALL_PROXY", "http://proxy.corp:3128"), ("no_proxy", "*")]);
+        assert_eq!(proxy_for("https://github.com/org/templates.git", everything), None);
+        assert_eq!(proxy_for("https://github.com/org/templates.git", env(&[])), None);
+        Ok(())
+    }
+
+    // Test how libgit2's errors are classified, to be retried or explained.
+    #[test]
+    fn test_clone_error_kinds() {
+        let error = |code, class, message: &str| clone_error(&git2::Error::new(code, class, message));
+        let generic = git2::ErrorCode::GenericError;
+
+        let auth = error(git2::ErrorCode::Auth, git2::ErrorClass::Http, "authentication required");
+        assert_eq!(auth.kind, CloneErrorKind::Auth);
+        assert_eq!(auth.message, "authentication required (class Http, code Auth)");
+        assert_eq!(
+            error(git2::ErrorCode::Certificate, git2::ErrorClass::Ssh, "bad host key").kind,
+            CloneErrorKind::Certificate
+        );
+        assert_eq!(
+            error(generic, git2::ErrorClass::Http, "unexpected http status code: 404").kind,
+            CloneErrorKind::Http(Some(404))
+        );
+        assert_eq!(
+            error(generic, git2::ErrorClass::Http, "unexpected http status code: 429").kind,
+            CloneErrorKind::Http(Some(429))
+        );
+        assert_eq!(
+            error(generic, git2::ErrorClass::Net, "connection timed out").kind,
+            CloneErrorKind::TimedOut
+        );
+        assert_eq!(
+            error(generic, git2::ErrorClass::Ssl, "handshake failed").kind,
+            CloneErrorKind::Network
+        );
+        assert_eq!(
+            error(generic, git2::ErrorClass::Config, "invalid config").kind,
+            CloneErrorKind::Other
+        );
+    }
+}
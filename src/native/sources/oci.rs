@@ -0,0 +1,450 @@
+//! Templates packaged as OCI artifacts: pulling them from registries and publishing them.
+
+use regex::Regex;
+use std::cell::RefCell;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use super::*;
+
+/// Media types of templates packaged as OCI artifacts.
+const OCI_MANIFEST_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+const OCI_ARTIFACT_TYPE: &str = "application/vnd.scaficionado.template.v1";
+const OCI_LAYER_TYPE: &str = "application/vnd.oci.image.layer.v1.tar+gzip";
+const OCI_EMPTY_TYPE: &str = "application/vnd.oci.empty.v1+json";
+
+/// An `oci://<registry>/<repository>:<tag>` or `oci://<registry>/<repository>@sha256:<digest>` reference to a template
+/// packaged as an OCI artifact.  The tag defaults to "latest".
+#[derive(Debug, PartialEq)]
+struct OciReference {
+    registry: String,
+    repository: String,
+    reference: String,
+}
+
+impl OciReference {
+    fn parse(url: &str) -> Result<OciReference, Box<dyn Error>> {
+        let invalid = || format!("Invalid OCI reference {:?}, expected oci://<registry>/<repository>:<tag>", url);
+        let rest = url.strip_prefix("oci://").ok_or_else(invalid)?;
+        let (registry, path) = rest.split_once('/').ok_or_else(invalid)?;
+        let (repository, reference) = match path.split_once('@') {
+            Some((repository, digest)) => (repository, digest),
+            None => match path.rsplit_once(':') {
+                Some((repository, tag)) if !tag.contains('/') => (repository, tag),
+                _ => (path, "latest"),
+            },
+        };
+        if registry.is_empty() || repository.is_empty() || reference.is_empty() {
+            return Err(invalid().into());
+        }
+        Ok(OciReference {
+            registry: registry.to_string(),
+            repository: repository.to_string(),
+            reference: reference.to_string(),
+        })
+    }
+
+    /// The base URL of the repository's registry API.  Registries on localhost are spoken to over plain HTTP.
+    fn api_url(&self) -> String {
+        let host = self.registry.split(':').next().unwrap_or(&self.registry);
+        let scheme = match host {
+            "localhost" | "127.0.0.1" => "http",
+            _ => "https",
+        };
+        format!("{}://{}/v2/{}", scheme, self.registry, self.repository)
+    }
+}
+
+/// A client of one OCI repository, authenticating when the registry asks: with a bearer token from the registry's
+/// token service, or with the registry's entry in the `tokens` of the user configuration (`user:password` or a
+/// token).
+struct OciClient {
+    agent: ureq::Agent,
+    reference: OciReference,
+    /// The actions to ask a token for, e.g. "pull,push".
+    actions: &'static str,
+    authorization: RefCell<Option<String>>,
+}
+
+impl OciClient {
+    fn new(reference: OciReference, actions: &'static str) -> Result<OciClient, Box<dyn Error>> {
+        Ok(OciClient {
+            agent: http_agent(false)?,
+            reference,
+            actions,
+            authorization: RefCell::new(None),
+        })
+    }
+
+    /// Send a request, made by `request` with the authorization header to use, authenticating and sending it again
+    /// if the registry asks.  Fails unless the response is successful.
+    fn send(
+        &self,
+        request: impl Fn(&ureq::Agent, Option<&str>) -> Result<ureq::http::Response<ureq::Body>, ureq::Error>,
+    ) -> Result<ureq::http::Response<ureq::Body>, Box<dyn Error>> {
+        let mut response = request(&self.agent, self.authorization.borrow().as_deref())?;
+        if response.status() == 401 {
+            let challenge = response
+                .headers()
+                .get("www-authenticate")
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default()
+                .to_string();
+            *self.authorization.borrow_mut() = Some(self.authenticate(&challenge)?);
+            response = request(&self.agent, self.authorization.borrow().as_deref())?;
+        }
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.body_mut().read_to_string().unwrap_or_default();
+            return Err(format!("The OCI registry {} answered {}: {}", self.reference.registry, status, body.trim()).into());
+        }
+        Ok(response)
+    }
+
+    /// Answer an authentication challenge, returning the authorization header to send.
+    fn authenticate(&self, challenge: &str) -> Result<String, Box<dyn Error>> {
+        let credentials = user_config().ok().and_then(|user| {
+            let host = self.reference.registry.split(':').next().unwrap_or_default();
+            user.tokens.get(&self.reference.registry).or_else(|| user.tokens.get(host)).cloned()
+        });
+        let basic = credentials.map(|credentials| match credentials.contains(':') {
+            true => format!("Basic {}", base64(credentials.as_bytes(), true)),
+            false => format!("Basic {}", base64(format!("scaficionado:{}", credentials).as_bytes(), true)),
+        });
+        let Some(parameters) = challenge.strip_prefix("Bearer ") else {
+            return basic.ok_or_else(|| format!("The OCI registry {} requires credentials", self.reference.registry).into());
+        };
+        static PARAMETER: OnceLock<Regex> = OnceLock::new();
+        let parameter = |name: &str| {
+            PARAMETER
+                .get_or_init(|| Regex::new(r#"(\w+)="([^"]*)""#).unwrap())
+                .captures_iter(parameters)
+                .find(|captures| &captures[1] == name)
+                .map(|captures| captures[2].to_string())
+        };
+        let realm = parameter("realm").ok_or("The OCI registry's authentication challenge has no realm")?;
+        let scope = parameter("scope").unwrap_or_else(|| format!("repository:{}:{}", self.reference.repository, self.actions));
+        let mut url = format!("{}?scope={}", realm, query_component(&scope));
+        if let Some(service) = parameter("service") {
+            url.push_str(&format!("&service={}", query_component(&service)));
+        }
+        let mut request = self.agent.get(&url);
+        if let Some(basic) = &basic {
+            request = request.header("Authorization", basic);
+        }
+        let mut response = request.call()?;
+        if !response.status().is_success() {
+            return Err(format!(
+                "Failed to authenticate with the OCI registry {}: {}",
+                self.reference.registry,
+                response.status()
+            )
+            .into());
+        }
+        let token: serde_json::Value = serde_json::from_str(&response.body_mut().read_to_string()?)?;
+        let token = token["token"]
+            .as_str()
+            .or(token["access_token"].as_str())
+            .ok_or("The OCI registry's token service returned no token")?;
+        Ok(format!("Bearer {}", token))
+    }
+
+    /// Download a blob of `size` bytes, as its descriptor declares, into a temporary file, checking its size and
+    /// digest.
+    fn blob(&self, digest: &str, size: u64) -> Result<fs::File, Box<dyn Error>> {
+        let url = format!("{}/blobs/{}", self.reference.api_url(), digest);
+        let response = self.send(|agent, authorization| {
+            let mut request = agent.get(&url);
+            if let Some(authorization) = authorization {
+                request = request.header("Authorization", authorization);
+            }
+            request.call()
+        })?;
+        // One byte more than declared is enough to tell that the blob is larger.
+        let content = io::Read::take(response.into_body().into_reader(), size.saturating_add(1));
+        let (blob, sha256) = spool(digest, content, None)?;
+        if blob.metadata()?.len() != size {
+            return Err(format!("The blob {} of {} doesn't match its size", digest, self.reference.repository).into());
+        }
+        if digest.strip_prefix("sha256:") != Some(&sha256) {
+            return Err(format!("The blob {} of {} doesn't match its digest", digest, self.reference.repository).into());
+        }
+        Ok(blob)
+    }
+
+    /// Upload a blob, returning its descriptor.
+    fn upload(&self, media_type: &str, blob: &[u8]) -> Result<serde_json::Value, Box<dyn Error>> {
+        let digest = format!("sha256:{}", sha256_hex(blob));
+        let start = format!("{}/blobs/uploads/", self.reference.api_url());
+        let response = self.send(|agent, authorization| {
+            let mut request = agent.post(&start);
+            if let Some(authorization) = authorization {
+                request = request.header("Authorization", authorization);
+            }
+            request.send_empty()
+        })?;
+        let location = response
+            .headers()
+            .get("location")
+            .and_then(|value| value.to_str().ok())
+            .ok_or("The OCI registry didn't say where to upload")?;
+        let location = match location.starts_with('/') {
+            true => format!("{}{}", self.reference.api_url().split("/v2/").next().unwrap_or_default(), location),
+            false => location.to_string(),
+        };
+        let separator = if location.contains('?') { '&' } else { '?' };
+        let url = format!("{}{}digest={}", location, separator, digest);
+        self.send(|agent, authorization| {
+            let mut request = agent.put(&url).header("Content-Type", "application/octet-stream");
+            if let Some(authorization) = authorization {
+                request = request.header("Authorization", authorization);
+            }
+            request.send(blob)
+        })?;
+        Ok(serde_json::json!({ "mediaType": media_type, "digest": digest, "size": blob.len() }))
+    }
+}
+
+/// Percent-encode `value` for the query of a URL, leaving only unreserved characters as they are.
+fn query_component(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// A template packaged as an OCI artifact, e.g. `oci://registry.example.com/templates/rust-service:1.2.0`: its first
+/// tar layer is unpacked like an archive.
+pub(crate) struct OciSource {
+    pub(crate) url: String,
+    pub(crate) offline: bool,
+    /// Bytes of the largest layer pulled, if limited.
+    pub(crate) max_size: Option<u64>,
+}
+
+impl ScaffoldSource for OciSource {
+    fn fetch(&self, dest: &Path) -> Result<PathBuf, Box<dyn Error>> {
+        if self.offline {
+            return Err(format!("Cannot pull {} with --offline", self.url).into());
+        }
+        let reference = OciReference::parse(&self.url)?;
+        say!("Pulling {}", self.url);
+        let client = OciClient::new(reference, "pull")?;
+        let url = format!("{}/manifests/{}", client.reference.api_url(), client.reference.reference);
+        let mut response = client.send(|agent, authorization| {
+            let mut request = agent.get(&url).header("Accept", OCI_MANIFEST_TYPE);
+            if let Some(authorization) = authorization {
+                request = request.header("Authorization", authorization);
+            }
+            request.call()
+        })?;
+        let manifest: serde_json::Value = serde_json::from_str(&response.body_mut().read_to_string()?)?;
+        let layer = manifest["layers"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .find(|layer| layer["mediaType"].as_str().is_some_and(|media_type| media_type.contains("tar")))
+            .ok_or_else(|| format!("{} has no tar layer to unpack", self.url))?;
+        let digest = layer["digest"].as_str().ok_or("The layer has no digest")?;
+        let size = layer["size"].as_u64().ok_or("The layer has no size")?;
+        if let Some(max_size) = self.max_size.filter(|max_size| size > *max_size) {
+            return Err(LimitExceeded(format!(
+                "The layer of {} is larger than {} bytes (max_total_bytes)",
+                self.url, max_size
+            ))
+            .into());
+        }
+        let blob = client.blob(digest, size)?;
+        unpack_archive(blob, dest).map_err(|e| format!("Failed to unpack {}: {}", self.url, e))?;
+        Ok(dest.to_path_buf())
+    }
+
+    fn identity(&self) -> String {
+        self.url.clone()
+    }
+}
+
+/// `publish` subcommand: pack the template repository in `dir` (without `.git`) and push it to `reference` as an OCI
+/// artifact with a single gzip-compressed tar layer.
+pub(crate) fn publish_template(dir: &Path, reference: &str) -> Result<(), Box<dyn Error>> {
+    if !dir.is_dir() {
+        return Err(format!("{:?} is not a directory", dir).into());
+    }
+    let client = OciClient::new(OciReference::parse(reference)?, "pull,push")?;
+    let archive = pack_archive(dir)?;
+    say!("Pushing {} ({} KiB) to {}", dir.display(), archive.len() / 1024, reference);
+    let mut layer = client.upload(OCI_LAYER_TYPE, &archive)?;
+    layer["annotations"] = serde_json::json!({ "org.opencontainers.image.title": "template.tar.gz" });
+    let config = client.upload(OCI_EMPTY_TYPE, b"{}")?;
+    let manifest = serde_json::to_vec(&serde_json::json!({
+        "schemaVersion": 2,
+        "mediaType": OCI_MANIFEST_TYPE,
+        "artifactType": OCI_ARTIFACT_TYPE,
+        "config": config,
+        "layers": [layer],
+    }))?;
+    let url = format!("{}/manifests/{}", client.reference.api_url(), client.reference.reference);
+    client.send(|agent, authorization| {
+        let mut request = agent.put(&url).header("Content-Type", OCI_MANIFEST_TYPE);
+        if let Some(authorization) = authorization {
+            request = request.header("Authorization", authorization);
+        }
+        request.send(&manifest)
+    })?;
+    say!("Published {} (sha256:{})", reference, sha256_hex(&manifest));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::fs;
+    use tempfile::TempDir;
+
+    // Serve a minimal OCI registry on localhost that asks for a bearer token, returning its address.
+    fn serve_registry() -> Result<String, Box<dyn std::error::Error>> {
+        use std::io::{Read, Write};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let address = listener.local_addr()?.to_string();
+        let realm = format!("http://{}/token", address);
+        std::thread::spawn(move || {
+            let mut stored: HashMap<String, (String, Vec<u8>)> = HashMap::new();
+            for mut stream in listener.incoming().flatten() {
+                let mut request = Vec::new();
+                let mut buffer = [0; 65536];
+                let header_end = loop {
+                    let read = stream.read(&mut buffer).unwrap_or(0);
+                    request.extend_from_slice(&buffer[..read]);
+                    if let Some(end) = request.windows(4).position(|window| window == b"\r\n\r\n") {
+                        break end + 4;
+                    }
+                    if read == 0 {
+                        break request.len();
+                    }
+                };
+                let head = String::from_utf8_lossy(&request[..header_end]).to_string();
+                let header = |name: &str| {
+                    head.lines()
+                        .find_map(|line| line.split_once(':').filter(|(key, _)| key.eq_ignore_ascii_case(name)))
+                        .map(|(_, value)| value.trim().to_string())
+                };
+                let length: usize = header("content-length").and_then(|length| length.parse().ok()).unwrap_or(0);
+                while request.len() < header_end + length {
+                    let read = stream.read(&mut buffer).unwrap_or(0);
+                    if read == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buffer[..read]);
+                }
+                let body = request[header_end..].to_vec();
+                let mut words = head.split_whitespace();
+                let (method, target) = (words.next().unwrap_or(""), words.next().unwrap_or("/"));
+                let (path, query) = target.split_once('?').unwrap_or((target, ""));
+                let respond = |status: &str, headers: &str, body: &[u8]| {
+                    let mut response = format!(
+                        "HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n",
+                        status,
+                        headers,
+                        body.len()
+                    )
+                    .into_bytes();
+                    response.extend_from_slice(body);
+                    response
+                };
+                let response = if path == "/token" {
+                    // The scope is encoded, e.g. "repository%3Atemplates%2Fservice%3Apull".
+                    match query.contains("scope=repository%3Atemplates%2Fservice%3Apull") {
+                        true => respond("200 OK", "", br#"{"token":"secret"}"#),
+                        false => respond("400 Bad Request", "", b""),
+                    }
+                } else if header("authorization").as_deref() != Some("Bearer secret") {
+                    let challenge = format!("WWW-Authenticate: Bearer realm=\"{}\",service=\"test\"\r\n", realm);
+                    respond("401 Unauthorized", &challenge, b"")
+                } else if method == "POST" {
+                    respond("202 Accepted", "Location: /upload/1\r\n", b"")
+                } else if method == "PUT" && path.starts_with("/upload/") {
+                    let digest = query.strip_prefix("digest=").unwrap_or_default().to_string();
+                    stored.insert(format!("blob {}", digest), (String::new(), body));
+                    respond("201 Created", "", b"")
+                } else if method == "PUT" {
+                    stored.insert(path.to_string(), (header("content-type").unwrap_or_default(), body));
+                    respond("201 Created", "", b"")
+                } else if let Some(digest) = path.rsplit_once("/blobs/").map(|(_, digest)| digest) {
+                    match stored.get(&format!("blob {}", digest)) {
+                        Some((_, blob)) => respond("200 OK", "", blob),
+                        None => respond("404 Not Found", "", b""),
+                    }
+                } else {
+                    match stored.get(path) {
+                        Some((content_type, manifest)) => respond("200 OK", &format!("Content-Type: {}\r\n", content_type), manifest),
+                        None => respond("404 Not Found", "", b""),
+                    }
+                };
+                stream.write_all(&response).ok();
+            }
+        });
+        Ok(address)
+    }
+
+    // Test publishing a template repository to an OCI registry and using it as a scaffold's repo.
+    #[test]
+    fn test_oci_publish_and_pull() -> Result<(), Box<dyn std::error::Error>> {
+        assert_eq!(
+            OciReference::parse("oci://registry.example.com/templates/rust-service:1.2.0")?,
+            OciReference {
+                registry: "registry.example.com".to_string(),
+                repository: "templates/rust-service".to_string(),
+                reference: "1.2.0".to_string(),
+            }
+        );
+        assert_eq!(OciReference::parse("oci://localhost:5000/templates")?.reference, "latest");
+        assert_eq!(OciReference::parse("oci://r.io/t@sha256:abc")?.reference, "sha256:abc");
+        assert!(OciReference::parse("oci://registry.example.com").is_err());
+
+        let template = TempDir::new()?;
+        fs::create_dir_all(template.path().join("templates"))?;
+        fs::create_dir_all(template.path().join(".git"))?;
+        fs::write(template.path().join("templates/README.md.tera"), "# {{ project_name }}")?;
+        fs::write(template.path().join(".git/HEAD"), "ref: refs/heads/main")?;
+        let long_name = format!("templates/{}.txt", "x".repeat(120));
+        fs::write(template.path().join(&long_name), "long")?;
+
+        let reference = format!("oci://{}/templates/service:1.0.0", serve_registry()?);
+        publish_template(template.path(), &reference)?;
+
+        let base = acquire_repo(scaffold_source(&reference, &GitOptions::default()).as_ref(), "Service")?;
+        assert_eq!(fs::read_to_string(base.join("templates/README.md.tera"))?, "# {{ project_name }}");
+        assert_eq!(fs::read_to_string(base.join(&long_name))?, "long");
+        assert!(!base.join(".git").exists());
+        clean_up_persistent_dirs(vec![base])?;
+
+        // Layers larger than the limit of the run aren't pulled.
+        let limited = GitOptions {
+            max_download: Some(10),
+            ..GitOptions::default()
+        };
+        let error = acquire_repo(scaffold_source(&reference, &limited).as_ref(), "Service")
+            .err()
+            .ok_or("the layer is larger than the limit")?;
+        assert!(error.to_string().contains("is larger than 10 bytes (max_total_bytes)"), "{}", error);
+        assert_eq!(query_component("repository:a/b:pull,push"), "repository%3Aa%2Fb%3Apull%2Cpush");
+        assert_eq!(base64(b"fooba", true), "Zm9vYmE=");
+
+        let offline = GitOptions {
+            offline: true,
+            ..GitOptions::default()
+        };
+        let error = acquire_repo(scaffold_source(&reference, &offline).as_ref(), "Service")
+            .err()
+            .ok_or("pulling needs the network")?;
+        assert!(error.to_string().starts_with("Cannot pull"));
+        Ok(())
+    }
+}
@@ -0,0 +1,1892 @@
+//! Rendering the templates of a scaffold: listed files and directories, destinations, and the variables that
+//! templates use.
+
+use crate::render::{error_chain, new_tera, take_sequence_numbers, template_variables};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tera::{Context, Template, Tera};
+
+use super::*;
+
+/// Marker file used to keep otherwise empty directories in template repositories.  It is never copied.
+const KEEP_FILE_NAME: &str = ".keep";
+
+/// File in the templates directory with glob patterns of paths left out of listed directories.  It is never copied.
+const SCAFFOLD_IGNORE_FILE: &str = ".scaffoldignore";
+
+/// Facts about the machine and the run, available to templates as `sys`, e.g. `{{ sys.git_user_name }}`.  Values
+/// that can't be determined are empty.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+struct SystemVariables {
+    os: String,
+    arch: String,
+    hostname: String,
+    username: String,
+    git_user_name: String,
+    git_user_email: String,
+    scaficionado_version: String,
+    /// The date of the run (UTC), e.g. "2025-02-09".
+    date: String,
+    /// The time of the run (UTC), e.g. "2025-02-09T14:03:00Z".
+    datetime: String,
+    year: i64,
+}
+
+/// The system variables, determined once per run.  `SOURCE_DATE_EPOCH` sets the date, for reproducible output.
+fn system_variables() -> &'static SystemVariables {
+    static SYSTEM: OnceLock<SystemVariables> = OnceLock::new();
+    SYSTEM.get_or_init(|| {
+        let env = |names: &[&str]| {
+            names
+                .iter()
+                .find_map(|name| std::env::var(name).ok().filter(|value| !value.is_empty()))
+        };
+        let git = git_library().ok();
+        let git_value = |name: &str| git.and_then(|git| git.config_value(name)).unwrap_or_default();
+        let seconds = env(&["SOURCE_DATE_EPOCH"])
+            .and_then(|epoch| epoch.trim().parse().ok())
+            .unwrap_or_else(|| {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |elapsed| elapsed.as_secs() as i64)
+            });
+        let (year, month, day) = civil_date(seconds.div_euclid(86400));
+        let time = seconds.rem_euclid(86400);
+        let date = format!("{:04}-{:02}-{:02}", year, month, day);
+        SystemVariables {
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            hostname: env(&["HOSTNAME", "COMPUTERNAME"])
+                .or_else(|| fs::read_to_string("/etc/hostname").ok().map(|name| name.trim().to_string()))
+                .unwrap_or_default(),
+            username: env(&["USER", "USERNAME", "LOGNAME"]).unwrap_or_default(),
+            git_user_name: git_value("user.name"),
+            git_user_email: git_value("user.email"),
+            scaficionado_version: env!("CARGO_PKG_VERSION").to_string(),
+            datetime: format!("{}T{:02}:{:02}:{:02}Z", date, time / 3600, time / 60 % 60, time % 60),
+            date,
+            year,
+        }
+    })
+}
+
+/// The (year, month, day) of a number of days since 1970-01-01, in the proleptic Gregorian calendar.
+fn civil_date(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Build the Tera context for a scaffold.  Global variables are inserted first so that
+/// per-scaffold variables with the same name override them.  The system variables are inserted as `sys`, unless
+/// a variable of that name replaces them.
+pub(crate) fn build_context(
+    project_name: &str,
+    global_variables: Option<&HashMap<String, toml::Value>>,
+    scaffold_variables: Option<&HashMap<String, toml::Value>>,
+) -> Context {
+    let mut context = Context::new();
+    context.insert("sys", system_variables());
+    context.insert("project_name", project_name);
+    for vars in [global_variables, scaffold_variables].into_iter().flatten() {
+        for (key, value) in vars {
+            context.insert(key, value);
+        }
+    }
+    context
+}
+
+/// Options that control how templates are rendered and written.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct RenderOptions {
+    /// Overwrite existing files in the output directory.
+    pub(crate) overwrite: bool,
+    /// Fail when a template references a variable that isn't in the context.
+    pub(crate) strict: bool,
+    /// Collect errors and continue with the remaining files and scaffolds instead of stopping.
+    pub(crate) keep_going: bool,
+    /// Warn instead of failing when two scaffolds write the same file.
+    pub(crate) allow_collisions: bool,
+    /// Accept the default of every prompt instead of reading answers from stdin.
+    pub(crate) defaults: bool,
+    /// Ask the LLM of the user configuration for the defaults of the prompts.
+    pub(crate) suggest: bool,
+    /// Line endings of rendered files without their own `line_endings`.
+    pub(crate) line_endings: Option<LineEndings>,
+    /// The limits of the run, or None with --no-limits.
+    pub(crate) limits: Option<Limits>,
+}
+
+/// Render a destination path, with either separator.  When it uses `sequence()` and the number collides with an existing numbered file
+/// in the destination directory (e.g. `V1__add.sql` next to an existing `V1__init.sql`), the next number is used.
+pub(crate) fn render_dest_path(
+    dest: &str,
+    output_base: &Path,
+    context: &Context,
+    options: &RenderOptions,
+) -> Result<PathBuf, Box<dyn Error>> {
+    take_sequence_numbers();
+    loop {
+        let rendered = render_string(dest, &format!("dest `{}`", dest), context, options)?;
+        let dest_path = output_base.join(native_separators(&rendered));
+        let numbers = take_sequence_numbers();
+        match numbers.last() {
+            Some(number) if numbered_file_collides(&dest_path, number) => {
+                say!(
+                    "Skipping sequence number {} for {:?}: already used in the destination",
+                    number,
+                    dest
+                );
+            }
+            _ => return Ok(dest_path),
+        }
+    }
+}
+
+/// Check that a rendered destination stays inside the output directory: it must not be absolute or climb out of
+/// it with `..`.  Symbolic links aren't resolved.
+fn check_inside_output(dest: &Path, output_base: &Path) -> Result<(), Box<dyn Error>> {
+    let escapes = match dest.strip_prefix(output_base) {
+        Ok(relative) => {
+            let mut depth = 0;
+            relative.components().any(|component| {
+                match component {
+                    Component::ParentDir => depth -= 1,
+                    Component::Normal(_) => depth += 1,
+                    _ => {}
+                }
+                depth < 0
+            })
+        }
+        Err(_) => true,
+    };
+    if escapes {
+        return Err(format!(
+            "Destination {:?} is outside the output directory {:?}; set allow_outside_output = true on the scaffold to allow it",
+            dest, output_base
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Check whether another file in the directory of `path` uses the same number at the same place, e.g.
+/// `V1__init.sql` for `V1__add.sql`.  The file itself existing is not a collision.
+fn numbered_file_collides(path: &Path, number: &str) -> bool {
+    let (Some(parent), Some(name)) = (path.parent(), path.file_name().and_then(|name| name.to_str())) else {
+        return false;
+    };
+    let Some(position) = name.find(number) else {
+        return false;
+    };
+    let prefix = &name[..position + number.len()];
+    let Ok(entries) = fs::read_dir(parent) else {
+        return false;
+    };
+    entries.filter_map(Result::ok).any(|entry| {
+        let other = entry.file_name().to_string_lossy().into_owned();
+        other != name && other.starts_with(prefix) && !other[prefix.len()..].starts_with(|c: char| c.is_ascii_digit())
+    })
+}
+
+/// The files written by render_templates, plus the per-file errors collected with --keep-going.
+#[derive(Debug, Default)]
+pub(crate) struct RenderResult {
+    pub(crate) written: Vec<(PathBuf, WriteStatus)>,
+    pub(crate) errors: Vec<FileError>,
+}
+
+/// An error rendering or copying a single template file.
+#[derive(Debug)]
+pub(crate) struct FileError {
+    pub(crate) file: String,
+    pub(crate) message: String,
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.file, self.message)
+    }
+}
+
+impl Error for FileError {}
+
+impl RenderResult {
+    /// Record the error of a single file, or return it attributed to the file when errors aren't being collected.
+    pub(crate) fn record(&mut self, file: &str, result: Result<(), Box<dyn Error>>, options: &RenderOptions) -> Result<(), Box<dyn Error>> {
+        match result {
+            // Exceeding a limit or cancelling stops the run, and the error names the file if it's about one.
+            Err(e) if e.is::<LimitExceeded>() || e.is::<Cancelled>() => Err(e),
+            Err(e) if options.keep_going => {
+                let label = Style::Error.paint(&format!("{:<13}", "! error"), &io::stdout());
+                say!("{} {}: {}", label, file, error_chain(e.as_ref()));
+                self.errors.push(FileError {
+                    file: file.to_string(),
+                    message: error_chain(e.as_ref()),
+                });
+                Ok(())
+            }
+            // Errors of files in a listed directory are already attributed to their file.
+            Err(e) if e.is::<FileError>() => Err(e),
+            Err(e) => Err(Box::new(FileError {
+                file: file.to_string(),
+                message: error_chain(e.as_ref()),
+            })),
+            Ok(()) => Ok(()),
+        }
+    }
+}
+
+/// The settings process_directory applies to the files of a listed directory.
+struct ListedDirectory<'a> {
+    /// The template library, with every `.tera` file of the templates directory.
+    tera: &'a Tera,
+    templates_dir: &'a Path,
+    template: &'a TemplateConfig,
+    exclude: &'a Exclude<'a>,
+    /// The postprocess steps of the directory's entry.
+    steps: &'a [PostProcess],
+    /// The line endings of the directory's entry, or of `[project]`.
+    line_endings: Option<LineEndings>,
+}
+
+fn process_directory(
+    src_dir: &Path,
+    dest_dir: &Path,
+    context: &Context,
+    listed: &ListedDirectory,
+    options: &RenderOptions,
+    result: &mut RenderResult,
+) -> Result<(), Box<dyn Error>> {
+    let overwrite = options.overwrite;
+    for entry in read_dir(src_dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let relative = path.strip_prefix(src_dir)?;
+        let dest_path = dest_dir.join(relative);
+        if listed.exclude.matches(&path) {
+            continue;
+        }
+        if path.is_dir() {
+            create_dir(&dest_path)?;
+            process_directory(&path, &dest_path, context, listed, options, result)?;
+        } else if path
+            .file_name()
+            .is_some_and(|name| name == KEEP_FILE_NAME || name == SCAFFOLD_IGNORE_FILE)
+        {
+            // .keep files only exist so that empty directories can be committed to git.
+            continue;
+        } else {
+            let outcome = (|| {
+                let raw = listed.template.is_raw(&library_key(&path, listed.templates_dir));
+                if let Some(ext) = path.extension().filter(|_| !raw) {
+                    if ext == "tera" {
+                        let content = listed.template.translate(TemplateEngine::Tera, &read_template_file(&path)?);
+                        let location = path.to_string_lossy();
+                        let key = library_key(&path, listed.templates_dir);
+                        let rendered = match listed.tera.get_template(&key) {
+                            Ok(template) => {
+                                if options.strict {
+                                    check_undefined_variables(template, &content, &location, context)?;
+                                }
+                                listed.tera.render(&key, context)?
+                            }
+                            // Files that didn't parse aren't in the library; rendering them reports the error.
+                            Err(_) => render_string(&content, &location, context, options)?,
+                        };
+                        let rendered = finish_rendered(listed.steps, listed.line_endings, rendered);
+                        let dest_file = dest_path.with_extension(""); // remove .tera extension
+                        let status = write_file(&dest_file, rendered.as_bytes(), overwrite)?;
+                        result.written.push((dest_file, status));
+                        return Ok(());
+                    }
+                }
+                let status = copy_file(&path, &dest_path, overwrite)?;
+                result.written.push((dest_path.clone(), status));
+                Ok(())
+            })();
+            result.record(&path.to_string_lossy(), outcome, options)?;
+        }
+    }
+    Ok(())
+}
+
+/// Paths left out when a listed directory is copied: the `exclude` patterns of the scaffold and the patterns in
+/// the `.scaffoldignore` file of the templates directory, matched against paths relative to it.
+struct Exclude<'a> {
+    templates_dir: &'a Path,
+    patterns: Vec<glob::Pattern>,
+    export_ignore: ExportIgnore,
+}
+
+impl<'a> Exclude<'a> {
+    fn load(templates_dir: &'a Path, exclude: &[String]) -> Result<Self, Box<dyn Error>> {
+        let ignore_file = templates_dir.join(SCAFFOLD_IGNORE_FILE);
+        let ignored = match ignore_file.is_file() {
+            true => read_text_file(&ignore_file)?,
+            false => String::new(),
+        };
+        // Blank lines and comments in .scaffoldignore are skipped.
+        let ignored = ignored
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+        let patterns = exclude
+            .iter()
+            .map(String::as_str)
+            .chain(ignored)
+            .map(|pattern| glob::Pattern::new(pattern).map_err(|e| format!("Invalid exclude pattern `{}`: {}", pattern, e)))
+            .collect::<Result<_, _>>()?;
+        Ok(Exclude {
+            templates_dir,
+            patterns,
+            export_ignore: ExportIgnore::load(templates_dir),
+        })
+    }
+
+    /// Whether `path` is excluded.  A directory is also excluded by patterns for everything in it, e.g. "docs/**".
+    fn matches(&self, path: &Path) -> bool {
+        let Ok(relative) = path.strip_prefix(self.templates_dir) else {
+            return false;
+        };
+        let relative = relative.to_string_lossy().replace('\\', "/");
+        let contents = format!("{}/**", relative);
+        self.patterns
+            .iter()
+            .any(|pattern| pattern.matches(&relative) || (path.is_dir() && pattern.as_str() == contents))
+            || self.export_ignore.matches(path)
+    }
+}
+
+/// The `export-ignore` attributes of the git repository a template directory is in: what `git archive` leaves out
+/// of the repository, such as its own test fixtures and CI files, isn't copied into generated projects either.
+struct ExportIgnore {
+    /// The attribute check of the repository, and its canonical working directory.
+    repo: Option<(ExportIgnoreCheck, PathBuf)>,
+}
+
+impl ExportIgnore {
+    fn load(templates_dir: &Path) -> Self {
+        let repo = git_library().ok().and_then(|library| {
+            let workdir = library.discover(templates_dir)?;
+            Some((library.export_ignore(&workdir)?, canonical_path(workdir).ok()?))
+        });
+        ExportIgnore { repo }
+    }
+
+    /// Whether `path` has the `export-ignore` attribute, from a `.gitattributes` file or the index.
+    fn matches(&self, path: &Path) -> bool {
+        let Some((export_ignore, workdir)) = &self.repo else {
+            return false;
+        };
+        let Some(relative) = canonical_path(path)
+            .ok()
+            .and_then(|path| Some(path.strip_prefix(workdir).ok()?.to_path_buf()))
+        else {
+            return false;
+        };
+        export_ignore(&relative)
+    }
+}
+
+/// The `.tera` files under the templates directory, keyed by their path relative to it (with `/`), for includes,
+/// imports and inheritance.  Hidden directories such as `.git` are skipped.
+pub(crate) fn template_library(templates_dir: &Path, template: &TemplateConfig) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+    fn collect(
+        dir: &Path,
+        templates_dir: &Path,
+        template: &TemplateConfig,
+        library: &mut Vec<(String, String)>,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut entries = read_dir(dir)?.map(|entry| entry.map(|e| e.path())).collect::<Result<Vec<_>, _>>()?;
+        entries.sort();
+        for path in entries {
+            if path.is_dir() {
+                if !path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.')) {
+                    collect(&path, templates_dir, template, library)?;
+                }
+            } else if path.extension().is_some_and(|ext| ext == "tera") && !template.is_raw(&library_key(&path, templates_dir)) {
+                // Unreadable and oversized files are only reported when they're rendered.
+                if let Ok(source) = read_template_file(&path) {
+                    library.push((library_key(&path, templates_dir), template.translate(TemplateEngine::Tera, &source)));
+                }
+            }
+        }
+        Ok(())
+    }
+    let mut library = Vec::new();
+    if templates_dir.is_dir() {
+        collect(templates_dir, templates_dir, template, &mut library)?;
+    }
+    Ok(library)
+}
+
+/// The name a file under the templates directory is registered under in the template library.
+fn library_key(path: &Path, templates_dir: &Path) -> String {
+    path.strip_prefix(templates_dir)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .replace('\\', "/")
+}
+
+/// Get the name a listed `.tera` file is registered under in Tera.
+fn template_key(src: &str) -> &str {
+    src.strip_prefix("templates/").unwrap_or(src)
+}
+
+/// Render and copy all template files of a scaffold, returning the status of every destination file.
+/// With `options.keep_going`, per-file errors are collected in the result instead of returned.
+pub(crate) fn render_templates(
+    templates_dir: &Path,
+    output_base: &Path,
+    scaffold: &Scaffold,
+    context_data: &Context,
+    options: &RenderOptions,
+) -> Result<RenderResult, Box<dyn Error>> {
+    let overwrite = options.overwrite;
+    let mut tera = new_tera();
+    let mut result = RenderResult::default();
+    let exclude = Exclude::load(templates_dir, &scaffold.template.exclude)?;
+
+    // Register individual template files (".tera" files unless an engine is set); files that fail to parse are
+    // reported once.
+    let mut unparsable: HashSet<&str> = HashSet::new();
+    let mut listed = Vec::new();
+    for file in &scaffold.template.files {
+        let src_path = templates_dir.join(&file.src);
+        let engine = scaffold.template.engine(file);
+        if src_path.is_file() && engine != TemplateEngine::None {
+            match read_template_file(&src_path) {
+                Ok(source) => listed.push((file, scaffold.template.translate(engine, &source))),
+                Err(e) => {
+                    unparsable.insert(&file.src);
+                    result.record(&file.src, Err(e), options)?;
+                }
+            }
+        }
+    }
+    // Every other .tera file under the templates directory is registered too, so that templates can include, import
+    // and extend each other.  They're added at once because a template can only be added after the one it extends.
+    let mut library = template_library(templates_dir, &scaffold.template)?;
+    let batch = library
+        .iter()
+        .map(|(key, source)| (key.as_str(), source.as_str()))
+        .chain(listed.iter().map(|(file, source)| (template_key(&file.src), source.as_str())));
+    if tera.add_raw_templates(batch).is_err() {
+        // Leave out what doesn't parse, reporting the listed files, and try again.
+        tera = new_tera();
+        library.retain(|(key, source)| Template::new(key, None, source).is_ok());
+        for (file, source) in std::mem::take(&mut listed) {
+            match Template::new(template_key(&file.src), None, &source) {
+                Ok(_) => listed.push((file, source)),
+                Err(e) => {
+                    unparsable.insert(&file.src);
+                    result.record(&file.src, Err(e.into()), options)?;
+                }
+            }
+        }
+        let batch = library
+            .iter()
+            .map(|(key, source)| (key.as_str(), source.as_str()))
+            .chain(listed.iter().map(|(file, source)| (template_key(&file.src), source.as_str())));
+        let registered = tera.add_raw_templates(batch);
+        result.record(&templates_dir.to_string_lossy(), registered.map_err(Into::into), options)?;
+    }
+
+    for file in &scaffold.template.files {
+        if unparsable.contains(file.src.as_str()) {
+            continue;
+        }
+        // With for_each, the entry is rendered once per item of a list variable, with the item as `item`.
+        let contexts = match for_each_contexts(file, context_data) {
+            Ok(contexts) => contexts,
+            Err(e) => {
+                result.record(file.dir.as_deref().unwrap_or(&file.src), Err(e), options)?;
+                continue;
+            }
+        };
+        for context_data in contexts.iter().map(Cow::as_ref) {
+            if let Some(dir) = &file.dir {
+                let outcome = (|| {
+                    let dir_path = output_base.join(render_string(dir, &format!("dir `{}`", dir), context_data, options)?);
+                    if !scaffold.allow_outside_output {
+                        check_inside_output(&dir_path, output_base)?;
+                    }
+                    result.written.push(create_empty_dir(&dir_path)?);
+                    Ok(())
+                })();
+                result.record(dir, outcome, options)?;
+                continue;
+            }
+
+            let outcome = (|| {
+                let dest_path = render_dest_path(&scaffold.template.dest(file), output_base, context_data, options)?;
+                if !scaffold.allow_outside_output {
+                    check_inside_output(&dest_path, output_base)?;
+                }
+                let src_path = templates_dir.join(&file.src);
+
+                if src_path.is_dir() {
+                    if file.engine.is_some() {
+                        return Err(format!("`engine` is only supported for files, but `{}` is a directory", file.src).into());
+                    }
+                    say!("Processing directory: {:?}", src_path);
+                    create_dir(&dest_path)?;
+                    let listed = ListedDirectory {
+                        tera: &tera,
+                        templates_dir,
+                        template: &scaffold.template,
+                        exclude: &exclude,
+                        steps: &file.postprocess,
+                        line_endings: file.line_endings.or(options.line_endings),
+                    };
+                    return process_directory(&src_path, &dest_path, context_data, &listed, options, &mut result);
+                }
+
+                if dest_path.exists() && !overwrite {
+                    report_write_status(&dest_path, WriteStatus::Skipped);
+                    result.written.push((dest_path, WriteStatus::Skipped));
+                    return Ok(());
+                }
+
+                let engine = scaffold.template.engine(file);
+                if engine != TemplateEngine::None {
+                    let key = template_key(&file.src);
+                    if options.strict {
+                        let source = scaffold.template.translate(engine, &read_text_file(&src_path)?);
+                        check_undefined_variables(tera.get_template(key)?, &source, &src_path.to_string_lossy(), context_data)?;
+                    }
+                    let rendered = finish_rendered(
+                        &file.postprocess,
+                        file.line_endings.or(options.line_endings),
+                        tera.render(key, context_data)?,
+                    );
+                    let status = write_file(&dest_path, rendered.as_bytes(), overwrite)?;
+                    result.written.push((dest_path, status));
+                } else {
+                    let status = copy_file(&src_path, &dest_path, overwrite)?;
+                    result.written.push((dest_path, status));
+                }
+                Ok(())
+            })();
+            result.record(&file.src, outcome, options)?;
+        }
+    }
+    Ok(result)
+}
+
+/// The contexts a file entry is rendered with: one per item of its `for_each` list, or just `context`.
+fn for_each_contexts<'a>(file: &TemplateFile, context: &'a Context) -> Result<Vec<Cow<'a, Context>>, Box<dyn Error>> {
+    let Some(name) = &file.for_each else {
+        return Ok(vec![Cow::Borrowed(context)]);
+    };
+    let items = match context.get(name) {
+        Some(tera::Value::Array(items)) => items,
+        Some(other) => return Err(format!("`for_each` variable `{}` must be a list, got {}", name, other).into()),
+        None => return Err(format!("`for_each` variable `{}` is not defined", name).into()),
+    };
+    Ok(items
+        .iter()
+        .map(|item| {
+            let mut context = context.clone();
+            context.insert("item", item);
+            Cow::Owned(context)
+        })
+        .collect())
+}
+
+/// A template directory where every file is a template, as used by the cookiecutter and cargo-generate formats.
+/// Patterns match paths relative to the template root, before rendering.
+pub(crate) struct TemplateTree<'a> {
+    pub(crate) template_root: &'a Path,
+    /// Paths that are never copied.
+    pub(crate) ignored: Vec<glob::Pattern>,
+    /// Paths copied without rendering their content or name.
+    pub(crate) verbatim: Vec<glob::Pattern>,
+    /// If not empty, only the content of these files is rendered; other files are copied as-is.
+    pub(crate) rendered_only: Vec<glob::Pattern>,
+    /// Suffix removed from rendered file names, e.g. ".liquid".
+    pub(crate) strip_suffix: Option<&'a str>,
+    /// Translates the template syntax of the format to Tera.
+    pub(crate) translate: fn(&str) -> String,
+    export_ignore: ExportIgnore,
+}
+
+impl<'a> TemplateTree<'a> {
+    pub(crate) fn new(template_root: &'a Path, translate: fn(&str) -> String) -> Self {
+        TemplateTree {
+            template_root,
+            ignored: Vec::new(),
+            verbatim: Vec::new(),
+            rendered_only: Vec::new(),
+            strip_suffix: None,
+            translate,
+            export_ignore: ExportIgnore::load(template_root),
+        }
+    }
+}
+
+/// Render a single file or directory of a template tree into `dest_dir`.
+pub(crate) fn render_tree_path(
+    tree: &TemplateTree,
+    src: &Path,
+    dest_dir: &Path,
+    context: &Context,
+    options: &RenderOptions,
+    result: &mut RenderResult,
+) -> Result<(), Box<dyn Error>> {
+    let relative = src.strip_prefix(tree.template_root)?.to_string_lossy().into_owned();
+    let matches = |patterns: &[glob::Pattern]| patterns.iter().any(|pattern| pattern.matches(&relative));
+    if matches(&tree.ignored) || tree.export_ignore.matches(src) {
+        return Ok(());
+    }
+    let verbatim = matches(&tree.verbatim);
+    let render_content = !verbatim && (tree.rendered_only.is_empty() || matches(&tree.rendered_only));
+
+    let outcome = (|| {
+        let name = src.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let mut dest_name = match verbatim {
+            true => name,
+            false => render_string(&(tree.translate)(&name), &relative, context, options)?,
+        };
+        if let Some(suffix) = tree.strip_suffix.filter(|_| src.is_file()) {
+            dest_name = dest_name.strip_suffix(suffix).map(str::to_string).unwrap_or(dest_name);
+        }
+        let dest_path = dest_dir.join(dest_name);
+
+        if src.is_dir() {
+            create_dir(&dest_path)?;
+            let mut entries = read_dir(src)?.map(|entry| entry.map(|e| e.path())).collect::<Result<Vec<_>, _>>()?;
+            entries.sort();
+            for entry in entries {
+                render_tree_path(tree, &entry, &dest_path, context, options, result)?;
+            }
+            return Ok(());
+        }
+
+        let text = match render_content {
+            true => read_tree_text(src)?,
+            false => None,
+        };
+        let status = match text {
+            Some(text) => {
+                let rendered = render_string(&(tree.translate)(&text), &relative, context, options)?;
+                write_file(&dest_path, rendered.as_bytes(), options.overwrite)?
+            }
+            // Binary files and files that are not rendered are copied as-is.
+            None => copy_file(src, &dest_path, options.overwrite)?,
+        };
+        result.written.push((dest_path, status));
+        Ok(())
+    })();
+    result.record(&relative, outcome, options)
+}
+
+/// The text of a file of a template tree, or `None` for a binary file, which is copied as-is.  Text files larger than
+/// the max_template_size of the run are refused; larger files with a NUL byte in their first 8000 bytes are binary,
+/// as for git, without reading the rest.
+pub(crate) fn read_tree_text(path: &Path) -> Result<Option<String>, Box<dyn Error>> {
+    let size = fs::metadata(path).map(|metadata| metadata.len()).unwrap_or_default();
+    if max_template_size().is_some_and(|max| size > max) {
+        let mut start = Vec::new();
+        fs::File::open(path)
+            .and_then(|file| file.take(8000).read_to_end(&mut start))
+            .map_err(|source| FsError::Read {
+                path: path.to_path_buf(),
+                source,
+            })?;
+        if start.contains(&0) {
+            return Ok(None);
+        }
+        return read_template_file(path).map(Some);
+    }
+    Ok(String::from_utf8(read_file(path)?).ok())
+}
+
+/// Parse a Tera template and return every variable it reads from the context, mapped to whether it
+/// is required.  Variables only used with a leading `default` filter or in `is defined` tests are optional.
+///
+/// ```
+/// let variables = scaficionado::referenced_variables("{{ name }} {{ port | default(value=80) }}").unwrap();
+/// assert_eq!(variables["name"], true);
+/// assert_eq!(variables["port"], false);
+/// ```
+pub fn referenced_variables(source: &str) -> Result<BTreeMap<String, bool>, Box<dyn Error>> {
+    let template = Template::new("__scaficionado_introspection", None, source)?;
+    Ok(template_variables(&template))
+}
+
+/// Collect every templated string of a scaffold as (location, source) pairs: destinations,
+/// directory entries, listed `.tera` files and `.tera` files inside listed directories.
+pub(crate) fn scaffold_template_sources(templates_dir: &Path, scaffold: &Scaffold) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+    let mut sources = Vec::new();
+    for file in &scaffold.template.files {
+        let first = sources.len();
+        if let Some(dir) = &file.dir {
+            sources.push((format!("dir `{}`", dir), dir.clone()));
+        } else {
+            let dest = scaffold.template.dest(file);
+            sources.push((format!("dest `{}`", dest), dest));
+            let src_path = templates_dir.join(&file.src);
+            if src_path.is_dir() {
+                collect_directory_template_sources(&src_path, templates_dir, &scaffold.template, &mut sources)?;
+            } else if scaffold.template.engine(file) != TemplateEngine::None {
+                let source = scaffold
+                    .template
+                    .translate(scaffold.template.engine(file), &fs::read_to_string(&src_path)?);
+                sources.push((file.src.clone(), source));
+            }
+        }
+        // for_each entries read their list and `item` as in a loop over the list, on the same line.
+        if let Some(name) = &file.for_each {
+            for (_, source) in &mut sources[first..] {
+                *source = format!("{{% for item in {} %}}{}{{% endfor %}}", name, source);
+            }
+        }
+    }
+    Ok(sources)
+}
+
+fn collect_directory_template_sources(
+    dir: &Path,
+    templates_dir: &Path,
+    template: &TemplateConfig,
+    sources: &mut Vec<(String, String)>,
+) -> Result<(), Box<dyn Error>> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_directory_template_sources(&path, templates_dir, template, sources)?;
+        } else if path.extension().is_some_and(|ext| ext == "tera") && !template.is_raw(&library_key(&path, templates_dir)) {
+            let source = template.translate(TemplateEngine::Tera, &fs::read_to_string(&path)?);
+            sources.push((path.to_string_lossy().to_string(), source));
+        }
+    }
+    Ok(())
+}
+
+/// Find the first (1-based) line of `source` that mentions `name` as a whole word.
+fn find_variable_line(source: &str, name: &str) -> Option<usize> {
+    let is_ident_char = |c: char| c.is_alphanumeric() || c == '_';
+    source
+        .lines()
+        .position(|line| {
+            line.match_indices(name).any(|(start, _)| {
+                let before = line[..start].chars().next_back();
+                let after = line[start + name.len()..].chars().next();
+                !before.is_some_and(is_ident_char) && !after.is_some_and(is_ident_char)
+            })
+        })
+        .map(|index| index + 1)
+}
+
+/// Return an error naming every required variable of `template` that is missing from `context`.
+pub(crate) fn check_undefined_variables(
+    template: &Template,
+    source: &str,
+    location: &str,
+    context: &Context,
+) -> Result<(), Box<dyn Error>> {
+    let missing: Vec<String> = template_variables(template)
+        .into_iter()
+        .filter(|(name, required)| *required && !context.contains_key(name))
+        .map(|(name, _)| match find_variable_line(source, &name) {
+            Some(line) => format!("`{}` at {}:{}", name, location, line),
+            None => format!("`{}` in {}", name, location),
+        })
+        .collect();
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("Undefined variable(s) in strict mode: {}", missing.join(", ")).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::native::testing::git;
+    use crate::render::{error_chain, template_variables};
+    use std::collections::{BTreeMap, HashMap};
+    use std::fs;
+    use std::io;
+    use tempfile::TempDir;
+    use tera::{Context, Template, Tera};
+
+    // Test that global variables are loaded and overridden by scaffold variables.
+    #[test]
+    fn test_global_variables_merged_into_context() -> Result<(), Box<dyn std::error::Error>> {
+        let toml_content = r#"
+[variables]
+team = "platform"
+environment = "production"
+
+[[scaffolds]]
+name = "Local"
+repo = "local_repo"
+
+[scaffolds.template]
+files = []
+
+[scaffolds.variables]
+environment = "development"
+"#;
+        let config: Config = toml::from_str(toml_content)?;
+        let context = build_context("GlobalProject", config.variables.as_ref(), config.scaffolds[0].variables.as_ref());
+        let rendered = Tera::one_off("{{ project_name }}/{{ team }}/{{ environment }}", &context, false)?;
+        assert_eq!(rendered, "GlobalProject/platform/development");
+        Ok(())
+    }
+
+    // Test that the system variables are in the context as `sys`, and that the run date is computed correctly.
+    #[test]
+    fn test_system_variables() -> Result<(), Box<dyn std::error::Error>> {
+        assert_eq!(civil_date(0), (1970, 1, 1));
+        assert_eq!(civil_date(20128), (2025, 2, 9));
+        assert_eq!(civil_date(11016), (2000, 2, 29));
+        assert_eq!(civil_date(-1), (1969, 12, 31));
+
+        let context = build_context("SysProject", None, None);
+        let rendered = Tera::one_off("{{ sys.os }}/{{ sys.arch }}/{{ sys.scaficionado_version }}", &context, false)?;
+        assert_eq!(
+            rendered,
+            format!("{}/{}/{}", std::env::consts::OS, std::env::consts::ARCH, env!("CARGO_PKG_VERSION"))
+        );
+        let sys = system_variables();
+        assert!(sys.datetime.starts_with(&sys.date) && sys.date.starts_with(&sys.year.to_string()));
+
+        // A variable named `sys` replaces them.
+        let variables = HashMap::from([("sys".to_string(), toml::Value::String("custom".to_string()))]);
+        let context = build_context("SysProject", Some(&variables), None);
+        assert_eq!(Tera::one_off("{{ sys }}", &context, false)?, "custom");
+        Ok(())
+    }
+
+    // Test the render_templates function.
+    #[test]
+    fn test_render_templates() -> Result<(), Box<dyn std::error::Error>> {
+        // Create temporary directories for templates and output.
+        let templates_dir = TempDir::new()?;
+        let output_dir = TempDir::new()?;
+
+        // Write a simple Tera template file with a placeholder.
+        let template_content = "Hello, {{ project_name }}!";
+        let template_file_path = templates_dir.path().join("greeting.txt.tera");
+        fs::write(&template_file_path, template_content)?;
+
+        // Build a Scaffold instance that uses this template.
+        let scaffold = Scaffold {
+            name: Some("Test".to_string()),
+            repo: "local_repo".into(),          // Not used in this test.
+            template_dir: Some("".to_string()), // Use the root of the templates_dir.
+            template: TemplateConfig {
+                files: vec![TemplateFile {
+                    src: "greeting.txt.tera".to_string(),
+                    dest: "greeting.txt".to_string(),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        // Create a Tera context and insert a value for project_name.
+        let mut context = Context::new();
+        context.insert("project_name", "TestProject");
+
+        // Render the template.
+        render_templates(templates_dir.path(), output_dir.path(), &scaffold, &context, &overwrite_options())?;
+
+        // Verify the rendered output.
+        let output_file_path = output_dir.path().join("greeting.txt");
+        let rendered_content = fs::read_to_string(output_file_path)?;
+        assert_eq!(rendered_content, "Hello, TestProject!");
+
+        Ok(())
+    }
+
+    // Test that destinations outside the output directory are rejected unless the scaffold allows them.
+    #[test]
+    fn test_destination_outside_output() -> Result<(), Box<dyn std::error::Error>> {
+        let templates_dir = TempDir::new()?;
+        let root = TempDir::new()?;
+        let output_base = root.path().join("out");
+        fs::write(templates_dir.path().join("file.txt"), "content")?;
+        let mut context = Context::new();
+        context.insert("dir", "../escaped");
+
+        let scaffold = |dest: &str, allow_outside_output: bool| Scaffold {
+            template: TemplateConfig {
+                files: vec![TemplateFile {
+                    src: "file.txt".to_string(),
+                    dest: dest.to_string(),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+            allow_outside_output,
+            ..Default::default()
+        };
+        let outside = root.path().join("outside.txt").to_string_lossy().into_owned();
+        for dest in ["{{ dir }}/file.txt", "a/../../file.txt", outside.as_str()] {
+            let error = render_templates(
+                templates_dir.path(),
+                &output_base,
+                &scaffold(dest, false),
+                &context,
+                &overwrite_options(),
+            )
+            .unwrap_err();
+            assert!(error.to_string().contains("is outside the output directory"), "{}", error);
+        }
+        assert!(!root.path().join("escaped").exists());
+        assert!(!root.path().join("file.txt").exists());
+
+        // Climbing back into the output directory is fine.
+        render_templates(
+            templates_dir.path(),
+            &output_base,
+            &scaffold("a/../b.txt", false),
+            &context,
+            &overwrite_options(),
+        )?;
+        assert!(output_base.join("b.txt").exists());
+        render_templates(
+            templates_dir.path(),
+            &output_base,
+            &scaffold(&outside, true),
+            &context,
+            &overwrite_options(),
+        )?;
+        assert_eq!(fs::read_to_string(&outside)?, "content");
+        Ok(())
+    }
+
+    // Test that templates can include, import and extend unlisted .tera files of the templates directory.
+    #[test]
+    fn test_template_library() -> Result<(), Box<dyn std::error::Error>> {
+        let templates_dir = TempDir::new()?;
+        let output_dir = TempDir::new()?;
+        let write = |path: &str, content: &str| -> Result<(), Box<dyn std::error::Error>> {
+            let path = templates_dir.path().join(path);
+            fs::create_dir_all(path.parent().ok_or("no parent")?)?;
+            Ok(fs::write(path, content)?)
+        };
+        write("partials/header.tera", "# {{ project_name }}")?;
+        write("macros.tera", "{% macro badge(name) %}[{{ name }}]{% endmacro badge %}")?;
+        write("layouts/base.tera", "{% block body %}{% endblock body %}!")?;
+        write("README.md.tera", "{% include \"partials/header.tera\" %}")?;
+        write(
+            "page.html.tera",
+            "{% extends \"layouts/base.tera\" %}{% import \"macros.tera\" as m %}{% block body %}{{ m::badge(name=project_name) }}{% endblock body %}",
+        )?;
+        write("docs/index.md.tera", "{% include \"partials/header.tera\" %} docs")?;
+        write(".git/hooks/broken.tera", "{% if %}")?;
+
+        let scaffold: Scaffold = from_toml(
+            r#"
+repo = "."
+template.files = [
+  { src = "README.md.tera", dest = "README.md" },
+  { src = "page.html.tera", dest = "page.html" },
+  { src = "docs", dest = "docs" },
+]
+"#,
+        )?;
+        let mut context = Context::new();
+        context.insert("project_name", "demo");
+        render_templates(templates_dir.path(), output_dir.path(), &scaffold, &context, &overwrite_options())?;
+        assert_eq!(fs::read_to_string(output_dir.path().join("README.md"))?, "# demo");
+        assert_eq!(fs::read_to_string(output_dir.path().join("page.html"))?, "[demo]!");
+        assert_eq!(fs::read_to_string(output_dir.path().join("docs/index.md"))?, "# demo docs");
+
+        // A broken unlisted file doesn't fail the files that don't use it.
+        write("partials/unused.tera", "{% if %}")?;
+        render_templates(templates_dir.path(), output_dir.path(), &scaffold, &context, &overwrite_options())?;
+        assert_eq!(fs::read_to_string(output_dir.path().join("page.html"))?, "[demo]!");
+        Ok(())
+    }
+
+    // Test the serialization filters and parse functions.
+    #[test]
+    fn test_serialization_filters() -> Result<(), Box<dyn std::error::Error>> {
+        let variables: toml::Table = "name = \"api\"\nports = [80, 443]\n[db]\nhost = \"localhost\"\n".parse()?;
+        let mut context = Context::new();
+        context.insert("service", &variables);
+        let render = |template: &str| render_string(template, "test", &context, &RenderOptions::default());
+
+        assert_eq!(
+            render("{{ service | to_json }}")?,
+            r#"{"name":"api","ports":[80,443],"db":{"host":"localhost"}}"#
+        );
+        assert!(render("{{ service.db | to_json(pretty=true) }}")?.contains("\n  \"host\": \"localhost\"\n"));
+        assert_eq!(
+            render("{{ service | to_yaml }}")?,
+            "name: api\nports:\n- 80\n- 443\ndb:\n  host: localhost"
+        );
+        assert_eq!(
+            render("{{ service | to_toml }}")?,
+            "name = \"api\"\nports = [80, 443]\n\n[db]\nhost = \"localhost\""
+        );
+        assert_eq!(render("ports = {{ service.ports | to_toml }}")?, "ports = [80, 443]");
+
+        assert_eq!(
+            render("{% set data = parse_json(value='{\"a\": [1, 2]}') %}{{ data.a | last }}")?,
+            "2"
+        );
+        assert_eq!(render("{% set data = parse_yaml(value='a:\n  b: true') %}{{ data.a.b }}")?, "true");
+        assert_eq!(render("{% set data = parse_toml(value='[a]\nb = \"c\"') %}{{ data.a.b }}")?, "c");
+
+        let error = render("{{ parse_json(value='{') }}").unwrap_err();
+        assert!(
+            error_chain(error.as_ref()).contains("`parse_json`: EOF while parsing"),
+            "{}",
+            error_chain(error.as_ref())
+        );
+        Ok(())
+    }
+
+    // Test that template_variables finds context variables and ignores locals.
+    #[test]
+    fn test_template_variables() -> Result<(), Box<dyn std::error::Error>> {
+        let source = r#"{% set greeting = "hi" %}{{ greeting }} {{ project_name | upper }}
+{% for svc in services %}{{ svc.name }}-{{ loop.index }}{% endfor %}
+{% if use_db %}{{ db.host }}{% endif %}{{ port | default(value=8080) }}{% if extra is defined %}x{% endif %}"#;
+        let template = Template::new("test", None, source)?;
+        let variables = template_variables(&template);
+        let names: Vec<&str> = variables.keys().map(String::as_str).collect();
+        assert_eq!(names, vec!["db", "extra", "port", "project_name", "services", "use_db"]);
+        assert!(variables["use_db"]);
+        assert!(!variables["port"]);
+        assert!(!variables["extra"]);
+        Ok(())
+    }
+
+    // Test that strict mode reports undefined variables with their location.
+    #[test]
+    fn test_strict_mode_undefined_variable() -> Result<(), Box<dyn std::error::Error>> {
+        let templates_dir = TempDir::new()?;
+        let output_dir = TempDir::new()?;
+        fs::write(
+            templates_dir.path().join("deploy.yaml.tera"),
+            "name: {{ project_name }}\n{% if replicas %}replicas: 2{% endif %}\n",
+        )?;
+
+        let scaffold = Scaffold {
+            name: Some("StrictTest".into()),
+            repo: "dummy".into(),
+            template_dir: Some("".into()),
+            template: TemplateConfig {
+                files: vec![TemplateFile {
+                    src: "deploy.yaml.tera".into(),
+                    dest: "deploy.yaml".into(),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut context = Context::new();
+        context.insert("project_name", "StrictProject");
+
+        // Without strict mode the undefined condition silently renders nothing.
+        render_templates(templates_dir.path(), output_dir.path(), &scaffold, &context, &overwrite_options())?;
+        assert_eq!(
+            fs::read_to_string(output_dir.path().join("deploy.yaml"))?,
+            "name: StrictProject\n\n"
+        );
+
+        let strict = RenderOptions {
+            strict: true,
+            ..overwrite_options()
+        };
+        let err = render_templates(templates_dir.path(), output_dir.path(), &scaffold, &context, &strict).unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("`replicas`"), "{}", message);
+        assert!(message.contains("deploy.yaml.tera:2"), "{}", message);
+        Ok(())
+    }
+
+    // Test that `dir` entries and `.keep` markers produce empty directories.
+    #[test]
+    fn test_render_empty_directories() -> Result<(), Box<dyn std::error::Error>> {
+        let templates_dir = TempDir::new()?;
+        let output_dir = TempDir::new()?;
+
+        // A template directory whose only content is a .keep marker.
+        let keep_dir = templates_dir.path().join("skeleton").join("data");
+        fs::create_dir_all(&keep_dir)?;
+        fs::write(keep_dir.join(KEEP_FILE_NAME), "")?;
+
+        let scaffold = Scaffold {
+            name: Some("DirTest".into()),
+            repo: "dummy".into(),
+            template_dir: Some("".into()),
+            template: TemplateConfig {
+                files: vec![
+                    TemplateFile {
+                        dir: Some("{{ project_name }}/logs".into()),
+                        ..Default::default()
+                    },
+                    TemplateFile {
+                        src: "skeleton".into(),
+                        dest: "skeleton".into(),
+                        ..Default::default()
+                    },
+                ],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let mut context = Context::new();
+        context.insert("project_name", "DirProject");
+
+        let written = render_templates(
+            templates_dir.path(),
+            output_dir.path(),
+            &scaffold,
+            &context,
+            &RenderOptions::default(),
+        )?;
+        let logs_dir = output_dir.path().join("DirProject").join("logs");
+        assert!(logs_dir.is_dir());
+        assert!(written.written.contains(&(logs_dir, WriteStatus::Created)));
+
+        // The .keep marker keeps the directory but is not copied itself.
+        let data_dir = output_dir.path().join("skeleton").join("data");
+        assert!(data_dir.is_dir());
+        assert!(!data_dir.join(KEEP_FILE_NAME).exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_overwrite_flag() -> Result<(), Box<dyn std::error::Error>> {
+        // Set up temporary directories.
+        let templates_dir = TempDir::new()?;
+        let output_dir = TempDir::new()?;
+
+        // Write a simple template file.
+        let template_file_path = templates_dir.path().join("template.tera");
+        fs::write(&template_file_path, "Original content: {{ value }}")?;
+
+        // Build a scaffold using the template.
+        let scaffold = Scaffold {
+            name: Some("OverwriteTest".into()),
+            repo: "dummy".into(), // not used in render_templates
+            template_dir: Some("".into()),
+            template: TemplateConfig {
+                files: vec![TemplateFile {
+                    src: "template.tera".into(),
+                    dest: "test.txt".into(),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        // Prepare a Tera context.
+        let mut context = Context::new();
+        context.insert("value", "new");
+
+        let output_file_path = output_dir.path().join("test.txt");
+
+        // Pre-create the output file with content "old".
+        fs::write(&output_file_path, "old")?;
+
+        // Render templates with overwrite = false; file should remain unchanged.
+        render_templates(
+            templates_dir.path(),
+            output_dir.path(),
+            &scaffold,
+            &context,
+            &RenderOptions::default(),
+        )?;
+        let content = fs::read_to_string(&output_file_path)?;
+        assert_eq!(content, "old");
+
+        // Render templates with overwrite = true; file should be overwritten.
+        render_templates(templates_dir.path(), output_dir.path(), &scaffold, &context, &overwrite_options())?;
+        let content = fs::read_to_string(&output_file_path)?;
+        assert_eq!(content, "Original content: new");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_full_directory_templating_and_skipping() -> Result<(), Box<dyn std::error::Error>> {
+        // Create temporary directories for full directory test.
+        let templates_dir = TempDir::new()?;
+        let output_dir = TempDir::new()?;
+
+        // Create a full directory structure in templates_dir.
+        let full_dir = templates_dir.path().join("full_dir");
+        fs::create_dir_all(&full_dir)?;
+
+        // Create file1.txt.tera in full_dir.
+        let file1_path = full_dir.join("file1.txt.tera");
+        fs::write(&file1_path, "Hello, {{ project_name }}!")?;
+
+        // Create file2.txt (non-templated) in full_dir.
+        let file2_path = full_dir.join("file2.txt");
+        fs::write(&file2_path, "Static content")?;
+
+        // Create nested directory and file3.txt.tera.
+        let nested_dir = full_dir.join("sub_dir");
+        fs::create_dir_all(&nested_dir)?;
+        let file3_path = nested_dir.join("file3.txt.tera");
+        fs::write(&file3_path, "Nested {{ project_name }}!")?;
+
+        // Build a scaffold that processes the entire "full_dir" directory.
+        let scaffold = Scaffold {
+            name: Some("FullDirTest".to_string()),
+            repo: "dummy".into(),               // not used in render_templates.
+            template_dir: Some("".to_string()), // Use the root of templates_dir.
+            template: TemplateConfig {
+                files: vec![TemplateFile {
+                    src: "full_dir".into(),
+                    dest: "rendered_dir".into(),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        // Create a Tera context.
+        let mut context = Context::new();
+        context.insert("project_name", "TestProject");
+
+        // First rendering with overwrite true.
+        render_templates(templates_dir.path(), output_dir.path(), &scaffold, &context, &overwrite_options())?;
+
+        // Verify file1.txt is rendered and its .tera extension is removed.
+        let rendered_file1 = output_dir.path().join("rendered_dir").join("file1.txt");
+        assert!(rendered_file1.exists());
+        let content1 = fs::read_to_string(&rendered_file1)?;
+        assert_eq!(content1, "Hello, TestProject!");
+
+        // Verify file2.txt is copied as-is.
+        let rendered_file2 = output_dir.path().join("rendered_dir").join("file2.txt");
+        assert!(rendered_file2.exists());
+        let content2 = fs::read_to_string(&rendered_file2)?;
+        assert_eq!(content2, "Static content");
+
+        // Verify the nested templated file is processed and renamed.
+        let rendered_file3 = output_dir.path().join("rendered_dir").join("sub_dir").join("file3.txt");
+        assert!(rendered_file3.exists());
+        let content3 = fs::read_to_string(&rendered_file3)?;
+        assert_eq!(content3, "Nested TestProject!");
+
+        // Now, simulate an existing file scenario.
+        fs::write(&rendered_file1, "Old Content")?;
+        // Run templating again with overwrite = false.
+        render_templates(
+            templates_dir.path(),
+            output_dir.path(),
+            &scaffold,
+            &context,
+            &RenderOptions::default(),
+        )?;
+        let content1_after = fs::read_to_string(&rendered_file1)?;
+        // The pre-existing file should remain unchanged.
+        assert_eq!(content1_after, "Old Content");
+
+        Ok(())
+    }
+
+    // Test that file system errors name the scaffold, the template file and the paths involved.
+    #[test]
+    fn test_file_system_error_context() -> Result<(), Box<dyn std::error::Error>> {
+        let repo_dir = TempDir::new()?;
+        let templates = repo_dir.path().join("templates");
+        fs::create_dir_all(templates.join("dir"))?;
+        fs::write(templates.join("dir/nested.txt"), "nested")?;
+        fs::write(templates.join("plain.txt"), "plain")?;
+        let output_dir = TempDir::new()?;
+        // A file where a directory is needed makes every write below it fail.
+        fs::write(output_dir.path().join("blocker"), "")?;
+
+        let scaffold = |src: &str, dest: &str| Scaffold {
+            name: Some("Service".to_string()),
+            repo: repo_dir.path().to_string_lossy().to_string().into(),
+            template: TemplateConfig {
+                files: vec![TemplateFile {
+                    src: src.to_string(),
+                    dest: dest.to_string(),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let run = |scaffold: &Scaffold| -> Result<RunError, Box<dyn std::error::Error>> {
+            let (repo_base, _) = prepare_scaffold(scaffold, &GitOptions::default())?;
+            let options = overwrite_options();
+            let outcome = process_scaffold(scaffold, &repo_base, "Demo", None, &BTreeMap::new(), output_dir.path(), &options);
+            outcome.err().ok_or_else(|| "the scaffold should fail".into())
+        };
+
+        let error = run(&scaffold("missing.txt", "copied.txt"))?;
+        assert_eq!(error.file.as_deref(), Some("missing.txt"));
+        assert!(error.message.starts_with("Failed to copy "), "{}", error.message);
+        assert!(error.message.contains("missing.txt\" to \""), "{}", error.message);
+
+        let error = run(&scaffold("plain.txt", "blocker/plain.txt"))?;
+        assert!(
+            error
+                .to_string()
+                .starts_with("scaffold 'Service': render error in plain.txt: Failed to create directory"),
+            "{}",
+            error
+        );
+        assert!(error.message.contains("blocker"), "{}", error.message);
+
+        // Errors of files in a listed directory name the file, not the directory.
+        fs::create_dir_all(output_dir.path().join("out/nested.txt"))?;
+        let error = run(&scaffold("dir", "out"))?;
+        assert!(
+            error.file.as_deref().is_some_and(|file| file.ends_with("nested.txt")),
+            "{:?}",
+            error.file
+        );
+        assert!(error.message.starts_with("Failed to copy "), "{}", error.message);
+        Ok(())
+    }
+
+    // Test that exclude patterns and .scaffoldignore leave files out of listed directories.
+    #[test]
+    fn test_exclude_patterns() -> Result<(), Box<dyn std::error::Error>> {
+        let templates_dir = TempDir::new()?;
+        let output_dir = TempDir::new()?;
+        let project = templates_dir.path().join("project");
+        for file in [
+            "README.md",
+            "src/main.rs",
+            "src/notes.md",
+            "docs/guide.txt",
+            "tests/it.rs",
+            "tests/data/x.json",
+        ] {
+            let path = project.join(file);
+            fs::create_dir_all(path.parent().ok_or("no parent")?)?;
+            fs::write(path, file)?;
+        }
+        fs::write(
+            templates_dir.path().join(SCAFFOLD_IGNORE_FILE),
+            "# template tests\n\nproject/tests/**\n",
+        )?;
+
+        let scaffold = Scaffold {
+            template: TemplateConfig {
+                files: vec![TemplateFile {
+                    src: "project".into(),
+                    dest: ".".into(),
+                    ..Default::default()
+                }],
+                exclude: vec!["**/*.md".to_string(), "project/docs/**".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        render_templates(
+            templates_dir.path(),
+            output_dir.path(),
+            &scaffold,
+            &Context::new(),
+            &overwrite_options(),
+        )?;
+        assert!(output_dir.path().join("src/main.rs").exists());
+        for excluded in ["README.md", "src/notes.md", "docs", "tests"] {
+            assert!(!output_dir.path().join(excluded).exists(), "{} should be excluded", excluded);
+        }
+
+        let invalid = Exclude::load(templates_dir.path(), &["[".to_string()])
+            .err()
+            .ok_or("expected an error")?;
+        assert!(invalid.to_string().starts_with("Invalid exclude pattern `[`"));
+        Ok(())
+    }
+
+    // Test that paths with the export-ignore attribute of the template repository aren't copied.
+    #[test]
+    fn test_export_ignore() -> Result<(), Box<dyn std::error::Error>> {
+        let repo_dir = TempDir::new()?;
+        let output_dir = TempDir::new()?;
+        git(repo_dir.path(), &["init", "-q"])?;
+        fs::write(
+            repo_dir.path().join(".gitattributes"),
+            "tests/ export-ignore\n.github export-ignore\n*.snap export-ignore\n",
+        )?;
+        for file in ["src/main.rs", "src/main.snap", "tests/fixture.txt", ".github/ci.yml", "README.md"] {
+            let path = repo_dir.path().join("project").join(file);
+            fs::create_dir_all(path.parent().ok_or("no parent")?)?;
+            fs::write(path, file)?;
+        }
+
+        let scaffold = Scaffold {
+            template: TemplateConfig {
+                files: vec![TemplateFile {
+                    src: "project".into(),
+                    dest: ".".into(),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        render_templates(repo_dir.path(), output_dir.path(), &scaffold, &Context::new(), &overwrite_options())?;
+        assert!(output_dir.path().join("src/main.rs").exists());
+        for ignored in ["src/main.snap", "tests", ".github"] {
+            assert!(!output_dir.path().join(ignored).exists(), "{} should be left out", ignored);
+        }
+
+        // Whole-directory formats leave them out too.
+        let tree_output = TempDir::new()?;
+        let tree = TemplateTree::new(repo_dir.path(), str::to_string);
+        let mut result = RenderResult::default();
+        render_tree_path(
+            &tree,
+            &repo_dir.path().join("project"),
+            tree_output.path(),
+            &Context::new(),
+            &overwrite_options(),
+            &mut result,
+        )?;
+        assert!(tree_output.path().join("project/src/main.rs").exists());
+        assert!(!tree_output.path().join("project/tests").exists());
+        Ok(())
+    }
+
+    // Test that auto_dest derives missing destinations from the source paths.
+    #[test]
+    fn test_auto_dest() -> Result<(), Box<dyn std::error::Error>> {
+        let templates_dir = TempDir::new()?;
+        let output_dir = TempDir::new()?;
+        fs::create_dir_all(templates_dir.path().join("{{ project_name }}"))?;
+        fs::create_dir_all(templates_dir.path().join("config"))?;
+        fs::write(templates_dir.path().join("{{ project_name }}/main.py.tera"), "# {{ project_name }}")?;
+        fs::write(templates_dir.path().join("config/app.toml"), "debug = true")?;
+
+        let config: Scaffold = from_toml(
+            r#"
+repo = "."
+template.auto_dest = true
+template.files = [
+  { src = "{{ project_name }}/main.py.tera" },
+  { src = "config/app.toml" },
+  { src = "config/app.toml", dest = "app.toml" },
+]
+"#,
+        )?;
+        validate_scaffold(&config)?;
+        let mut context = Context::new();
+        context.insert("project_name", "demo");
+        render_templates(templates_dir.path(), output_dir.path(), &config, &context, &overwrite_options())?;
+        assert_eq!(fs::read_to_string(output_dir.path().join("demo/main.py"))?, "# demo");
+        assert!(output_dir.path().join("config/app.toml").exists());
+        assert!(output_dir.path().join("app.toml").exists());
+
+        // Without auto_dest, dest is still required.
+        let scaffold = Scaffold {
+            template: TemplateConfig {
+                auto_dest: false,
+                ..config.template
+            },
+            ..config
+        };
+        let error = validate_scaffold(&scaffold).unwrap_err();
+        assert!(error.to_string().contains("must set both `src` and `dest`"));
+        Ok(())
+    }
+
+    // Test that files in the raw list are copied byte for byte, even with a `.tera` extension.
+    #[test]
+    fn test_raw_files() -> Result<(), Box<dyn std::error::Error>> {
+        let templates_dir = TempDir::new()?;
+        let output_dir = TempDir::new()?;
+        let write = |path: &str, content: &[u8]| -> io::Result<()> {
+            let path = templates_dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap_or(templates_dir.path()))?;
+            fs::write(path, content)
+        };
+        write("assets/logo.png", b"\x89PNG{{ \xff")?;
+        write("assets/snippet.html.tera", b"{% if %}{{ project_name }}")?;
+        write("assets/title.txt.tera", b"{{ project_name }}")?;
+        write("icon.png", b"{{ icon }}")?;
+        write("README.md.tera", b"# {{ project_name }}")?;
+
+        let scaffold: Scaffold = from_toml(
+            r#"
+repo = "."
+template.raw = ["assets/**", "*.png"]
+template.files = [
+  { src = "assets", dest = "assets" },
+  { src = "icon.png", dest = "icon.png" },
+  { src = "README.md.tera", dest = "README.md" },
+  { src = "assets/title.txt.tera", dest = "title.txt", engine = "tera" },
+]
+"#,
+        )?;
+        validate_scaffold(&scaffold)?;
+        let mut context = Context::new();
+        context.insert("project_name", "demo");
+        render_templates(templates_dir.path(), output_dir.path(), &scaffold, &context, &overwrite_options())?;
+        let output = |path: &str| fs::read(output_dir.path().join(path));
+        assert_eq!(output("assets/logo.png")?, b"\x89PNG{{ \xff");
+        assert_eq!(output("assets/snippet.html.tera")?, b"{% if %}{{ project_name }}");
+        assert_eq!(output("icon.png")?, b"{{ icon }}");
+        assert_eq!(output("README.md")?, b"# demo");
+        // An entry's engine still renders a raw file.
+        assert_eq!(output("title.txt")?, b"demo");
+
+        let invalid = Scaffold {
+            template: TemplateConfig {
+                raw: vec!["[".to_string()],
+                ..Default::default()
+            },
+            ..scaffold
+        };
+        let error = validate_scaffold(&invalid).unwrap_err();
+        assert!(error.to_string().starts_with("Invalid raw pattern `[`"), "{}", error);
+        Ok(())
+    }
+
+    // Test that custom delimiters replace Tera's and leave its own syntax in the output.
+    #[test]
+    fn test_custom_delimiters() -> Result<(), Box<dyn std::error::Error>> {
+        let delimiters = Delimiters {
+            variable: Some(("[[".to_string(), "]]".to_string())),
+            block: Some(("[%".to_string(), "%]".to_string())),
+            ..Default::default()
+        };
+        assert_eq!(
+            delimiters.translate("name: [[ name ]]\nimage: {{ .Values.image }}\n[% if x %]{# keep #}[% endif %]"),
+            "name: {{ name }}{% raw %}\nimage: {{ .Values.image }}\n{% endraw %}{% if x %}{# keep #}{% endif %}"
+        );
+        // Delimiters that aren't replaced keep their meaning; an unclosed marker is literal text.
+        let variables_only = Delimiters {
+            variable: Some(("<<".to_string(), ">>".to_string())),
+            ..Default::default()
+        };
+        assert_eq!(
+            variables_only.translate("{% if a %}<<a>>{% endif %} <<b"),
+            "{% if a %}{{a}}{% endif %} <<b"
+        );
+
+        let templates_dir = TempDir::new()?;
+        let output_dir = TempDir::new()?;
+        fs::create_dir_all(templates_dir.path().join("chart"))?;
+        fs::write(
+            templates_dir.path().join("chart/values.yaml.tera"),
+            "name: [[ project_name ]]\ntag: {{ .Values.tag }}\n",
+        )?;
+        fs::write(
+            templates_dir.path().join("ci.yml"),
+            "run: ${{ matrix.os }} [[ project_name | upper ]]",
+        )?;
+        fs::write(templates_dir.path().join("jinja.j2"), "{{ [[ project_name ]] }}")?;
+        let scaffold: Scaffold = from_toml(
+            r#"
+repo = "."
+template.delimiters = { variable = ["[[", "]]"] }
+template.files = [
+  { src = "chart", dest = "chart" },
+  { src = "ci.yml", dest = "ci.yml", engine = "tera" },
+  { src = "jinja.j2", dest = "jinja.j2", engine = "raw" },
+]
+"#,
+        )?;
+        let mut context = Context::new();
+        context.insert("project_name", "demo");
+        render_templates(templates_dir.path(), output_dir.path(), &scaffold, &context, &overwrite_options())?;
+        assert_eq!(
+            fs::read_to_string(output_dir.path().join("chart/values.yaml"))?,
+            "name: demo\ntag: {{ .Values.tag }}\n"
+        );
+        assert_eq!(fs::read_to_string(output_dir.path().join("ci.yml"))?, "run: ${{ matrix.os }} DEMO");
+        assert_eq!(fs::read_to_string(output_dir.path().join("jinja.j2"))?, "{{ [[ project_name ]] }}");
+        Ok(())
+    }
+
+    // Test that for_each renders an entry once per item of a list variable.
+    #[test]
+    fn test_for_each() -> Result<(), Box<dyn std::error::Error>> {
+        let templates_dir = TempDir::new()?;
+        let output_dir = TempDir::new()?;
+        fs::write(
+            templates_dir.path().join("deploy.yaml.tera"),
+            "name: {{ item.name }}\nreplicas: {{ item.replicas }}\nproject: {{ project_name }}",
+        )?;
+        let scaffold: Scaffold = from_toml(
+            r#"
+repo = "."
+template.files = [
+  { src = "deploy.yaml.tera", dest = "deploy/{{ item.name }}.yaml", for_each = "services" },
+  { dir = "logs/{{ item }}", for_each = "environments" },
+]
+"#,
+        )?;
+        let variables: toml::Table = r#"
+services = [{ name = "api", replicas = 2 }, { name = "worker", replicas = 1 }]
+environments = ["dev", "prod"]
+"#
+        .parse()?;
+        let mut context = Context::from_serialize(&variables)?;
+        context.insert("project_name", "demo");
+        let result = render_templates(templates_dir.path(), output_dir.path(), &scaffold, &context, &overwrite_options())?;
+        assert_eq!(result.written.len(), 4);
+        assert_eq!(
+            fs::read_to_string(output_dir.path().join("deploy/worker.yaml"))?,
+            "name: worker\nreplicas: 1\nproject: demo"
+        );
+        assert!(output_dir.path().join("deploy/api.yaml").exists());
+        assert!(output_dir.path().join("logs/prod").is_dir());
+
+        // The vars report sees the list, not `item`.
+        let usage = scaffold_variable_usage(templates_dir.path(), &scaffold, &context)?;
+        assert!(usage.contains_key("services") && usage.contains_key("environments"));
+        assert!(!usage.contains_key("item"));
+
+        context.insert("services", "api");
+        let error = render_templates(templates_dir.path(), output_dir.path(), &scaffold, &context, &overwrite_options()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "deploy.yaml.tera: `for_each` variable `services` must be a list, got \"api\""
+        );
+        Ok(())
+    }
+
+    // Test that sequence() numbers destination files and skips numbers already used in the output.
+    #[test]
+    fn test_sequence_dest_numbers() -> Result<(), Box<dyn std::error::Error>> {
+        let repo_dir = TempDir::new()?;
+        fs::create_dir_all(repo_dir.path().join("templates"))?;
+        for name in ["users.sql", "orders.sql", "readme.txt"] {
+            fs::write(repo_dir.path().join("templates").join(name), name)?;
+        }
+        let file = |src: &str, dest: &str| TemplateFile {
+            src: src.to_string(),
+            dest: dest.to_string(),
+            ..Default::default()
+        };
+        let scaffold = Scaffold {
+            repo: repo_dir.path().to_string_lossy().to_string().into(),
+            template: TemplateConfig {
+                files: vec![
+                    file("users.sql", "migrations/V{{ sequence() }}__users.sql"),
+                    file("orders.sql", "migrations/V{{ sequence() }}__orders.sql"),
+                    file("readme.txt", "docs/{{ sequence(start=10, step=10, width=4) }}-readme.txt"),
+                ],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let output_dir = TempDir::new()?;
+        let migrations = output_dir.path().join("migrations");
+        fs::create_dir_all(&migrations)?;
+        fs::write(migrations.join("V1__init.sql"), "existing")?;
+
+        let (repo_base, _) = prepare_scaffold(&scaffold, &GitOptions::default())?;
+        process_scaffold(
+            &scaffold,
+            &repo_base,
+            "Seq",
+            None,
+            &BTreeMap::new(),
+            output_dir.path(),
+            &overwrite_options(),
+        )?;
+        assert!(migrations.join("V2__users.sql").exists());
+        assert!(migrations.join("V3__orders.sql").exists());
+        assert!(output_dir.path().join("docs").join("0010-readme.txt").exists());
+
+        // Running again reuses the same numbers instead of adding new files.
+        process_scaffold(
+            &scaffold,
+            &repo_base,
+            "Seq",
+            None,
+            &BTreeMap::new(),
+            output_dir.path(),
+            &overwrite_options(),
+        )?;
+        assert_eq!(fs::read_dir(&migrations)?.count(), 3);
+        Ok(())
+    }
+
+    // Test that postprocess steps clean up rendered output in order, including files of listed directories.
+    #[test]
+    fn test_postprocess() -> Result<(), Box<dyn std::error::Error>> {
+        use PostProcess::*;
+        assert_eq!(StripTrailingWhitespace.apply("a  \nb\t\n"), "a\nb\n");
+        assert_eq!(EnsureFinalNewline.apply("a\n\n\n"), "a\n");
+        assert_eq!(EnsureFinalNewline.apply("a"), "a\n");
+        assert_eq!(EnsureFinalNewline.apply(""), "");
+        assert_eq!(SortLines.apply("b\nc\na"), "a\nb\nc");
+
+        let templates_dir = TempDir::new()?;
+        let output_dir = TempDir::new()?;
+        fs::create_dir_all(templates_dir.path().join("deps"))?;
+        let deps = "{% for dep in deps %}{{ dep }}   \n{% endfor %}\n";
+        fs::write(templates_dir.path().join("deps.txt.tera"), deps)?;
+        fs::write(templates_dir.path().join("deps/list.txt.tera"), deps)?;
+        fs::write(templates_dir.path().join("copied.txt"), "kept   \n")?;
+        let steps = vec![StripTrailingWhitespace, SortLines, EnsureFinalNewline];
+        let file = |src: &str| TemplateFile {
+            src: src.to_string(),
+            dest: src.trim_end_matches(".tera").to_string(),
+            postprocess: steps.clone(),
+            ..Default::default()
+        };
+        let scaffold = Scaffold {
+            template: TemplateConfig {
+                files: vec![file("deps.txt.tera"), file("deps"), file("copied.txt")],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut context = Context::new();
+        context.insert("deps", &["tera", "serde", "clap"]);
+        render_templates(templates_dir.path(), output_dir.path(), &scaffold, &context, &overwrite_options())?;
+
+        let read = |name: &str| fs::read_to_string(output_dir.path().join(name));
+        assert_eq!(read("deps.txt")?, "clap\nserde\ntera\n");
+        assert_eq!(read("deps/list.txt")?, "clap\nserde\ntera\n");
+        assert_eq!(read("copied.txt")?, "kept   \n");
+        Ok(())
+    }
+
+    // Test that line endings apply to rendered files, per entry or from the project, and leave copies alone.
+    #[test]
+    fn test_line_endings() -> Result<(), Box<dyn std::error::Error>> {
+        assert_eq!(LineEndings::Lf.apply("a\r\nb\n".to_string()), "a\nb\n");
+        assert_eq!(LineEndings::Crlf.apply("a\r\nb\n".to_string()), "a\r\nb\r\n");
+        let native = if cfg!(windows) { "a\r\n" } else { "a\n" };
+        assert_eq!(LineEndings::Native.apply("a\r\n".to_string()), native);
+
+        let templates_dir = TempDir::new()?;
+        let output_dir = TempDir::new()?;
+        // Authored on Windows.
+        for name in ["run.sh.tera", "run.bat.tera", "copied.txt"] {
+            fs::write(templates_dir.path().join(name), "echo {{ name }}\r\nexit\r\n")?;
+        }
+        let file = |src: &str, line_endings| TemplateFile {
+            src: src.to_string(),
+            dest: src.trim_end_matches(".tera").to_string(),
+            line_endings,
+            ..Default::default()
+        };
+        let scaffold = Scaffold {
+            template: TemplateConfig {
+                files: vec![
+                    file("run.sh.tera", None),
+                    file("run.bat.tera", Some(LineEndings::Crlf)),
+                    file("copied.txt", None),
+                ],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut context = Context::new();
+        context.insert("name", "demo");
+        let options = RenderOptions {
+            line_endings: Some(LineEndings::Lf),
+            ..overwrite_options()
+        };
+        render_templates(templates_dir.path(), output_dir.path(), &scaffold, &context, &options)?;
+
+        let read = |name: &str| fs::read_to_string(output_dir.path().join(name));
+        assert_eq!(read("run.sh")?, "echo demo\nexit\n");
+        assert_eq!(read("run.bat")?, "echo demo\r\nexit\r\n");
+        assert_eq!(read("copied.txt")?, "echo {{ name }}\r\nexit\r\n");
+        Ok(())
+    }
+
+    // Test that `engine` renders single files with Tera, Liquid or Handlebars, or copies them as-is.
+    #[test]
+    fn test_template_engine_override() -> Result<(), Box<dyn std::error::Error>> {
+        let templates_dir = TempDir::new()?;
+        let output_dir = TempDir::new()?;
+        let sources = [
+            ("config.j2", "name={{ name | upper }}"),
+            ("notes.liquid", "{% unless draft %}{{ name | upcase }}{% endunless %}"),
+            (
+                "list.hbs",
+                "{{!-- items --}}{{#each items}}{{@index}}:{{this}}{{#unless @last}},{{/unless}}{{/each}}{{#if draft}}x{{else}} {{{name}}}{{/if}}",
+            ),
+            ("keep.txt.tera", "{{ name }}"),
+        ];
+        for (name, content) in sources {
+            fs::write(templates_dir.path().join(name), content)?;
+        }
+        let file = |src: &str, engine: Option<TemplateEngine>| TemplateFile {
+            src: src.to_string(),
+            dest: src.to_string(),
+            engine,
+            ..Default::default()
+        };
+        let scaffold = Scaffold {
+            template: TemplateConfig {
+                files: vec![
+                    file("config.j2", Some(TemplateEngine::Tera)),
+                    file("notes.liquid", Some(TemplateEngine::Liquid)),
+                    file("list.hbs", Some(TemplateEngine::Handlebars)),
+                    file("keep.txt.tera", Some(TemplateEngine::None)),
+                ],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut context = Context::new();
+        context.insert("name", "demo");
+        context.insert("draft", &false);
+        context.insert("items", &["a", "b"]);
+        render_templates(templates_dir.path(), output_dir.path(), &scaffold, &context, &overwrite_options())?;
+
+        let read = |name: &str| fs::read_to_string(output_dir.path().join(name));
+        assert_eq!(read("config.j2")?, "name=DEMO");
+        assert_eq!(read("notes.liquid")?, "DEMO");
+        assert_eq!(read("list.hbs")?, "0:a,1:b demo");
+        assert_eq!(read("keep.txt.tera")?, "{{ name }}");
+        Ok(())
+    }
+
+    // Test the path filters and the join_path function.
+    #[test]
+    fn test_path_filters() -> Result<(), Box<dyn std::error::Error>> {
+        let mut context = Context::new();
+        context.insert("path", "src/models/user.py");
+        context.insert("dir", "src/");
+        let render = |template: &str| render_string(template, "test", &context, &RenderOptions::default());
+        assert_eq!(render("{{ path | basename }}")?, "user.py");
+        assert_eq!(render("{{ path | dirname }}")?, "src/models");
+        assert_eq!(render("{{ path | file_stem }}")?, "user");
+        assert_eq!(render("{{ path | extension }}")?, "py");
+        assert_eq!(render("{{ path | with_extension(ext=\"rs\") }}")?, "src/models/user.rs");
+        assert_eq!(render("{{ dir | join_path(path=\"/lib.rs\") }}")?, "src/lib.rs");
+        assert_eq!(render("{{ \"file\" | dirname }}")?, "");
+        assert_eq!(
+            render("{{ join_path(parts=[dir, \"\", \"v\" ~ 2, path | basename]) }}")?,
+            "src/v2/user.py"
+        );
+        assert!(render("{{ path | with_extension }}").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,78 @@
+//! Helpers shared by the tests of the modules.
+
+use std::path::{Path, PathBuf};
+
+use super::*;
+
+// The paths that tests use for the environment variables of `env_path`: a cache directory of their own, and a
+// user configuration that doesn't exist.
+pub(crate) fn env_path_in_tests(name: &str) -> Option<PathBuf> {
+    let dir = std::env::temp_dir().join("scaficionado-tests");
+    match name {
+        "SCAFICIONADO_CONFIG" => Some(dir.join("config.toml")),
+        "SCAFICIONADO_CACHE_DIR" => Some(dir),
+        _ => None,
+    }
+}
+
+// Run git in `dir` as a test user and return its output.  Fixture repositories are made with the git command line,
+// whichever backends scaficionado is built with.
+pub(crate) fn git(dir: &Path, args: &[&str]) -> Result<String, Box<dyn std::error::Error>> {
+    let output = std::process::Command::new("git")
+        .args([
+            "-c",
+            "user.name=test",
+            "-c",
+            "user.email=test@localhost",
+            "-c",
+            "init.defaultBranch=main",
+        ])
+        .args([
+            "-c",
+            "commit.gpgSign=false",
+            "-c",
+            "tag.gpgSign=false",
+            "-c",
+            "protocol.file.allow=always",
+        ])
+        .args(args)
+        .current_dir(dir)
+        .output()?;
+    if !output.status.success() {
+        return Err(format!("git {} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr)).into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+// Render options that overwrite existing files.
+pub(crate) fn overwrite_options() -> RenderOptions {
+    RenderOptions {
+        overwrite: true,
+        ..Default::default()
+    }
+}
+
+// Serve `files` (path, content) over HTTP on localhost, returning the base URL.
+pub(crate) fn serve_http(files: Vec<(&'static str, String)>) -> Result<String, Box<dyn std::error::Error>> {
+    use std::io::{Read, Write};
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let base_url = format!("http://{}", listener.local_addr()?);
+    std::thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let mut request = [0; 4096];
+            let read = stream.read(&mut request).unwrap_or(0);
+            let request = String::from_utf8_lossy(&request[..read]);
+            let path = request.split_whitespace().nth(1).unwrap_or("/");
+            let response = match files.iter().find(|(file, _)| *file == path) {
+                Some((_, content)) => format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    content.len(),
+                    content
+                ),
+                None => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+            };
+            stream.write_all(response.as_bytes()).ok();
+        }
+    });
+    Ok(base_url)
+}
@@ -0,0 +1,40 @@
+//! The template playground: renders templates with variables in the browser, with the filters and functions of a
+//! real run, so template authors can try them without installing anything.  Built for `wasm32-unknown-unknown` with
+//! `--no-default-features --features wasm`; see bindings/wasm.
+
+use crate::render::{error_chain, new_tera, reset_sequences, template_variables};
+use std::collections::BTreeMap;
+use wasm_bindgen::prelude::*;
+
+/// Render `templates`, a JSON object of template paths to their sources, with `variables`, a JSON object.  Every
+/// template is loaded under its path, as in a template directory, so they can include, import and extend each other.
+/// Returns a JSON object with the rendered `files`, under their paths without the `.tera` extension, and the
+/// `errors` of the templates that failed to render.
+#[wasm_bindgen(js_name = renderTemplates)]
+pub fn render_templates(templates: &str, variables: &str) -> Result<String, JsError> {
+    let templates: BTreeMap<String, String> =
+        serde_json::from_str(templates).map_err(|e| JsError::new(&format!("Invalid templates: {}", e)))?;
+    let variables: serde_json::Value = serde_json::from_str(variables).map_err(|e| JsError::new(&format!("Invalid variables: {}", e)))?;
+    let context = tera::Context::from_value(variables).map_err(|e| JsError::new(&error_chain(&e)))?;
+    let mut tera = new_tera();
+    tera.add_raw_templates(templates.iter().map(|(path, source)| (path.as_str(), source.as_str())))
+        .map_err(|e| JsError::new(&error_chain(&e)))?;
+    reset_sequences();
+    let mut files = BTreeMap::new();
+    let mut errors = BTreeMap::new();
+    for path in templates.keys() {
+        let output = path.strip_suffix(".tera").unwrap_or(path).to_string();
+        match tera.render(path, &context) {
+            Ok(rendered) => files.insert(output, rendered),
+            Err(e) => errors.insert(output, error_chain(&e)),
+        };
+    }
+    Ok(serde_json::json!({ "files": files, "errors": errors }).to_string())
+}
+
+/// The variables a template reads, as a JSON object mapping each to whether it is required.
+#[wasm_bindgen(js_name = referencedVariables)]
+pub fn referenced_variables(source: &str) -> Result<String, JsError> {
+    let template = tera::Template::new("playground", None, source).map_err(|e| JsError::new(&error_chain(&e)))?;
+    Ok(serde_json::json!(template_variables(&template)).to_string())
+}
@@ -0,0 +1,354 @@
+//! The template rendering core: a Tera instance with the filters and functions available in every template.  It
+//! doesn't touch git, the network or processes, so it also compiles to WebAssembly for the playground.
+
+use heck::{ToKebabCase, ToLowerCamelCase, ToPascalCase, ToShoutyKebabCase, ToShoutySnakeCase, ToSnakeCase, ToTitleCase, ToUpperCamelCase};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::path::Path;
+use tera::ast::{Expr, ExprVal, Node};
+use tera::{Template, Tera};
+
+/// Converts a string to another case.
+type CaseConversion = fn(&str) -> String;
+
+/// Case conversion filters available in every template, e.g. `{{ project_name | snake_case }}`.
+const CASE_FILTERS: [(&str, CaseConversion); 8] = [
+    ("kebab_case", |s| s.to_kebab_case()),
+    ("snake_case", |s| s.to_snake_case()),
+    ("pascal_case", |s| s.to_pascal_case()),
+    ("upper_camel_case", |s| s.to_upper_camel_case()),
+    ("lower_camel_case", |s| s.to_lower_camel_case()),
+    ("shouty_kebab_case", |s| s.to_shouty_kebab_case()),
+    ("shouty_snake_case", |s| s.to_shouty_snake_case()),
+    ("title_case", |s| s.to_title_case()),
+];
+
+type PathFilter = fn(&str, &HashMap<String, tera::Value>) -> tera::Result<String>;
+
+/// Path manipulation filters available in every template, e.g. `{{ src | with_extension(ext="rs") }}`.  A trailing
+/// separator is ignored, and `join_path` joins with `/`.
+const PATH_FILTERS: [(&str, PathFilter); 6] = [
+    ("basename", |path, _| Ok(os_str_to_string(Path::new(path).file_name()))),
+    ("dirname", |path, _| {
+        Ok(os_str_to_string(Path::new(path).parent().map(Path::as_os_str)))
+    }),
+    ("file_stem", |path, _| Ok(os_str_to_string(Path::new(path).file_stem()))),
+    ("extension", |path, _| Ok(os_str_to_string(Path::new(path).extension()))),
+    ("with_extension", |path, args| {
+        let extension = string_arg(args, "with_extension", "ext")?;
+        Ok(Path::new(path).with_extension(extension).to_string_lossy().into_owned())
+    }),
+    ("join_path", |path, args| {
+        Ok(join_path([path, &string_arg(args, "join_path", "path")?]))
+    }),
+];
+
+fn os_str_to_string(value: Option<&std::ffi::OsStr>) -> String {
+    value.map_or_else(String::new, |value| value.to_string_lossy().into_owned())
+}
+
+/// Get a required string (or number) argument of a filter or function.
+fn string_arg(args: &HashMap<String, tera::Value>, function: &str, name: &str) -> tera::Result<String> {
+    match args.get(name) {
+        Some(tera::Value::String(value)) => Ok(value.clone()),
+        Some(value @ tera::Value::Number(_)) => Ok(value.to_string()),
+        Some(value) => Err(tera::Error::msg(format!(
+            "`{}`: `{}` must be a string, got {}",
+            function, name, value
+        ))),
+        None => Err(tera::Error::msg(format!("`{}` requires the argument `{}`", function, name))),
+    }
+}
+
+/// Join path segments with `/`, skipping empty segments and doubled separators.
+fn join_path<'a>(segments: impl IntoIterator<Item = &'a str>) -> String {
+    let mut joined = String::new();
+    for segment in segments.into_iter().filter(|segment| !segment.is_empty()) {
+        if joined.is_empty() {
+            joined.push_str(segment);
+        } else {
+            joined = format!("{}/{}", joined.trim_end_matches('/'), segment.trim_start_matches('/'));
+        }
+    }
+    joined
+}
+
+/// `join_path(parts=["src", name, "mod.rs"])`: the parts joined into a path.
+fn join_path_function(args: &HashMap<String, tera::Value>) -> tera::Result<tera::Value> {
+    let parts = args
+        .get("parts")
+        .and_then(tera::Value::as_array)
+        .ok_or_else(|| tera::Error::msg("`join_path` requires the argument `parts`, a list of path segments"))?;
+    let segments: Vec<String> = parts
+        .iter()
+        .map(|part| match part {
+            tera::Value::String(segment) => segment.clone(),
+            other => other.to_string(),
+        })
+        .collect();
+    Ok(tera::Value::String(join_path(segments.iter().map(String::as_str))))
+}
+
+/// Create a Tera instance with the case conversion, path and serialization filters registered.  Autoescaping is off, since
+/// templates are rarely HTML.
+pub(crate) fn new_tera() -> Tera {
+    let mut tera = Tera::default();
+    tera.autoescape_on(Vec::new());
+    for (name, convert) in CASE_FILTERS {
+        tera.register_filter(name, move |value: &tera::Value, _: &HashMap<String, tera::Value>| {
+            match value.as_str() {
+                Some(text) => Ok(tera::Value::String(convert(text))),
+                None => Err(tera::Error::msg(format!("Filter `{}` expects a string, got {}", name, value))),
+            }
+        });
+    }
+    for (name, filter) in PATH_FILTERS {
+        tera.register_filter(name, move |value: &tera::Value, args: &HashMap<String, tera::Value>| {
+            match value.as_str() {
+                Some(path) => Ok(tera::Value::String(filter(path, args)?)),
+                None => Err(tera::Error::msg(format!("Filter `{}` expects a path string, got {}", name, value))),
+            }
+        });
+    }
+    for (name, format) in DATA_FORMATS {
+        let filter = format!("to_{}", name);
+        tera.register_filter(&filter.clone(), move |value: &tera::Value, args: &HashMap<String, tera::Value>| {
+            let pretty = args.get("pretty").and_then(tera::Value::as_bool).unwrap_or(false);
+            format
+                .serialize(value, pretty)
+                .map(tera::Value::String)
+                .map_err(|e| tera::Error::msg(format!("Filter `{}`: {}", filter, e)))
+        });
+        let function = format!("parse_{}", name);
+        tera.register_function(&function.clone(), move |args: &HashMap<String, tera::Value>| {
+            let text = string_arg(args, &function, "value")?;
+            format.parse(&text).map_err(|e| tera::Error::msg(format!("`{}`: {}", function, e)))
+        });
+    }
+    tera.register_function("sequence", sequence_function);
+    tera.register_function("join_path", join_path_function);
+    tera
+}
+
+/// A data format of the serialization filters (`to_json`, `to_yaml`, `to_toml`) and parse functions
+/// (`parse_json`, `parse_yaml`, `parse_toml`), e.g. for dumping a table of variables into a config file.
+#[derive(Debug, Clone, Copy)]
+enum DataFormat {
+    Json,
+    Yaml,
+    Toml,
+}
+
+const DATA_FORMATS: [(&str, DataFormat); 3] = [("json", DataFormat::Json), ("yaml", DataFormat::Yaml), ("toml", DataFormat::Toml)];
+
+impl DataFormat {
+    /// Serialize a value without a trailing newline.  `pretty` spreads JSON over several lines and TOML arrays
+    /// over one line per item; YAML is always block style.
+    fn serialize(self, value: &tera::Value, pretty: bool) -> Result<String, Box<dyn Error>> {
+        let text = match self {
+            DataFormat::Json if pretty => serde_json::to_string_pretty(value)?,
+            DataFormat::Json => serde_json::to_string(value)?,
+            DataFormat::Yaml => serde_yaml_ng::to_string(value)?,
+            DataFormat::Toml => match toml::Value::try_from(value)? {
+                // Tables become documents; other values their inline representation, e.g. for `key = {{ v | to_toml }}`.
+                toml::Value::Table(table) if pretty => toml::to_string_pretty(&table)?,
+                toml::Value::Table(table) => toml::to_string(&table)?,
+                other => other.to_string(),
+            },
+        };
+        Ok(text.trim_end_matches('\n').to_string())
+    }
+
+    fn parse(self, text: &str) -> Result<tera::Value, Box<dyn Error>> {
+        Ok(match self {
+            DataFormat::Json => serde_json::from_str(text)?,
+            DataFormat::Yaml => serde_yaml_ng::from_str(text)?,
+            DataFormat::Toml => serde_json::to_value(text.parse::<toml::Table>()?)?,
+        })
+    }
+}
+
+/// State of the `sequence()` template function: the next number of every (start, step) sequence and the
+/// numbers handed out since the last `take_sequence_numbers`.  Tera functions can't borrow render state, so it
+/// is kept per thread and reset for every scaffold.
+#[derive(Default)]
+struct Sequences {
+    next: HashMap<(i64, i64), i64>,
+    emitted: Vec<String>,
+}
+
+thread_local! {
+    static SEQUENCES: RefCell<Sequences> = RefCell::default();
+}
+
+/// Start every sequence over, at the beginning of a scaffold.
+pub(crate) fn reset_sequences() {
+    SEQUENCES.with(|sequences| *sequences.borrow_mut() = Sequences::default());
+}
+
+/// Take the numbers handed out by `sequence()` since the last call.
+#[cfg(feature = "native")]
+pub(crate) fn take_sequence_numbers() -> Vec<String> {
+    SEQUENCES.with(|sequences| std::mem::take(&mut sequences.borrow_mut().emitted))
+}
+
+/// `sequence(start=1, step=1, width=0)`: the next number of a sequence, zero-padded to `width` digits,
+/// e.g. `V{{ sequence(width=3) }}__init.sql` renders as `V001__init.sql`, then `V002__...`.
+fn sequence_function(args: &HashMap<String, tera::Value>) -> tera::Result<tera::Value> {
+    let arg = |name: &str, default: i64| match args.get(name) {
+        Some(value) => value
+            .as_i64()
+            .ok_or_else(|| tera::Error::msg(format!("sequence(): `{}` must be an integer, got {}", name, value))),
+        None => Ok(default),
+    };
+    let (start, step, width) = (arg("start", 1)?, arg("step", 1)?, arg("width", 0)?);
+    if step == 0 {
+        return Err(tera::Error::msg("sequence(): `step` must not be 0"));
+    }
+    let value = SEQUENCES.with(|sequences| {
+        let mut sequences = sequences.borrow_mut();
+        let next = sequences.next.entry((start, step)).or_insert(start);
+        let value = *next;
+        *next += step;
+        let formatted = format!("{:0width$}", value, width = width.max(0) as usize);
+        sequences.emitted.push(formatted.clone());
+        match width {
+            0 => tera::Value::from(value),
+            _ => tera::Value::String(formatted),
+        }
+    });
+    Ok(value)
+}
+
+/// Collect the root variables a template reads from its context, mapped to whether they are required.
+/// Variables only used with a leading `default` filter or in `is defined` tests are not required.
+pub(crate) fn template_variables(template: &Template) -> BTreeMap<String, bool> {
+    let mut variables = BTreeMap::new();
+    collect_node_variables(&template.ast, &HashSet::new(), &mut variables);
+    variables
+}
+
+fn record_variable(ident: &str, locals: &HashSet<String>, required: bool, variables: &mut BTreeMap<String, bool>) {
+    let root = ident.split(['.', '[']).next().unwrap_or(ident);
+    if root.is_empty() || root.starts_with("__tera") || locals.contains(root) {
+        return;
+    }
+    *variables.entry(root.to_string()).or_insert(false) |= required;
+}
+
+fn collect_node_variables(nodes: &[Node], locals: &HashSet<String>, variables: &mut BTreeMap<String, bool>) {
+    let mut locals = locals.clone();
+    for node in nodes {
+        match node {
+            Node::VariableBlock(_, expr) => collect_expr_variables(expr, &locals, true, variables),
+            Node::Set(_, set) => {
+                collect_expr_variables(&set.value, &locals, true, variables);
+                locals.insert(set.key.clone());
+            }
+            Node::MacroDefinition(_, definition, _) => {
+                // Macros only see their own arguments.
+                let macro_locals: HashSet<String> = definition.args.keys().cloned().collect();
+                for default in definition.args.values().flatten() {
+                    collect_expr_variables(default, &locals, true, variables);
+                }
+                collect_node_variables(&definition.body, &macro_locals, variables);
+            }
+            Node::FilterSection(_, section, _) => {
+                for arg in section.filter.args.values() {
+                    collect_expr_variables(arg, &locals, true, variables);
+                }
+                collect_node_variables(&section.body, &locals, variables);
+            }
+            Node::Block(_, block, _) => collect_node_variables(&block.body, &locals, variables),
+            Node::Forloop(_, forloop, _) => {
+                collect_expr_variables(&forloop.container, &locals, true, variables);
+                let mut loop_locals = locals.clone();
+                loop_locals.insert(forloop.value.clone());
+                loop_locals.extend(forloop.key.clone());
+                loop_locals.insert("loop".to_string());
+                collect_node_variables(&forloop.body, &loop_locals, variables);
+                if let Some(empty_body) = &forloop.empty_body {
+                    collect_node_variables(empty_body, &locals, variables);
+                }
+            }
+            Node::If(if_node, _) => {
+                for (_, condition, body) in &if_node.conditions {
+                    collect_expr_variables(condition, &locals, true, variables);
+                    collect_node_variables(body, &locals, variables);
+                }
+                if let Some((_, body)) = &if_node.otherwise {
+                    collect_node_variables(body, &locals, variables);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn collect_expr_variables(expr: &Expr, locals: &HashSet<String>, required: bool, variables: &mut BTreeMap<String, bool>) {
+    collect_value_variables(&expr.val, locals, required && !expr.has_default_filter(), variables);
+    for filter in &expr.filters {
+        for arg in filter.args.values() {
+            collect_expr_variables(arg, locals, true, variables);
+        }
+    }
+}
+
+fn collect_value_variables(value: &ExprVal, locals: &HashSet<String>, required: bool, variables: &mut BTreeMap<String, bool>) {
+    match value {
+        ExprVal::Ident(ident) => record_variable(ident, locals, required, variables),
+        ExprVal::Math(math) => {
+            collect_expr_variables(&math.lhs, locals, required, variables);
+            collect_expr_variables(&math.rhs, locals, required, variables);
+        }
+        ExprVal::Logic(logic) => {
+            collect_expr_variables(&logic.lhs, locals, required, variables);
+            collect_expr_variables(&logic.rhs, locals, required, variables);
+        }
+        ExprVal::In(in_expr) => {
+            collect_expr_variables(&in_expr.lhs, locals, required, variables);
+            collect_expr_variables(&in_expr.rhs, locals, required, variables);
+        }
+        ExprVal::Test(test) => {
+            let checks_definition = test.name == "defined" || test.name == "undefined";
+            record_variable(&test.ident, locals, required && !checks_definition, variables);
+            for arg in &test.args {
+                collect_expr_variables(arg, locals, required, variables);
+            }
+        }
+        ExprVal::FunctionCall(call) => {
+            for arg in call.args.values() {
+                collect_expr_variables(arg, locals, required, variables);
+            }
+        }
+        ExprVal::MacroCall(call) => {
+            for arg in call.args.values() {
+                collect_expr_variables(arg, locals, required, variables);
+            }
+        }
+        ExprVal::Array(items) => {
+            for item in items {
+                collect_expr_variables(item, locals, required, variables);
+            }
+        }
+        ExprVal::StringConcat(concat) => {
+            for value in &concat.values {
+                collect_value_variables(value, locals, required, variables);
+            }
+        }
+        ExprVal::String(_) | ExprVal::Int(_) | ExprVal::Float(_) | ExprVal::Bool(_) => {}
+    }
+}
+
+/// Format an error with all of its sources, e.g. "Failed to render 'x': Variable `y` not found".
+pub(crate) fn error_chain(error: &dyn Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        message.push_str(": ");
+        message.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    message
+}